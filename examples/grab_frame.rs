@@ -4,7 +4,6 @@
 //
 // The frame is written to disk as 'grab_frame_example_gen_img.png', at the top-level repository folder.
 
-use image;
 use std::{sync::mpsc, time::Duration};
 use tinyrigel;
 
@@ -18,17 +17,14 @@ fn main() -> tinyrigel::Result<()> {
   let (io_done_tx, io_done_rx) = mpsc::channel::<u32>();
 
   // Set the frame callback handler for the Rigel.
-  rigel.set_callback(|frame: &[u8]| {
+  rigel.set_callback(move |frame: &tinyrigel::Frame| {
     // Get permission from the main thread to write the frame to disk.
     // Timeout after 100ms to wait for the next frame callback.
     let io_permission = io_permission_rx.recv_timeout(Duration::from_millis(100));
     if io_permission.is_err() { return; }
 
     // Write the frame to disk.
-    let mut img = image::DynamicImage::new_luma8(384 * 2, 384);
-    let img_luma8 = img.as_mut_luma8().unwrap();
-    img_luma8.copy_from_slice(frame);
-    img.save("grab_frame_example_gen_img.png").unwrap();
+    frame.side_by_side().save("grab_frame_example_gen_img.png").unwrap();
     println!("[Frame] Saved frame data to test.png.");
 
     // Send the done signal to the main thread, which could fail if the process halts ot the main thread otherwise hangs up the channel unexpectedly, in which case we just exit.
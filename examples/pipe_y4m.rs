@@ -0,0 +1,37 @@
+// pipe_y4m.rs
+//
+// Streams the first connected Rigel to stdout as YUV4MPEG2, for handing capture off to ffmpeg:
+//
+//   cargo run --example pipe_y4m | ffmpeg -i - out.mkv
+//
+// Capture runs until the receiving process exits (closing the pipe) or the device disconnects.
+
+use std::time::Duration;
+
+fn main() -> tinyrigel::Result<()> {
+  // Retrieve the first connected Rigel if there is one.
+  let mut rigel = tinyrigel::get_rigel::<fn(&tinyrigel::Frame)>()?;
+
+  // Attach the writer as a sink; without a callback, every frame pulled with next_frame() is written to it.
+  let y4m = tinyrigel::Y4mWriter::stdout(tinyrigel::EyeLayout::SideBySide, tinyrigel::Y4mColorspace::Mono, rigel.mode().fps);
+  rigel.attach_sink(Box::new(y4m));
+
+  rigel.open()?;
+  loop {
+    match rigel.next_frame(Duration::from_millis(500)) {
+      // A failed write, such as the pipe closing, is kept for take_error() rather than returned.
+      Ok(_) => if let Some(err) = rigel.take_error() {
+        eprintln!("[pipe_y4m] Stopping: {}", err.to_string());
+        break;
+      },
+      Err(err) if err.kind() == tinyrigel::ErrorKind::Timeout => continue,
+      Err(err) => {
+        eprintln!("[pipe_y4m] Stopping: {}", err.to_string());
+        break;
+      }
+    }
+  }
+  rigel.close()?;
+
+  Ok(())
+}
@@ -0,0 +1,450 @@
+// apriltag.rs - tinyrigel
+//
+// AprilTag detection on one eye, in pure Rust. Each eye is thresholded against its local contrast, dark regions are labelled, and the outline of each is fitted with a quadrilateral whose edges are then refined to subpixel accuracy along the image gradient. Quads are sampled on the tag grid and decoded against the family's code table in all four rotations, allowing a few bit errors. Poses come from the corner homography, refined by minimizing the reprojection error through the eye's lens model.
+
+use std::borrow::Cow;
+
+use image::GenericImageView;
+
+use crate::*;
+
+/// A family of tags: the data grid size, the minimum Hamming distance between its codes, and the codes themselves.
+///
+/// Codes use the classic row-major layout of the AprilTag 2 tables: the most significant bit is the top-left data cell of the upright tag, and a set bit is a white cell. (AprilTag 3's C sources store the same tags in a different bit order.)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFamily {
+  name: String,
+  size: u32,
+  min_hamming: u32,
+  codes: Cow<'static, [u64]>
+}
+
+impl TagFamily {
+  /// 36 bits on a 6x6 grid, minimum Hamming distance 11, limited to IDs below `ids`. Decoding against fewer codes also means fewer false detections.
+  ///
+  /// Only IDs 0 to 72 of the family's 587 are built in, so asking for more than 73 is an error rather than a family that silently misses the rest; load the complete table with `TagFamily::new` for those.
+  pub fn tag36h11(ids: u32) -> Result<Self> {
+    if ids as usize > TAG36H11.len() {
+      return Err(Error::new(format!("Only tag36h11 IDs 0 to {} are built in, not the {} requested; load the complete 587-code table with TagFamily::new.", TAG36H11.len() - 1, ids)));
+    }
+    Ok(Self { name: "tag36h11".to_string(), size: 6, min_hamming: 11, codes: Cow::Borrowed(&TAG36H11[..ids as usize]) })
+  }
+
+  /// 16 bits on a 4x4 grid, minimum Hamming distance 5; all 30 IDs. Small tags are easier to see from far away but more prone to false detections, so consider `set_max_hamming(0)` with this family.
+  pub fn tag16h5() -> Self {
+    Self { name: "tag16h5".to_string(), size: 4, min_hamming: 5, codes: Cow::Borrowed(TAG16H5) }
+  }
+
+  /// A family from its code table, for families or IDs not built in. `size` is the number of data cells per side (at most 8), and codes are in the layout described on the type.
+  pub fn new(name: &str, size: u32, min_hamming: u32, codes: Vec<u64>) -> Result<Self> {
+    if !(2..=8).contains(&size) {
+      return Err(Error::new(format!("A tag family needs 2 to 8 data cells per side, not {}.", size)));
+    }
+    let bits = size * size;
+    if bits < 64 {
+      if let Some(code) = codes.iter().find(|&&code| code >> bits != 0) {
+        return Err(Error::new(format!("Code {:#x} of {} has more than {} bits.", code, name, bits)));
+      }
+    }
+    Ok(Self { name: name.to_string(), size, min_hamming, codes: Cow::Owned(codes) })
+  }
+
+  pub fn name(&self) -> &str { &self.name }
+
+  /// Data cells per side. The printed tag adds a one-cell black border on each side.
+  pub fn size(&self) -> u32 { self.size }
+
+  pub fn len(&self) -> usize { self.codes.len() }
+
+  pub fn is_empty(&self) -> bool { self.codes.is_empty() }
+
+  /// The code of tag `id`.
+  pub fn code(&self, id: u32) -> Option<u64> { self.codes.get(id as usize).copied() }
+
+  // The code of the tag turned a quarter clockwise: cell (x, y) of the result is cell (y, size - 1 - x) of the input.
+  fn rotate(&self, code: u64) -> u64 {
+    let (d, bits) = (self.size, self.size * self.size);
+    let mut out = 0;
+    for y in 0..d {
+      for x in 0..d {
+        out = out << 1 | (code >> (bits - 1 - ((d - 1 - x) * d + y))) & 1;
+      }
+    }
+    out
+  }
+
+  // The closest code in any rotation, as (id, bit errors, quarter turns that take `code` to it).
+  fn decode(&self, code: u64, max_hamming: u32) -> Option<(u32, u32, usize)> {
+    let mut best: Option<(u32, u32, usize)> = None;
+    let mut rotated = code;
+    for rotation in 0..4 {
+      for (id, &candidate) in self.codes.iter().enumerate() {
+        let errors = (candidate ^ rotated).count_ones();
+        if errors <= max_hamming && best.map_or(true, |(_, best_errors, _)| errors < best_errors) {
+          best = Some((id as u32, errors, rotation));
+        }
+      }
+      rotated = self.rotate(rotated);
+    }
+    best
+  }
+}
+
+/// A decoded tag in one eye.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagDetection {
+  pub family: String,
+  pub id: u32,
+  /// Number of bit errors corrected.
+  pub hamming: u32,
+  /// Outer corners of the black border in eye pixels: top-left, top-right, bottom-right and bottom-left of the upright tag.
+  pub corners: [(f64, f64); 4],
+  pub center: (f64, f64)
+}
+
+/// Position and orientation of a tag in the camera frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TagPose {
+  /// Rotation taking tag coordinates into the camera frame. The tag frame has its origin at the tag center, x to the right and y down across the upright tag, and z into the tag, so a tag facing the camera squarely has the identity rotation.
+  pub rotation: [[f64; 3]; 3],
+  /// Tag center in meters in the camera frame.
+  pub translation: [f64; 3],
+  /// RMS reprojection error of the corners, in pixels.
+  pub error: f64
+}
+
+impl TagDetection {
+  /// The 6-DoF pose of the tag relative to the eye it was detected in, given that eye's intrinsics and the edge length of the tag's black square in meters. `None` if the corners don't admit a pose in front of the camera.
+  pub fn pose(&self, camera: &CameraIntrinsics, tag_size: f64) -> Option<TagPose> {
+    let s = tag_size / 2.0;
+    let object = [(-s, -s), (s, -s), (s, s), (-s, s)];
+    let observed: Vec<(f64, f64)> = self.corners.iter().map(|&(u, v)| camera.unproject(u, v)).collect();
+    let (rotation, translation) = plane_pose(&object, &observed)?;
+
+    let mut squared = 0.0;
+    for (&(x, y), &(u, v)) in object.iter().zip(&self.corners) {
+      let point = mat_vec(&rotation, [x, y, 0.0]);
+      let z = point[2] + translation[2];
+      if z <= 0.0 { return None; }
+      let (pu, pv) = camera.project((point[0] + translation[0]) / z, (point[1] + translation[1]) / z);
+      squared += (pu - u).powi(2) + (pv - v).powi(2);
+    }
+    Some(TagPose { rotation, translation, error: (squared / 4.0).sqrt() })
+  }
+}
+
+/// Finds AprilTags of one or more families in an eye image.
+pub struct AprilTagDetector {
+  families: Vec<TagFamily>,
+  max_hamming: u32,
+  min_contrast: u8,
+  min_size: u32
+}
+
+impl AprilTagDetector {
+  pub fn new(family: TagFamily) -> Self {
+    Self { families: vec![family], max_hamming: 2, min_contrast: 20, min_size: 8 }
+  }
+
+  /// Also looks for tags of `family`. Families are tried in the order they were added.
+  pub fn add_family(&mut self, family: TagFamily) { self.families.push(family); }
+
+  /// The most bit errors to correct. Defaults to 2, and is never more than a family's minimum Hamming distance allows to correct unambiguously.
+  pub fn set_max_hamming(&mut self, bits: u32) { self.max_hamming = bits; }
+
+  /// Ignores regions where the difference between dark and light is below `contrast` gray levels. Defaults to 20.
+  pub fn set_min_contrast(&mut self, contrast: u8) { self.min_contrast = contrast; }
+
+  /// Ignores tags whose black square spans fewer than `pixels` pixels. Defaults to 8.
+  pub fn set_min_size(&mut self, pixels: u32) { self.min_size = pixels.max(4); }
+
+  /// Detects tags in one eye of `frame`. Errors if the frame is incomplete.
+  pub fn detect(&self, frame: &Frame, eye: Eye) -> Result<Vec<TagDetection>> {
+    let view = frame.eye_view(eye).ok_or_else(|| Error::new(format!("Frame {} is incomplete.", frame.sequence)))?;
+    Ok(self.detect_view(&view))
+  }
+
+  /// Detects tags in an eye view.
+  pub fn detect_view(&self, view: &EyeView) -> Vec<TagDetection> {
+    let (width, height) = (view.width() as usize, view.height() as usize);
+    if width < 8 || height < 8 { return Vec::new(); }
+    let dark = adaptive_threshold(view, 4, self.min_contrast);
+    let mut detections: Vec<TagDetection> = Vec::new();
+    for region in dark_regions(&dark, width, height, self.min_size) {
+      let Some(quad) = fit_quad(&region.outline, self.min_size as f64, 0.85) else { continue; };
+      for family in &self.families {
+        let cell = side_lengths(&quad).iter().cloned().fold(f64::INFINITY, f64::min) / (family.size + 2) as f64;
+        let Some(corners) = refine_edges(view, &quad, (cell * 0.4).clamp(1.0, 3.0)) else { continue; };
+        let Some(detection) = self.decode(view, family, corners) else { continue; };
+        // The same tag can be outlined twice, e.g. by a dark surround merged with its border; keep the better read.
+        let duplicate = detections.iter().position(|other| {
+          other.family == detection.family && other.id == detection.id && (other.center.0 - detection.center.0).hypot(other.center.1 - detection.center.1) < cell * 2.0
+        });
+        match duplicate {
+          Some(index) if detections[index].hamming > detection.hamming => detections[index] = detection,
+          Some(_) => {}
+          None => detections.push(detection)
+        }
+        break;
+      }
+    }
+    detections
+  }
+
+  // Samples the grid through the refined quad and decodes it.
+  fn decode(&self, view: &EyeView, family: &TagFamily, corners: [(f64, f64); 4]) -> Option<TagDetection> {
+    let n = (family.size + 2) as f64;
+    let h = homography(&[(0.0, 0.0), (n, 0.0), (n, n), (0.0, n)], &corners)?;
+    // Averages a few points around a cell center, in tag grid units.
+    let cell = |x: f64, y: f64| -> Option<f64> {
+      let mut sum = 0.0;
+      for (dx, dy) in [(0.0, 0.0), (-0.2, -0.2), (0.2, -0.2), (0.2, 0.2), (-0.2, 0.2)].iter() {
+        let (u, v) = apply_homography(&h, (x + dx, y + dy));
+        sum += sample(view, u, v)?;
+      }
+      Some(sum / 5.0)
+    };
+
+    let cells = family.size as usize + 2;
+    let mut border = Vec::new();
+    let mut quiet = Vec::new();
+    for i in 0..cells {
+      let c = i as f64 + 0.5;
+      for (x, y) in [(c, 0.5), (c, n - 0.5), (0.5, c), (n - 0.5, c)].iter() {
+        border.push(cell(*x, *y)?);
+      }
+      // The white margin just outside the black square; parts of it may be cut off by the image edge.
+      quiet.extend([(c, -0.5), (c, n + 0.5), (-0.5, c), (n + 0.5, c)].iter().filter_map(|&(x, y)| cell(x, y)));
+    }
+    if quiet.len() < cells * 2 { return None; }
+    let black = border.iter().sum::<f64>() / border.len() as f64;
+    let white = quiet.iter().sum::<f64>() / quiet.len() as f64;
+    if white - black < self.min_contrast as f64 { return None; }
+    let threshold = (black + white) / 2.0;
+    if border.iter().filter(|&&value| value > threshold).count() * 8 > border.len() { return None; }
+    if quiet.iter().filter(|&&value| value < threshold).count() * 4 > quiet.len() { return None; }
+
+    let mut code = 0u64;
+    for y in 0..family.size {
+      for x in 0..family.size {
+        code = code << 1 | (cell(x as f64 + 1.5, y as f64 + 1.5)? > threshold) as u64;
+      }
+    }
+    let max_hamming = self.max_hamming.min(family.min_hamming.saturating_sub(1) / 2);
+    let (id, hamming, rotation) = family.decode(code, max_hamming)?;
+    // After `rotation` quarter turns, the tag's top-left corner is the quad corner that many places back.
+    let corners = [0, 1, 2, 3].map(|i| corners[(i + 4 - rotation) % 4]);
+    Some(TagDetection { family: family.name.clone(), id, hamming, corners, center: apply_homography(&h, (n / 2.0, n / 2.0)) })
+  }
+}
+
+// Dark pixels, against a threshold halfway between the local minimum and maximum over 3x3 tiles of `tile` pixels square. Regions without `min_contrast` between the two count as light.
+pub(crate) fn adaptive_threshold(view: &EyeView, tile: usize, min_contrast: u8) -> Vec<bool> {
+  let (width, height) = (view.width() as usize, view.height() as usize);
+  let (tiles_x, tiles_y) = (width.div_ceil(tile), height.div_ceil(tile));
+  let mut extremes = vec![(255u8, 0u8); tiles_x * tiles_y];
+  for y in 0..height {
+    for (x, &value) in view.row(y as u32).iter().enumerate() {
+      let tile = &mut extremes[(y / tile) * tiles_x + x / tile];
+      *tile = (tile.0.min(value), tile.1.max(value));
+    }
+  }
+  let mut spread = vec![(255u8, 0u8); extremes.len()];
+  for ty in 0..tiles_y {
+    for tx in 0..tiles_x {
+      let mut range = (255u8, 0u8);
+      for ny in ty.saturating_sub(1)..(ty + 2).min(tiles_y) {
+        for nx in tx.saturating_sub(1)..(tx + 2).min(tiles_x) {
+          let (min, max) = extremes[ny * tiles_x + nx];
+          range = (range.0.min(min), range.1.max(max));
+        }
+      }
+      spread[ty * tiles_x + tx] = range;
+    }
+  }
+  let mut dark = vec![false; width * height];
+  for y in 0..height {
+    for (x, &value) in view.row(y as u32).iter().enumerate() {
+      let (min, max) = spread[(y / tile) * tiles_x + x / tile];
+      dark[y * width + x] = max - min >= min_contrast && (value as u32) * 2 < min as u32 + max as u32;
+    }
+  }
+  dark
+}
+
+// A dark 4-connected region: the pixel-corner points at the ends of each of its runs, enough for a convex hull, and its size.
+pub(crate) struct Region {
+  pub(crate) outline: Vec<(f64, f64)>,
+  pub(crate) pixels: usize
+}
+
+// The dark regions that don't touch the image edge and are at least `min_size` wide and high.
+pub(crate) fn dark_regions(dark: &[bool], width: usize, height: usize, min_size: u32) -> Vec<Region> {
+  let mut regions = Vec::new();
+  for runs in connected_components(width, height, false, |x, y| dark[y * width + x]) {
+    let (x0, y0) = (runs.iter().map(|run| run.x0).min().unwrap(), runs[0].y);
+    let (x1, y1) = (runs.iter().map(|run| run.x1).max().unwrap(), runs[runs.len() - 1].y + 1);
+    if x0 == 0 || y0 == 0 || x1 == width || y1 == height { continue; }
+    if x1 - x0 < min_size as usize || y1 - y0 < min_size as usize { continue; }
+    let mut points = Vec::with_capacity(runs.len() * 4);
+    let mut pixels = 0;
+    for run in &runs {
+      let (top, bottom) = (run.y as f64 - 0.5, run.y as f64 + 0.5);
+      let (left, right) = (run.x0 as f64 - 0.5, run.x1 as f64 - 0.5);
+      points.extend_from_slice(&[(left, top), (left, bottom), (right, top), (right, bottom)]);
+      pixels += run.x1 - run.x0;
+    }
+    regions.push(Region { outline: points, pixels });
+  }
+  regions
+}
+
+// The largest quadrilateral on the convex hull of `points`, if it covers at least `min_fill` of the hull. Corners are clockwise on screen (image y points down).
+pub(crate) fn fit_quad(points: &[(f64, f64)], min_size: f64, min_fill: f64) -> Option<[(f64, f64); 4]> {
+  let hull = convex_hull(points);
+  let n = hull.len();
+  if n < 4 { return None; }
+  let area = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).abs() / 2.0;
+  let at = |i: usize| hull[i % n];
+  let mut best = (0.0, [0usize; 4]);
+  for i in 0..n {
+    let (mut j, mut l) = (i + 1, i + 3);
+    for k in i + 2..i + n - 1 {
+      // The best third corner on each side of the diagonal only moves forward as the diagonal's end does.
+      while j + 1 < k && area(at(i), at(j + 1), at(k)) >= area(at(i), at(j), at(k)) { j += 1; }
+      l = l.max(k + 1);
+      while l + 1 < i + n && area(at(k), at(l + 1), at(i)) >= area(at(k), at(l), at(i)) { l += 1; }
+      let total = area(at(i), at(j), at(k)) + area(at(k), at(l), at(i));
+      if total > best.0 { best = (total, [i, j, k, l]); }
+    }
+  }
+  let hull_area: f64 = (1..n - 1).map(|i| area(hull[0], hull[i], hull[i + 1])).sum();
+  if best.0 < min_fill * hull_area { return None; }
+  let quad = best.1.map(at);
+  let sides = side_lengths(&quad);
+  let (shortest, longest) = sides.iter().fold((f64::INFINITY, 0.0f64), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+  if shortest < min_size * 0.7 || shortest * 4.0 < longest { return None; }
+  Some(quad)
+}
+
+pub(crate) fn side_lengths(quad: &[(f64, f64); 4]) -> [f64; 4] {
+  [0, 1, 2, 3].map(|i| (quad[(i + 1) % 4].0 - quad[i].0).hypot(quad[(i + 1) % 4].1 - quad[i].1))
+}
+
+// Andrew's monotone chain. With image y down, the result runs clockwise on screen.
+fn convex_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+  let mut sorted = points.to_vec();
+  sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+  sorted.dedup();
+  if sorted.len() < 3 { return sorted; }
+  let turn = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+  let mut hull: Vec<(f64, f64)> = Vec::with_capacity(sorted.len() + 1);
+  for pass in 0..2 {
+    let start = hull.len();
+    let iter: Box<dyn Iterator<Item = &(f64, f64)>> = if pass == 0 { Box::new(sorted.iter()) } else { Box::new(sorted.iter().rev()) };
+    for &p in iter {
+      while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0 { hull.pop(); }
+      hull.push(p);
+    }
+    hull.pop();
+  }
+  hull
+}
+
+// Moves each quad edge onto the dark-to-light transition of the image: samples along the edge find the gradient-weighted edge position within `range` pixels along the normal, a line is fitted through them, and adjacent lines are intersected.
+fn refine_edges(view: &EyeView, quad: &[(f64, f64); 4], range: f64) -> Option<[(f64, f64); 4]> {
+  let mut lines = [((0.0, 0.0), (0.0, 0.0)); 4];
+  for (i, line) in lines.iter_mut().enumerate() {
+    let (a, b) = (quad[i], quad[(i + 1) % 4]);
+    let length = (b.0 - a.0).hypot(b.1 - a.1);
+    let direction = ((b.0 - a.0) / length, (b.1 - a.1) / length);
+    // Clockwise on screen, so this points out of the quad.
+    let normal = (direction.1, -direction.0);
+    let count = ((length / 2.0) as usize).clamp(4, 32);
+    let mut points = Vec::with_capacity(count);
+    for k in 0..count {
+      let t = 0.15 + 0.7 * (k as f64 + 0.5) / count as f64;
+      let p = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+      let at = |s: f64| sample(view, p.0 + normal.0 * s, p.1 + normal.1 * s);
+      let (mut weighted, mut total) = (0.0, 0.0);
+      let mut s = -range;
+      while s <= range {
+        if let (Some(inner), Some(outer)) = (at(s - 0.25), at(s + 0.25)) {
+          let gradient = (outer - inner).max(0.0);
+          weighted += gradient * s;
+          total += gradient;
+        }
+        s += 0.25;
+      }
+      if total > 0.0 {
+        let offset = weighted / total;
+        points.push((p.0 + normal.0 * offset, p.1 + normal.1 * offset));
+      }
+    }
+    if points.len() < 3 { return None; }
+    *line = fit_line(&points);
+  }
+  let mut corners = [(0.0, 0.0); 4];
+  for (i, corner) in corners.iter_mut().enumerate() {
+    *corner = intersect(lines[(i + 3) % 4], lines[i])?;
+    // Refinement moves corners by a pixel or two, never across the tag.
+    if (corner.0 - quad[i].0).hypot(corner.1 - quad[i].1) > range * 2.0 + 1.0 { return None; }
+  }
+  Some(corners)
+}
+
+// Total least squares: the centroid and the direction of largest spread.
+fn fit_line(points: &[(f64, f64)]) -> ((f64, f64), (f64, f64)) {
+  let n = points.len() as f64;
+  let (cx, cy) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+  let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+  for p in points {
+    let (dx, dy) = (p.0 - cx, p.1 - cy);
+    xx += dx * dx;
+    xy += dx * dy;
+    yy += dy * dy;
+  }
+  let angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+  ((cx, cy), (angle.cos(), angle.sin()))
+}
+
+fn intersect((p, d): ((f64, f64), (f64, f64)), (q, e): ((f64, f64), (f64, f64))) -> Option<(f64, f64)> {
+  let denominator = d.0 * e.1 - d.1 * e.0;
+  if denominator.abs() < 1e-9 { return None; }
+  let t = ((q.0 - p.0) * e.1 - (q.1 - p.1) * e.0) / denominator;
+  Some((p.0 + d.0 * t, p.1 + d.1 * t))
+}
+
+// Bilinear interpolation at a position with pixel centers on integers; `None` outside the image.
+pub(crate) fn sample(view: &EyeView, x: f64, y: f64) -> Option<f64> {
+  let (width, height) = (view.width() as f64, view.height() as f64);
+  if !(x >= 0.0 && y >= 0.0 && x <= width - 1.0 && y <= height - 1.0) { return None; }
+  let (x0, y0) = ((x as usize).min(view.width() as usize - 2), (y as usize).min(view.height() as usize - 2));
+  let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+  let (top, bottom) = (view.row(y0 as u32), view.row(y0 as u32 + 1));
+  let upper = top[x0] as f64 * (1.0 - fx) + top[x0 + 1] as f64 * fx;
+  let lower = bottom[x0] as f64 * (1.0 - fx) + bottom[x0 + 1] as f64 * fx;
+  Some(upper * (1.0 - fy) + lower * fy)
+}
+
+const TAG16H5: &[u64] = &[
+  0x231b, 0x2ea5, 0x346a, 0x45b9, 0x79a6, 0x7f6b, 0xb358, 0xe745, 0xfe59, 0x156d,
+  0x380b, 0xf0ab, 0x0d84, 0x4736, 0x8c72, 0xaf10, 0x093c, 0x93b4, 0xa503, 0x468f,
+  0xe137, 0x5795, 0xdf42, 0x1c1d, 0xe9dc, 0x73ad, 0xad5f, 0xd530, 0x07ca, 0xaf2e
+];
+
+const TAG36H11: &[u64] = &[
+  0xd5d628584, 0xd97f18b49, 0xdd280910e, 0xe479e9c98, 0xebcbca822, 0xf31dab3ac,
+  0x056a5d085, 0x10652e1d4, 0x22b1dfead, 0x265ad0472, 0x34fe91b86, 0x3ff962cd5,
+  0x43a25329a, 0x474b4385f, 0x4e9d243e9, 0x5246149ae, 0x5997f5538, 0x683bb6c4c,
+  0x6be4a7211, 0x7e3158eea, 0x81da494af, 0x858339a74, 0x8cd51a5fe, 0x9f21cc2d7,
+  0xa2cabc89c, 0xadc58d9eb, 0xb16e7dfb0, 0xb8c05eb3a, 0xd25ef139d, 0xd607e1962,
+  0xe4aba3076, 0x2dde6a3da, 0x43d40c678, 0x5620be351, 0x64c47fa65, 0x686d7002a,
+  0x6c16605ef, 0x6fbf50bb4, 0x8d06d39dc, 0x9f53856b5, 0xadf746dc9, 0xbc9b084dd,
+  0xd290aa77b, 0xd9e28b305, 0xe4dd5c454, 0xfad2fe6f2, 0x181a8151a, 0x26be42c2e,
+  0x2e10237b8, 0x405cd5491, 0x7742eab1c, 0x85e6ac230, 0x8d388cdba, 0x9f853ea93,
+  0xc41ea2445, 0xcf1973594, 0x14a34a333, 0x31eacd15b, 0x6c79d2dab, 0x73cbb3935,
+  0x89c155bd3, 0x8d6a46198, 0x91133675d, 0xa708d89fb, 0xae5ab9585, 0xb9558a6d4,
+  0xb98743ab2, 0xd6cec68da, 0x1506bcaef, 0x4becd217a, 0x4f95c273f, 0x658b649dd,
+  0xa76c4b1b7
+];
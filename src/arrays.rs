@@ -0,0 +1,43 @@
+// arrays.rs - tinyrigel
+//
+// Borrowed numeric views of frames, behind the `ndarray` and `nalgebra` features. The views index the frame's side-by-side buffer in place with the right strides, so an eye is a plain 2-D array without copying or index math over the row layout.
+
+#[cfg(feature = "ndarray")]
+use ndarray::{ArrayView2, ArrayView3, ShapeBuilder};
+
+#[cfg(feature = "nalgebra")]
+use nalgebra::{DMatrixView, Dyn};
+
+use crate::*;
+
+impl Frame {
+  // The frame data from an eye's first pixel on.
+  fn eye_data(&self, eye: Eye) -> &[u8] {
+    let start = match eye { Eye::Left => 0, Eye::Right => self.width as usize };
+    &self.data[start.min(self.data.len())..]
+  }
+
+  /// One eye as a `(row, col)` array view. `None` for incomplete frames.
+  #[cfg(feature = "ndarray")]
+  pub fn eye_array(&self, eye: Eye) -> Option<ArrayView2<'_, u8>> {
+    if !self.is_complete() { return None; }
+    let shape = (self.height as usize, self.width as usize).strides((self.stride(), 1));
+    ArrayView2::from_shape(shape, self.eye_data(eye)).ok()
+  }
+
+  /// The whole stereo frame as an `(eye, row, col)` array view, with the left eye at index 0. `None` for incomplete frames.
+  #[cfg(feature = "ndarray")]
+  pub fn stereo_array(&self) -> Option<ArrayView3<'_, u8>> {
+    if !self.is_complete() { return None; }
+    let shape = (2, self.height as usize, self.width as usize).strides((self.width as usize, self.stride(), 1));
+    ArrayView3::from_shape(shape, &self.data).ok()
+  }
+
+  /// One eye as a `height x width` matrix view. `None` for incomplete frames.
+  #[cfg(feature = "nalgebra")]
+  pub fn eye_matrix(&self, eye: Eye) -> Option<DMatrixView<'_, u8, Dyn, Dyn>> {
+    if !self.is_complete() { return None; }
+    let (rows, cols) = (self.height as usize, self.width as usize);
+    Some(DMatrixView::from_slice_with_strides(self.eye_data(eye), rows, cols, self.stride(), 1))
+  }
+}
@@ -0,0 +1,148 @@
+// blobs.rs - tinyrigel
+//
+// Bright blob detection for retroreflective markers under the IR LEDs: thresholds each eye, labels connected components over pixel runs, and matches blobs between the eyes along epipolar lines to triangulate them. Only the blob centroids are rectified, not the images, so a frame takes well under a millisecond for a few dozen markers.
+
+use image::GenericImageView;
+
+use crate::*;
+
+/// A connected bright region of one eye.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blob {
+  /// Subpixel centroid in the original (unrectified) eye image, weighted by brightness above the threshold.
+  pub x: f64,
+  pub y: f64,
+  /// Number of pixels.
+  pub area: u32,
+  /// Mean pixel value.
+  pub intensity: f32,
+  pub peak: u8
+}
+
+/// A blob seen by both eyes and triangulated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+  /// Indices into `BlobDetection::left` and `BlobDetection::right`.
+  pub left: usize,
+  pub right: usize,
+  /// Position in meters in the left camera frame (x right, y down, z forward).
+  pub position: [f64; 3],
+  /// Vertical distance between the two rectified centroids, in pixels.
+  pub epipolar_error: f64
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlobDetection {
+  pub left: Vec<Blob>,
+  pub right: Vec<Blob>,
+  pub markers: Vec<Marker>
+}
+
+/// Finds bright blobs in both eyes of a frame and triangulates the ones it can match.
+pub struct BlobDetector {
+  rectifier: StereoRectifier,
+  threshold: u8,
+  min_area: u32,
+  max_area: u32,
+  max_epipolar_error: f64,
+  depth_range: (f64, f64)
+}
+
+impl BlobDetector {
+  pub fn new(calibration: &StereoCalibration) -> Self {
+    Self {
+      rectifier: StereoRectifier::new(calibration),
+      threshold: 200,
+      min_area: 2,
+      max_area: 5000,
+      max_epipolar_error: 2.0,
+      depth_range: (0.02, 10.0)
+    }
+  }
+
+  /// Pixels at or above `threshold` belong to blobs. Defaults to 200.
+  pub fn set_threshold(&mut self, threshold: u8) { self.threshold = threshold; }
+
+  /// Ignores blobs with fewer than `min` or more than `max` pixels. Defaults to 2 and 5000.
+  pub fn set_area_range(&mut self, min: u32, max: u32) {
+    self.min_area = min;
+    self.max_area = max;
+  }
+
+  /// How far apart, in rectified pixels, two blobs' rows may be to match. Defaults to 2.
+  pub fn set_max_epipolar_error(&mut self, pixels: f64) { self.max_epipolar_error = pixels; }
+
+  /// Only matches pairs whose triangulated depth is within `min..=max` meters. Defaults to 0.02 to 10.
+  pub fn set_depth_range(&mut self, min: f64, max: f64) { self.depth_range = (min, max); }
+
+  /// The blobs of one eye, in no particular order. Errors if the frame is incomplete.
+  pub fn detect_eye(&self, frame: &Frame, eye: Eye) -> Result<Vec<Blob>> {
+    let view = frame.eye_view(eye).ok_or_else(|| Error::new(format!("Frame {} is incomplete.", frame.sequence)))?;
+    Ok(self.find_blobs(&view))
+  }
+
+  /// Detects blobs in both eyes and triangulates the pairs that lie on the same epipolar line. Each blob is used in at most one marker; when several pair up, the ones closest to their epipolar lines win.
+  pub fn detect(&self, frame: &Frame) -> Result<BlobDetection> {
+    let left = self.detect_eye(frame, Eye::Left)?;
+    let right = self.detect_eye(frame, Eye::Right)?;
+    let rectified = |eye: Eye, blobs: &[Blob]| -> Vec<(f64, f64)> { blobs.iter().map(|blob| self.rectifier.rectify_point(eye, blob.x, blob.y)).collect() };
+    let (left_points, right_points) = (rectified(Eye::Left, &left), rectified(Eye::Right, &right));
+
+    let mut candidates = Vec::new();
+    for (i, &(xl, yl)) in left_points.iter().enumerate() {
+      for (j, &(xr, yr)) in right_points.iter().enumerate() {
+        let error = (yl - yr).abs();
+        if error > self.max_epipolar_error { continue; }
+        if let Some(point) = self.rectifier.reproject(xl, (yl + yr) / 2.0, xl - xr) {
+          if point[2] >= self.depth_range.0 && point[2] <= self.depth_range.1 {
+            candidates.push((error, i, j, point));
+          }
+        }
+      }
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Back from the rectified left frame into the original left camera frame.
+    let unrotate = transpose(&self.rectifier.rotation(Eye::Left));
+    let (mut left_used, mut right_used) = (vec![false; left.len()], vec![false; right.len()]);
+    let mut markers = Vec::new();
+    for (error, i, j, point) in candidates {
+      if left_used[i] || right_used[j] { continue; }
+      left_used[i] = true;
+      right_used[j] = true;
+      markers.push(Marker { left: i, right: j, position: mat_vec(&unrotate, point), epipolar_error: error });
+    }
+    Ok(BlobDetection { left, right, markers })
+  }
+
+  // 8-connected components of the bright pixels.
+  fn find_blobs(&self, view: &EyeView) -> Vec<Blob> {
+    let components = connected_components(view.width() as usize, view.height() as usize, true, |x, y| view.row(y as u32)[x] >= self.threshold);
+    components.iter().filter_map(|runs| {
+      let mut sum = Sums::default();
+      for run in runs {
+        for (x, &value) in view.row(run.y as u32)[run.x0..run.x1].iter().enumerate() {
+          let weight = (value - self.threshold) as f64 + 1.0;
+          sum.weight += weight;
+          sum.x += weight * (run.x0 + x) as f64;
+          sum.y += weight * run.y as f64;
+          sum.total += value as u64;
+          sum.peak = sum.peak.max(value);
+        }
+        sum.area += (run.x1 - run.x0) as u32;
+      }
+      if sum.area < self.min_area || sum.area > self.max_area { return None; }
+      Some(Blob { x: sum.x / sum.weight, y: sum.y / sum.weight, area: sum.area, intensity: sum.total as f32 / sum.area as f32, peak: sum.peak })
+    }).collect()
+  }
+}
+
+#[derive(Default)]
+struct Sums {
+  weight: f64,
+  x: f64,
+  y: f64,
+  total: u64,
+  area: u32,
+  peak: u8
+}
@@ -0,0 +1,706 @@
+// calibrate.rs - tinyrigel
+//
+// Calibrates a Rigel from frames of a printed board, for units whose factory calibration is missing or damaged. Board corners are detected with subpixel refinement in both eyes; each camera is initialized with Zhang's closed-form method and refined with Levenberg-Marquardt, then both cameras, the transform between them and every board pose are refined together.
+//
+// The joint refinement has a handful of shared parameters and six per frame, so its normal equations are solved through the Schur complement of the per-frame blocks, which keeps a few dozen frames well under a second.
+
+use std::fs;
+use std::path::Path;
+
+use image::GenericImageView;
+
+use crate::*;
+
+// Fewer corners than this in an eye and the board is treated as not seen there.
+const MIN_CORNERS: usize = 6;
+
+/// A printed calibration board. Squares are counted across (`columns`) and down (`rows`) with the top-left square black, and `square` is their edge in meters. Corners are the inner corners where four squares meet, numbered row by row from the top left.
+///
+/// Build targets with `checkerboard` and `charuco`, which check the board. One built directly with fewer than 3 squares on a side has no usable corners and is never detected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalibrationTarget {
+  /// A plain checkerboard, only found when all of it is visible. With an odd number of squares on one side and an even number on the other its orientation is unambiguous; otherwise the corner nearest the image's top left is taken as the first.
+  Checkerboard { columns: u32, rows: u32, square: f64 },
+  /// OpenCV's ChArUco layout with AprilTag markers: a tag of `family` in every white square, numbered row by row from the top left, with black squares `marker` meters wide. Corners are found next to decoded tags, so the board may be partly out of view.
+  ChArUco { columns: u32, rows: u32, square: f64, marker: f64, family: TagFamily }
+}
+
+/// A board corner found in an eye image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardCorner {
+  pub id: u32,
+  /// Subpixel position in the original (unrectified) eye image.
+  pub x: f64,
+  pub y: f64
+}
+
+impl CalibrationTarget {
+  pub fn checkerboard(columns: u32, rows: u32, square: f64) -> Result<Self> {
+    if columns < 3 || rows < 3 || columns == rows {
+      return Err(Error::new(format!("A checkerboard needs at least 3 squares per side and different row and column counts, not {}x{}.", columns, rows)));
+    }
+    if square.is_nan() || square <= 0.0 { return Err(Error::new(format!("Invalid square size {}.", square))); }
+    Ok(CalibrationTarget::Checkerboard { columns, rows, square })
+  }
+
+  /// A ChArUco board. The tags' white margin, one cell wide, must fit inside their squares.
+  pub fn charuco(columns: u32, rows: u32, square: f64, marker: f64, family: TagFamily) -> Result<Self> {
+    if columns < 3 || rows < 3 {
+      return Err(Error::new(format!("A ChArUco board needs at least 3 squares per side, not {}x{}.", columns, rows)));
+    }
+    let cells = (family.size() + 2) as f64;
+    if !(square > 0.0 && marker > 0.0 && marker * (cells + 2.0) / cells <= square) {
+      return Err(Error::new(format!("{} m markers with their margin don't fit in {} m squares.", marker, square)));
+    }
+    let markers = (columns * rows / 2) as usize;
+    if family.len() < markers {
+      return Err(Error::new(format!("A {}x{} ChArUco board needs {} markers, but {} has {}.", columns, rows, markers, family.name(), family.len())));
+    }
+    Ok(CalibrationTarget::ChArUco { columns, rows, square, marker, family })
+  }
+
+  fn squares(&self) -> (u32, u32, f64) {
+    match self {
+      CalibrationTarget::Checkerboard { columns, rows, square } | CalibrationTarget::ChArUco { columns, rows, square, .. } => (*columns, *rows, *square)
+    }
+  }
+
+  pub fn corner_count(&self) -> usize {
+    let (columns, rows, _) = self.squares();
+    columns.saturating_sub(1) as usize * rows.saturating_sub(1) as usize
+  }
+
+  /// Position of a corner on the board in meters, from the board's top-left corner with x to the right and y down.
+  pub fn corner_position(&self, id: u32) -> Option<(f64, f64)> {
+    let (columns, _, square) = self.squares();
+    if id as usize >= self.corner_count() { return None; }
+    let (i, j) = (id % (columns - 1), id / (columns - 1));
+    Some(((i + 1) as f64 * square, (j + 1) as f64 * square))
+  }
+
+  /// Finds the board's corners in one eye of `frame`. Errors if the frame is incomplete.
+  pub fn detect(&self, frame: &Frame, eye: Eye) -> Result<Vec<BoardCorner>> {
+    let view = frame.eye_view(eye).ok_or_else(|| Error::new(format!("Frame {} is incomplete.", frame.sequence)))?;
+    Ok(self.detect_view(&view))
+  }
+
+  /// Finds the board's corners in an eye view, sorted by ID. Empty if the board isn't there.
+  pub fn detect_view(&self, view: &EyeView) -> Vec<BoardCorner> {
+    let (columns, rows, _) = self.squares();
+    if view.width() < 16 || view.height() < 16 || columns < 3 || rows < 3 { return Vec::new(); }
+    match self {
+      CalibrationTarget::Checkerboard { columns, rows, .. } => detect_checkerboard(view, *columns, *rows),
+      CalibrationTarget::ChArUco { columns, rows, square, marker, family } => detect_charuco(view, *columns, *rows, *square, *marker, family)
+    }
+  }
+}
+
+// Checkerboards
+// ---
+
+// Thresholds the image and shrinks the dark parts until the black squares come apart, then pairs up the corners of diagonally touching squares and walks the grid they form.
+fn detect_checkerboard(view: &EyeView, columns: u32, rows: u32) -> Vec<BoardCorner> {
+  let (width, height) = (view.width() as usize, view.height() as usize);
+  let mut dark = adaptive_threshold(view, 8, 20);
+  let mut quads: Vec<[(f64, f64); 4]> = Vec::new();
+  for _ in 0..4 {
+    // Squares that came apart at an earlier, less eroded level keep the outline they had there.
+    let center = |quad: &[(f64, f64); 4]| ((quad[0].0 + quad[2].0) / 2.0, (quad[0].1 + quad[2].1) / 2.0);
+    // Small squares turned 45 degrees fill their pixelated hull poorly, so the hull test is loose; squares still merged along a diagonal are told apart by how little of their quad they cover.
+    let found = dark_regions(&dark, width, height, 4).into_iter()
+      .filter_map(|region| fit_quad(&region.outline, 4.0, 0.7).filter(|quad| region.pixels as f64 >= 0.85 * quad_area(quad)));
+    for quad in found {
+      let (x, y) = center(&quad);
+      let size = shortest_side(&quad);
+      let same = |other: &[(f64, f64); 4]| (center(other).0 - x).hypot(center(other).1 - y) < size / 2.0 && (shortest_side(other) / size - 1.0).abs() < 0.5;
+      if !quads.iter().any(same) { quads.push(quad); }
+    }
+    if let Some(grid) = checkerboard_grid(view, &quads, columns as usize - 1, rows as usize - 1) {
+      let mut corners = Vec::with_capacity(grid.len());
+      for (id, &point) in grid.iter().enumerate() {
+        // Stay clear of the neighbouring corners.
+        let (i, j) = (id % (columns as usize - 1), id / (columns as usize - 1));
+        let neighbour = if i > 0 { grid[id - 1] } else if j > 0 { grid[id + 1 - columns as usize] } else { grid[id + 1] };
+        let spacing = (point.0 - neighbour.0).hypot(point.1 - neighbour.1);
+        let Some((x, y)) = refine_corner(view, point, (spacing * 0.3).clamp(2.0, 8.0) as i64) else { return Vec::new(); };
+        corners.push(BoardCorner { id: id as u32, x, y });
+      }
+      return corners;
+    }
+    dark = erode(&dark, width, height);
+  }
+  Vec::new()
+}
+
+// Keeps the dark pixels whose four neighbours are dark too.
+fn erode(dark: &[bool], width: usize, height: usize) -> Vec<bool> {
+  let mut out = vec![false; dark.len()];
+  for y in 1..height.saturating_sub(1) {
+    for x in 1..width - 1 {
+      let i = y * width + x;
+      out[i] = dark[i] && dark[i - 1] && dark[i + 1] && dark[i - width] && dark[i + width];
+    }
+  }
+  out
+}
+
+fn quad_area(quad: &[(f64, f64); 4]) -> f64 {
+  (0..4).map(|i| quad[i].0 * quad[(i + 1) % 4].1 - quad[(i + 1) % 4].0 * quad[i].1).sum::<f64>().abs() / 2.0
+}
+
+fn shortest_side(quad: &[(f64, f64); 4]) -> f64 { side_lengths(quad).iter().cloned().fold(f64::INFINITY, f64::min) }
+
+// Corner positions of a board with `columns` x `rows` inner corners, row-major, if the quads contain exactly one such grid.
+fn checkerboard_grid(view: &EyeView, quads: &[[(f64, f64); 4]], columns: usize, rows: usize) -> Option<Vec<(f64, f64)>> {
+  // Every inner corner is where two black squares touch diagonally: pair up corners of similar quads that are each other's nearest, and close compared to the squares.
+  let sizes: Vec<f64> = quads.iter().map(shortest_side).collect();
+  let nearest = |q: usize, k: usize| -> Option<(usize, usize)> {
+    let p = quads[q][k];
+    let mut best: Option<(f64, usize, usize)> = None;
+    for (other, quad) in quads.iter().enumerate() {
+      if other == q { continue; }
+      for (l, corner) in quad.iter().enumerate() {
+        let distance = (p.0 - corner.0).hypot(p.1 - corner.1);
+        let similar = sizes[q].max(sizes[other]) < 2.0 * sizes[q].min(sizes[other]);
+        if similar && distance < 0.5 * sizes[q].min(sizes[other]) && best.map_or(true, |b| distance < b.0) { best = Some((distance, other, l)); }
+      }
+    }
+    best.map(|(_, other, l)| (other, l))
+  };
+  let mut corner_of = vec![[None; 4]; quads.len()];
+  let mut points: Vec<(f64, f64)> = Vec::new();
+  for q in 0..quads.len() {
+    for k in 0..4 {
+      if corner_of[q][k].is_some() { continue; }
+      let Some((other, l)) = nearest(q, k) else { continue; };
+      if corner_of[other][l].is_some() || nearest(other, l) != Some((q, k)) { continue; }
+      let (a, b) = (quads[q][k], quads[other][l]);
+      corner_of[q][k] = Some(points.len());
+      corner_of[other][l] = Some(points.len());
+      points.push(((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0));
+    }
+  }
+
+  // Corners along a square's edge are neighbours in the grid.
+  let mut neighbours = vec![Vec::new(); points.len()];
+  for corners in &corner_of {
+    for k in 0..4 {
+      if let (Some(a), Some(b)) = (corners[k], corners[(k + 1) % 4]) {
+        if !neighbours[a].contains(&b) {
+          neighbours[a].push(b);
+          neighbours[b].push(a);
+        }
+      }
+    }
+  }
+
+  let mut labels: Vec<Option<(i64, i64)>> = vec![None; points.len()];
+  for seed in 0..points.len() {
+    if labels[seed].is_some() || neighbours[seed].is_empty() { continue; }
+    let Some(members) = label_grid(&points, &neighbours, &mut labels, seed) else { continue; };
+    if members.len() != columns * rows { continue; }
+    let (min_x, max_x) = members.iter().map(|&m| labels[m].unwrap().0).fold((i64::MAX, i64::MIN), |(lo, hi), x| (lo.min(x), hi.max(x)));
+    let (min_y, max_y) = members.iter().map(|&m| labels[m].unwrap().1).fold((i64::MAX, i64::MIN), |(lo, hi), y| (lo.min(y), hi.max(y)));
+    let extent = ((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+    if extent != (columns, rows) && extent != (rows, columns) { continue; }
+    let mut grid = vec![None; columns * rows];
+    for &m in &members {
+      let (x, y) = labels[m].unwrap();
+      // Turn a quarter if the board lies the other way round; rotations keep the handedness the walk established.
+      let (i, j) = if extent == (columns, rows) { (x - min_x, y - min_y) } else { (y - min_y, max_x - x) };
+      grid[j as usize * columns + i as usize] = Some(points[m]);
+    }
+    let Some(mut grid) = grid.into_iter().collect::<Option<Vec<_>>>() else { continue; };
+
+    // Square (0, 0), outside the first corner, is black. If both end squares are black the board is symmetric; go by the image instead.
+    let (first, last) = (grid[0], grid[columns * rows - 1]);
+    let flip = if (columns + rows) % 2 == 1 {
+      let (across, down) = ((grid[1].0 - first.0, grid[1].1 - first.1), (grid[columns].0 - first.0, grid[columns].1 - first.1));
+      let outer = sample(view, first.0 - (across.0 + down.0) / 2.0, first.1 - (across.1 + down.1) / 2.0);
+      let beside = sample(view, first.0 + (across.0 - down.0) / 2.0, first.1 + (across.1 - down.1) / 2.0);
+      match (outer, beside) {
+        (Some(outer), Some(beside)) => outer > beside,
+        _ => continue
+      }
+    } else {
+      last.0 + last.1 < first.0 + first.1
+    };
+    if flip { grid.reverse(); }
+    return Some(grid);
+  }
+  None
+}
+
+// Assigns grid coordinates to the corners connected to `seed`, walking from corner to corner and classifying each step by the local directions of the grid axes in the image. `None` if the walk contradicts itself.
+fn label_grid(points: &[(f64, f64)], neighbours: &[Vec<usize>], labels: &mut [Option<(i64, i64)>], seed: usize) -> Option<Vec<usize>> {
+  let first = neighbours[seed][0];
+  let across = (points[first].0 - points[seed].0, points[first].1 - points[seed].1);
+  // A quarter turn clockwise on screen, so the grid has the board's handedness as seen from the front.
+  let down = (-across.1, across.0);
+  labels[seed] = Some((0, 0));
+  let mut axes = vec![(across, down); points.len()];
+  let mut members = vec![seed];
+  let mut consistent = true;
+  let mut next = 0;
+  while next < members.len() {
+    let current = members[next];
+    next += 1;
+    let (x, y) = labels[current].unwrap();
+    let (across, down) = axes[current];
+    for &neighbour in &neighbours[current] {
+      let step = (points[neighbour].0 - points[current].0, points[neighbour].1 - points[current].1);
+      // step = a * across + b * down
+      let det = across.0 * down.1 - across.1 * down.0;
+      if det.abs() < 1e-9 { consistent = false; continue; }
+      let a = (step.0 * down.1 - step.1 * down.0) / det;
+      let b = (across.0 * step.1 - across.1 * step.0) / det;
+      let (label, local) = if a.abs() > b.abs() {
+        ((x + a.signum() as i64, y), ((step.0 * a.signum(), step.1 * a.signum()), down))
+      } else {
+        ((x, y + b.signum() as i64), (across, (step.0 * b.signum(), step.1 * b.signum())))
+      };
+      if a.abs().max(b.abs()) < 0.5 || a.abs().min(b.abs()) > 0.5 { consistent = false; continue; }
+      match labels[neighbour] {
+        Some(existing) => consistent &= existing == label,
+        None => {
+          labels[neighbour] = Some(label);
+          axes[neighbour] = local;
+          members.push(neighbour);
+        }
+      }
+    }
+  }
+  let mut seen: Vec<(i64, i64)> = members.iter().map(|&m| labels[m].unwrap()).collect();
+  seen.sort_unstable();
+  seen.dedup();
+  if !consistent || seen.len() != members.len() { return None; }
+  Some(members)
+}
+
+// ChArUco boards
+// ---
+
+fn detect_charuco(view: &EyeView, columns: u32, rows: u32, square: f64, marker: f64, family: &TagFamily) -> Vec<BoardCorner> {
+  let (columns, rows) = (columns as usize, rows as usize);
+  // The white squares, in marker order.
+  let white: Vec<(usize, usize)> = (0..rows).flat_map(|y| (0..columns).map(move |x| (x, y))).filter(|(x, y)| (x + y) % 2 == 1).collect();
+  let mut homographies: Vec<Option<[[f64; 3]; 3]>> = vec![None; columns * rows];
+  for tag in AprilTagDetector::new(family.clone()).detect_view(view) {
+    let Some(&(x, y)) = white.get(tag.id as usize) else { continue; };
+    if homographies[y * columns + x].is_some() { continue; }
+    let (left, top) = (x as f64 * square + (square - marker) / 2.0, y as f64 * square + (square - marker) / 2.0);
+    let board = [(left, top), (left + marker, top), (left + marker, top + marker), (left, top + marker)];
+    homographies[y * columns + x] = homography(&board, &tag.corners);
+  }
+
+  let mut corners = Vec::new();
+  for j in 0..rows - 1 {
+    for i in 0..columns - 1 {
+      let position = ((i + 1) as f64 * square, (j + 1) as f64 * square);
+      // Predict the corner from the tags in the two white squares touching it.
+      let predictions: Vec<((f64, f64), f64)> = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)].iter()
+        .filter_map(|&(x, y)| homographies[y * columns + x].as_ref())
+        .map(|h| {
+          let p = apply_homography(h, position);
+          let (a, b) = (apply_homography(h, (position.0 + square, position.1)), apply_homography(h, (position.0, position.1 + square)));
+          (p, (a.0 - p.0).hypot(a.1 - p.1).min((b.0 - p.0).hypot(b.1 - p.1)))
+        })
+        .collect();
+      if predictions.is_empty() { continue; }
+      let n = predictions.len() as f64;
+      let guess = predictions.iter().fold((0.0, 0.0), |(x, y), (p, _)| (x + p.0 / n, y + p.1 / n));
+      let spacing = predictions.iter().map(|(_, spacing)| *spacing).fold(f64::INFINITY, f64::min);
+      // The window stays inside the white margins so the markers' black borders don't pull on it.
+      let margin = spacing * (square - marker) / (2.0 * square);
+      if let Some((x, y)) = refine_corner(view, guess, (spacing * 0.3).min(margin + 1.0).clamp(2.0, 8.0) as i64) {
+        corners.push(BoardCorner { id: (j * (columns - 1) + i) as u32, x, y });
+      }
+    }
+  }
+  corners
+}
+
+// Moves a corner estimate to the saddle point around it: the point that every image gradient in the window is orthogonal to the direction from (the method of OpenCV's cornerSubPix). `None` if it wanders off by more than `radius`.
+pub(crate) fn refine_corner(view: &EyeView, start: (f64, f64), radius: i64) -> Option<(f64, f64)> {
+  let (width, height) = (view.width() as i64, view.height() as i64);
+  let mut p = start;
+  for _ in 0..20 {
+    let (cx, cy) = (p.0.round() as i64, p.1.round() as i64);
+    if cx - radius < 1 || cy - radius < 1 || cx + radius >= width - 1 || cy + radius >= height - 1 { return None; }
+    let (mut xx, mut xy, mut yy, mut bx, mut by) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for y in cy - radius..=cy + radius {
+      let (above, row, below) = (view.row(y as u32 - 1), view.row(y as u32), view.row(y as u32 + 1));
+      for x in cx - radius..=cx + radius {
+        let xi = x as usize;
+        let gx = (row[xi + 1] as f64 - row[xi - 1] as f64) / 2.0;
+        let gy = (below[xi] as f64 - above[xi] as f64) / 2.0;
+        let weight = (-((x as f64 - p.0).powi(2) + (y as f64 - p.1).powi(2)) / (radius * radius) as f64).exp();
+        let (gxx, gxy, gyy) = (weight * gx * gx, weight * gx * gy, weight * gy * gy);
+        xx += gxx;
+        xy += gxy;
+        yy += gyy;
+        bx += gxx * x as f64 + gxy * y as f64;
+        by += gxy * x as f64 + gyy * y as f64;
+      }
+    }
+    let det = xx * yy - xy * xy;
+    if det <= 1e-9 * (xx + yy).powi(2) { return None; }
+    let next = ((yy * bx - xy * by) / det, (xx * by - xy * bx) / det);
+    let moved = (next.0 - p.0).hypot(next.1 - p.1);
+    p = next;
+    if (p.0 - start.0).hypot(p.1 - start.1) > radius as f64 { return None; }
+    if moved < 0.005 { break; }
+  }
+  Some(p)
+}
+
+// Calibration
+// ---
+
+// A board pose as a rotation vector and a translation.
+type Pose = [f64; 6];
+// A corner's position on the board and in the image.
+type Observation = ((f64, f64), (f64, f64));
+
+/// The outcome of `StereoCalibrator::calibrate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationResult {
+  pub calibration: StereoCalibration,
+  /// RMS reprojection error over all corners of both eyes, in pixels.
+  pub rms: f64,
+  pub left_rms: f64,
+  pub right_rms: f64,
+  /// RMS reprojection error of each view and eye, in the order the views were added; `None` where the eye didn't see the board.
+  pub view_errors: Vec<[Option<f64>; 2]>
+}
+
+impl CalibrationResult {
+  /// The calibration as native JSON (see `StereoCalibration::to_json`), with the reprojection errors added.
+  pub fn to_json(&self) -> String {
+    let mut fields = self.calibration.json_fields();
+    fields.push(format!("  \"rms\": {}", self.rms));
+    fields.push(format!("  \"left_rms\": {}", self.left_rms));
+    fields.push(format!("  \"right_rms\": {}", self.right_rms));
+    fields.push(format!("  \"views\": {}", self.view_errors.len()));
+    format!("{{\n{}\n}}\n", fields.join(",\n"))
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, self.to_json()).map_err(|err| Error::new(format!("Failed to write {}: {}", path.display(), err)))
+  }
+}
+
+/// Collects board detections from stereo frames and solves for the calibration of both cameras and the transform between them.
+pub struct StereoCalibrator {
+  target: CalibrationTarget,
+  model: DistortionModel,
+  size: Option<(u32, u32)>,
+  views: Vec<[Vec<BoardCorner>; 2]>
+}
+
+impl StereoCalibrator {
+  pub fn new(target: CalibrationTarget, model: DistortionModel) -> Self {
+    Self { target, model, size: None, views: Vec::new() }
+  }
+
+  pub fn target(&self) -> &CalibrationTarget { &self.target }
+
+  /// Detects the board in both eyes of `frame` and keeps the frame if either eye saw it. Returns how many corners each eye found. Errors if the frame is incomplete or its size differs from earlier frames.
+  pub fn add_frame(&mut self, frame: &Frame) -> Result<[usize; 2]> {
+    let left = self.target.detect(frame, Eye::Left)?;
+    let right = self.target.detect(frame, Eye::Right)?;
+    let counts = [left.len(), right.len()];
+    self.add_view(frame.width, frame.height, left, right)?;
+    Ok(counts)
+  }
+
+  /// Adds corners detected elsewhere, in eye images of `width` x `height` pixels. Eyes with too few corners (fewer than 6) count as not seeing the board, and a view neither eye saw is dropped.
+  pub fn add_view(&mut self, width: u32, height: u32, left: Vec<BoardCorner>, right: Vec<BoardCorner>) -> Result<()> {
+    if let Some(size) = self.size.filter(|&size| size != (width, height)) {
+      return Err(Error::new(format!("A {}x{} view doesn't match the earlier {}x{} views.", width, height, size.0, size.1)));
+    }
+    if let Some(corner) = left.iter().chain(&right).find(|corner| corner.id as usize >= self.target.corner_count()) {
+      return Err(Error::new(format!("Corner {} is not on a board with {} corners.", corner.id, self.target.corner_count())));
+    }
+    let keep = |corners: Vec<BoardCorner>| if corners.len() >= MIN_CORNERS { corners } else { Vec::new() };
+    let (left, right) = (keep(left), keep(right));
+    if left.is_empty() && right.is_empty() { return Ok(()); }
+    self.size = Some((width, height));
+    self.views.push([left, right]);
+    Ok(())
+  }
+
+  /// The views kept so far, left and right corners.
+  pub fn views(&self) -> &[[Vec<BoardCorner>; 2]] { &self.views }
+
+  /// Solves for the calibration. Each eye needs the board in at least 3 views, seen at varied angles, and at least one view needs it in both eyes.
+  pub fn calibrate(&self) -> Result<CalibrationResult> {
+    let (width, height) = self.size.ok_or_else(|| Error::new("No views to calibrate from.".to_string()))?;
+    let mono = [self.calibrate_eye(Eye::Left, width, height)?, self.calibrate_eye(Eye::Right, width, height)?];
+
+    // The transform between the eyes, averaged over the views both saw.
+    let mut sum = ([0.0; 3], [0.0; 3], 0.0);
+    for (left, right) in mono[0].1.iter().zip(&mono[1].1) {
+      let (Some(left), Some(right)) = (left, right) else { continue; };
+      let (rl, rr) = (rodrigues([left[0], left[1], left[2]]), rodrigues([right[0], right[1], right[2]]));
+      let rotation = mat_mul(&rr, &transpose(&rl));
+      let moved = mat_vec(&rotation, [left[3], left[4], left[5]]);
+      let r = rotation_vector(&rotation);
+      for a in 0..3 {
+        sum.0[a] += r[a];
+        sum.1[a] += right[3 + a] - moved[a];
+      }
+      sum.2 += 1.0;
+    }
+    if sum.2 == 0.0 { return Err(Error::new("Calibrating the pair needs at least one view with the board in both eyes.".to_string())); }
+    let (rotation, translation) = (scale(sum.0, 1.0 / sum.2), scale(sum.1, 1.0 / sum.2));
+
+    // Board poses in the left camera frame, taken over from the right eye where the left didn't see the board.
+    let to_left = transpose(&rodrigues(rotation));
+    let mut poses: Vec<Pose> = mono[0].1.iter().zip(&mono[1].1).map(|(left, right)| match (left, right) {
+      (Some(left), _) => *left,
+      (None, Some(right)) => {
+        let r = rotation_vector(&mat_mul(&to_left, &rodrigues([right[0], right[1], right[2]])));
+        let t = mat_vec(&to_left, [right[3] - translation[0], right[4] - translation[1], right[5] - translation[2]]);
+        [r[0], r[1], r[2], t[0], t[1], t[2]]
+      }
+      (None, None) => [0.0, 0.0, 0.0, 0.0, 0.0, 1.0]
+    }).collect();
+
+    let count = 4 + self.model.coefficients();
+    let mut shared: Vec<f64> = mono[0].0.iter().chain(&mono[1].0).chain(&rotation).chain(&translation).cloned().collect();
+    let residuals = |shared: &[f64], pose: &Pose, view: usize| -> Vec<f64> {
+      let (left, right) = (self.camera(width, height, &shared[..count]), self.camera(width, height, &shared[count..2 * count]));
+      let extrinsics = &shared[2 * count..];
+      let (rotation, translation) = (rodrigues([pose[0], pose[1], pose[2]]), [pose[3], pose[4], pose[5]]);
+      let between = rodrigues([extrinsics[0], extrinsics[1], extrinsics[2]]);
+      let moved = mat_vec(&between, translation);
+      let right_pose = (mat_mul(&between, &rotation), [moved[0] + extrinsics[3], moved[1] + extrinsics[4], moved[2] + extrinsics[5]]);
+      let mut out = self.residuals(&left, &rotation, translation, &self.views[view][0]);
+      out.extend(self.residuals(&right, &right_pose.0, right_pose.1, &self.views[view][1]));
+      out
+    };
+    refine(&mut shared, &mut poses, residuals, 100);
+
+    let (left, right) = (self.camera(width, height, &shared[..count]), self.camera(width, height, &shared[count..2 * count]));
+    let extrinsics = &shared[2 * count..];
+    let between = rodrigues([extrinsics[0], extrinsics[1], extrinsics[2]]);
+    let mut view_errors = Vec::with_capacity(self.views.len());
+    let mut totals = [(0.0, 0usize); 2];
+    for (view, pose) in self.views.iter().zip(&poses) {
+      let (rotation, translation) = (rodrigues([pose[0], pose[1], pose[2]]), [pose[3], pose[4], pose[5]]);
+      let moved = mat_vec(&between, translation);
+      let right_translation = [moved[0] + extrinsics[3], moved[1] + extrinsics[4], moved[2] + extrinsics[5]];
+      let errors = [
+        self.residuals(&left, &rotation, translation, &view[0]),
+        self.residuals(&right, &mat_mul(&between, &rotation), right_translation, &view[1])
+      ];
+      let mut view_error = [None; 2];
+      for ((errors, total), error) in errors.iter().zip(totals.iter_mut()).zip(view_error.iter_mut()) {
+        if errors.is_empty() { continue; }
+        let squared: f64 = errors.iter().map(|e| e * e).sum();
+        *total = (total.0 + squared, total.1 + errors.len() / 2);
+        *error = Some((squared / (errors.len() / 2) as f64).sqrt());
+      }
+      view_errors.push(view_error);
+    }
+    let rms = |(squared, corners): (f64, usize)| if corners == 0 { 0.0 } else { (squared / corners as f64).sqrt() };
+    Ok(CalibrationResult {
+      calibration: StereoCalibration { left, right, rotation: between, translation: [extrinsics[3], extrinsics[4], extrinsics[5]], serial: None },
+      rms: rms((totals[0].0 + totals[1].0, totals[0].1 + totals[1].1)),
+      left_rms: rms(totals[0]),
+      right_rms: rms(totals[1]),
+      view_errors
+    })
+  }
+
+  // Intrinsics and per-view board poses (rotation vector, translation) of one eye on its own.
+  fn calibrate_eye(&self, eye: Eye, width: u32, height: u32) -> Result<(Vec<f64>, Vec<Option<Pose>>)> {
+    let index = match eye { Eye::Left => 0, Eye::Right => 1 };
+    let seen: Vec<usize> = (0..self.views.len()).filter(|&v| !self.views[v][index].is_empty()).collect();
+    if seen.len() < 3 {
+      return Err(Error::new(format!("Calibrating the {:?} eye needs the board in at least 3 views, not {}.", eye, seen.len())));
+    }
+    let observations: Vec<Vec<Observation>> = seen.iter()
+      .map(|&v| self.views[v][index].iter().map(|corner| (self.target.corner_position(corner.id).unwrap(), (corner.x, corner.y))).collect())
+      .collect();
+    let (fx, fy) = self.initial_focal(&observations, width, height)
+      .ok_or_else(|| Error::new(format!("Could not estimate the {:?} eye's focal length; capture the board tilted at more varied angles.", eye)))?;
+    let mut params = vec![fx, fy, (width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0];
+    params.resize(4 + self.model.coefficients(), 0.0);
+
+    let camera = self.camera(width, height, &params);
+    let mut poses = Vec::with_capacity(seen.len());
+    for points in &observations {
+      let (board, normalized): (Vec<_>, Vec<_>) = points.iter().map(|&(board, (u, v))| (board, camera.unproject(u, v))).unzip();
+      let h = homography(&board, &normalized).ok_or_else(|| Error::new(format!("Degenerate board view in the {:?} eye.", eye)))?;
+      let (rotation, translation) = pose_from_homography(&h);
+      let r = rotation_vector(&rotation);
+      poses.push([r[0], r[1], r[2], translation[0], translation[1], translation[2]]);
+    }
+    let residuals = |params: &[f64], pose: &Pose, view: usize| -> Vec<f64> {
+      self.residuals(&self.camera(width, height, params), &rodrigues([pose[0], pose[1], pose[2]]), [pose[3], pose[4], pose[5]], &self.views[seen[view]][index])
+    };
+    refine(&mut params, &mut poses, residuals, 100);
+
+    let mut all = vec![None; self.views.len()];
+    for (&v, pose) in seen.iter().zip(poses) { all[v] = Some(pose); }
+    Ok((params, all))
+  }
+
+  // Zhang's closed form with the principal point at the image center and no skew, which leaves the two focal lengths: each view's homography H = K [r1 r2 t] gives h1' B h2 = 0 and h1' B h1 = h2' B h2 for B = K^-T K^-1. The equidistant model starts from a pinhole and iterates, straightening the points with the current estimate.
+  fn initial_focal(&self, observations: &[Vec<Observation>], width: u32, height: u32) -> Option<(f64, f64)> {
+    let (cx, cy, s) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0, width.max(height) as f64);
+    let mut focal: Option<(f64, f64)> = None;
+    let rounds = if self.model == DistortionModel::Equidistant { 8 } else { 1 };
+    for _ in 0..rounds {
+      let (mut m, mut rhs) = ([[0.0; 2]; 2], [0.0; 2]);
+      for points in observations {
+        let (board, pixels): (Vec<_>, Vec<_>) = points.iter().filter_map(|&(board, (u, v))| {
+          let (mut x, mut y) = ((u - cx) / s, (v - cy) / s);
+          if let Some((fx, fy)) = focal {
+            let (xd, yd) = ((u - cx) / fx, (v - cy) / fy);
+            let theta = xd.hypot(yd);
+            if theta > 1.4 { return None; }
+            let straighten = if theta > 1e-9 { theta.tan() / theta } else { 1.0 };
+            (x, y) = (xd * straighten * fx / s, yd * straighten * fy / s);
+          }
+          Some((board, (x, y)))
+        }).unzip();
+        let Some(h) = homography(&board, &pixels) else { continue; };
+        let (c1, c2) = ([h[0][0], h[1][0], h[2][0]], [h[0][1], h[1][1], h[2][1]]);
+        let n = norm(c1).max(norm(c2));
+        let (c1, c2) = (scale(c1, 1.0 / n), scale(c2, 1.0 / n));
+        for (row, value) in [
+          ([c1[0] * c2[0], c1[1] * c2[1]], -c1[2] * c2[2]),
+          ([c1[0] * c1[0] - c2[0] * c2[0], c1[1] * c1[1] - c2[1] * c2[1]], -(c1[2] * c1[2] - c2[2] * c2[2]))
+        ].iter() {
+          for i in 0..2 {
+            rhs[i] += row[i] * value;
+            for j in 0..2 { m[i][j] += row[i] * row[j]; }
+          }
+        }
+      }
+      let solution = solve(vec![m[0].to_vec(), m[1].to_vec()], rhs.to_vec())?;
+      if solution[0] <= 0.0 || solution[1] <= 0.0 { return None; }
+      focal = Some((s / solution[0].sqrt(), s / solution[1].sqrt()));
+    }
+    focal
+  }
+
+  fn camera(&self, width: u32, height: u32, params: &[f64]) -> CameraIntrinsics {
+    let distortion = Distortion::from_coefficients(self.model, &params[4..]);
+    CameraIntrinsics { width, height, fx: params[0], fy: params[1], cx: params[2], cy: params[3], distortion }
+  }
+
+  // Pixel errors (x, y per corner) of the corners of one eye and view, for a board with the given pose in that eye's camera frame.
+  fn residuals(&self, camera: &CameraIntrinsics, rotation: &[[f64; 3]; 3], translation: [f64; 3], corners: &[BoardCorner]) -> Vec<f64> {
+    let mut out = Vec::with_capacity(corners.len() * 2);
+    for corner in corners {
+      let (x, y) = self.target.corner_position(corner.id).unwrap();
+      let p = mat_vec(rotation, [x, y, 0.0]);
+      let p = [p[0] + translation[0], p[1] + translation[1], p[2] + translation[2]];
+      // Behind the camera: a large error that steers the refinement away.
+      if p[2] <= 1e-6 {
+        out.extend_from_slice(&[1e3, 1e3]);
+        continue;
+      }
+      let (u, v) = camera.project(p[0] / p[2], p[1] / p[2]);
+      out.push(u - corner.x);
+      out.push(v - corner.y);
+    }
+    out
+  }
+}
+
+// Levenberg-Marquardt over parameters shared by all views plus a pose per view. The pose blocks of the normal equations are independent of each other, so they are eliminated first (Schur complement) and only a system the size of the shared parameters is solved.
+fn refine<F: Fn(&[f64], &Pose, usize) -> Vec<f64>>(shared: &mut [f64], poses: &mut [Pose], residuals: F, iterations: usize) {
+  let step = |value: f64| 1e-6 * value.abs().max(1e-2);
+  let evaluate = |shared: &[f64], poses: &[Pose]| -> Vec<Vec<f64>> { poses.iter().enumerate().map(|(view, pose)| residuals(shared, pose, view)).collect() };
+  let cost = |all: &[Vec<f64>]| all.iter().flatten().map(|e| e * e).sum::<f64>();
+  let transposed_product = |a: &[Vec<f64>], b: &[Vec<f64>]| -> Vec<Vec<f64>> {
+    a.iter().map(|column| b.iter().map(|other| column.iter().zip(other).map(|(x, y)| x * y).sum()).collect()).collect()
+  };
+  let transposed_vector = |a: &[Vec<f64>], r: &[f64]| -> Vec<f64> { a.iter().map(|column| -column.iter().zip(r).map(|(x, y)| x * y).sum::<f64>()).collect() };
+  let n = shared.len();
+  let mut current = evaluate(shared, poses);
+  let mut lambda = 1e-3;
+  for _ in 0..iterations {
+    // Jacobian columns, per view: shared parameters, then pose parameters.
+    let mut shared_columns: Vec<Vec<Vec<f64>>> = vec![Vec::with_capacity(n); poses.len()];
+    let mut perturbed = shared.to_vec();
+    for i in 0..n {
+      let h = step(shared[i]);
+      perturbed[i] += h;
+      for (view, pose) in poses.iter().enumerate() {
+        shared_columns[view].push(residuals(&perturbed, pose, view).iter().zip(&current[view]).map(|(a, b)| (a - b) / h).collect());
+      }
+      perturbed[i] = shared[i];
+    }
+    let mut a = vec![vec![0.0; n]; n];
+    let mut g = vec![0.0; n];
+    let mut blocks = Vec::with_capacity(poses.len());
+    for (view, pose) in poses.iter().enumerate() {
+      let mut pose_columns = Vec::with_capacity(6);
+      for i in 0..6 {
+        let (h, mut perturbed) = (step(pose[i]), *pose);
+        perturbed[i] += h;
+        pose_columns.push(residuals(shared, &perturbed, view).iter().zip(&current[view]).map(|(a, b)| (a - b) / h).collect::<Vec<f64>>());
+      }
+      let columns = &shared_columns[view];
+      for (row, value) in a.iter_mut().zip(transposed_product(columns, columns)) {
+        for (a, value) in row.iter_mut().zip(value) { *a += value; }
+      }
+      for (g, value) in g.iter_mut().zip(transposed_vector(columns, &current[view])) { *g += value; }
+      blocks.push((transposed_product(columns, &pose_columns), transposed_product(&pose_columns, &pose_columns), transposed_vector(&pose_columns, &current[view])));
+    }
+
+    let mut improved = false;
+    while lambda < 1e12 {
+      let damp = |mut m: Vec<Vec<f64>>| -> Vec<Vec<f64>> {
+        for (i, row) in m.iter_mut().enumerate() { row[i] += lambda * row[i].max(1e-12); }
+        m
+      };
+      let mut reduced = damp(a.clone());
+      let mut rhs = g.clone();
+      // Per view: C^-1 and B C^-1.
+      let mut eliminated = Vec::with_capacity(blocks.len());
+      for (b, c, gk) in &blocks {
+        let c = damp(c.clone());
+        let Some(inverse) = (0..6).map(|i| solve(c.clone(), (0..6).map(|j| if i == j { 1.0 } else { 0.0 }).collect())).collect::<Option<Vec<Vec<f64>>>>() else { break; };
+        // `inverse` holds the columns of C^-1, which is symmetric, so they are its rows as well.
+        let bc: Vec<Vec<f64>> = b.iter().map(|row| (0..6).map(|j| (0..6).map(|k| row[k] * inverse[k][j]).sum()).collect()).collect();
+        for (i, bc_row) in bc.iter().enumerate() {
+          for (j, b_row) in b.iter().enumerate() {
+            reduced[i][j] -= bc_row.iter().zip(b_row).map(|(x, y)| x * y).sum::<f64>();
+          }
+          rhs[i] -= bc_row.iter().zip(gk).map(|(x, y)| x * y).sum::<f64>();
+        }
+        eliminated.push(inverse);
+      }
+      let solved = if eliminated.len() == blocks.len() { solve(reduced, rhs) } else { None };
+      let Some(delta) = solved else { lambda *= 10.0; continue; };
+      let candidate_shared: Vec<f64> = shared.iter().zip(&delta).map(|(p, d)| p + d).collect();
+      let candidate_poses: Vec<Pose> = poses.iter().zip(&blocks).zip(&eliminated).map(|((pose, (b, _, gk)), inverse)| {
+        // C^-1 (g_k - B^T delta)
+        let rhs: Vec<f64> = (0..6).map(|k| gk[k] - b.iter().zip(&delta).map(|(row, d)| row[k] * d).sum::<f64>()).collect();
+        let mut next = *pose;
+        for (k, value) in next.iter_mut().enumerate() { *value += inverse[k].iter().zip(&rhs).map(|(x, y)| x * y).sum::<f64>(); }
+        next
+      }).collect();
+      let next = evaluate(&candidate_shared, &candidate_poses);
+      if cost(&next) < cost(&current) {
+        let converged = cost(&current) - cost(&next) < 1e-12 * cost(&current).max(1e-30);
+        shared.copy_from_slice(&candidate_shared);
+        poses.copy_from_slice(&candidate_poses);
+        current = next;
+        lambda = (lambda / 10.0).max(1e-12);
+        improved = !converged;
+        break;
+      }
+      lambda *= 10.0;
+    }
+    if !improved { break; }
+  }
+}
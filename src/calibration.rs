@@ -0,0 +1,559 @@
+// calibration.rs - tinyrigel
+//
+// Camera calibration data for the Rigel's two cameras: per-eye intrinsics and lens distortion, plus the rigid transform between the eyes. Calibrations load from and save to tinyrigel's JSON, OpenCV FileStorage YAML and Kalibr camchains, and are tied to a device serial so a `Rigel` can find its own.
+
+use std::{convert::TryInto, env, fs, io, path::{Path, PathBuf}};
+
+use crate::*;
+
+/// Lens distortion model and coefficients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distortion {
+  None,
+  /// Radial-tangential (Brown-Conrady) model, coefficients in OpenCV order: k1, k2, p1, p2, k3.
+  BrownConrady([f64; 5]),
+  /// Equidistant (Kannala-Brandt / OpenCV fisheye) model, coefficients k1..k4. Better suited to the Rigel's very wide lenses.
+  Equidistant([f64; 4])
+}
+
+impl Distortion {
+  pub fn model(&self) -> DistortionModel {
+    match self {
+      Distortion::None => DistortionModel::None,
+      Distortion::BrownConrady(_) => DistortionModel::BrownConrady,
+      Distortion::Equidistant(_) => DistortionModel::Equidistant
+    }
+  }
+
+  // The distortion of `model` with the given coefficients, missing ones zero.
+  pub(crate) fn from_coefficients(model: DistortionModel, k: &[f64]) -> Distortion {
+    let k = |i: usize| k.get(i).copied().unwrap_or(0.0);
+    match model {
+      DistortionModel::None => Distortion::None,
+      DistortionModel::BrownConrady => Distortion::BrownConrady([k(0), k(1), k(2), k(3), k(4)]),
+      DistortionModel::Equidistant => Distortion::Equidistant([k(0), k(1), k(2), k(3)])
+    }
+  }
+
+  pub(crate) fn coefficients(&self) -> &[f64] {
+    match self {
+      Distortion::None => &[],
+      Distortion::BrownConrady(k) => k,
+      Distortion::Equidistant(k) => k
+    }
+  }
+}
+
+/// A lens model without its coefficients, e.g. to calibrate or convert to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistortionModel {
+  /// An ideal pinhole.
+  None,
+  /// Radial-tangential, k1, k2, p1, p2, k3.
+  BrownConrady,
+  /// Fisheye, k1..k4. The better fit for the Rigel's wide lenses.
+  Equidistant
+}
+
+impl DistortionModel {
+  pub(crate) fn coefficients(&self) -> usize {
+    match self {
+      DistortionModel::None => 0,
+      DistortionModel::BrownConrady => 5,
+      DistortionModel::Equidistant => 4
+    }
+  }
+}
+
+/// Pinhole intrinsics of a single camera, in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraIntrinsics {
+  pub width: u32,
+  pub height: u32,
+  pub fx: f64,
+  pub fy: f64,
+  pub cx: f64,
+  pub cy: f64,
+  pub distortion: Distortion
+}
+
+impl CameraIntrinsics {
+  /// The 3x3 camera matrix K, row-major.
+  pub fn camera_matrix(&self) -> [[f64; 3]; 3] {
+    [[self.fx, 0.0, self.cx], [0.0, self.fy, self.cy], [0.0, 0.0, 1.0]]
+  }
+
+  /// Applies the lens distortion to a point in normalized image coordinates (x / z, y / z).
+  pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+    match &self.distortion {
+      Distortion::None => (x, y),
+      Distortion::BrownConrady([k1, k2, p1, p2, k3]) => {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        (
+          x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+          y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y
+        )
+      }
+      Distortion::Equidistant(k) => {
+        let r = (x * x + y * y).sqrt();
+        if r < 1e-12 { return (x, y); }
+        let theta = r.atan();
+        let scale = equidistant_theta_d(k, theta) / r;
+        (x * scale, y * scale)
+      }
+    }
+  }
+
+  /// Inverts `distort`, iteratively.
+  pub fn undistort(&self, xd: f64, yd: f64) -> (f64, f64) {
+    match &self.distortion {
+      Distortion::None => (xd, yd),
+      Distortion::BrownConrady(_) => {
+        // Newton's method with a numerical Jacobian; it converges far from the center, where the fixed-point iteration OpenCV uses stalls.
+        let (mut x, mut y) = (xd, yd);
+        const H: f64 = 1e-7;
+        for _ in 0..20 {
+          let (dx, dy) = self.distort(x, y);
+          let (ex, ey) = (dx - xd, dy - yd);
+          if ex.abs() < 1e-14 && ey.abs() < 1e-14 { break; }
+          let (dx_x, dy_x) = self.distort(x + H, y);
+          let (dx_y, dy_y) = self.distort(x, y + H);
+          let (j00, j01, j10, j11) = ((dx_x - dx) / H, (dx_y - dx) / H, (dy_x - dy) / H, (dy_y - dy) / H);
+          let det = j00 * j11 - j01 * j10;
+          if det.abs() < 1e-12 { break; }
+          x -= (j11 * ex - j01 * ey) / det;
+          y -= (j00 * ey - j10 * ex) / det;
+        }
+        (x, y)
+      }
+      Distortion::Equidistant(k) => {
+        let theta_d = (xd * xd + yd * yd).sqrt();
+        if theta_d < 1e-12 { return (xd, yd); }
+        // Newton's method on theta_d(theta) = theta_d.
+        let mut theta = theta_d;
+        for _ in 0..20 {
+          let t2 = theta * theta;
+          let f = equidistant_theta_d(k, theta) - theta_d;
+          let df = 1.0 + t2 * (3.0 * k[0] + t2 * (5.0 * k[1] + t2 * (7.0 * k[2] + t2 * 9.0 * k[3])));
+          let step = f / df;
+          theta -= step;
+          if step.abs() < 1e-12 { break; }
+        }
+        let scale = theta.tan() / theta_d;
+        (xd * scale, yd * scale)
+      }
+    }
+  }
+
+  /// Projects a point in normalized image coordinates to distorted pixel coordinates.
+  pub fn project(&self, x: f64, y: f64) -> (f64, f64) {
+    let (xd, yd) = self.distort(x, y);
+    (self.fx * xd + self.cx, self.fy * yd + self.cy)
+  }
+
+  /// Maps distorted pixel coordinates back to normalized image coordinates, removing the lens distortion.
+  pub fn unproject(&self, u: f64, v: f64) -> (f64, f64) {
+    self.undistort((u - self.cx) / self.fx, (v - self.cy) / self.fy)
+  }
+
+  /// Distortion model name and coefficients as ROS CameraInfo (and Foxglove CameraCalibration) expect them.
+  pub fn ros_distortion(&self) -> (&'static str, Vec<f64>) {
+    match &self.distortion {
+      Distortion::None => ("plumb_bob", vec![0.0; 5]),
+      Distortion::BrownConrady(k) => ("plumb_bob", k.to_vec()),
+      Distortion::Equidistant(k) => ("equidistant", k.to_vec())
+    }
+  }
+}
+
+// Distorted angle of the equidistant model: theta (1 + k1 theta^2 + k2 theta^4 + k3 theta^6 + k4 theta^8).
+fn equidistant_theta_d(k: &[f64; 4], theta: f64) -> f64 {
+  let t2 = theta * theta;
+  theta * (1.0 + t2 * (k[0] + t2 * (k[1] + t2 * (k[2] + t2 * k[3]))))
+}
+
+/// Calibration of a stereo pair. `rotation` and `translation` take points from the left camera frame into the right camera frame (x_right = R * x_left + T), with the translation in meters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StereoCalibration {
+  pub left: CameraIntrinsics,
+  pub right: CameraIntrinsics,
+  pub rotation: [[f64; 3]; 3],
+  pub translation: [f64; 3],
+  /// Serial number of the device this calibration belongs to, if known. `Rigel` uses it to pick its calibration.
+  pub serial: Option<String>
+}
+
+impl StereoCalibration {
+  /// Distance between the two camera centers, in meters.
+  pub fn baseline(&self) -> f64 {
+    let t = self.translation;
+    (t[0] * t[0] + t[1] * t[1] + t[2] * t[2]).sqrt()
+  }
+
+  pub fn camera(&self, eye: Eye) -> &CameraIntrinsics {
+    match eye {
+      Eye::Left => &self.left,
+      Eye::Right => &self.right
+    }
+  }
+
+  /// ROS-style 3x4 projection matrix of an eye, row-major, for the unrectified pair: K [I | 0] for the left eye, and for the right eye the same with the baseline term Tx = -fx * baseline.
+  pub fn ros_projection(&self, eye: Eye) -> [f64; 12] {
+    let camera = self.camera(eye);
+    let tx = match eye { Eye::Left => 0.0, Eye::Right => -camera.fx * self.baseline() };
+    [camera.fx, 0.0, camera.cx, tx, 0.0, camera.fy, camera.cy, 0.0, 0.0, 0.0, 1.0, 0.0]
+  }
+}
+
+// Native JSON
+// ---
+
+impl StereoCalibration {
+  /// The calibration as tinyrigel's native JSON: the device serial if known, both cameras with their distortion model and coefficients, and the rotation (row-major) and translation from the left to the right camera frame.
+  pub fn to_json(&self) -> String { format!("{{\n{}\n}}\n", self.json_fields().join(",\n")) }
+
+  /// Reads a calibration written by `to_json`. Other fields, such as the errors a calibration run adds, are ignored.
+  pub fn from_json(text: &str) -> Result<Self> {
+    let json = Json::parse(text)?;
+    let numbers = |value: Option<&Json>, name: &str| -> Result<Vec<f64>> {
+      let values = value.and_then(|value| value.as_array()).ok_or_else(|| Error::new(format!("Calibration JSON has no \"{}\" array.", name)))?;
+      values.iter().map(|value| value.as_f64().ok_or_else(|| Error::new(format!("Calibration JSON \"{}\" holds a non-number.", name)))).collect()
+    };
+    let rows = json.get("rotation").and_then(|value| value.as_array()).filter(|rows| rows.len() == 3)
+      .ok_or_else(|| Error::new("Calibration JSON has no 3x3 \"rotation\".".to_string()))?;
+    let mut rotation = [[0.0; 3]; 3];
+    for (row, value) in rotation.iter_mut().zip(rows) {
+      *row = numbers(Some(value), "rotation")?.try_into().map_err(|_| Error::new("Calibration JSON has no 3x3 \"rotation\".".to_string()))?;
+    }
+    let translation = numbers(json.get("translation"), "translation")?.try_into().map_err(|_| Error::new("Calibration JSON \"translation\" needs 3 values.".to_string()))?;
+    let serial = json.get("serial").and_then(|value| value.as_str()).map(|serial| serial.to_string());
+    Ok(StereoCalibration { left: camera_from_json(&json, "left")?, right: camera_from_json(&json, "right")?, rotation, translation, serial })
+  }
+
+  // Top-level `"key": value` entries, for writers that add their own.
+  pub(crate) fn json_fields(&self) -> Vec<String> {
+    let rows: Vec<String> = self.rotation.iter().map(|row| format!("[{}, {}, {}]", row[0], row[1], row[2])).collect();
+    let mut fields = Vec::with_capacity(5);
+    if let Some(serial) = &self.serial { fields.push(format!("  \"serial\": {}", json_string(serial))); }
+    fields.extend(vec![
+      format!("  \"left\": {}", camera_json(&self.left)),
+      format!("  \"right\": {}", camera_json(&self.right)),
+      format!("  \"rotation\": [{}]", rows.join(", ")),
+      format!("  \"translation\": [{}, {}, {}]", self.translation[0], self.translation[1], self.translation[2])
+    ]);
+    fields
+  }
+}
+
+fn camera_json(camera: &CameraIntrinsics) -> String {
+  let (model, coefficients): (&str, &[f64]) = match &camera.distortion {
+    Distortion::None => ("none", &[]),
+    Distortion::BrownConrady(k) => ("brown_conrady", k),
+    Distortion::Equidistant(k) => ("equidistant", k)
+  };
+  let coefficients: Vec<String> = coefficients.iter().map(|k| k.to_string()).collect();
+  format!(
+    "{{\"width\": {}, \"height\": {}, \"fx\": {}, \"fy\": {}, \"cx\": {}, \"cy\": {}, \"model\": \"{}\", \"distortion\": [{}]}}",
+    camera.width, camera.height, camera.fx, camera.fy, camera.cx, camera.cy, model, coefficients.join(", ")
+  )
+}
+
+fn camera_from_json(json: &Json, eye: &str) -> Result<CameraIntrinsics> {
+  let camera = json.get(eye).ok_or_else(|| Error::new(format!("Calibration JSON has no \"{}\" camera.", eye)))?;
+  let number = |name: &str| camera.get(name).and_then(|value| value.as_f64()).ok_or_else(|| Error::new(format!("Calibration JSON {} camera has no \"{}\".", eye, name)));
+  let coefficients: Vec<f64> = camera.get("distortion").and_then(|value| value.as_array()).unwrap_or_default().iter().filter_map(|value| value.as_f64()).collect();
+  let model = camera.get("model").and_then(|value| value.as_str()).unwrap_or("none");
+  let distortion = match (model, coefficients.len()) {
+    ("none", _) => Distortion::None,
+    ("brown_conrady", 5) => Distortion::BrownConrady([coefficients[0], coefficients[1], coefficients[2], coefficients[3], coefficients[4]]),
+    ("equidistant", 4) => Distortion::Equidistant([coefficients[0], coefficients[1], coefficients[2], coefficients[3]]),
+    _ => return Err(Error::new(format!("Calibration JSON {} camera has an unknown distortion model \"{}\" with {} coefficients.", eye, model, coefficients.len())))
+  };
+  Ok(CameraIntrinsics {
+    width: number("width")? as u32,
+    height: number("height")? as u32,
+    fx: number("fx")?,
+    fy: number("fy")?,
+    cx: number("cx")?,
+    cy: number("cy")?,
+    distortion
+  })
+}
+
+// Distortion model conversion
+// ---
+
+// Pixels sampled along each side of the image when refitting a lens model.
+const CONVERSION_GRID: usize = 17;
+// The worst pixel error a refit lens model may make.
+const MAX_CONVERSION_ERROR: f64 = 0.1;
+
+impl CameraIntrinsics {
+  /// The same camera with its lens distortion expressed in `model`, refit so every pixel still sees the same ray to within 0.1 px. Fails where `model` can't follow the lens, e.g. a wide fisheye lens as Brown-Conrady, or any distortion as `None`.
+  pub fn convert_distortion(&self, model: DistortionModel) -> Result<CameraIntrinsics> {
+    if model == self.distortion.model() { return Ok(self.clone()); }
+    self.fit_distortion(model, model.coefficients())
+  }
+
+  // Refits the first `free` coefficients of `model` and leaves the rest zero, as Kalibr's radtan is Brown-Conrady without k3.
+  pub(crate) fn fit_distortion(&self, model: DistortionModel, free: usize) -> Result<CameraIntrinsics> {
+    let mut samples = Vec::with_capacity(CONVERSION_GRID * CONVERSION_GRID);
+    for j in 0..CONVERSION_GRID {
+      for i in 0..CONVERSION_GRID {
+        let u = i as f64 * (self.width as f64 - 1.0) / (CONVERSION_GRID - 1) as f64;
+        let v = j as f64 * (self.height as f64 - 1.0) / (CONVERSION_GRID - 1) as f64;
+        let (x, y) = self.unproject(u, v);
+        // Rays 90 degrees or more off the axis have no pinhole coordinates, so no lens model can take them.
+        let (pu, pv) = self.project(x, y);
+        let error = (pu - u).hypot(pv - v);
+        if error.is_nan() || error > 1e-3 {
+          return Err(Error::new(format!("Pixel ({:.0}, {:.0}) looks 90 degrees or more off the axis, which only the {:?} model can describe.", u, v, self.distortion.model())));
+        }
+        samples.push(((x, y), (u, v)));
+      }
+    }
+    let candidate = |k: &[f64]| CameraIntrinsics { distortion: Distortion::from_coefficients(model, k), ..self.clone() };
+    let residuals = |k: &[f64]| -> Vec<f64> {
+      let camera = candidate(k);
+      samples.iter().flat_map(|&((x, y), (u, v))| {
+        let (pu, pv) = camera.project(x, y);
+        vec![pu - u, pv - v]
+      }).collect()
+    };
+    let mut params = if self.distortion.model() == model { self.distortion.coefficients()[..free].to_vec() } else { vec![0.0; free] };
+    if free > 0 { levenberg_marquardt(&mut params, residuals, 200); }
+    let worst = residuals(&params).chunks(2).map(|e| e[0].hypot(e[1])).fold(0.0, f64::max);
+    if worst.is_nan() || worst > MAX_CONVERSION_ERROR {
+      return Err(Error::new(format!("The {:?} model can't stand in for this camera's {:?} lens: it is off by up to {:.2} px.", model, self.distortion.model(), worst)));
+    }
+    Ok(candidate(&params))
+  }
+}
+
+// OpenCV FileStorage YAML
+// ---
+
+impl StereoCalibration {
+  /// The calibration as OpenCV FileStorage YAML: `camera_matrix_left`, `dist_coeffs_left`, the same for the right eye, and `R` and `T` as `cv::stereoCalibrate` returns them, with the image size, the serial, and each eye's `distortion_model_left` / `_right` as "plumb_bob", "fisheye" or "none". Both eyes are taken to have the left eye's image size.
+  pub fn to_opencv_yaml(&self) -> String {
+    let mut out = String::from("%YAML:1.0\n---\n");
+    if let Some(serial) = &self.serial { out += &format!("serial: {}\n", json_string(serial)); }
+    out += &format!("image_width: {}\nimage_height: {}\n", self.left.width, self.left.height);
+    for (camera, side) in [(&self.left, "left"), (&self.right, "right")].iter() {
+      let (model, coefficients) = match &camera.distortion {
+        Distortion::None => ("none", vec![0.0; 5]),
+        Distortion::BrownConrady(k) => ("plumb_bob", k.to_vec()),
+        Distortion::Equidistant(k) => ("fisheye", k.to_vec())
+      };
+      out += &opencv_matrix(&format!("camera_matrix_{}", side), 3, 3, &camera.camera_matrix().concat());
+      out += &opencv_matrix(&format!("dist_coeffs_{}", side), 1, coefficients.len(), &coefficients);
+      out += &format!("distortion_model_{}: \"{}\"\n", side, model);
+    }
+    out += &opencv_matrix("R", 3, 3, &self.rotation.concat());
+    out += &opencv_matrix("T", 3, 1, &self.translation);
+    out
+  }
+
+  /// Reads OpenCV FileStorage YAML: what `to_opencv_yaml` writes, or the `M1` / `D1` / `M2` / `D2` (or `K1` ...) names of OpenCV's stereo samples. A `distortion_model` of "fisheye" or "equidistant", per eye or shared, marks fisheye coefficients; otherwise 4, 5, 8, 12 or 14 coefficients are OpenCV's standard model, which tinyrigel follows up to k3. `T` is taken to be in meters, as it is when the board was measured in meters.
+  pub fn from_opencv_yaml(text: &str) -> Result<Self> { Self::from_opencv(&parse_yaml(text)?) }
+
+  fn from_opencv(yaml: &Json) -> Result<Self> {
+    let number = |name: &str| yaml.get(name).and_then(|value| value.as_f64());
+    let (width, height) = match (number("image_width"), number("image_height")) {
+      (Some(width), Some(height)) => (width, height),
+      _ => {
+        let size = opencv_values(yaml, &["image_size", "imageSize"])?;
+        if size.len() != 2 { return Err(Error::new("OpenCV calibration image_size needs 2 values.".to_string())); }
+        (size[0], size[1])
+      }
+    };
+    let camera = |side: &str, index: usize| -> Result<CameraIntrinsics> {
+      let k = opencv_values(yaml, &[&format!("camera_matrix_{}", side), &format!("M{}", index), &format!("K{}", index)])?;
+      if k.len() != 9 { return Err(Error::new(format!("OpenCV calibration {} camera matrix needs 9 values, not {}.", side, k.len()))); }
+      let d = opencv_values(yaml, &[&format!("dist_coeffs_{}", side), &format!("D{}", index)])?;
+      let model = yaml.get(&format!("distortion_model_{}", side)).or_else(|| yaml.get("distortion_model")).and_then(|value| value.as_str()).unwrap_or("plumb_bob");
+      let distortion = match (model, d.len()) {
+        ("none", _) => Distortion::None,
+        ("fisheye", 4) | ("equidistant", 4) => Distortion::Equidistant([d[0], d[1], d[2], d[3]]),
+        ("fisheye", _) | ("equidistant", _) => return Err(Error::new(format!("OpenCV calibration {} fisheye distortion needs 4 coefficients, not {}.", side, d.len()))),
+        (_, 4) | (_, 5) => Distortion::from_coefficients(DistortionModel::BrownConrady, &d),
+        (_, 8) | (_, 12) | (_, 14) if d[5..].iter().all(|&k| k == 0.0) => Distortion::from_coefficients(DistortionModel::BrownConrady, &d),
+        (_, 8) | (_, 12) | (_, 14) => return Err(Error::new(format!("OpenCV calibration {} distortion uses the rational, thin prism or tilt terms, which tinyrigel's lens models don't have.", side))),
+        (_, count) => return Err(Error::new(format!("OpenCV calibration {} distortion has {} coefficients; expected 4, 5, 8, 12 or 14.", side, count)))
+      };
+      Ok(CameraIntrinsics { width: width as u32, height: height as u32, fx: k[0], fy: k[4], cx: k[2], cy: k[5], distortion })
+    };
+    let (left, right) = (camera("left", 1)?, camera("right", 2)?);
+    let r = opencv_values(yaml, &["R"])?;
+    let t = opencv_values(yaml, &["T"])?;
+    if r.len() != 9 || t.len() != 3 { return Err(Error::new("OpenCV calibration R must be 3x3 and T 3x1.".to_string())); }
+    let serial = yaml.get("serial").and_then(|value| value.as_str()).map(|serial| serial.to_string());
+    Ok(StereoCalibration { left, right, rotation: [[r[0], r[1], r[2]], [r[3], r[4], r[5]], [r[6], r[7], r[8]]], translation: [t[0], t[1], t[2]], serial })
+  }
+}
+
+fn opencv_matrix(name: &str, rows: usize, cols: usize, data: &[f64]) -> String {
+  let data: Vec<String> = data.iter().map(|value| format!("{:?}", value)).collect();
+  format!("{}: !!opencv-matrix\n   rows: {}\n   cols: {}\n   dt: d\n   data: [ {} ]\n", name, rows, cols, data.join(", "))
+}
+
+// The values, row by row, of the `!!opencv-matrix` (or plain sequence) under the first of `names` that `yaml` has.
+fn opencv_values(yaml: &Json, names: &[&str]) -> Result<Vec<f64>> {
+  let value = names.iter().find_map(|name| yaml.get(name)).ok_or_else(|| Error::new(format!("OpenCV calibration has no {}.", names.join(" or "))))?;
+  let data = value.get("data").unwrap_or(value);
+  let values = data.as_array().and_then(|values| values.iter().map(|value| value.as_f64()).collect::<Option<Vec<f64>>>())
+    .ok_or_else(|| Error::new(format!("OpenCV calibration {} is not a matrix of numbers.", names[0])))?;
+  let size = |key: &str| value.get(key).and_then(|value| value.as_f64());
+  if let (Some(rows), Some(cols)) = (size("rows"), size("cols")) {
+    if (rows * cols) as usize != values.len() { return Err(Error::new(format!("OpenCV calibration {} has {} values for {}x{}.", names[0], values.len(), rows, cols))); }
+  }
+  Ok(values)
+}
+
+// Kalibr camchain YAML
+// ---
+
+impl StereoCalibration {
+  /// The calibration as a Kalibr camchain: `cam0` is the left eye and `cam1` the right, pinhole cameras with `radtan`, `equidistant` or no distortion, and `T_cn_cnm1` takes left camera points into the right camera frame. Kalibr's radtan has no k3, so a Brown-Conrady lens with one is refit without it, which fails if that is off by more than 0.1 px. The serial, which Kalibr has no field for, goes in a comment.
+  pub fn to_kalibr_yaml(&self) -> Result<String> {
+    let list = |values: &[f64]| values.iter().map(|value| format!("{:?}", value)).collect::<Vec<_>>().join(", ");
+    let mut out = String::new();
+    if let Some(serial) = &self.serial { out += &format!("# serial: {}\n", serial); }
+    for (index, camera) in [&self.left, &self.right].iter().enumerate() {
+      let (model, coefficients) = match &camera.distortion {
+        Distortion::None => ("none", Vec::new()),
+        Distortion::BrownConrady(k) if k[4] == 0.0 => ("radtan", k[..4].to_vec()),
+        Distortion::BrownConrady(_) => ("radtan", camera.fit_distortion(DistortionModel::BrownConrady, 4)?.distortion.coefficients()[..4].to_vec()),
+        Distortion::Equidistant(k) => ("equidistant", k.to_vec())
+      };
+      out += &format!("cam{}:\n", index);
+      if index == 1 {
+        out += "  T_cn_cnm1:\n";
+        for (row, t) in self.rotation.iter().zip(self.translation.iter()) { out += &format!("  - [{}, {:?}]\n", list(row), t); }
+        out += "  - [0.0, 0.0, 0.0, 1.0]\n";
+      }
+      out += &format!("  cam_overlaps: [{}]\n", 1 - index);
+      out += "  camera_model: pinhole\n";
+      out += &format!("  distortion_coeffs: [{}]\n", list(&coefficients));
+      out += &format!("  distortion_model: {}\n", model);
+      out += &format!("  intrinsics: [{}]\n", list(&[camera.fx, camera.fy, camera.cx, camera.cy]));
+      out += &format!("  resolution: [{}, {}]\n", camera.width, camera.height);
+      out += &format!("  rostopic: /cam{}/image_raw\n", index);
+    }
+    Ok(out)
+  }
+
+  /// Reads a Kalibr camchain of two pinhole cameras, `cam0` being the left eye. Kalibr's `radtan` and `equidistant` models become Brown-Conrady (without k3) and equidistant; its others have no counterpart here.
+  pub fn from_kalibr_yaml(text: &str) -> Result<Self> { Self::from_kalibr(&parse_yaml(text)?, text) }
+
+  fn from_kalibr(yaml: &Json, text: &str) -> Result<Self> {
+    let numbers = |value: Option<&Json>| value.and_then(|value| value.as_array()).and_then(|values| values.iter().map(|value| value.as_f64()).collect::<Option<Vec<f64>>>());
+    let camera = |name: &str| -> Result<CameraIntrinsics> {
+      let camera = yaml.get(name).ok_or_else(|| Error::new(format!("Kalibr camchain has no {}.", name)))?;
+      let model = camera.get("camera_model").and_then(|value| value.as_str()).unwrap_or("pinhole");
+      if model != "pinhole" { return Err(Error::new(format!("Kalibr camchain {} is a {} camera; only pinhole cameras are supported.", name, model))); }
+      let intrinsics = numbers(camera.get("intrinsics")).filter(|values| values.len() == 4)
+        .ok_or_else(|| Error::new(format!("Kalibr camchain {} needs 4 intrinsics.", name)))?;
+      let resolution = numbers(camera.get("resolution")).filter(|values| values.len() == 2)
+        .ok_or_else(|| Error::new(format!("Kalibr camchain {} needs a resolution.", name)))?;
+      let coefficients = numbers(camera.get("distortion_coeffs")).unwrap_or_default();
+      let model = camera.get("distortion_model").and_then(|value| value.as_str()).unwrap_or("none");
+      let distortion = match (model, coefficients.len()) {
+        ("none", _) => Distortion::None,
+        ("radtan", 4) => Distortion::from_coefficients(DistortionModel::BrownConrady, &coefficients),
+        ("equidistant", 4) => Distortion::from_coefficients(DistortionModel::Equidistant, &coefficients),
+        _ => return Err(Error::new(format!("Kalibr camchain {} has {} distortion with {} coefficients; only radtan and equidistant with 4 are supported.", name, model, coefficients.len())))
+      };
+      Ok(CameraIntrinsics { width: resolution[0] as u32, height: resolution[1] as u32, fx: intrinsics[0], fy: intrinsics[1], cx: intrinsics[2], cy: intrinsics[3], distortion })
+    };
+    let (left, right) = (camera("cam0")?, camera("cam1")?);
+    let transform: Vec<Vec<f64>> = yaml.get("cam1").and_then(|camera| camera.get("T_cn_cnm1")).and_then(|rows| rows.as_array())
+      .and_then(|rows| rows.iter().map(|row| numbers(Some(row)).filter(|row| row.len() == 4)).collect::<Option<Vec<_>>>())
+      .filter(|rows| rows.len() >= 3)
+      .ok_or_else(|| Error::new("Kalibr camchain cam1 needs a 4x4 T_cn_cnm1.".to_string()))?;
+    let rotation = [[transform[0][0], transform[0][1], transform[0][2]], [transform[1][0], transform[1][1], transform[1][2]], [transform[2][0], transform[2][1], transform[2][2]]];
+    let serial = text.lines().find_map(|line| line.trim().strip_prefix("# serial:")).map(|serial| serial.trim().to_string()).filter(|serial| !serial.is_empty());
+    Ok(StereoCalibration { left, right, rotation, translation: [transform[0][3], transform[1][3], transform[2][3]], serial })
+  }
+}
+
+// Files
+// ---
+
+/// The formats `StereoCalibration::save` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationFormat {
+  /// tinyrigel's native JSON, see `to_json`.
+  Json,
+  /// OpenCV FileStorage YAML, see `to_opencv_yaml`.
+  OpenCv,
+  /// A Kalibr camchain, see `to_kalibr_yaml`.
+  Kalibr
+}
+
+impl StereoCalibration {
+  /// Reads a calibration file in any of the `CalibrationFormat`s, told apart by their contents.
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|err| Error::new(format!("Failed to read {}: {}", path.display(), err)))?;
+    Self::parse(&text).map_err(|err| Error::new(format!("{}: {}", path.display(), err.to_string())))
+  }
+
+  // JSON opens with a brace; of the YAML formats, only Kalibr's has a cam0.
+  fn parse(text: &str) -> Result<Self> {
+    if text.trim_start().starts_with('{') { return Self::from_json(text); }
+    let yaml = parse_yaml(text)?;
+    if yaml.get("cam0").is_some() { Self::from_kalibr(&yaml, text) } else { Self::from_opencv(&yaml) }
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P, format: CalibrationFormat) -> Result<()> {
+    let path = path.as_ref();
+    let text = match format {
+      CalibrationFormat::Json => self.to_json(),
+      CalibrationFormat::OpenCv => self.to_opencv_yaml(),
+      CalibrationFormat::Kalibr => self.to_kalibr_yaml()?
+    };
+    fs::write(path, text).map_err(|err| Error::new(format!("Failed to write {}: {}", path.display(), err)))
+  }
+
+  /// Finds the calibration of the device with serial `serial` among the `.json`, `.yaml` and `.yml` files in `dir`: the first by file name that records that serial, or else one named after it (e.g. `<serial>.yaml`, for files without a serial). Files that aren't calibrations are skipped, and a missing `dir` has none.
+  pub fn find<P: AsRef<Path>>(dir: P, serial: &str) -> Result<Option<Self>> {
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(Error::new(format!("Failed to list {}: {}", dir.display(), err)))
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|path| matches!(path.extension().and_then(|extension| extension.to_str()), Some("json") | Some("yaml") | Some("yml")))
+      .collect();
+    paths.sort();
+    let mut named = None;
+    for path in paths {
+      let Ok(mut calibration) = Self::load(&path) else { continue; };
+      match &calibration.serial {
+        Some(found) if found == serial => return Ok(Some(calibration)),
+        None if named.is_none() && path.file_stem().is_some_and(|stem| stem == serial) => {
+          calibration.serial = Some(serial.to_string());
+          named = Some(calibration);
+        }
+        _ => {}
+      }
+    }
+    Ok(named)
+  }
+}
+
+/// Where `Rigel` looks for its calibration: the directory in `TINYRIGEL_CALIBRATIONS`, or else `tinyrigel/calibrations` in the user's configuration directory. `None` if neither is known.
+pub fn calibration_dir() -> Option<PathBuf> {
+  if let Some(dir) = env::var_os("TINYRIGEL_CALIBRATIONS") { return Some(PathBuf::from(dir)); }
+  let config = if cfg!(target_os = "windows") {
+    env::var_os("APPDATA").map(PathBuf::from)
+  } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+  } else {
+    env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+  };
+  config.map(|config| config.join("tinyrigel").join("calibrations"))
+}
@@ -0,0 +1,136 @@
+// codec.rs - tinyrigel
+//
+// Lossless frame compression for recordings. Rigel frames change little from one frame to the next and are smooth within a frame, so each frame is turned into small residuals by a predictor (the previous frame, or the pixel's own neighbours on keyframes) and the residuals are compressed with zstd.
+
+use crate::*;
+
+/// How a recording stores frame pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+  /// Pixels as captured. Cheapest to write; about 26 MB/s at the Rigel's native mode.
+  Raw,
+  /// Lossless compression. Every `KEYFRAME_INTERVAL`th frame is predicted from its own neighbouring pixels, the frames in between from the frame before them.
+  Lossless
+}
+
+/// Frames between keyframes in a `Codec::Lossless` recording. Random access decodes at most this many frames.
+pub const KEYFRAME_INTERVAL: u32 = 30;
+
+// Fast enough to keep up with capture on one core while still compressing the residuals well.
+const ZSTD_LEVEL: i32 = 1;
+
+const KEYFRAME: u8 = 0;
+const DELTA_FRAME: u8 = 1;
+
+// Encoded frame layout: kind u8, raw length u32, zstd-compressed residuals.
+const ENCODED_PREFIX_LEN: usize = 5;
+
+fn zstd_error(err: std::io::Error) -> Error { Error::new(format!("zstd: {}", err)) }
+
+/// Encodes the frames of one recording, in order.
+pub(crate) struct FrameEncoder {
+  compressor: zstd::bulk::Compressor<'static>,
+  previous: Option<Vec<u8>>,
+  since_keyframe: u32,
+  residuals: Vec<u8>
+}
+
+impl FrameEncoder {
+  pub(crate) fn new() -> Result<Self> {
+    let compressor = zstd::bulk::Compressor::new(ZSTD_LEVEL).map_err(zstd_error)?;
+    Ok(Self { compressor, previous: None, since_keyframe: 0, residuals: Vec::new() })
+  }
+
+  /// Makes the next frame a keyframe, e.g. at the start of a new file.
+  pub(crate) fn reset(&mut self) { self.previous = None; }
+
+  pub(crate) fn encode(&mut self, data: &[u8], stride: usize) -> Result<Vec<u8>> {
+    self.residuals.resize(data.len(), 0);
+    let kind = match &self.previous {
+      Some(previous) if previous.len() == data.len() && self.since_keyframe < KEYFRAME_INTERVAL => {
+        for ((residual, &px), &prev) in self.residuals.iter_mut().zip(data).zip(previous) {
+          *residual = px.wrapping_sub(prev);
+        }
+        self.since_keyframe += 1;
+        DELTA_FRAME
+      }
+      _ => {
+        predict_spatial(data, stride, &mut self.residuals);
+        self.since_keyframe = 1;
+        KEYFRAME
+      }
+    };
+
+    let compressed = self.compressor.compress(&self.residuals).map_err(zstd_error)?;
+    let mut encoded = Vec::with_capacity(ENCODED_PREFIX_LEN + compressed.len());
+    encoded.push(kind);
+    encoded.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(&compressed);
+
+    match &mut self.previous {
+      Some(previous) => { previous.clear(); previous.extend_from_slice(data); }
+      None => self.previous = Some(data.to_vec())
+    }
+    Ok(encoded)
+  }
+}
+
+/// Whether an encoded frame can be decoded on its own.
+pub(crate) fn is_keyframe(encoded: &[u8]) -> bool {
+  encoded.first() == Some(&KEYFRAME)
+}
+
+/// Decodes a frame produced by `FrameEncoder::encode`. Delta frames need the decoded frame before them as `previous`.
+pub(crate) fn decode(encoded: &[u8], stride: usize, previous: Option<&[u8]>) -> Result<Vec<u8>> {
+  if encoded.len() < ENCODED_PREFIX_LEN {
+    return Err(Error::new("Encoded frame is truncated.".to_string()));
+  }
+  let raw_len = u32::from_le_bytes([encoded[1], encoded[2], encoded[3], encoded[4]]) as usize;
+  let mut data = zstd::bulk::decompress(&encoded[ENCODED_PREFIX_LEN..], raw_len).map_err(zstd_error)?;
+  if data.len() != raw_len {
+    return Err(Error::new("Encoded frame has the wrong length.".to_string()));
+  }
+
+  match encoded[0] {
+    KEYFRAME => unpredict_spatial(&mut data, stride),
+    DELTA_FRAME => {
+      let previous = previous.filter(|previous| previous.len() == raw_len)
+        .ok_or_else(|| Error::new("Delta frame decoded without the frame before it.".to_string()))?;
+      for (px, &prev) in data.iter_mut().zip(previous) {
+        *px = px.wrapping_add(prev);
+      }
+    }
+    kind => return Err(Error::new(format!("Unknown encoded frame kind {}.", kind)))
+  }
+  Ok(data)
+}
+
+// Median edge detector (as in LOCO-I / JPEG-LS) from the left, upper and upper-left neighbours. Off the top and left edges it falls back to whichever neighbour exists.
+#[inline]
+fn med(data: &[u8], idx: usize, stride: usize) -> u8 {
+  let x = idx % stride;
+  match (idx >= stride, x > 0) {
+    (false, false) => 0,
+    (false, true) => data[idx - 1],
+    (true, false) => data[idx - stride],
+    (true, true) => {
+      let (a, b, c) = (data[idx - 1], data[idx - stride], data[idx - stride - 1]);
+      if c >= a.max(b) { a.min(b) } else if c <= a.min(b) { a.max(b) } else { (a as i16 + b as i16 - c as i16) as u8 }
+    }
+  }
+}
+
+fn predict_spatial(data: &[u8], stride: usize, residuals: &mut [u8]) {
+  let stride = stride.max(1);
+  for idx in 0..data.len() {
+    residuals[idx] = data[idx].wrapping_sub(med(data, idx, stride));
+  }
+}
+
+// Inverts predict_spatial in place. Raster order means every neighbour is already reconstructed when it's needed.
+fn unpredict_spatial(data: &mut [u8], stride: usize) {
+  let stride = stride.max(1);
+  for idx in 0..data.len() {
+    data[idx] = data[idx].wrapping_add(med(data, idx, stride));
+  }
+}
@@ -0,0 +1,60 @@
+// components.rs - tinyrigel
+//
+// Connected components of a binary image, labelled over horizontal runs of set pixels and merged with a union-find, shared by the blob detector and the quad finder behind AprilTag and checkerboard detection. Working on runs rather than pixels keeps the union-find small, and each component's runs are all either caller needs.
+
+/// Pixels `x0..x1` of row `y`, all set.
+pub(crate) struct Run {
+  pub(crate) y: usize,
+  pub(crate) x0: usize,
+  pub(crate) x1: usize
+}
+
+/// The connected components of the pixels of a `width` x `height` image for which `set(x, y)` holds, each as its runs in row order. With `diagonal`, pixels touching only at a corner are connected too (8-connectivity); otherwise only edge neighbours are (4-connectivity). Components come in the order of their first run.
+pub(crate) fn connected_components(width: usize, height: usize, diagonal: bool, set: impl Fn(usize, usize) -> bool) -> Vec<Vec<Run>> {
+  let reach = diagonal as usize;
+  let mut runs: Vec<Run> = Vec::new();
+  let mut parents: Vec<usize> = Vec::new();
+  let mut previous = 0..0;
+  for y in 0..height {
+    let start = runs.len();
+    let mut x = 0;
+    while x < width {
+      if !set(x, y) { x += 1; continue; }
+      let x0 = x;
+      while x < width && set(x, y) { x += 1; }
+      let index = runs.len();
+      runs.push(Run { y, x0, x1: x });
+      parents.push(index);
+      // Runs of the row above that touch this one.
+      for above in previous.clone() {
+        if runs[above].x1 + reach > x0 && runs[above].x0 < x + reach {
+          let (a, b) = (find_root(&mut parents, above), find_root(&mut parents, index));
+          // Roots are always the component's first run, which keeps the output order stable.
+          if a != b { parents[a.max(b)] = a.min(b); }
+        }
+      }
+    }
+    previous = start..runs.len();
+  }
+
+  let mut component_of = vec![usize::MAX; runs.len()];
+  let mut components: Vec<Vec<Run>> = Vec::new();
+  for (index, run) in runs.into_iter().enumerate() {
+    let root = find_root(&mut parents, index);
+    if component_of[root] == usize::MAX {
+      component_of[root] = components.len();
+      components.push(Vec::new());
+    }
+    components[component_of[root]].push(run);
+  }
+  components
+}
+
+// Path halving.
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+  while parents[index] != index {
+    parents[index] = parents[parents[index]];
+    index = parents[index];
+  }
+  index
+}
@@ -2,12 +2,30 @@
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Broad classification of an `Error`, so callers can tell recoverable conditions (a frame that didn't arrive in time, a device that went away) apart from everything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+  /// The device was unplugged or otherwise stopped responding. The source needs to be reopened.
+  Disconnected,
+  /// No frame arrived within the requested timeout.
+  Timeout,
+  /// A finite source, such as a replayed recording, has no more frames.
+  EndOfStream,
+  Other
+}
+
 #[derive(Debug)]
 pub struct Error {
+  kind: ErrorKind,
   details: String
 }
 
 impl Error {
-  pub fn new(details: String) -> Self { Self { details: details }}
+  pub fn new(details: String) -> Self { Self { kind: ErrorKind::Other, details }}
+  pub fn with_kind(kind: ErrorKind, details: String) -> Self { Self { kind, details }}
+  pub fn disconnected(details: String) -> Self { Self::with_kind(ErrorKind::Disconnected, details) }
+  pub fn timeout(details: String) -> Self { Self::with_kind(ErrorKind::Timeout, details) }
+  pub fn end_of_stream(details: String) -> Self { Self::with_kind(ErrorKind::EndOfStream, details) }
+  pub fn kind(&self) -> ErrorKind { self.kind }
   pub fn to_string(self: &Self) -> String { self.details.clone() }
 }
@@ -0,0 +1,208 @@
+// enhance.rs - tinyrigel
+//
+// Contrast enhancement for viewing IR frames or feeding them to models that expect normally exposed images. Raw Rigel frames are mostly dark, with a few bright highlights around the LEDs that keep a plain stretch from brightening anything else. Every enhancement maps each pixel through a 256 entry lookup table built from the eye's own histogram (or, for CLAHE, through tables built per tile and blended between tile centers), so it works in place and costs about one table lookup per pixel.
+
+use crate::*;
+
+/// A contrast adjustment, applied to each eye on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Enhancement {
+  /// Global histogram equalization: spreads the eye's pixel values evenly over 0 to 255.
+  Equalize,
+  /// Contrast limited adaptive histogram equalization: equalizes each of `tiles_x` by `tiles_y` tiles on its own, blending between neighbouring tiles. No value may take more than `clip_limit` times its fair share of a tile's histogram, which keeps flat, noisy areas from being blown up.
+  Clahe { tiles_x: u32, tiles_y: u32, clip_limit: f32 },
+  /// Maps v to 255 (v / 255)^(1 / gamma). Values above 1 brighten the shadows.
+  Gamma(f32),
+  /// Maps each value v to `table[v]`.
+  Lut(Box<[u8; 256]>),
+  /// Stretches the values linearly so that the `low` percentile becomes 0 and the `high` percentile 255, clipping the rest. Percentiles are from 0 to 100.
+  AutoLevels { low: f32, high: f32 }
+}
+
+impl Enhancement {
+  /// CLAHE over `tiles_x` by `tiles_y` tiles. 8 by 8 tiles with a clip limit of 2 to 4 suit most frames; a clip limit of 1 leaves them almost as they are.
+  pub fn clahe(tiles_x: u32, tiles_y: u32, clip_limit: f32) -> Result<Self> {
+    let enhancement = Enhancement::Clahe { tiles_x, tiles_y, clip_limit };
+    enhancement.validate()?;
+    Ok(enhancement)
+  }
+
+  pub fn gamma(gamma: f32) -> Result<Self> {
+    let enhancement = Enhancement::Gamma(gamma);
+    enhancement.validate()?;
+    Ok(enhancement)
+  }
+
+  pub fn lut(table: [u8; 256]) -> Self { Enhancement::Lut(Box::new(table)) }
+
+  pub fn auto_levels(low: f32, high: f32) -> Result<Self> {
+    let enhancement = Enhancement::AutoLevels { low, high };
+    enhancement.validate()?;
+    Ok(enhancement)
+  }
+
+  fn validate(&self) -> Result<()> {
+    match *self {
+      Enhancement::Clahe { tiles_x, tiles_y, clip_limit } => {
+        if tiles_x == 0 || tiles_y == 0 { return Err(Error::new(format!("CLAHE needs at least one tile each way, not {}x{}.", tiles_x, tiles_y))); }
+        if clip_limit.is_nan() || clip_limit < 1.0 { return Err(Error::new(format!("Invalid CLAHE clip limit {}; it must be at least 1.", clip_limit))); }
+      }
+      Enhancement::Gamma(gamma) => {
+        if !(gamma.is_finite() && gamma > 0.0) { return Err(Error::new(format!("Invalid gamma {}.", gamma))); }
+      }
+      Enhancement::AutoLevels { low, high } => {
+        if !((0.0..100.0).contains(&low) && low < high && high <= 100.0) {
+          return Err(Error::new(format!("Invalid auto-levels percentiles {} and {}.", low, high)));
+        }
+      }
+      Enhancement::Equalize | Enhancement::Lut(_) => {}
+    }
+    Ok(())
+  }
+
+  /// Enhances one eye of `frame` in place, leaving the other eye alone. Errors if the frame is incomplete.
+  pub fn apply_eye(&self, frame: &mut Frame, eye: Eye) -> Result<()> {
+    self.validate()?;
+    if !frame.is_complete() {
+      return Err(Error::new(format!("Frame {} is incomplete ({} of {} bytes).", frame.sequence, frame.data.len(), frame.expected_len())));
+    }
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    if width == 0 || height == 0 { return Ok(()); }
+    let start = match eye { Eye::Left => 0, Eye::Right => width };
+    let mut rows: Vec<&mut [u8]> = frame.data.chunks_exact_mut(width * 2).map(|row| &mut row[start..start + width]).collect();
+
+    let table = match self {
+      Enhancement::Clahe { tiles_x, tiles_y, clip_limit } => {
+        clahe(&mut rows, width, (*tiles_x as usize).min(width), (*tiles_y as usize).min(height), *clip_limit);
+        return Ok(());
+      }
+      Enhancement::Equalize => equalization(&histogram(&rows)),
+      Enhancement::Gamma(gamma) => {
+        let mut table = [0u8; 256];
+        for (v, out) in table.iter_mut().enumerate() { *out = (255.0 * (v as f32 / 255.0).powf(1.0 / gamma)).round() as u8; }
+        table
+      }
+      Enhancement::Lut(table) => **table,
+      Enhancement::AutoLevels { low, high } => {
+        let histogram = histogram(&rows);
+        let (black, white) = (percentile(&histogram, *low), percentile(&histogram, *high));
+        // A flat eye has nothing to stretch.
+        if white <= black { return Ok(()); }
+        let mut table = [0u8; 256];
+        for (v, out) in table.iter_mut().enumerate() {
+          *out = ((v as f32 - black as f32) * 255.0 / (white - black) as f32).round().clamp(0.0, 255.0) as u8;
+        }
+        table
+      }
+    };
+    for row in rows.iter_mut() {
+      for value in row.iter_mut() { *value = table[*value as usize]; }
+    }
+    Ok(())
+  }
+
+  /// Enhances both eyes of `frame` in place, each from its own histogram.
+  pub fn apply(&self, frame: &mut Frame) -> Result<()> {
+    self.apply_eye(frame, Eye::Left)?;
+    self.apply_eye(frame, Eye::Right)
+  }
+
+  /// Enhances both eyes into a new frame with the same sequence number and timestamp, for use between other processing steps such as `StereoRectifier::rectify`.
+  pub fn enhance(&self, frame: &Frame) -> Result<Frame> {
+    let mut out = frame.clone();
+    self.apply(&mut out)?;
+    Ok(out)
+  }
+}
+
+fn histogram(rows: &[&mut [u8]]) -> [u32; 256] {
+  let mut histogram = [0u32; 256];
+  for row in rows {
+    for &value in row.iter() { histogram[value as usize] += 1; }
+  }
+  histogram
+}
+
+// The value below which `percent` of the pixels lie.
+fn percentile(histogram: &[u32; 256], percent: f32) -> u8 {
+  let total: u32 = histogram.iter().sum();
+  let target = (total as f64 * percent as f64 / 100.0).round() as u64;
+  let mut count = 0u64;
+  for (v, &n) in histogram.iter().enumerate() {
+    count += n as u64;
+    if count > target || count == total as u64 { return v as u8; }
+  }
+  255
+}
+
+// Maps values through the histogram's cumulative distribution, with the darkest value present going to 0 and the brightest to 255.
+fn equalization(histogram: &[u32; 256]) -> [u8; 256] {
+  let mut table = [0u8; 256];
+  let total: u32 = histogram.iter().sum();
+  let darkest = histogram.iter().copied().find(|&n| n > 0).unwrap_or(0);
+  if total == darkest {
+    // A single value: leave it where it is.
+    for (v, out) in table.iter_mut().enumerate() { *out = v as u8; }
+    return table;
+  }
+  let mut cumulative = 0;
+  for (out, &n) in table.iter_mut().zip(histogram.iter()) {
+    cumulative += n;
+    *out = (cumulative.saturating_sub(darkest) as f64 * 255.0 / (total - darkest) as f64).round() as u8;
+  }
+  table
+}
+
+// Equalizes each tile with a clipped histogram, then maps every pixel through the tables of the four tiles whose centers surround it, weighted by its distance to them.
+fn clahe(rows: &mut [&mut [u8]], width: usize, tiles_x: usize, tiles_y: usize, clip_limit: f32) {
+  let height = rows.len();
+  let bounds = |tile: usize, tiles: usize, size: usize| (tile * size / tiles, (tile + 1) * size / tiles);
+  let mut tables = vec![[0u8; 256]; tiles_x * tiles_y];
+  for ty in 0..tiles_y {
+    let (y0, y1) = bounds(ty, tiles_y, height);
+    for tx in 0..tiles_x {
+      let (x0, x1) = bounds(tx, tiles_x, width);
+      let mut histogram = [0u32; 256];
+      for row in &rows[y0..y1] {
+        for &value in &row[x0..x1] { histogram[value as usize] += 1; }
+      }
+      let pixels = ((x1 - x0) * (y1 - y0)) as u32;
+      // What the clip cuts off is spread evenly over all values.
+      let limit = ((clip_limit * pixels as f32 / 256.0) as u32).max(1);
+      let mut excess = 0;
+      for n in histogram.iter_mut() {
+        if *n > limit {
+          excess += *n - limit;
+          *n = limit;
+        }
+      }
+      let (share, remainder) = (excess / 256, (excess % 256) as usize);
+      for (v, n) in histogram.iter_mut().enumerate() { *n += share + (v < remainder) as u32; }
+
+      let mut cumulative = 0;
+      for (out, &n) in tables[ty * tiles_x + tx].iter_mut().zip(histogram.iter()) {
+        cumulative += n;
+        *out = (cumulative as f32 * 255.0 / pixels as f32).round() as u8;
+      }
+    }
+  }
+
+  // Per column and row, the neighbouring tiles and the weight of the second.
+  let neighbours = |position: usize, tiles: usize, size: usize| -> (usize, usize, f32) {
+    let t = ((position as f32 + 0.5) * tiles as f32 / size as f32 - 0.5).max(0.0);
+    let first = (t as usize).min(tiles - 1);
+    (first, (first + 1).min(tiles - 1), t - first as f32)
+  };
+  let columns: Vec<(usize, usize, f32)> = (0..width).map(|x| neighbours(x, tiles_x, width)).collect();
+  for (y, row) in rows.iter_mut().enumerate() {
+    let (top, bottom, wy) = neighbours(y, tiles_y, height);
+    for (value, &(left, right, wx)) in row.iter_mut().zip(columns.iter()) {
+      let v = *value as usize;
+      let blend = |tile_row: usize| {
+        let tables = &tables[tile_row * tiles_x..];
+        tables[left][v] as f32 * (1.0 - wx) + tables[right][v] as f32 * wx
+      };
+      *value = (blend(top) * (1.0 - wy) + blend(bottom) * wy).round() as u8;
+    }
+  }
+}
@@ -0,0 +1,283 @@
+// fault.rs - tinyrigel
+//
+// FaultInjector wraps any DeviceSource and makes it misbehave on purpose, so that an application's recovery paths (dropped frames, garbage buffers, stalls, unplugs) can be exercised without hardware. Faults are either scheduled at a specific frame index or triggered randomly with a per-frame probability. Pair it with MockSource to run these paths in CI.
+
+use std::{collections::BTreeMap, thread, time::{Duration, Instant}};
+
+use crate::*;
+
+/// A single kind of misbehavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+  /// Discard the frame; the caller sees a gap in sequence numbers.
+  DropFrame,
+  /// Overwrite this many bytes at random positions in the frame buffer.
+  CorruptBuffer(usize),
+  /// Cut the frame buffer down to at most this many bytes.
+  TruncateBuffer(usize),
+  /// Deliver the frame this much later than it would otherwise arrive. If that is past the caller's timeout, `next_frame` times out and the frame is lost.
+  Latency(Duration),
+  /// Stop delivering frames for this long. Calls to `next_frame` time out in the meantime.
+  Stall(Duration),
+  /// Behave as if the device was unplugged: every call fails with `ErrorKind::Disconnected` until `reconnect` is called.
+  Disconnect
+}
+
+/// Counts of faults injected so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+  pub frames_delivered: u64,
+  pub frames_dropped: u64,
+  pub frames_corrupted: u64,
+  pub frames_truncated: u64,
+  pub frames_delayed: u64,
+  pub stalls: u64,
+  pub disconnects: u64
+}
+
+pub struct FaultInjector<S: DeviceSource> {
+  inner: S,
+  rng: XorShift,
+  schedule: BTreeMap<u64, Vec<Fault>>,
+
+  drop_probability: f64,
+  corrupt_probability: f64,
+  corrupt_bytes: usize,
+  truncate_probability: f64,
+  latency_jitter: Duration,
+  stall_probability: f64,
+  stall_duration: Duration,
+  disconnect_probability: f64,
+
+  frame_index: u64,
+  stalled_until: Option<Instant>,
+  disconnected: bool,
+  stats: FaultStats
+}
+
+impl<S: DeviceSource> FaultInjector<S> {
+  /// Wraps `inner` without any faults configured; it passes frames through untouched until faults are scheduled or probabilities set.
+  pub fn new(inner: S) -> Self {
+    Self {
+      inner,
+      rng: XorShift::new(0x5EED_F00D),
+      schedule: BTreeMap::new(),
+      drop_probability: 0.0,
+      corrupt_probability: 0.0,
+      corrupt_bytes: 64,
+      truncate_probability: 0.0,
+      latency_jitter: Duration::from_millis(0),
+      stall_probability: 0.0,
+      stall_duration: Duration::from_millis(0),
+      disconnect_probability: 0.0,
+      frame_index: 0,
+      stalled_until: None,
+      disconnected: false,
+      stats: FaultStats::default()
+    }
+  }
+
+  /// Seeds the random number generator so randomized fault runs are reproducible.
+  pub fn set_seed(&mut self, seed: u64) { self.rng = XorShift::new(seed); }
+
+  /// Injects `fault` when the `frame_index`th frame (counting from zero across the injector's lifetime) is pulled from the inner source. Several faults may be scheduled for the same frame.
+  pub fn schedule(&mut self, frame_index: u64, fault: Fault) {
+    self.schedule.entry(frame_index).or_default().push(fault);
+  }
+
+  pub fn set_drop_probability(&mut self, p: f64) { self.drop_probability = p; }
+
+  pub fn set_corrupt_probability(&mut self, p: f64, bytes: usize) {
+    self.corrupt_probability = p;
+    self.corrupt_bytes = bytes;
+  }
+
+  pub fn set_truncate_probability(&mut self, p: f64) { self.truncate_probability = p; }
+
+  /// Adds a uniformly random delay of up to `max` to every frame.
+  pub fn set_latency_jitter(&mut self, max: Duration) { self.latency_jitter = max; }
+
+  pub fn set_stall_probability(&mut self, p: f64, duration: Duration) {
+    self.stall_probability = p;
+    self.stall_duration = duration;
+  }
+
+  pub fn set_disconnect_probability(&mut self, p: f64) { self.disconnect_probability = p; }
+
+  /// Clears a simulated disconnect. The next `start` restarts the inner source.
+  pub fn reconnect(&mut self) { self.disconnected = false; }
+
+  pub fn stats(&self) -> FaultStats { self.stats }
+
+  pub fn inner(&self) -> &S { &self.inner }
+
+  pub fn inner_mut(&mut self) -> &mut S { &mut self.inner }
+
+  pub fn into_inner(self) -> S { self.inner }
+
+  fn disconnect_error() -> Error {
+    Error::disconnected("Device disconnected (injected fault).".to_string())
+  }
+
+  // Collects the scheduled faults for the current frame plus any random ones that fire.
+  fn faults_for_frame(&mut self, frame_len: usize) -> Vec<Fault> {
+    let mut faults = self.schedule.remove(&self.frame_index).unwrap_or_default();
+    if self.rng.chance(self.disconnect_probability) { faults.push(Fault::Disconnect); }
+    if self.rng.chance(self.stall_probability) { faults.push(Fault::Stall(self.stall_duration)); }
+    if self.rng.chance(self.drop_probability) { faults.push(Fault::DropFrame); }
+    if self.rng.chance(self.corrupt_probability) { faults.push(Fault::CorruptBuffer(self.corrupt_bytes)); }
+    if self.rng.chance(self.truncate_probability) {
+      faults.push(Fault::TruncateBuffer(self.rng.below(frame_len as u64) as usize));
+    }
+    if self.latency_jitter > Duration::from_millis(0) {
+      let jitter_us = self.rng.below(self.latency_jitter.as_micros() as u64 + 1);
+      faults.push(Fault::Latency(Duration::from_micros(jitter_us)));
+    }
+    faults
+  }
+
+  // Sleeps through an active stall. Returns a timeout error if the stall outlasts `timeout`.
+  fn wait_out_stall(&mut self, deadline: Instant) -> Result<()> {
+    if let Some(stalled_until) = self.stalled_until {
+      let now = Instant::now();
+      if stalled_until > deadline {
+        if deadline > now { thread::sleep(deadline - now); }
+        return Err(Error::timeout("Timed out waiting for a frame (injected stall).".to_string()));
+      }
+      if stalled_until > now { thread::sleep(stalled_until - now); }
+      self.stalled_until = None;
+    }
+    Ok(())
+  }
+}
+
+impl<S: DeviceSource> DeviceSource for FaultInjector<S> {
+  fn info(&self) -> DeviceInfo { self.inner.info() }
+
+  fn modes(&self) -> Result<Vec<CaptureMode>> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.modes()
+  }
+
+  fn mode(&self) -> CaptureMode { self.inner.mode() }
+
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.configure(mode)
+  }
+
+  fn start(&mut self) -> Result<()> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.start()
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    self.stalled_until = None;
+    self.inner.stop()
+  }
+
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      if self.disconnected { return Err(Self::disconnect_error()); }
+      self.wait_out_stall(deadline)?;
+
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      let mut frame = self.inner.next_frame(remaining)?;
+      let faults = self.faults_for_frame(frame.data.len());
+      self.frame_index += 1;
+
+      let mut dropped = false;
+      let mut delay = Duration::from_millis(0);
+      for fault in faults {
+        match fault {
+          Fault::Disconnect => {
+            self.disconnected = true;
+            self.stats.disconnects += 1;
+            // Like a real unplug, the inner device goes away mid-stream; failure to stop it cleanly is expected.
+            let _ = self.inner.stop();
+            return Err(Self::disconnect_error());
+          }
+          Fault::Stall(duration) => {
+            self.stalled_until = Some(Instant::now() + duration);
+            self.stats.stalls += 1;
+            // The frame in flight when the stall started is lost.
+            dropped = true;
+          }
+          Fault::DropFrame => { dropped = true; }
+          Fault::CorruptBuffer(bytes) => {
+            if !frame.data.is_empty() {
+              for _ in 0..bytes {
+                let idx = self.rng.below(frame.data.len() as u64) as usize;
+                frame.data[idx] = self.rng.next() as u8;
+              }
+              self.stats.frames_corrupted += 1;
+            }
+          }
+          Fault::TruncateBuffer(len) => {
+            if len < frame.data.len() {
+              frame.data.truncate(len);
+              self.stats.frames_truncated += 1;
+            }
+          }
+          Fault::Latency(extra) => { delay += extra; }
+        }
+      }
+
+      if dropped {
+        self.stats.frames_dropped += 1;
+        continue;
+      }
+      if delay > Duration::from_millis(0) {
+        self.stats.frames_delayed += 1;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if delay > remaining {
+          // The frame would arrive after the caller gave up on it, so it is lost.
+          thread::sleep(remaining);
+          self.stats.frames_dropped += 1;
+          return Err(Error::timeout("Timed out waiting for a frame (injected latency).".to_string()));
+        }
+        thread::sleep(delay);
+      }
+      self.stats.frames_delivered += 1;
+      return Ok(frame);
+    }
+  }
+
+  fn controls(&self) -> Result<Vec<ControlInfo>> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.controls()
+  }
+
+  fn control(&self, control: Control) -> Result<i64> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.control(control)
+  }
+
+  fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+    if self.disconnected { return Err(Self::disconnect_error()); }
+    self.inner.set_control(control, value)
+  }
+}
+
+// Small, dependency-free PRNG (xorshift64*). Fault injection only needs cheap, reproducible randomness.
+struct XorShift(u64);
+
+impl XorShift {
+  fn new(seed: u64) -> Self { Self(if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed }) }
+
+  fn next(&mut self) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+  }
+
+  fn below(&mut self, n: u64) -> u64 { if n == 0 { 0 } else { self.next() % n } }
+
+  fn chance(&mut self, p: f64) -> bool {
+    if p <= 0.0 { return false; }
+    let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+    unit < p
+  }
+}
@@ -0,0 +1,483 @@
+// features.rs - tinyrigel
+//
+// Sparse features for visual odometry and SLAM: FAST corners with ORB descriptors (BRIEF tests steered by the corner's orientation) in each eye, matched between the eyes along rectified epipolar lines and between frames within a search radius. Rigel images are mostly dark and flat with a few hard edges around the lit areas, so one FAST threshold either finds corners only along those edges or drowns them in noise: the eye is split into cells that each keep their own strongest corners, and cells with no corner at the threshold take the ones found at a lower one. Corners are found at a single scale, since neither the eyes nor consecutive frames differ much in scale. Both eyes of a 384x384 frame take a few milliseconds in a release build, most of it in FAST on densely textured scenes.
+
+use std::convert::TryInto;
+
+use image::GenericImageView;
+
+use crate::*;
+
+// The 16 pixel circle of radius 3 that FAST compares against the center, clockwise from the top.
+const CIRCLE: [(i32, i32); 16] = [(0, -3), (1, -3), (2, -2), (3, -1), (3, 0), (3, 1), (2, 2), (1, 3), (0, 3), (-1, 3), (-2, 2), (-3, 1), (-3, 0), (-3, -1), (-2, -2), (-1, -3)];
+// How many contiguous circle pixels must all be brighter, or all darker, than the center.
+const ARC: usize = 9;
+// Orientation and descriptor tests look at pixels within this radius of the corner, as ORB does.
+const PATCH_RADIUS: i32 = 15;
+// Side of the cells that each keep their own corners.
+const CELL: usize = 32;
+// Steered test patterns are precomputed for this many orientations.
+const ANGLE_BINS: usize = 30;
+// A match must be this much closer than the runner-up, as a fraction of its distance.
+const MAX_RATIO: f64 = 0.8;
+
+/// A FAST corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keypoint {
+  /// Subpixel position in the original (unrectified) eye image.
+  pub x: f64,
+  pub y: f64,
+  /// Contrast of the weakest pixel on the corner's arc: the highest FAST threshold it passes.
+  pub score: u8,
+  /// Direction from the corner to the intensity centroid of its patch, in radians clockwise from the x axis.
+  pub angle: f32
+}
+
+/// A 256 bit ORB descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Descriptor(pub [u8; 32]);
+
+impl Descriptor {
+  /// The Hamming distance: how many of the 256 tests differ.
+  pub fn distance(&self, other: &Descriptor) -> u32 {
+    let word = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+    self.0.chunks_exact(8).zip(other.0.chunks_exact(8)).map(|(a, b)| (word(a) ^ word(b)).count_ones()).sum()
+  }
+}
+
+/// The features of one eye, `descriptors[i]` describing `keypoints[i]`.
+#[derive(Debug, Clone, Default)]
+pub struct EyeFeatures {
+  pub keypoints: Vec<Keypoint>,
+  pub descriptors: Vec<Descriptor>
+}
+
+/// A feature seen by both eyes and triangulated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoMatch {
+  /// Indices into the left and right `EyeFeatures`.
+  pub left: usize,
+  pub right: usize,
+  /// Hamming distance between the descriptors.
+  pub distance: u32,
+  /// Position in meters in the left camera frame (x right, y down, z forward).
+  pub position: [f64; 3],
+  /// Vertical distance between the two rectified keypoints, in pixels.
+  pub epipolar_error: f64
+}
+
+/// A feature of one frame found again in a later frame, by the same eye.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureMatch {
+  /// Indices into the earlier and the later `EyeFeatures`.
+  pub previous: usize,
+  pub current: usize,
+  /// Hamming distance between the descriptors.
+  pub distance: u32
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameFeatures {
+  pub sequence: u64,
+  pub left: EyeFeatures,
+  pub right: EyeFeatures,
+  pub stereo: Vec<StereoMatch>,
+  /// Per eye, the previous frame's features found again in this one. Empty for the first frame.
+  pub left_temporal: Vec<FeatureMatch>,
+  pub right_temporal: Vec<FeatureMatch>
+}
+
+/// Finds and describes corners in both eyes, and matches them between the eyes and with the previous frame.
+pub struct FeatureDetector {
+  rectifier: StereoRectifier,
+  threshold: u8,
+  fallback_threshold: u8,
+  max_features: usize,
+  max_distance: u32,
+  max_epipolar_error: f64,
+  depth_range: (f64, f64),
+  search_radius: f64,
+  // Per orientation bin, the 256 pairs of test offsets, rotated.
+  patterns: Vec<[[(i8, i8); 2]; 256]>,
+  previous: Option<(EyeFeatures, EyeFeatures)>
+}
+
+impl FeatureDetector {
+  pub fn new(calibration: &StereoCalibration) -> Self {
+    Self {
+      rectifier: StereoRectifier::new(calibration),
+      threshold: 20,
+      fallback_threshold: 7,
+      max_features: 500,
+      max_distance: 50,
+      max_epipolar_error: 2.0,
+      depth_range: (0.02, 10.0),
+      search_radius: 32.0,
+      patterns: steered_patterns(),
+      previous: None
+    }
+  }
+
+  /// The FAST threshold: how much brighter or darker than the center the arc's pixels must be. Cells with no corner at `threshold` take the ones at `fallback`. Default to 20 and 7.
+  pub fn set_thresholds(&mut self, threshold: u8, fallback: u8) {
+    self.threshold = threshold;
+    self.fallback_threshold = fallback.min(threshold);
+  }
+
+  /// Keeps at most this many features per eye, spread over the cells. Defaults to 500.
+  pub fn set_max_features(&mut self, count: usize) { self.max_features = count; }
+
+  /// The largest Hamming distance between matching descriptors, out of 256. Defaults to 50.
+  pub fn set_max_distance(&mut self, bits: u32) { self.max_distance = bits; }
+
+  /// How far apart, in rectified pixels, two features' rows may be to match between the eyes. Defaults to 2.
+  pub fn set_max_epipolar_error(&mut self, pixels: f64) { self.max_epipolar_error = pixels; }
+
+  /// Only matches features between the eyes whose triangulated depth is within `min..=max` meters. Defaults to 0.02 to 10.
+  pub fn set_depth_range(&mut self, min: f64, max: f64) { self.depth_range = (min, max); }
+
+  /// How far, in pixels, a feature may move from one frame to the next and still be matched. Defaults to 32.
+  pub fn set_search_radius(&mut self, pixels: f64) { self.search_radius = pixels; }
+
+  /// Forgets the previous frame, so the next has no temporal matches.
+  pub fn reset(&mut self) { self.previous = None; }
+
+  /// The features of one eye, best first. Errors if the frame is incomplete.
+  pub fn detect_eye(&self, frame: &Frame, eye: Eye) -> Result<EyeFeatures> {
+    let view = frame.eye_view(eye).ok_or_else(|| Error::new(format!("Frame {} is incomplete.", frame.sequence)))?;
+    let plane = Plane::new(&view);
+    Ok(self.describe(&plane, self.find_corners(&plane)))
+  }
+
+  /// Detects features in both eyes, matches them between the eyes, and matches each eye's with those of the frame processed before.
+  pub fn process(&mut self, frame: &Frame) -> Result<FrameFeatures> {
+    let left = self.detect_eye(frame, Eye::Left)?;
+    let right = self.detect_eye(frame, Eye::Right)?;
+    let stereo = self.match_stereo(&left, &right);
+    let (left_temporal, right_temporal) = match &self.previous {
+      Some((previous_left, previous_right)) => (self.match_temporal(previous_left, &left), self.match_temporal(previous_right, &right)),
+      None => (Vec::new(), Vec::new())
+    };
+    self.previous = Some((left.clone(), right.clone()));
+    Ok(FrameFeatures { sequence: frame.sequence, left, right, stereo, left_temporal, right_temporal })
+  }
+
+  /// Matches features of the left eye to features of the right eye on the same rectified row, in front of the cameras within the depth range. A match must be clearly better than the feature's next best, and each feature is used at most once, the closest descriptors winning.
+  pub fn match_stereo(&self, left: &EyeFeatures, right: &EyeFeatures) -> Vec<StereoMatch> {
+    let rectified = |eye: Eye, features: &EyeFeatures| -> Vec<(f64, f64)> { features.keypoints.iter().map(|keypoint| self.rectifier.rectify_point(eye, keypoint.x, keypoint.y)).collect() };
+    let (left_points, right_points) = (rectified(Eye::Left, left), rectified(Eye::Right, right));
+    let mut by_row: Vec<usize> = (0..right_points.len()).collect();
+    by_row.sort_by(|&a, &b| right_points[a].1.total_cmp(&right_points[b].1));
+
+    let mut candidates = Vec::new();
+    for (i, &(xl, yl)) in left_points.iter().enumerate() {
+      let start = by_row.partition_point(|&j| right_points[j].1 < yl - self.max_epipolar_error);
+      let mut best = Best::default();
+      for &j in &by_row[start..] {
+        let (xr, yr) = right_points[j];
+        if yr > yl + self.max_epipolar_error { break; }
+        let Some(point) = self.rectifier.reproject(xl, (yl + yr) / 2.0, xl - xr) else { continue; };
+        if point[2] < self.depth_range.0 || point[2] > self.depth_range.1 { continue; }
+        best.offer(left.descriptors[i].distance(&right.descriptors[j]), j, point);
+      }
+      if let Some((distance, j, point)) = best.accept(self.max_distance) {
+        candidates.push((distance, i, j, point));
+      }
+    }
+
+    // Back from the rectified left frame into the original left camera frame.
+    let unrotate = transpose(&self.rectifier.rotation(Eye::Left));
+    unique(candidates, left.keypoints.len(), right.keypoints.len())
+      .map(|(distance, i, j, point)| StereoMatch { left: i, right: j, distance, position: mat_vec(&unrotate, point), epipolar_error: (left_points[i].1 - right_points[j].1).abs() })
+      .collect()
+  }
+
+  /// Matches features of an earlier frame to those of a later one seen by the same eye, within the search radius. As between the eyes, a match must be clearly better than the runner-up and each feature is used once.
+  pub fn match_temporal(&self, previous: &EyeFeatures, current: &EyeFeatures) -> Vec<FeatureMatch> {
+    let mut by_row: Vec<usize> = (0..current.keypoints.len()).collect();
+    by_row.sort_by(|&a, &b| current.keypoints[a].y.total_cmp(&current.keypoints[b].y));
+    let radius = self.search_radius;
+
+    let mut candidates = Vec::new();
+    for (i, from) in previous.keypoints.iter().enumerate() {
+      let start = by_row.partition_point(|&j| current.keypoints[j].y < from.y - radius);
+      let mut best = Best::default();
+      for &j in &by_row[start..] {
+        let to = &current.keypoints[j];
+        if to.y > from.y + radius { break; }
+        if (to.x - from.x).hypot(to.y - from.y) > radius { continue; }
+        best.offer(previous.descriptors[i].distance(&current.descriptors[j]), j, ());
+      }
+      if let Some((distance, j, ())) = best.accept(self.max_distance) {
+        candidates.push((distance, i, j, ()));
+      }
+    }
+    unique(candidates, previous.keypoints.len(), current.keypoints.len())
+      .map(|(distance, i, j, ())| FeatureMatch { previous: i, current: j, distance })
+      .collect()
+  }
+
+  // FAST corners, kept where no neighbour scores higher and spread over the cells: every cell's best corner comes first, then every cell's second best, and so on, until there are enough.
+  fn find_corners(&self, plane: &Plane) -> Vec<Keypoint> {
+    let (width, height) = (plane.width, plane.height);
+    let edge = PATCH_RADIUS as usize + 1;
+    if width <= 2 * edge || height <= 2 * edge { return Vec::new(); }
+    let scores = fast_scores(plane, self.fallback_threshold, edge);
+
+    let columns = width.div_ceil(CELL);
+    let mut cells: Vec<Vec<(u8, usize, usize)>> = vec![Vec::new(); columns * height.div_ceil(CELL)];
+    for y in edge..height - edge {
+      for x in edge..width - edge {
+        let s = scores[y * width + x];
+        if s == 0 { continue; }
+        let at = |dx: isize, dy: isize| scores[(y as isize + dy) as usize * width + (x as isize + dx) as usize];
+        // Ties go to the first in scan order.
+        if at(-1, -1) >= s || at(0, -1) >= s || at(1, -1) >= s || at(-1, 0) >= s { continue; }
+        if at(1, 0) > s || at(-1, 1) > s || at(0, 1) > s || at(1, 1) > s { continue; }
+        cells[y / CELL * columns + x / CELL].push((s, x, y));
+      }
+    }
+
+    let mut ranked = Vec::new();
+    for cell in &mut cells {
+      if cell.iter().any(|&(s, _, _)| s > self.threshold) { cell.retain(|&(s, _, _)| s > self.threshold); }
+      cell.sort_by_key(|corner| std::cmp::Reverse(corner.0));
+      ranked.extend(cell.iter().enumerate().map(|(rank, &corner)| (rank, corner)));
+    }
+    ranked.sort_by(|a, b| a.0.cmp(&b.0).then(b.1 .0.cmp(&a.1 .0)));
+    ranked.truncate(self.max_features);
+
+    ranked.into_iter().map(|(_, (score, x, y))| {
+      // A parabola through the scores on either side places the corner between pixels.
+      let offset = |before: u8, after: u8| {
+        let (before, center, after) = (before as f64, score as f64, after as f64);
+        let curvature = before - 2.0 * center + after;
+        if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 }
+      };
+      let dx = offset(scores[y * width + x - 1], scores[y * width + x + 1]);
+      let dy = offset(scores[(y - 1) * width + x], scores[(y + 1) * width + x]);
+      Keypoint { x: x as f64 + dx, y: y as f64 + dy, score, angle: orientation(plane, x, y) }
+    }).collect()
+  }
+
+  // ORB descriptors, whose tests compare pixels of a smoothed image at offsets rotated by the keypoint's angle.
+  fn describe(&self, plane: &Plane, keypoints: Vec<Keypoint>) -> EyeFeatures {
+    let (width, height) = (plane.width, plane.height);
+    let smooth = smoothed(plane);
+    // The patterns' offsets into `smooth`.
+    let offsets: Vec<Vec<[isize; 2]>> = self.patterns.iter().map(|pattern| {
+      pattern.iter().map(|pair| { let offset = |(dx, dy): (i8, i8)| dy as isize * width as isize + dx as isize; [offset(pair[0]), offset(pair[1])] }).collect()
+    }).collect();
+    let descriptors = keypoints.iter().map(|keypoint| {
+      let bin = ((keypoint.angle as f64 / std::f64::consts::TAU * ANGLE_BINS as f64).round() as i64).rem_euclid(ANGLE_BINS as i64) as usize;
+      let (x, y) = (keypoint.x.round() as usize, keypoint.y.round() as usize);
+      debug_assert!(x >= PATCH_RADIUS as usize && y >= PATCH_RADIUS as usize && x + (PATCH_RADIUS as usize) < width && y + (PATCH_RADIUS as usize) < height);
+      let center = (y * width + x) as isize;
+      let mut bits = [0u8; 32];
+      for (byte, tests) in bits.iter_mut().zip(offsets[bin].chunks_exact(8)) {
+        for (bit, [a, b]) in tests.iter().enumerate() {
+          *byte |= ((smooth[(center + a) as usize] < smooth[(center + b) as usize]) as u8) << bit;
+        }
+      }
+      Descriptor(bits)
+    }).collect();
+    EyeFeatures { keypoints, descriptors }
+  }
+}
+
+// The best and second best distances offered for one feature.
+struct Best<T> {
+  best: Option<(u32, usize, T)>,
+  second: u32
+}
+
+impl<T> Default for Best<T> {
+  fn default() -> Self { Self { best: None, second: u32::MAX } }
+}
+
+impl<T> Best<T> {
+  fn offer(&mut self, distance: u32, index: usize, value: T) {
+    match &self.best {
+      Some((best, _, _)) if distance >= *best => self.second = self.second.min(distance),
+      _ => {
+        if let Some((best, _, _)) = &self.best { self.second = *best; }
+        self.best = Some((distance, index, value));
+      }
+    }
+  }
+
+  // The best, if it's close enough and clearly better than the second.
+  fn accept(self, max_distance: u32) -> Option<(u32, usize, T)> {
+    let second = self.second as f64;
+    self.best.filter(|&(distance, _, _)| distance <= max_distance && (distance as f64) < MAX_RATIO * second)
+  }
+}
+
+// Candidate pairs with each index used at most once, the smallest distances first.
+fn unique<T>(mut candidates: Vec<(u32, usize, usize, T)>, from: usize, to: usize) -> impl Iterator<Item = (u32, usize, usize, T)> {
+  candidates.sort_by_key(|candidate| candidate.0);
+  let (mut from_used, mut to_used) = (vec![false; from], vec![false; to]);
+  candidates.into_iter().filter(move |&(_, i, j, _)| {
+    if from_used[i] || to_used[j] { return false; }
+    from_used[i] = true;
+    to_used[j] = true;
+    true
+  })
+}
+
+// An eye copied into one contiguous buffer, so that neighbours are at fixed offsets.
+struct Plane {
+  pixels: Vec<u8>,
+  width: usize,
+  height: usize
+}
+
+impl Plane {
+  fn new(view: &EyeView) -> Self {
+    let (width, height) = (view.width() as usize, view.height() as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height as u32 { pixels.extend_from_slice(view.row(y)); }
+    Self { pixels, width, height }
+  }
+}
+
+// FAST-9 scores, 0 where there is no corner at `threshold`, `edge` pixels in from the sides.
+fn fast_scores(plane: &Plane, threshold: u8, edge: usize) -> Vec<u8> {
+  let (width, height, pixels) = (plane.width, plane.height, &plane.pixels[..]);
+  let mut offsets = [0isize; 16];
+  for (offset, &(dx, dy)) in offsets.iter_mut().zip(CIRCLE.iter()) { *offset = dy as isize * width as isize + dx as isize; }
+  let mut scores = vec![0u8; width * height];
+  let mut candidates = vec![false; width];
+  for y in edge..height - edge {
+    // Any arc of 9 includes two of the four compass points, which rules most pixels out cheaply, a whole row at a time.
+    let row = |dy: isize| &pixels[(y as isize + dy) as usize * width..][..width];
+    let (above, center, below) = (row(-3), row(0), row(3));
+    for x in edge..width - edge {
+      let (high, low) = (center[x].saturating_add(threshold), center[x].saturating_sub(threshold));
+      let compass = [above[x], center[x + 3], below[x], center[x - 3]];
+      let brighter = compass.iter().filter(|&&value| value > high).count();
+      let darker = compass.iter().filter(|&&value| value < low).count();
+      candidates[x] = brighter >= 2 || darker >= 2;
+    }
+
+    let threshold = threshold as i16;
+    for x in edge..width - edge {
+      if !candidates[x] { continue; }
+      let index = y * width + x;
+      let center = pixels[index] as i16;
+      let mut differences = [0i16; 16];
+      let (mut brighter, mut darker) = (0u32, 0u32);
+      for (k, difference) in differences.iter_mut().enumerate() {
+        *difference = pixels[(index as isize + offsets[k]) as usize] as i16 - center;
+        if *difference > threshold { brighter |= 1 << k; }
+        if *difference < -threshold { darker |= 1 << k; }
+      }
+      // Most survivors lie on straight edges, whose arcs are too short; bit masks find the corners before scoring them.
+      if !has_arc(brighter) && !has_arc(darker) { continue; }
+      // The weakest difference along each arc of 9, from minima over arcs of 2, 4 and 8.
+      let (mut lightest, mut darkest) = ([0i16; 16], [0i16; 16]);
+      for k in 0..16 {
+        let (a, b) = (differences[k], differences[(k + 1) % 16]);
+        lightest[k] = a.min(b);
+        darkest[k] = a.max(b);
+      }
+      for step in [2, 4].iter() {
+        let (previous_lightest, previous_darkest) = (lightest, darkest);
+        for k in 0..16 {
+          lightest[k] = previous_lightest[k].min(previous_lightest[(k + step) % 16]);
+          darkest[k] = previous_darkest[k].max(previous_darkest[(k + step) % 16]);
+        }
+      }
+      let mut score = 0;
+      for k in 0..16 {
+        let last = differences[(k + ARC - 1) % 16];
+        score = score.max(lightest[k].min(last)).max(-darkest[k].max(last));
+      }
+      if score > threshold { scores[y * width + x] = score.min(255) as u8; }
+    }
+  }
+  scores
+}
+
+// Whether the circle's pixels set in `mask` include ARC contiguous ones, wrapping around.
+fn has_arc(mask: u32) -> bool {
+  let mut run = mask | mask << 16;
+  for _ in 1..ARC { run &= run >> 1; }
+  run & 0xffff != 0
+}
+
+// The angle from (x, y) to the intensity centroid of the disc of radius PATCH_RADIUS around it.
+fn orientation(plane: &Plane, x: usize, y: usize) -> f32 {
+  let (mut m10, mut m01) = (0i64, 0i64);
+  for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+    let span = ((PATCH_RADIUS * PATCH_RADIUS - dy * dy) as f64).sqrt() as usize;
+    let start = (y as isize + dy as isize) as usize * plane.width + x;
+    let (mut sum, mut moment) = (0i64, 0i64);
+    for (dx, &value) in plane.pixels[start - span..=start + span].iter().enumerate() {
+      sum += value as i64;
+      moment += dx as i64 * value as i64;
+    }
+    m10 += moment - span as i64 * sum;
+    m01 += dy as i64 * sum;
+  }
+  (m01 as f32).atan2(m10 as f32)
+}
+
+// The eye blurred with a 5x5 binomial kernel, which keeps sensor noise from flipping descriptor tests.
+fn smoothed(plane: &Plane) -> Vec<u8> {
+  let (width, height) = (plane.width, plane.height);
+  // Edge pixels repeated two deep all around.
+  let padded_width = width + 4;
+  let mut padded = Vec::with_capacity(padded_width * (height + 4));
+  for y in 0..height + 4 {
+    let row = &plane.pixels[y.saturating_sub(2).min(height - 1) * width..][..width];
+    padded.extend_from_slice(&[row[0]; 2]);
+    padded.extend_from_slice(row);
+    padded.extend_from_slice(&[row[width - 1]; 2]);
+  }
+  let mut across = vec![0u16; width * (height + 4)];
+  for (row, out) in padded.chunks_exact(padded_width).zip(across.chunks_exact_mut(width)) {
+    for (window, value) in row.windows(5).zip(out.iter_mut()) {
+      *value = window[0] as u16 + 4 * window[1] as u16 + 6 * window[2] as u16 + 4 * window[3] as u16 + window[4] as u16;
+    }
+  }
+  let mut out = vec![0u8; width * height];
+  for (y, out) in out.chunks_exact_mut(width).enumerate() {
+    let row = |k: usize| &across[(y + k) * width..][..width];
+    let (r0, r1, r2, r3, r4) = (row(0), row(1), row(2), row(3), row(4));
+    for x in 0..width {
+      // At most 256 * 255, so the sum stays within 16 bits.
+      let sum = r0[x] + 4 * r1[x] + 6 * r2[x] + 4 * r3[x] + r4[x];
+      out[x] = ((sum as u32 + 128) >> 8) as u8;
+    }
+  }
+  out
+}
+
+// BRIEF's test pairs, drawn from an isotropic Gaussian around the corner (sigma a fifth of the patch) with a fixed seed, and kept within the patch whatever their rotation.
+fn steered_patterns() -> Vec<[[(i8, i8); 2]; 256]> {
+  let mut state = 0x2545_f491_4f6c_dd1du64;
+  let mut uniform = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    (state >> 11) as f64 / (1u64 << 53) as f64
+  };
+  let sigma = (2 * PATCH_RADIUS + 1) as f64 / 5.0;
+  let limit = (PATCH_RADIUS - 2) as f64;
+  let mut point = move || loop {
+    // Box-Muller.
+    let (radius, angle) = ((-2.0 * (1.0 - uniform()).ln()).sqrt() * sigma, uniform() * std::f64::consts::TAU);
+    if radius <= limit { return (radius * angle.cos(), radius * angle.sin()); }
+  };
+  let pairs: Vec<[(f64, f64); 2]> = (0..256).map(|_| [point(), point()]).collect();
+
+  (0..ANGLE_BINS).map(|bin| {
+    let angle = bin as f64 / ANGLE_BINS as f64 * std::f64::consts::TAU;
+    let (sin, cos) = angle.sin_cos();
+    let rotate = |(x, y): (f64, f64)| ((cos * x - sin * y).round() as i8, (sin * x + cos * y).round() as i8);
+    let mut pattern = [[(0i8, 0i8); 2]; 256];
+    for (rotated, [a, b]) in pattern.iter_mut().zip(pairs.iter()) { *rotated = [rotate(*a), rotate(*b)]; }
+    pattern
+  }).collect()
+}
@@ -0,0 +1,185 @@
+// frame.rs - tinyrigel
+
+use std::{borrow::Cow, ops::Deref, time::Duration};
+
+use image::{GenericImageView, GrayImage, ImageBuffer, Luma};
+
+use crate::*;
+
+/// Width of a single Rigel eye image, in pixels.
+pub const RIGEL_EYE_WIDTH: u32 = 384;
+/// Height of a single Rigel eye image, in pixels.
+pub const RIGEL_EYE_HEIGHT: u32 = 384;
+
+/// One of the Rigel's two cameras.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Eye {
+  Left,
+  Right
+}
+
+/// Which part of a stereo frame to export: both eyes side-by-side as captured, or a single eye.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EyeLayout {
+  SideBySide,
+  Left,
+  Right
+}
+
+impl EyeLayout {
+  /// Dimensions of the exported image for frames with the given per-eye dimensions.
+  pub fn dimensions(&self, eye_width: u32, height: u32) -> (u32, u32) {
+    match self {
+      EyeLayout::SideBySide => (eye_width * 2, height),
+      EyeLayout::Left | EyeLayout::Right => (eye_width, height)
+    }
+  }
+}
+
+/// A single stereo frame.
+///
+/// Pixels are 8-bit grayscale, with the left and right eye images side-by-side in each row: a row is `width` left-eye bytes followed by `width` right-eye bytes. This is exactly the buffer the Rigel delivers over UVC as "YUYV".
+#[derive(Debug, Clone)]
+pub struct Frame {
+  /// Raw side-by-side pixel data, `width * 2 * height` bytes for a complete frame.
+  pub data: Vec<u8>,
+  /// Width of a single eye image, in pixels.
+  pub width: u32,
+  /// Height of the frame, in pixels.
+  pub height: u32,
+  /// Sequence number as counted by the source. Gaps indicate dropped frames.
+  pub sequence: u64,
+  /// Capture timestamp as reported by the source (driver time for hardware, stream time for synthetic sources).
+  pub timestamp: Duration
+}
+
+impl Frame {
+  pub fn new(width: u32, height: u32, data: Vec<u8>, sequence: u64, timestamp: Duration) -> Self {
+    Self { data, width, height, sequence, timestamp }
+  }
+
+  /// Builds a frame from a side-by-side stereo image, taking over its buffer. The image width must be even.
+  pub fn from_side_by_side(image: GrayImage, sequence: u64, timestamp: Duration) -> Result<Self> {
+    if image.width() % 2 != 0 {
+      return Err(Error::new(format!("A side-by-side image must have an even width, but it is {}.", image.width())));
+    }
+    Ok(Self::new(image.width() / 2, image.height(), image.into_raw(), sequence, timestamp))
+  }
+
+  /// Builds a frame from separate left and right eye images, which must have the same dimensions.
+  pub fn from_images<L, R>(left: &ImageBuffer<Luma<u8>, L>, right: &ImageBuffer<Luma<u8>, R>, sequence: u64, timestamp: Duration) -> Result<Self>
+    where L: Deref<Target = [u8]>, R: Deref<Target = [u8]>
+  {
+    if left.dimensions() != right.dimensions() {
+      return Err(Error::new(format!(
+        "The left image is {}x{}, but the right image is {}x{}.", left.width(), left.height(), right.width(), right.height()
+      )));
+    }
+    let (width, height) = (left.width() as usize, left.height() as usize);
+    let mut data = Vec::with_capacity(width * 2 * height);
+    for row in 0..height {
+      data.extend_from_slice(&left.as_raw()[row * width..(row + 1) * width]);
+      data.extend_from_slice(&right.as_raw()[row * width..(row + 1) * width]);
+    }
+    Ok(Self::new(width as u32, height as u32, data, sequence, timestamp))
+  }
+
+  /// Number of bytes in one row of the side-by-side stereo image.
+  pub fn stride(&self) -> usize { self.width as usize * 2 }
+
+  /// Number of bytes a complete frame with these dimensions occupies.
+  pub fn expected_len(&self) -> usize { self.stride() * self.height as usize }
+
+  /// Whether `data` holds a whole frame. Sources may deliver short buffers when a transfer is cut off.
+  pub fn is_complete(&self) -> bool { self.data.len() == self.expected_len() }
+
+  /// Copies the pixels selected by `layout` into a tightly packed buffer. Pixels missing from an incomplete frame come out black.
+  pub fn layout_pixels(&self, layout: EyeLayout) -> Vec<u8> {
+    let stride = self.stride();
+    let (start, len) = match layout {
+      EyeLayout::SideBySide => (0, stride),
+      EyeLayout::Left => (0, self.width as usize),
+      EyeLayout::Right => (self.width as usize, self.width as usize)
+    };
+    let mut pixels = vec![0u8; len * self.height as usize];
+    if len == 0 { return pixels; }
+    for (row, out) in pixels.chunks_exact_mut(len).enumerate() {
+      let begin = (row * stride + start).min(self.data.len());
+      let end = (row * stride + start + len).min(self.data.len());
+      out[..end - begin].copy_from_slice(&self.data[begin..end]);
+    }
+    pixels
+  }
+
+  /// The pixels selected by `layout` as an image. Borrows the frame's buffer when those pixels are contiguous in it (the side-by-side image of a complete frame), and copies them otherwise, padding incomplete frames with black.
+  pub fn image(&self, layout: EyeLayout) -> ImageBuffer<Luma<u8>, Cow<'_, [u8]>> {
+    let (width, height) = layout.dimensions(self.width, self.height);
+    let start = if layout == EyeLayout::Right { self.width as usize } else { 0 };
+    let end = start + (width * height) as usize;
+    // A zero-height frame has no data for the right eye to start in, so it takes the copying path.
+    let pixels = if self.is_complete() && (layout == EyeLayout::SideBySide || self.height <= 1) && end <= self.data.len() {
+      Cow::Borrowed(&self.data[start..end])
+    } else {
+      Cow::Owned(self.layout_pixels(layout))
+    };
+    ImageBuffer::from_raw(width, height, pixels).unwrap()
+  }
+
+  /// The left eye image. See `image`.
+  pub fn left_image(&self) -> ImageBuffer<Luma<u8>, Cow<'_, [u8]>> { self.image(EyeLayout::Left) }
+
+  /// The right eye image. See `image`.
+  pub fn right_image(&self) -> ImageBuffer<Luma<u8>, Cow<'_, [u8]>> { self.image(EyeLayout::Right) }
+
+  /// Both eyes side-by-side, as captured. Never copies a complete frame.
+  pub fn side_by_side(&self) -> ImageBuffer<Luma<u8>, Cow<'_, [u8]>> { self.image(EyeLayout::SideBySide) }
+
+  /// A zero-copy view of one eye that steps over the other eye's half of each row. `None` for incomplete frames.
+  pub fn eye_view(&self, eye: Eye) -> Option<EyeView<'_>> {
+    if !self.is_complete() { return None; }
+    let start = match eye { Eye::Left => 0, Eye::Right => self.width as usize };
+    Some(EyeView { data: self.data.get(start..).unwrap_or(&[]), width: self.width, height: self.height, stride: self.stride() })
+  }
+}
+
+/// One eye of a frame, borrowed in place. Implements `image::GenericImageView`, so it works with the `image` crate's processing functions without copying.
+#[derive(Debug, Clone, Copy)]
+pub struct EyeView<'a> {
+  data: &'a [u8],
+  width: u32,
+  height: u32,
+  stride: usize
+}
+
+impl<'a> EyeView<'a> {
+  /// Bytes between the starts of consecutive rows.
+  pub fn stride(&self) -> usize { self.stride }
+
+  /// The pixels of row `y`.
+  pub fn row(&self, y: u32) -> &'a [u8] {
+    let start = y as usize * self.stride;
+    &self.data[start..start + self.width as usize]
+  }
+
+  /// Copies the eye into a tightly packed image.
+  pub fn to_image(&self) -> GrayImage {
+    let pixels = (0..self.height).flat_map(|y| self.row(y).iter().copied()).collect();
+    GrayImage::from_raw(self.width, self.height, pixels).unwrap()
+  }
+}
+
+impl GenericImageView for EyeView<'_> {
+  type Pixel = Luma<u8>;
+  type InnerImageView = Self;
+
+  fn dimensions(&self) -> (u32, u32) { (self.width, self.height) }
+
+  fn bounds(&self) -> (u32, u32, u32, u32) { (0, 0, self.width, self.height) }
+
+  fn get_pixel(&self, x: u32, y: u32) -> Luma<u8> {
+    assert!(x < self.width && y < self.height, "Pixel ({}, {}) is outside the {}x{} eye image.", x, y, self.width, self.height);
+    Luma([self.data[y as usize * self.stride + x as usize]])
+  }
+
+  fn inner(&self) -> &Self { self }
+}
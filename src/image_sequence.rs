@@ -0,0 +1,305 @@
+// image_sequence.rs - tinyrigel
+//
+// Exports frames as numbered image files with per-frame metadata, from a live Rigel (as a FrameSink) or from any FrameArchive such as a recording. Images are encoded on a pool of worker threads so a live capture thread only ever copies the frame and queues it.
+//
+// Files are numbered in the order frames were exported, counting from 0, rather than by sequence number, which restarts when a device is reopened and would overwrite earlier files; each frame's sequence number is kept in its metadata. Layout of an export directory, for the frame numbered 42:
+//
+//   left/00000042.png, right/00000042.png   with SequenceLayout::PerEye
+//   00000042.png                            with SequenceLayout::Combined (side-by-side)
+//   00000042.json                           with Sidecar::Json
+//   frames.csv                              with Sidecar::Csv, one row per frame
+
+use std::{collections::BTreeMap, fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}, sync::{mpsc::{self, Receiver, SyncSender, TrySendError}, Arc, Mutex}, thread::{self, JoinHandle}, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::*;
+
+/// Image file format of an exported sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFileFormat {
+  Png,
+  Tiff,
+  /// Binary PGM (P5).
+  Pgm
+}
+
+impl ImageFileFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ImageFileFormat::Png => "png",
+      ImageFileFormat::Tiff => "tiff",
+      ImageFileFormat::Pgm => "pgm"
+    }
+  }
+}
+
+/// How the two eyes of a frame are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceLayout {
+  /// One image per eye, in `left/` and `right/` subdirectories.
+  PerEye,
+  /// A single side-by-side image per frame.
+  Combined
+}
+
+/// Per-frame metadata written next to the images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sidecar {
+  None,
+  /// A `<number>.json` file per frame.
+  Json,
+  /// A row per frame in `frames.csv`.
+  Csv
+}
+
+// Everything a worker needs to write a frame.
+#[derive(Clone)]
+struct ExportSettings {
+  dir: PathBuf,
+  format: ImageFileFormat,
+  layout: SequenceLayout,
+  sidecar: Sidecar
+}
+
+struct ExportJob {
+  // Number of the frame's files, in export order.
+  number: u64,
+  frame: Frame,
+  host_time: Option<SystemTime>,
+  controls: BTreeMap<String, i64>
+}
+
+struct WorkerPool {
+  sender: SyncSender<ExportJob>,
+  workers: Vec<JoinHandle<()>>
+}
+
+/// Writes frames to a directory as an image sequence. See the module comment for the layout.
+///
+/// Attached to a Rigel, frames that arrive while the queue is full are dropped (and reported as errors) rather than stalling capture; `export_archive` waits for the queue instead, so every recorded frame is written.
+pub struct ImageSequenceExporter {
+  settings: ExportSettings,
+  workers: usize,
+  queue_capacity: usize,
+  controls: BTreeMap<String, i64>,
+  pool: Option<WorkerPool>,
+  csv: Option<BufWriter<File>>,
+  // First error reported by a worker, returned from the next write_frame or finish.
+  worker_error: Arc<Mutex<Option<Error>>>,
+  // Frames queued so far, which numbers the next one's files.
+  exported: u64,
+  dropped: u64
+}
+
+impl ImageSequenceExporter {
+  /// Exports per-eye images with JSON sidecars into `dir`, which is created if needed.
+  pub fn new<P: AsRef<Path>>(dir: P, format: ImageFileFormat) -> Self {
+    let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(2).clamp(1, 4);
+    Self {
+      settings: ExportSettings { dir: dir.as_ref().to_path_buf(), format, layout: SequenceLayout::PerEye, sidecar: Sidecar::Json },
+      workers,
+      queue_capacity: workers * 4,
+      controls: BTreeMap::new(),
+      pool: None,
+      csv: None,
+      worker_error: Arc::new(Mutex::new(None)),
+      exported: 0,
+      dropped: 0
+    }
+  }
+
+  // Layout and pool settings take effect when the first frame is written.
+
+  pub fn set_layout(&mut self, layout: SequenceLayout) { self.settings.layout = layout; }
+
+  pub fn set_sidecar(&mut self, sidecar: Sidecar) { self.settings.sidecar = sidecar; }
+
+  /// Number of encoding threads. Defaults to the number of cores, up to 4.
+  pub fn set_workers(&mut self, workers: usize) { self.workers = workers.max(1); }
+
+  /// Frames that can wait for a worker before live frames start being dropped.
+  pub fn set_queue_capacity(&mut self, capacity: usize) { self.queue_capacity = capacity.max(1); }
+
+  /// Frames dropped because the workers fell behind.
+  pub fn dropped_frames(&self) -> u64 { self.dropped }
+
+  /// Exports every frame of `archive`, e.g. a `RecordingReader`, waiting for the workers when they fall behind. Returns the number of frames queued; call `finish` to wait for them to be written.
+  pub fn export_archive(&mut self, archive: &mut dyn FrameArchive) -> Result<usize> {
+    for index in 0..archive.frame_count() {
+      let frame = archive.read_frame(index)?;
+      self.submit(frame, None, true)?;
+    }
+    Ok(archive.frame_count())
+  }
+
+  fn start(&mut self) -> Result<()> {
+    let dir = &self.settings.dir;
+    let subdirs: &[&str] = match self.settings.layout { SequenceLayout::PerEye => &["left", "right"], SequenceLayout::Combined => &[] };
+    fs::create_dir_all(dir).map_err(|err| Error::new(format!("Failed to create {}: {}", dir.display(), err)))?;
+    for subdir in subdirs {
+      fs::create_dir_all(dir.join(subdir)).map_err(|err| Error::new(format!("Failed to create {}: {}", dir.join(subdir).display(), err)))?;
+    }
+
+    if self.settings.sidecar == Sidecar::Csv {
+      let path = dir.join("frames.csv");
+      let file = File::create(&path).map_err(|err| Error::new(format!("Failed to create {}: {}", path.display(), err)))?;
+      let mut csv = BufWriter::new(file);
+      writeln!(csv, "sequence,timestamp_ns,host_time_ns,width,height,images,controls").map_err(|err| Error::new(format!("Failed to write frames.csv: {}", err)))?;
+      self.csv = Some(csv);
+    }
+
+    let (sender, receiver) = mpsc::sync_channel::<ExportJob>(self.queue_capacity);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers = (0..self.workers).map(|_| {
+      let (settings, receiver, worker_error) = (self.settings.clone(), receiver.clone(), self.worker_error.clone());
+      thread::spawn(move || run_worker(settings, receiver, worker_error))
+    }).collect();
+    self.pool = Some(WorkerPool { sender, workers });
+    Ok(())
+  }
+
+  fn submit(&mut self, frame: Frame, host_time: Option<SystemTime>, wait: bool) -> Result<()> {
+    if let Some(err) = self.worker_error.lock().unwrap().take() {
+      return Err(err);
+    }
+    if self.pool.is_none() {
+      self.start()?;
+    }
+
+    // The CSV row is written here rather than by a worker so rows stay in frame order.
+    let row = self.csv.as_ref().map(|_| {
+      let images = image_names(&self.settings, self.exported).join(";");
+      let controls: Vec<String> = self.controls.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+      format!(
+        "{},{},{},{},{},{},{}",
+        frame.sequence, frame.timestamp.as_nanos(), host_time.map(|time| unix_nanos(time).to_string()).unwrap_or_default(),
+        frame.width, frame.height, images, controls.join(";")
+      )
+    });
+
+    let sequence = frame.sequence;
+    let job = ExportJob { number: self.exported, frame, host_time, controls: self.controls.clone() };
+    let sender = &self.pool.as_ref().unwrap().sender;
+    let sent = if wait { sender.send(job).map_err(|err| TrySendError::Disconnected(err.0)) } else { sender.try_send(job) };
+    match sent {
+      Ok(()) => {
+        self.exported += 1;
+        if let (Some(csv), Some(row)) = (&mut self.csv, row) {
+          writeln!(csv, "{}", row).map_err(|err| Error::new(format!("Failed to write frames.csv: {}", err)))?;
+        }
+        Ok(())
+      }
+      Err(TrySendError::Full(_)) => {
+        self.dropped += 1;
+        Err(Error::new(format!("Image export is falling behind; dropped frame {}.", sequence)))
+      }
+      Err(TrySendError::Disconnected(_)) => Err(Error::new("Image export workers have stopped.".to_string()))
+    }
+  }
+}
+
+impl FrameSink for ImageSequenceExporter {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    self.submit(frame.clone(), Some(SystemTime::now()), false)
+  }
+
+  fn control_changed(&mut self, control: Control, value: i64) {
+    self.controls.insert(control_name(control), value);
+  }
+
+  /// Waits for every queued frame to be written.
+  fn finish(&mut self) -> Result<()> {
+    if let Some(pool) = self.pool.take() {
+      drop(pool.sender);
+      for worker in pool.workers {
+        let _ = worker.join();
+      }
+    }
+    if let Some(mut csv) = self.csv.take() {
+      csv.flush().map_err(|err| Error::new(format!("Failed to write frames.csv: {}", err)))?;
+    }
+    match self.worker_error.lock().unwrap().take() {
+      Some(err) => Err(err),
+      None => Ok(())
+    }
+  }
+}
+
+impl Drop for ImageSequenceExporter {
+  fn drop(&mut self) {
+    if self.pool.is_some() {
+      let _ = self.finish();
+    }
+  }
+}
+
+fn run_worker(settings: ExportSettings, receiver: Arc<Mutex<Receiver<ExportJob>>>, worker_error: Arc<Mutex<Option<Error>>>) {
+  loop {
+    // Hold the lock only while waiting for a job, not while encoding it.
+    let job = receiver.lock().unwrap().recv();
+    match job {
+      Ok(job) => {
+        if let Err(err) = export_job(&settings, &job) {
+          worker_error.lock().unwrap().get_or_insert(err);
+        }
+      }
+      Err(_) => return
+    }
+  }
+}
+
+fn export_job(settings: &ExportSettings, job: &ExportJob) -> Result<()> {
+  let frame = &job.frame;
+  let names = image_names(settings, job.number);
+  let layouts: &[EyeLayout] = match settings.layout {
+    SequenceLayout::PerEye => &[EyeLayout::Left, EyeLayout::Right],
+    SequenceLayout::Combined => &[EyeLayout::SideBySide]
+  };
+  for (name, layout) in names.iter().zip(layouts) {
+    let (width, height) = layout.dimensions(frame.width, frame.height);
+    let path = settings.dir.join(name);
+    image::save_buffer(&path, &frame.layout_pixels(*layout), width, height, image::ColorType::L8)
+      .map_err(|err| Error::new(format!("Failed to write {}: {}", path.display(), err)))?;
+  }
+
+  if settings.sidecar == Sidecar::Json {
+    let images: Vec<String> = names.iter().map(|name| json_string(name)).collect();
+    let controls: Vec<String> = job.controls.iter().map(|(name, value)| format!("{}: {}", json_string(name), value)).collect();
+    let json = format!(
+      "{{\n  \"sequence\": {},\n  \"timestamp_ns\": {},\n  \"host_time_ns\": {},\n  \"width\": {},\n  \"height\": {},\n  \"images\": [{}],\n  \"controls\": {{{}}}\n}}\n",
+      frame.sequence,
+      frame.timestamp.as_nanos(),
+      job.host_time.map(|time| unix_nanos(time).to_string()).unwrap_or_else(|| "null".to_string()),
+      frame.width,
+      frame.height,
+      images.join(", "),
+      controls.join(", ")
+    );
+    let path = settings.dir.join(format!("{:08}.json", job.number));
+    fs::write(&path, json).map_err(|err| Error::new(format!("Failed to write {}: {}", path.display(), err)))?;
+  }
+  Ok(())
+}
+
+// Image paths of the frame numbered `number`, relative to the export directory.
+fn image_names(settings: &ExportSettings, number: u64) -> Vec<String> {
+  let ext = settings.format.extension();
+  match settings.layout {
+    SequenceLayout::PerEye => vec![format!("left/{:08}.{}", number, ext), format!("right/{:08}.{}", number, ext)],
+    SequenceLayout::Combined => vec![format!("{:08}.{}", number, ext)]
+  }
+}
+
+fn control_name(control: Control) -> String {
+  match control {
+    Control::Exposure => "exposure".to_string(),
+    Control::Gain => "gain".to_string(),
+    Control::Gamma => "gamma".to_string(),
+    Control::Brightness => "brightness".to_string(),
+    Control::Contrast => "contrast".to_string(),
+    Control::Other(id) => format!("control_{}", id)
+  }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+  time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
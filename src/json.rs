@@ -0,0 +1,180 @@
+// json.rs - tinyrigel
+//
+// A small JSON reader for the files tinyrigel writes itself, such as calibrations, so they load back without a serde dependency. Writing stays plain `format!`.
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Json>),
+  Object(Vec<(String, Json)>)
+}
+
+impl Json {
+  pub(crate) fn parse(text: &str) -> Result<Json> {
+    let mut parser = Parser { bytes: text.as_bytes(), position: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.position < parser.bytes.len() { return Err(parser.error("trailing characters")); }
+    Ok(value)
+  }
+
+  /// The value of `key`, if this is an object that has it.
+  pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Json::Object(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+      _ => None
+    }
+  }
+
+  pub(crate) fn as_f64(&self) -> Option<f64> {
+    match self { Json::Number(value) => Some(*value), _ => None }
+  }
+
+  pub(crate) fn as_str(&self) -> Option<&str> {
+    match self { Json::String(value) => Some(value), _ => None }
+  }
+
+  pub(crate) fn as_array(&self) -> Option<&[Json]> {
+    match self { Json::Array(values) => Some(values), _ => None }
+  }
+}
+
+/// `text` as a quoted JSON string.
+pub(crate) fn json_string(text: &str) -> String {
+  let mut out = String::with_capacity(text.len() + 2);
+  out.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c)
+    }
+  }
+  out.push('"');
+  out
+}
+
+struct Parser<'a> {
+  bytes: &'a [u8],
+  position: usize
+}
+
+impl Parser<'_> {
+  fn error(&self, message: &str) -> Error { Error::new(format!("Invalid JSON at byte {}: {}.", self.position, message)) }
+
+  fn whitespace(&mut self) {
+    while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() { self.position += 1; }
+  }
+
+  fn peek(&mut self) -> Option<u8> {
+    self.whitespace();
+    self.bytes.get(self.position).copied()
+  }
+
+  fn expect(&mut self, byte: u8) -> Result<()> {
+    if self.peek() != Some(byte) { return Err(self.error(&format!("expected '{}'", byte as char))); }
+    self.position += 1;
+    Ok(())
+  }
+
+  fn literal(&mut self, word: &str, value: Json) -> Result<Json> {
+    if !self.bytes[self.position..].starts_with(word.as_bytes()) { return Err(self.error("unexpected character")); }
+    self.position += word.len();
+    Ok(value)
+  }
+
+  fn value(&mut self) -> Result<Json> {
+    match self.peek() {
+      Some(b'{') => {
+        self.position += 1;
+        let mut entries = Vec::new();
+        if self.peek() == Some(b'}') {
+          self.position += 1;
+          return Ok(Json::Object(entries));
+        }
+        loop {
+          let key = self.string()?;
+          self.expect(b':')?;
+          entries.push((key, self.value()?));
+          match self.peek() {
+            Some(b',') => self.position += 1,
+            Some(b'}') => { self.position += 1; return Ok(Json::Object(entries)); }
+            _ => return Err(self.error("expected ',' or '}'"))
+          }
+        }
+      }
+      Some(b'[') => {
+        self.position += 1;
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+          self.position += 1;
+          return Ok(Json::Array(values));
+        }
+        loop {
+          values.push(self.value()?);
+          match self.peek() {
+            Some(b',') => self.position += 1,
+            Some(b']') => { self.position += 1; return Ok(Json::Array(values)); }
+            _ => return Err(self.error("expected ',' or ']'"))
+          }
+        }
+      }
+      Some(b'"') => Ok(Json::String(self.string()?)),
+      Some(b't') => self.literal("true", Json::Bool(true)),
+      Some(b'f') => self.literal("false", Json::Bool(false)),
+      Some(b'n') => self.literal("null", Json::Null),
+      Some(b'-') | Some(b'0'..=b'9') => {
+        let start = self.position;
+        while self.position < self.bytes.len() && matches!(self.bytes[self.position], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') { self.position += 1; }
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap_or_default();
+        text.parse().map(Json::Number).map_err(|_| self.error("invalid number"))
+      }
+      Some(_) => Err(self.error("unexpected character")),
+      None => Err(self.error("unexpected end"))
+    }
+  }
+
+  fn string(&mut self) -> Result<String> {
+    self.expect(b'"')?;
+    let mut bytes = Vec::new();
+    loop {
+      let Some(&byte) = self.bytes.get(self.position) else { return Err(self.error("unterminated string")); };
+      self.position += 1;
+      match byte {
+        b'"' => break,
+        b'\\' => {
+          let Some(&escape) = self.bytes.get(self.position) else { return Err(self.error("unterminated string")); };
+          self.position += 1;
+          let decoded = match escape {
+            b'"' | b'\\' | b'/' => escape as char,
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+              let hex = self.bytes.get(self.position..self.position + 4).and_then(|hex| std::str::from_utf8(hex).ok());
+              let code = hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()).ok_or_else(|| self.error("invalid \\u escape"))?;
+              self.position += 4;
+              // Surrogate pairs are not combined; nothing tinyrigel writes needs them.
+              char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ => return Err(self.error("invalid escape"))
+          };
+          bytes.extend_from_slice(decoded.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        _ => bytes.push(byte)
+      }
+    }
+    String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+  }
+}
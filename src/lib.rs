@@ -3,9 +3,115 @@
 mod core;
 pub use crate::core::*;
 
+mod frame;
+pub use frame::*;
+
 mod rigel;
 pub use rigel::*;
 
+mod sink;
+pub use sink::*;
+
+mod calibration;
+pub use calibration::*;
+
+mod json;
+use json::*;
+
+mod yaml;
+use yaml::*;
+
+#[cfg(any(feature = "ndarray", feature = "nalgebra"))]
+mod arrays;
+
+// Sources
+// ---
+
+mod source;
+pub use source::*;
+
+mod mock;
+pub use mock::*;
+
+mod fault;
+pub use fault::*;
+
+mod replay;
+pub use replay::*;
+
+mod recording;
+pub use recording::*;
+
+mod codec;
+pub use codec::*;
+
+#[cfg(target_os = "linux")]
+mod v4l2;
+#[cfg(target_os = "linux")]
+pub use v4l2::*;
+
+// Export
+// ---
+
+mod y4m;
+pub use y4m::*;
+
+mod image_sequence;
+pub use image_sequence::*;
+
+mod rosbag;
+pub use rosbag::*;
+
+#[cfg(feature = "mcap")]
+mod mcap;
+#[cfg(feature = "mcap")]
+pub use mcap::*;
+
+// Processing
+// ---
+
+mod linalg;
+use linalg::*;
+
+mod remap;
+use remap::*;
+
+mod components;
+use components::*;
+
+mod undistort;
+pub use undistort::*;
+
+mod rectify;
+pub use rectify::*;
+
+mod stereo;
+pub use stereo::*;
+
+mod pointcloud;
+pub use pointcloud::*;
+
+mod blobs;
+pub use blobs::*;
+
+mod tracker;
+pub use tracker::*;
+
+mod apriltag;
+pub use apriltag::*;
+
+mod calibrate;
+pub use calibrate::*;
+
+mod validation;
+pub use validation::*;
+
+mod features;
+pub use features::*;
+
+mod enhance;
+pub use enhance::*;
+
 // Tests
 // ---
 
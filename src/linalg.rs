@@ -0,0 +1,217 @@
+// linalg.rs - tinyrigel
+//
+// Small fixed-size vector and matrix helpers for the geometry code, on plain arrays so calibration types stay dependency-free. Matrices are row-major.
+
+pub(crate) type Vec3 = [f64; 3];
+pub(crate) type Mat3 = [[f64; 3]; 3];
+
+pub(crate) const IDENTITY: Mat3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+pub(crate) fn dot(a: Vec3, b: Vec3) -> f64 { a[0] * b[0] + a[1] * b[1] + a[2] * b[2] }
+
+pub(crate) fn norm(a: Vec3) -> f64 { dot(a, a).sqrt() }
+
+pub(crate) fn scale(a: Vec3, s: f64) -> Vec3 { [a[0] * s, a[1] * s, a[2] * s] }
+
+pub(crate) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+  [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub(crate) fn mat_vec(m: &Mat3, v: Vec3) -> Vec3 { [dot(m[0], v), dot(m[1], v), dot(m[2], v)] }
+
+pub(crate) fn mat_mul(a: &Mat3, b: &Mat3) -> Mat3 {
+  let mut out = [[0.0; 3]; 3];
+  for (row, out_row) in out.iter_mut().enumerate() {
+    for (col, value) in out_row.iter_mut().enumerate() {
+      *value = (0..3).map(|k| a[row][k] * b[k][col]).sum();
+    }
+  }
+  out
+}
+
+pub(crate) fn transpose(m: &Mat3) -> Mat3 {
+  [[m[0][0], m[1][0], m[2][0]], [m[0][1], m[1][1], m[2][1]], [m[0][2], m[1][2], m[2][2]]]
+}
+
+/// Rotation matrix of a rotation vector (axis times angle in radians).
+pub(crate) fn rodrigues(r: Vec3) -> Mat3 {
+  let theta = norm(r);
+  if theta < 1e-12 { return IDENTITY; }
+  let [x, y, z] = scale(r, 1.0 / theta);
+  let (s, c) = theta.sin_cos();
+  let t = 1.0 - c;
+  [
+    [c + x * x * t, x * y * t - z * s, x * z * t + y * s],
+    [y * x * t + z * s, c + y * y * t, y * z * t - x * s],
+    [z * x * t - y * s, z * y * t + x * s, c + z * z * t]
+  ]
+}
+
+/// Rotation vector of a rotation matrix; the inverse of `rodrigues`.
+pub(crate) fn rotation_vector(m: &Mat3) -> Vec3 {
+  let cos = ((m[0][0] + m[1][1] + m[2][2] - 1.0) / 2.0).clamp(-1.0, 1.0);
+  let theta = cos.acos();
+  let axis = [m[2][1] - m[1][2], m[0][2] - m[2][0], m[1][0] - m[0][1]];
+  if theta < 1e-9 {
+    return scale(axis, 0.5);
+  }
+  if std::f64::consts::PI - theta < 1e-6 {
+    // Near a half turn the antisymmetric part vanishes. Read the axis magnitudes off the diagonal instead, and their signs off the symmetric part (2 a_i a_j).
+    let mut axis = [0.0; 3];
+    for (i, value) in axis.iter_mut().enumerate() {
+      *value = ((m[i][i] + 1.0) / 2.0).max(0.0).sqrt();
+    }
+    let largest = (0..3).fold(0, |best, i| if axis[i] > axis[best] { i } else { best });
+    for i in 0..3 {
+      if i != largest && m[largest][i] + m[i][largest] < 0.0 { axis[i] = -axis[i]; }
+    }
+    return scale(axis, theta);
+  }
+  scale(axis, theta / (2.0 * theta.sin()))
+}
+
+/// Solves the square system `a x = b` by Gaussian elimination with partial pivoting. `None` if `a` is singular.
+pub(crate) fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+  let n = b.len();
+  for col in 0..n {
+    let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+    if a[pivot][col].abs() < 1e-300 { return None; }
+    a.swap(col, pivot);
+    b.swap(col, pivot);
+    let (upper, lower) = a.split_at_mut(col + 1);
+    let pivot_row = &upper[col];
+    for (offset, row) in lower.iter_mut().enumerate() {
+      let factor = row[col] / pivot_row[col];
+      if factor == 0.0 { continue; }
+      for (value, pivot_value) in row[col..].iter_mut().zip(&pivot_row[col..]) { *value -= factor * pivot_value; }
+      b[col + 1 + offset] -= factor * b[col];
+    }
+  }
+  let mut x = vec![0.0; n];
+  for row in (0..n).rev() {
+    let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+    x[row] = (b[row] - sum) / a[row][row];
+  }
+  Some(x)
+}
+
+/// The homography mapping each of `from` onto the matching point of `to`, least squares for more than four pairs. Points are normalized first (Hartley) to keep the system well conditioned.
+pub(crate) fn homography(from: &[(f64, f64)], to: &[(f64, f64)]) -> Option<Mat3> {
+  if from.len() < 4 || from.len() != to.len() { return None; }
+  // Moves the centroid to the origin and scales the mean distance to sqrt(2).
+  let normalize = |points: &[(f64, f64)]| -> Mat3 {
+    let n = points.len() as f64;
+    let (cx, cy) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let spread = points.iter().map(|p| ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt()).sum::<f64>() / n;
+    let s = if spread > 0.0 { std::f64::consts::SQRT_2 / spread } else { 1.0 };
+    [[s, 0.0, -s * cx], [0.0, s, -s * cy], [0.0, 0.0, 1.0]]
+  };
+  let (tf, tt) = (normalize(from), normalize(to));
+  // Normal equations of the DLT rows with h33 = 1.
+  let mut ata = vec![vec![0.0; 8]; 8];
+  let mut atb = vec![0.0; 8];
+  for (&p, &q) in from.iter().zip(to) {
+    let (x, y) = apply_homography(&tf, p);
+    let (u, v) = apply_homography(&tt, q);
+    for (row, rhs) in [([x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y], u), ([0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y], v)].iter() {
+      for i in 0..8 {
+        atb[i] += row[i] * rhs;
+        for j in 0..8 { ata[i][j] += row[i] * row[j]; }
+      }
+    }
+  }
+  let h = solve(ata, atb)?;
+  let normalized = [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]];
+  // Undo the normalizations: H = Tt^-1 Hn Tf.
+  let (s, cx, cy) = (tt[0][0], -tt[0][2] / tt[0][0], -tt[1][2] / tt[0][0]);
+  let tt_inverse = [[1.0 / s, 0.0, cx], [0.0, 1.0 / s, cy], [0.0, 0.0, 1.0]];
+  let m = mat_mul(&tt_inverse, &mat_mul(&normalized, &tf));
+  if m[2][2].abs() < 1e-300 { return None; }
+  Some([scale(m[0], 1.0 / m[2][2]), scale(m[1], 1.0 / m[2][2]), scale(m[2], 1.0 / m[2][2])])
+}
+
+pub(crate) fn apply_homography(h: &Mat3, (x, y): (f64, f64)) -> (f64, f64) {
+  let [u, v, w] = mat_vec(h, [x, y, 1.0]);
+  (u / w, v / w)
+}
+
+/// Rotation and translation of a plane (z = 0 in its own frame) from its homography onto normalized image coordinates, H ~ [r1 r2 t]. The rotation is re-orthonormalized and the plane put in front of the camera.
+pub(crate) fn pose_from_homography(h: &Mat3) -> (Mat3, Vec3) {
+  let column = |c: usize| [h[0][c], h[1][c], h[2][c]];
+  let mut lambda = 2.0 / (norm(column(0)) + norm(column(1)));
+  if h[2][2] * lambda < 0.0 { lambda = -lambda; }
+  let (r1, r2, t) = (scale(column(0), lambda), scale(column(1), lambda), scale(column(2), lambda));
+  let r1 = scale(r1, 1.0 / norm(r1));
+  let r2 = { let r2 = [r2[0] - dot(r1, r2) * r1[0], r2[1] - dot(r1, r2) * r1[1], r2[2] - dot(r1, r2) * r1[2]]; scale(r2, 1.0 / norm(r2)) };
+  let r3 = cross(r1, r2);
+  (transpose(&[r1, r2, r3]), t)
+}
+
+/// Minimizes the sum of squared `residuals` over `params` with Levenberg-Marquardt, using a forward-difference Jacobian. Returns the final RMS residual.
+pub(crate) fn levenberg_marquardt<F: Fn(&[f64]) -> Vec<f64>>(params: &mut [f64], residuals: F, iterations: usize) -> f64 {
+  let cost = |r: &[f64]| r.iter().map(|e| e * e).sum::<f64>();
+  let mut current = residuals(params);
+  let mut lambda = 1e-3;
+  for _ in 0..iterations {
+    let n = params.len();
+    let mut jacobian = vec![Vec::new(); n];
+    for (i, column) in jacobian.iter_mut().enumerate() {
+      let step = 1e-7 * params[i].abs().max(1e-3);
+      let saved = params[i];
+      params[i] += step;
+      *column = residuals(params).iter().zip(&current).map(|(a, b)| (a - b) / step).collect();
+      params[i] = saved;
+    }
+    let mut jtj = vec![vec![0.0; n]; n];
+    let mut jtr = vec![0.0; n];
+    for i in 0..n {
+      jtr[i] = -jacobian[i].iter().zip(&current).map(|(j, r)| j * r).sum::<f64>();
+      for k in i..n {
+        let value = jacobian[i].iter().zip(&jacobian[k]).map(|(a, b)| a * b).sum::<f64>();
+        jtj[i][k] = value;
+        jtj[k][i] = value;
+      }
+    }
+    let mut improved = false;
+    while lambda < 1e12 {
+      let mut damped = jtj.clone();
+      for (i, row) in damped.iter_mut().enumerate() { row[i] += lambda * jtj[i][i].max(1e-12); }
+      let Some(delta) = solve(damped, jtr.clone()) else { lambda *= 10.0; continue; };
+      let candidate: Vec<f64> = params.iter().zip(&delta).map(|(p, d)| p + d).collect();
+      let next = residuals(&candidate);
+      if cost(&next) < cost(&current) {
+        params.copy_from_slice(&candidate);
+        let converged = cost(&current) - cost(&next) < 1e-14 * cost(&current).max(1e-30);
+        current = next;
+        lambda = (lambda / 10.0).max(1e-12);
+        improved = !converged;
+        break;
+      }
+      lambda *= 10.0;
+    }
+    if !improved { break; }
+  }
+  (cost(&current) / current.len().max(1) as f64).sqrt()
+}
+
+/// Pose of a plane (z = 0 in its own frame) from points `object` on it and where they were seen, in normalized image coordinates: the homography pose, refined by minimizing the reprojection error. `None` if the points don't admit a pose in front of the camera.
+pub(crate) fn plane_pose(object: &[(f64, f64)], observed: &[(f64, f64)]) -> Option<(Mat3, Vec3)> {
+  let h = homography(object, observed)?;
+  let (rotation, t) = pose_from_homography(&h);
+  let r = rotation_vector(&rotation);
+  let mut params = [r[0], r[1], r[2], t[0], t[1], t[2]];
+  let residuals = |p: &[f64]| -> Vec<f64> {
+    let rotation = rodrigues([p[0], p[1], p[2]]);
+    let mut out = Vec::with_capacity(object.len() * 2);
+    for (&(x, y), &(u, v)) in object.iter().zip(observed) {
+      let point = mat_vec(&rotation, [x, y, 0.0]);
+      let z = point[2] + p[5];
+      out.push((point[0] + p[3]) / z - u);
+      out.push((point[1] + p[4]) / z - v);
+    }
+    out
+  };
+  levenberg_marquardt(&mut params, residuals, 50);
+  if params[5] <= 0.0 { return None; }
+  Some((rodrigues([params[0], params[1], params[2]]), [params[3], params[4], params[5]]))
+}
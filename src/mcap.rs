@@ -0,0 +1,448 @@
+// mcap.rs - tinyrigel
+//
+// MCAP export for Foxglove and ROS 2 tooling, behind the `mcap` feature. Frames become two image channels plus, when a calibration is known, per-eye camera calibration channels, and a JSON channel of capture stats:
+//
+//   /rigel/left/image_raw     /rigel/right/image_raw     image (mono8)
+//   /rigel/left/camera_info   /rigel/right/camera_info   calibration, one message per frame
+//   /rigel/stats              frames, dropped frames and frame rate, once per second of capture
+//
+// Images and calibrations use ROS 2 message definitions ("ros2msg" schemas, CDR encoded): sensor_msgs Image / CameraInfo, or foxglove_msgs RawImage / CameraCalibration. Messages are written in zstd-compressed chunks with message indexes and a summary section, so files open quickly and seek well. Record layouts follow the MCAP specification, https://mcap.dev/spec.
+
+use std::{collections::BTreeMap, fs::File, io::{self, BufWriter, Write}, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::*;
+
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+const OP_STATISTICS: u8 = 0x0B;
+const OP_SUMMARY_OFFSET: u8 = 0x0E;
+const OP_DATA_END: u8 = 0x0F;
+
+// Chunks are closed once their uncompressed records reach this size.
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+const IMAGE_SCHEMA_ID: u16 = 1;
+const CALIBRATION_SCHEMA_ID: u16 = 2;
+const STATS_SCHEMA_ID: u16 = 3;
+
+const LEFT_IMAGE_CHANNEL: u16 = 1;
+const RIGHT_IMAGE_CHANNEL: u16 = 2;
+const LEFT_INFO_CHANNEL: u16 = 3;
+const RIGHT_INFO_CHANNEL: u16 = 4;
+const STATS_CHANNEL: u16 = 5;
+
+const ROS_TIME_DEFINITION: &str = "MSG: builtin_interfaces/Time\nint32 sec\nuint32 nanosec\n";
+const ROS_HEADER_DEFINITION: &str = "MSG: std_msgs/Header\nbuiltin_interfaces/Time stamp\nstring frame_id\n";
+const DEFINITION_SEPARATOR: &str = "================================================================================\n";
+
+/// Message definitions used for the image and calibration channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McapSchema {
+  /// sensor_msgs/msg/Image and sensor_msgs/msg/CameraInfo, for ROS 2 tooling.
+  Ros2,
+  /// foxglove_msgs/msg/RawImage and foxglove_msgs/msg/CameraCalibration.
+  Foxglove
+}
+
+impl McapSchema {
+  fn image_schema(&self) -> (&'static str, String) {
+    match self {
+      McapSchema::Ros2 => ("sensor_msgs/msg/Image", format!(
+        "std_msgs/Header header\nuint32 height\nuint32 width\nstring encoding\nuint8 is_bigendian\nuint32 step\nuint8[] data\n{}{}{}{}",
+        DEFINITION_SEPARATOR, ROS_HEADER_DEFINITION, DEFINITION_SEPARATOR, ROS_TIME_DEFINITION
+      )),
+      McapSchema::Foxglove => ("foxglove_msgs/msg/RawImage", format!(
+        "builtin_interfaces/Time timestamp\nstring frame_id\nuint32 width\nuint32 height\nstring encoding\nuint32 step\nuint8[] data\n{}{}",
+        DEFINITION_SEPARATOR, ROS_TIME_DEFINITION
+      ))
+    }
+  }
+
+  fn calibration_schema(&self) -> (&'static str, String) {
+    match self {
+      McapSchema::Ros2 => ("sensor_msgs/msg/CameraInfo", format!(
+        "std_msgs/Header header\nuint32 height\nuint32 width\nstring distortion_model\nfloat64[] d\nfloat64[9] k\nfloat64[9] r\nfloat64[12] p\nuint32 binning_x\nuint32 binning_y\nsensor_msgs/RegionOfInterest roi\n{}{}{}{}{}{}",
+        DEFINITION_SEPARATOR, ROS_HEADER_DEFINITION, DEFINITION_SEPARATOR, ROS_TIME_DEFINITION, DEFINITION_SEPARATOR,
+        "MSG: sensor_msgs/RegionOfInterest\nuint32 x_offset\nuint32 y_offset\nuint32 height\nuint32 width\nbool do_rectify\n"
+      )),
+      McapSchema::Foxglove => ("foxglove_msgs/msg/CameraCalibration", format!(
+        "builtin_interfaces/Time timestamp\nstring frame_id\nuint32 width\nuint32 height\nstring distortion_model\nfloat64[] d\nfloat64[9] k\nfloat64[9] r\nfloat64[12] p\n{}{}",
+        DEFINITION_SEPARATOR, ROS_TIME_DEFINITION
+      ))
+    }
+  }
+}
+
+const STATS_JSON_SCHEMA: &str = r#"{"type":"object","properties":{"frames":{"type":"integer"},"dropped":{"type":"integer"},"fps":{"type":"number"}}}"#;
+
+// Little-endian MCAP record fields.
+struct Fields(Vec<u8>);
+
+impl Fields {
+  fn new() -> Self { Self(Vec::new()) }
+  fn u8(&mut self, v: u8) -> &mut Self { self.0.push(v); self }
+  fn u16(&mut self, v: u16) -> &mut Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+  fn u32(&mut self, v: u32) -> &mut Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+  fn u64(&mut self, v: u64) -> &mut Self { self.0.extend_from_slice(&v.to_le_bytes()); self }
+  fn str(&mut self, s: &str) -> &mut Self { self.bytes(s.as_bytes()) }
+  fn bytes(&mut self, b: &[u8]) -> &mut Self { self.u32(b.len() as u32); self.0.extend_from_slice(b); self }
+  fn raw(&mut self, b: &[u8]) -> &mut Self { self.0.extend_from_slice(b); self }
+
+  fn record(&self, opcode: u8) -> Vec<u8> {
+    let mut record = Vec::with_capacity(9 + self.0.len());
+    record.push(opcode);
+    record.extend_from_slice(&(self.0.len() as u64).to_le_bytes());
+    record.extend_from_slice(&self.0);
+    record
+  }
+}
+
+// Little-endian CDR, as ROS 2 serializes messages. Alignment is relative to the end of the 4-byte encapsulation header.
+struct Cdr(Vec<u8>);
+
+impl Cdr {
+  fn new() -> Self { Self(vec![0x00, 0x01, 0x00, 0x00]) }
+  fn align(&mut self, n: usize) { while (self.0.len() - 4) % n != 0 { self.0.push(0); } }
+  fn u8(&mut self, v: u8) { self.0.push(v); }
+  fn u32(&mut self, v: u32) { self.align(4); self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn i32(&mut self, v: i32) { self.align(4); self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn f64(&mut self, v: f64) { self.align(8); self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn str(&mut self, s: &str) { self.u32(s.len() as u32 + 1); self.0.extend_from_slice(s.as_bytes()); self.0.push(0); }
+  fn f64s(&mut self, values: &[f64]) { for v in values { self.f64(*v); } }
+  fn f64_seq(&mut self, values: &[f64]) { self.u32(values.len() as u32); self.f64s(values); }
+  fn time(&mut self, nanos: u64) { self.i32((nanos / 1_000_000_000) as i32); self.u32((nanos % 1_000_000_000) as u32); }
+  fn bytes(&mut self, b: &[u8]) { self.u32(b.len() as u32); self.0.extend_from_slice(b); }
+}
+
+/// Writes frames to an MCAP file. A FrameSink, so it can be attached to a Rigel; `write_archive` converts a recording.
+pub struct McapWriter<W: Write + Send> {
+  writer: Option<W>,
+  position: u64,
+  schema: McapSchema,
+  calibration: Option<StereoCalibration>,
+  clock: FrameClock,
+  // Schema and channel records with their counts, repeated in the summary.
+  schemas: (Vec<u8>, u16),
+  channels: (Vec<u8>, u32),
+  finished: bool,
+  chunk: Vec<u8>,
+  chunk_times: Option<(u64, u64)>,
+  chunk_message_indexes: BTreeMap<u16, Vec<(u64, u64)>>,
+  chunk_indexes: Vec<Vec<u8>>,
+  channel_counts: BTreeMap<u16, u64>,
+  channel_sequences: BTreeMap<u16, u32>,
+  message_times: Option<(u64, u64)>,
+  compressor: zstd::bulk::Compressor<'static>,
+  // Capture stats: frames written, sequence-gap drops, last sequence, and the start of the current stats window (timestamp, frames).
+  frames: u64,
+  dropped: u64,
+  last_sequence: Option<u64>,
+  stats_window: Option<(Duration, u64)>
+}
+
+fn io_error(err: io::Error) -> Error { Error::new(format!("Failed to write MCAP: {}", err)) }
+
+impl McapWriter<BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(path: P, schema: McapSchema, calibration: Option<StereoCalibration>) -> Result<Self> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|err| Error::new(format!("Failed to create {}: {}", path.display(), err)))?;
+    Self::new(BufWriter::new(file), schema, calibration)
+  }
+}
+
+impl<W: Write + Send> McapWriter<W> {
+  /// Starts an MCAP stream on `writer`, writing its header, schemas and channels. The camera_info channels are only present with a calibration.
+  pub fn new(writer: W, schema: McapSchema, calibration: Option<StereoCalibration>) -> Result<Self> {
+    let mut mcap = Self {
+      writer: Some(writer),
+      position: 0,
+      schema,
+      calibration,
+      clock: FrameClock::new(),
+      schemas: (Vec::new(), 0),
+      channels: (Vec::new(), 0),
+      finished: false,
+      chunk: Vec::new(),
+      chunk_times: None,
+      chunk_message_indexes: BTreeMap::new(),
+      chunk_indexes: Vec::new(),
+      channel_counts: BTreeMap::new(),
+      channel_sequences: BTreeMap::new(),
+      message_times: None,
+      compressor: zstd::bulk::Compressor::new(3).map_err(io_error)?,
+      frames: 0,
+      dropped: 0,
+      last_sequence: None,
+      stats_window: None
+    };
+
+    mcap.write(MAGIC)?;
+    mcap.write(&Fields::new().str("ros2").str(concat!("tinyrigel ", env!("CARGO_PKG_VERSION"))).record(OP_HEADER))?;
+
+    let (image_name, image_definition) = schema.image_schema();
+    mcap.add_schema(IMAGE_SCHEMA_ID, image_name, "ros2msg", &image_definition)?;
+    mcap.add_channel(LEFT_IMAGE_CHANNEL, IMAGE_SCHEMA_ID, "/rigel/left/image_raw", "cdr")?;
+    mcap.add_channel(RIGHT_IMAGE_CHANNEL, IMAGE_SCHEMA_ID, "/rigel/right/image_raw", "cdr")?;
+    if mcap.calibration.is_some() {
+      let (calibration_name, calibration_definition) = schema.calibration_schema();
+      mcap.add_schema(CALIBRATION_SCHEMA_ID, calibration_name, "ros2msg", &calibration_definition)?;
+      mcap.add_channel(LEFT_INFO_CHANNEL, CALIBRATION_SCHEMA_ID, "/rigel/left/camera_info", "cdr")?;
+      mcap.add_channel(RIGHT_INFO_CHANNEL, CALIBRATION_SCHEMA_ID, "/rigel/right/camera_info", "cdr")?;
+    }
+    mcap.add_schema(STATS_SCHEMA_ID, "tinyrigel.CaptureStats", "jsonschema", STATS_JSON_SCHEMA)?;
+    mcap.add_channel(STATS_CHANNEL, STATS_SCHEMA_ID, "/rigel/stats", "json")?;
+    Ok(mcap)
+  }
+
+  /// Anchors message times: the first frame written is stamped `start`, later frames by their timestamp offset from it. Defaults to the wall-clock time the first frame is written, which suits live capture; set it to e.g. a recording's start time when converting.
+  pub fn set_time_base(&mut self, start: SystemTime) { self.clock.set_start(start); }
+
+  /// Writes every frame of `archive`, e.g. a `RecordingReader`. Returns the number of frames written.
+  pub fn write_archive(&mut self, archive: &mut dyn FrameArchive) -> Result<usize> {
+    for index in 0..archive.frame_count() {
+      self.write_frame(&archive.read_frame(index)?)?;
+    }
+    Ok(archive.frame_count())
+  }
+
+  /// Finishes the file if that hasn't happened yet and returns the underlying writer.
+  pub fn into_inner(mut self) -> Result<W> {
+    if !self.finished {
+      self.finish()?;
+    }
+    Ok(self.writer.take().unwrap())
+  }
+
+  fn write(&mut self, bytes: &[u8]) -> Result<()> {
+    let writer = self.writer.as_mut().unwrap();
+    writer.write_all(bytes).map_err(io_error)?;
+    self.position += bytes.len() as u64;
+    Ok(())
+  }
+
+  fn add_schema(&mut self, id: u16, name: &str, encoding: &str, definition: &str) -> Result<()> {
+    let record = Fields::new().u16(id).str(name).str(encoding).bytes(definition.as_bytes()).record(OP_SCHEMA);
+    self.write(&record)?;
+    self.schemas.0.extend(record);
+    self.schemas.1 += 1;
+    Ok(())
+  }
+
+  fn add_channel(&mut self, id: u16, schema_id: u16, topic: &str, encoding: &str) -> Result<()> {
+    // No channel metadata: an empty map.
+    let record = Fields::new().u16(id).u16(schema_id).str(topic).str(encoding).u32(0).record(OP_CHANNEL);
+    self.write(&record)?;
+    self.channels.0.extend(record);
+    self.channels.1 += 1;
+    Ok(())
+  }
+
+  // Unix time in nanoseconds for a frame timestamp.
+  fn stamp(&mut self, timestamp: Duration) -> u64 {
+    self.clock.stamp(timestamp).duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+  }
+
+  fn add_message(&mut self, channel: u16, log_time: u64, data: &[u8]) -> Result<()> {
+    let sequence = self.channel_sequences.entry(channel).or_insert(0);
+    *sequence += 1;
+    let record = Fields::new().u16(channel).u32(*sequence).u64(log_time).u64(log_time).raw(data).record(OP_MESSAGE);
+
+    self.chunk_message_indexes.entry(channel).or_default().push((log_time, self.chunk.len() as u64));
+    self.chunk.extend_from_slice(&record);
+    self.chunk_times = Some(self.chunk_times.map_or((log_time, log_time), |(start, end)| (start.min(log_time), end.max(log_time))));
+    self.message_times = Some(self.message_times.map_or((log_time, log_time), |(start, end)| (start.min(log_time), end.max(log_time))));
+    *self.channel_counts.entry(channel).or_insert(0) += 1;
+
+    if self.chunk.len() >= CHUNK_SIZE {
+      self.flush_chunk()?;
+    }
+    Ok(())
+  }
+
+  // Writes the open chunk, its message indexes, and remembers a chunk index for the summary.
+  fn flush_chunk(&mut self) -> Result<()> {
+    let (start_time, end_time) = match self.chunk_times.take() {
+      Some(times) => times,
+      None => return Ok(())
+    };
+    let records = std::mem::take(&mut self.chunk);
+    let compressed = self.compressor.compress(&records).map_err(io_error)?;
+    let chunk = Fields::new()
+      .u64(start_time).u64(end_time).u64(records.len() as u64).u32(crc32fast::hash(&records)).str("zstd")
+      .u64(compressed.len() as u64).raw(&compressed)
+      .record(OP_CHUNK);
+    let chunk_start = self.position;
+    self.write(&chunk)?;
+
+    let message_index_start = self.position;
+    let mut message_index_offsets = Fields::new();
+    for (channel, entries) in std::mem::take(&mut self.chunk_message_indexes) {
+      let mut index = Fields::new();
+      index.u16(channel).u32(entries.len() as u32 * 16);
+      for (time, offset) in entries {
+        index.u64(time).u64(offset);
+      }
+      message_index_offsets.u16(channel).u64(self.position);
+      self.write(&index.record(OP_MESSAGE_INDEX))?;
+    }
+
+    self.chunk_indexes.push(Fields::new()
+      .u64(start_time).u64(end_time).u64(chunk_start).u64(chunk.len() as u64)
+      .bytes(&message_index_offsets.0).u64(self.position - message_index_start)
+      .str("zstd").u64(compressed.len() as u64).u64(records.len() as u64)
+      .record(OP_CHUNK_INDEX));
+    Ok(())
+  }
+
+  fn image_message(&self, frame: &Frame, eye: Eye, stamp: u64) -> Vec<u8> {
+    let layout = match eye { Eye::Left => EyeLayout::Left, Eye::Right => EyeLayout::Right };
+    let mut cdr = Cdr::new();
+    match self.schema {
+      McapSchema::Ros2 => {
+        cdr.time(stamp);
+        cdr.str(frame_id(eye));
+        cdr.u32(frame.height);
+        cdr.u32(frame.width);
+        cdr.str("mono8");
+        cdr.u8(0);
+        cdr.u32(frame.width);
+      }
+      McapSchema::Foxglove => {
+        cdr.time(stamp);
+        cdr.str(frame_id(eye));
+        cdr.u32(frame.width);
+        cdr.u32(frame.height);
+        cdr.str("mono8");
+        cdr.u32(frame.width);
+      }
+    }
+    cdr.bytes(&frame.layout_pixels(layout));
+    cdr.0
+  }
+
+  fn calibration_message(&self, calibration: &StereoCalibration, eye: Eye, stamp: u64) -> Vec<u8> {
+    let camera = calibration.camera(eye);
+    let (model, coefficients) = camera.ros_distortion();
+    let k: Vec<f64> = camera.camera_matrix().iter().flatten().copied().collect();
+    let r = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    let mut cdr = Cdr::new();
+    cdr.time(stamp);
+    cdr.str(frame_id(eye));
+    match self.schema {
+      McapSchema::Ros2 => { cdr.u32(camera.height); cdr.u32(camera.width); }
+      McapSchema::Foxglove => { cdr.u32(camera.width); cdr.u32(camera.height); }
+    }
+    cdr.str(model);
+    cdr.f64_seq(&coefficients);
+    cdr.f64s(&k);
+    cdr.f64s(&r);
+    cdr.f64s(&calibration.ros_projection(eye));
+    if self.schema == McapSchema::Ros2 {
+      // binning_x, binning_y, then an empty region of interest.
+      for _ in 0..6 { cdr.u32(0); }
+      cdr.u8(0);
+    }
+    cdr.0
+  }
+
+  fn update_stats(&mut self, frame: &Frame, stamp: u64) -> Result<()> {
+    if let Some(last) = self.last_sequence {
+      self.dropped += frame.sequence.saturating_sub(last + 1);
+    }
+    self.last_sequence = Some(frame.sequence);
+    self.frames += 1;
+
+    let (window_start, window_frames) = *self.stats_window.get_or_insert((frame.timestamp, self.frames));
+    let elapsed = frame.timestamp.saturating_sub(window_start);
+    if elapsed >= STATS_INTERVAL {
+      let fps = (self.frames - window_frames) as f64 / elapsed.as_secs_f64();
+      let json = format!("{{\"frames\":{},\"dropped\":{},\"fps\":{:.2}}}", self.frames, self.dropped, fps);
+      self.add_message(STATS_CHANNEL, stamp, json.as_bytes())?;
+      self.stats_window = Some((frame.timestamp, self.frames));
+    }
+    Ok(())
+  }
+}
+
+fn frame_id(eye: Eye) -> &'static str {
+  match eye { Eye::Left => "rigel_left", Eye::Right => "rigel_right" }
+}
+
+impl<W: Write + Send> FrameSink for McapWriter<W> {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    if self.finished {
+      return Err(Error::new("The MCAP file has already been finished.".to_string()));
+    }
+    let stamp = self.stamp(frame.timestamp);
+    for (eye, channel) in [(Eye::Left, LEFT_IMAGE_CHANNEL), (Eye::Right, RIGHT_IMAGE_CHANNEL)].iter().copied() {
+      let message = self.image_message(frame, eye, stamp);
+      self.add_message(channel, stamp, &message)?;
+    }
+    if let Some(calibration) = self.calibration.clone() {
+      for (eye, channel) in [(Eye::Left, LEFT_INFO_CHANNEL), (Eye::Right, RIGHT_INFO_CHANNEL)].iter().copied() {
+        let message = self.calibration_message(&calibration, eye, stamp);
+        self.add_message(channel, stamp, &message)?;
+      }
+    }
+    self.update_stats(frame, stamp)
+  }
+
+  /// Writes the last chunk and the summary section. The file is incomplete (though still readable by tolerant tools) until this is called.
+  fn finish(&mut self) -> Result<()> {
+    if self.finished {
+      return Ok(());
+    }
+    self.finished = true;
+    self.flush_chunk()?;
+    self.write(&Fields::new().u32(0).record(OP_DATA_END))?;
+
+    // Summary section: one group of records per opcode, each located by a summary offset record.
+    let summary_start = self.position;
+    let (start_time, end_time) = self.message_times.unwrap_or((0, 0));
+    let mut channel_counts = Fields::new();
+    for (channel, count) in &self.channel_counts {
+      channel_counts.u16(*channel).u64(*count);
+    }
+    let statistics = Fields::new()
+      .u64(self.channel_counts.values().sum()).u16(self.schemas.1).u32(self.channels.1).u32(0).u32(0).u32(self.chunk_indexes.len() as u32)
+      .u64(start_time).u64(end_time).bytes(&channel_counts.0)
+      .record(OP_STATISTICS);
+    let groups = [
+      (OP_SCHEMA, std::mem::take(&mut self.schemas.0)),
+      (OP_CHANNEL, std::mem::take(&mut self.channels.0)),
+      (OP_STATISTICS, statistics),
+      (OP_CHUNK_INDEX, std::mem::take(&mut self.chunk_indexes).concat())
+    ];
+    let mut offsets = Vec::new();
+    for (opcode, group) in groups.iter() {
+      if !group.is_empty() {
+        offsets.extend(Fields::new().u8(*opcode).u64(self.position).u64(group.len() as u64).record(OP_SUMMARY_OFFSET));
+        self.write(group)?;
+      }
+    }
+    let summary_offset_start = self.position;
+    self.write(&offsets)?;
+
+    // A zero summary CRC means "not computed".
+    self.write(&Fields::new().u64(summary_start).u64(summary_offset_start).u32(0).record(OP_FOOTER))?;
+    self.write(MAGIC)?;
+    self.writer.as_mut().unwrap().flush().map_err(io_error)
+  }
+}
+
+impl<W: Write + Send> Drop for McapWriter<W> {
+  fn drop(&mut self) {
+    if !self.finished && self.writer.is_some() {
+      let _ = self.finish();
+    }
+  }
+}
@@ -0,0 +1,198 @@
+// mock.rs - tinyrigel
+//
+// A synthetic source that behaves like a Rigel without any hardware attached. Used by tests and CI to exercise capture code paths.
+
+use std::{collections::HashMap, thread, time::{Duration, Instant}};
+
+use crate::*;
+
+const MOCK_MODES: [CaptureMode; 3] = [
+  CaptureMode::RIGEL_DEFAULT,
+  CaptureMode { width: RIGEL_EYE_WIDTH, height: RIGEL_EYE_HEIGHT, fps: 60 },
+  CaptureMode { width: RIGEL_EYE_WIDTH, height: RIGEL_EYE_HEIGHT, fps: 30 }
+];
+
+pub struct MockSource {
+  info: DeviceInfo,
+  mode: CaptureMode,
+  realtime: bool,
+  frame_limit: Option<u64>,
+  controls: HashMap<Control, i64>,
+  streaming: bool,
+  sequence: u64,
+  stream_start: Option<Instant>
+}
+
+impl MockSource {
+  /// A mock Rigel producing 384x384 stereo frames at 90 fps.
+  pub fn new() -> Self {
+    Self::with_mode(RIGEL_EYE_WIDTH, RIGEL_EYE_HEIGHT, 90)
+  }
+
+  pub fn with_mode(width: u32, height: u32, fps: u32) -> Self {
+    let mut controls = HashMap::new();
+    for info in mock_controls() {
+      controls.insert(info.control, info.default);
+    }
+    Self {
+      info: DeviceInfo { name: "Mock Rigel".to_string(), path: "mock:0".to_string(), serial: Some("MOCK0000".to_string()) },
+      mode: CaptureMode { width, height, fps },
+      realtime: true,
+      frame_limit: None,
+      controls,
+      streaming: false,
+      sequence: 0,
+      stream_start: None
+    }
+  }
+
+  /// When realtime is enabled (the default), `next_frame` paces delivery to the configured frame rate. Disable it to produce frames as fast as they're requested, e.g. in tests.
+  pub fn set_realtime(&mut self, realtime: bool) { self.realtime = realtime; }
+
+  /// After `limit` frames, the source reports a disconnect, like a device that was unplugged mid-stream.
+  pub fn set_frame_limit(&mut self, limit: Option<u64>) { self.frame_limit = limit; }
+
+  pub fn frame_interval(&self) -> Duration { Duration::from_secs(1) / self.mode.fps.max(1) }
+
+  fn render(&self, sequence: u64) -> Vec<u8> {
+    // A diagonal gradient that scrolls one pixel per frame, offset between eyes so left and right are distinguishable.
+    let width = self.mode.width as usize;
+    let stride = width * 2;
+    let mut data = vec![0u8; stride * self.mode.height as usize];
+    for (y, row) in data.chunks_exact_mut(stride).enumerate() {
+      for (x, px) in row.iter_mut().enumerate() {
+        let eye_x = x % width;
+        let eye_offset = if x < width { 0 } else { 64 };
+        *px = ((eye_x + y + sequence as usize + eye_offset) & 0xFF) as u8;
+      }
+    }
+    data
+  }
+}
+
+impl Default for MockSource {
+  fn default() -> Self { Self::new() }
+}
+
+fn mock_controls() -> Vec<ControlInfo> {
+  vec![
+    ControlInfo { control: Control::Exposure, name: "Exposure".to_string(), min: 10, max: 10000, step: 1, default: 1000 },
+    ControlInfo { control: Control::Gain, name: "Gain".to_string(), min: 0, max: 79, step: 1, default: 16 }
+  ]
+}
+
+impl DeviceSource for MockSource {
+  fn info(&self) -> DeviceInfo { self.info.clone() }
+
+  fn modes(&self) -> Result<Vec<CaptureMode>> { Ok(MOCK_MODES.to_vec()) }
+
+  fn mode(&self) -> CaptureMode { self.mode }
+
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> {
+    if self.streaming {
+      return Err(Error::new("Cannot configure MockSource while it is streaming.".to_string()));
+    }
+    self.mode = mode;
+    Ok(mode)
+  }
+
+  fn start(&mut self) -> Result<()> {
+    self.streaming = true;
+    self.sequence = 0;
+    self.stream_start = Some(Instant::now());
+    Ok(())
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    self.streaming = false;
+    self.stream_start = None;
+    Ok(())
+  }
+
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+    if !self.streaming {
+      return Err(Error::new("MockSource is not streaming; call start() first.".to_string()));
+    }
+    if let Some(limit) = self.frame_limit {
+      if self.sequence >= limit {
+        return Err(Error::disconnected(format!("MockSource disconnected after its frame limit of {} frames.", limit)));
+      }
+    }
+
+    let timestamp = self.frame_interval() * self.sequence as u32;
+    if self.realtime {
+      let due = self.stream_start.unwrap() + timestamp;
+      let now = Instant::now();
+      if due > now {
+        let wait = due - now;
+        if wait > timeout {
+          thread::sleep(timeout);
+          return Err(Error::timeout("Timed out waiting for the next MockSource frame.".to_string()));
+        }
+        thread::sleep(wait);
+      }
+    }
+
+    let frame = Frame::new(self.mode.width, self.mode.height, self.render(self.sequence), self.sequence, timestamp);
+    self.sequence += 1;
+    Ok(frame)
+  }
+
+  fn controls(&self) -> Result<Vec<ControlInfo>> { Ok(mock_controls()) }
+
+  fn control(&self, control: Control) -> Result<i64> {
+    self.controls.get(&control).copied()
+      .ok_or_else(|| Error::new(format!("Control {:?} is not supported by {}.", control, self.info.name)))
+  }
+
+  fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+    let info = mock_controls().into_iter().find(|c| c.control == control)
+      .ok_or_else(|| Error::new(format!("Control {:?} is not supported by {}.", control, self.info.name)))?;
+    if value < info.min || value > info.max {
+      return Err(Error::new(format!("Value {} for {} is out of range [{}, {}].", value, info.name, info.min, info.max)));
+    }
+    self.controls.insert(control, value);
+    Ok(())
+  }
+}
+
+/// A Backend exposing a configurable number of MockSource devices.
+pub struct MockBackend {
+  device_count: usize,
+  realtime: bool
+}
+
+impl MockBackend {
+  pub fn new() -> Self { Self { device_count: 1, realtime: true } }
+
+  pub fn set_device_count(&mut self, count: usize) { self.device_count = count; }
+
+  /// Applies `MockSource::set_realtime` to every source this backend opens.
+  pub fn set_realtime(&mut self, realtime: bool) { self.realtime = realtime; }
+}
+
+impl Default for MockBackend {
+  fn default() -> Self { Self::new() }
+}
+
+impl Backend for MockBackend {
+  fn name(&self) -> &str { "mock" }
+
+  fn enumerate(&self) -> Result<Vec<DeviceInfo>> {
+    Ok((0..self.device_count).map(|idx| DeviceInfo {
+      name: "Mock Rigel".to_string(),
+      path: format!("mock:{}", idx),
+      serial: Some(format!("MOCK{:04}", idx))
+    }).collect())
+  }
+
+  fn open(&self, device: &DeviceInfo) -> Result<Box<dyn DeviceSource>> {
+    if !self.enumerate()?.contains(device) {
+      return Err(Error::new(format!("No mock device at {}.", device.path)));
+    }
+    let mut source = MockSource::new();
+    source.info = device.clone();
+    source.set_realtime(self.realtime);
+    Ok(Box::new(source))
+  }
+}
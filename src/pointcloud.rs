@@ -0,0 +1,179 @@
+// pointcloud.rs - tinyrigel
+//
+// Point clouds from depth maps, written as PLY (ASCII or binary) or PCD for MeshLab, CloudCompare and PCL. Points are in meters in the rectified left camera frame (x right, y down, z forward) and carry the IR intensity of the pixel they were seen at.
+
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::{Path, PathBuf}};
+
+use crate::*;
+
+/// File format of a written point cloud.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointCloudFormat {
+  PlyAscii,
+  /// Little-endian binary PLY.
+  PlyBinary,
+  PcdAscii,
+  PcdBinary
+}
+
+impl PointCloudFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      PointCloudFormat::PlyAscii | PointCloudFormat::PlyBinary => "ply",
+      PointCloudFormat::PcdAscii | PointCloudFormat::PcdBinary => "pcd"
+    }
+  }
+}
+
+/// A point with its IR intensity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CloudPoint {
+  pub position: [f32; 3],
+  pub intensity: u8
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PointCloud {
+  pub points: Vec<CloudPoint>
+}
+
+impl PointCloud {
+  /// One point per pixel of `depth` that has a depth, with the intensity of the same pixel in the left eye of `frame`, the rectified frame the depth was computed from.
+  pub fn from_depth(depth: &DepthMap, frame: &Frame) -> Result<Self> {
+    if (frame.width, frame.height) != (depth.width, depth.height) || !frame.is_complete() {
+      return Err(Error::new(format!(
+        "The depth map is {}x{}, but frame {} is {}x{} per eye{}.",
+        depth.width, depth.height, frame.sequence, frame.width, frame.height, if frame.is_complete() { "" } else { " and incomplete" }
+      )));
+    }
+    let mut points = Vec::new();
+    for y in 0..depth.height {
+      for x in 0..depth.width {
+        if let Some(position) = depth.point(x, y) {
+          points.push(CloudPoint { position, intensity: frame.data[y as usize * frame.stride() + x as usize] });
+        }
+      }
+    }
+    Ok(Self { points })
+  }
+
+  /// Keeps only the points with a depth (z) in `min..=max` meters.
+  pub fn crop_depth(&mut self, min: f32, max: f32) {
+    self.points.retain(|point| point.position[2] >= min && point.position[2] <= max);
+  }
+
+  /// Writes the cloud to `writer` in the given format.
+  pub fn write<W: Write>(&self, writer: W, format: PointCloudFormat) -> Result<()> {
+    self.write_points(writer, format).map_err(|err| Error::new(format!("Failed to write point cloud: {}", err)))
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P, format: PointCloudFormat) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|err| Error::new(format!("Failed to create {}: {}", path.display(), err)))?;
+    self.write(BufWriter::new(file), format)
+  }
+
+  fn write_points<W: Write>(&self, mut writer: W, format: PointCloudFormat) -> std::io::Result<()> {
+    let count = self.points.len();
+    match format {
+      PointCloudFormat::PlyAscii | PointCloudFormat::PlyBinary => {
+        let encoding = if format == PointCloudFormat::PlyAscii { "ascii" } else { "binary_little_endian" };
+        write!(writer, "ply\nformat {} 1.0\ncomment tinyrigel point cloud, meters\nelement vertex {}\n", encoding, count)?;
+        writer.write_all(b"property float x\nproperty float y\nproperty float z\nproperty uchar intensity\nend_header\n")?;
+      }
+      PointCloudFormat::PcdAscii | PointCloudFormat::PcdBinary => {
+        let encoding = if format == PointCloudFormat::PcdAscii { "ascii" } else { "binary" };
+        write!(writer, "# .PCD v0.7 - Point Cloud Data file format\nVERSION 0.7\nFIELDS x y z intensity\nSIZE 4 4 4 4\nTYPE F F F F\nCOUNT 1 1 1 1\n")?;
+        write!(writer, "WIDTH {}\nHEIGHT 1\nVIEWPOINT 0 0 0 1 0 0 0\nPOINTS {}\nDATA {}\n", count, count, encoding)?;
+      }
+    }
+    for point in &self.points {
+      let [x, y, z] = point.position;
+      match format {
+        PointCloudFormat::PlyAscii | PointCloudFormat::PcdAscii => writeln!(writer, "{} {} {} {}", x, y, z, point.intensity)?,
+        PointCloudFormat::PlyBinary => {
+          for value in &point.position { writer.write_all(&value.to_le_bytes())?; }
+          writer.write_all(&[point.intensity])?;
+        }
+        // PCL reads intensity as a float.
+        PointCloudFormat::PcdBinary => {
+          for value in &[x, y, z, point.intensity as f32] { writer.write_all(&value.to_le_bytes())?; }
+        }
+      }
+    }
+    writer.flush()
+  }
+}
+
+/// Writes a point cloud file per frame to a directory, named by sequence number (`00000042.ply`). A FrameSink: each raw frame is rectified, matched and triangulated on the calling thread, which takes far longer than a frame period, so live use should keep `set_interval` high.
+pub struct PointCloudExporter {
+  dir: PathBuf,
+  format: PointCloudFormat,
+  rectifier: StereoRectifier,
+  matcher: StereoMatcher,
+  depth_range: Option<(f32, f32)>,
+  interval: u64,
+  created: bool,
+  rectified: Frame
+}
+
+impl PointCloudExporter {
+  pub fn new<P: AsRef<Path>>(dir: P, format: PointCloudFormat, calibration: &StereoCalibration, matcher: StereoMatcher) -> Self {
+    Self {
+      dir: dir.as_ref().to_path_buf(),
+      format,
+      rectifier: StereoRectifier::new(calibration),
+      matcher,
+      depth_range: None,
+      interval: 1,
+      created: false,
+      rectified: Frame::new(0, 0, Vec::new(), 0, Default::default())
+    }
+  }
+
+  /// Crops every cloud to depths in `min..=max` meters, e.g. `Some((0.1, 0.6))` for hand range. Defaults to no cropping.
+  pub fn set_depth_range(&mut self, range: Option<(f32, f32)>) { self.depth_range = range; }
+
+  /// Only exports frames whose sequence number is a multiple of `interval`. Defaults to 1, every frame.
+  pub fn set_interval(&mut self, interval: u64) { self.interval = interval.max(1); }
+
+  /// Rectifies `frame`, computes its depth and returns the (cropped) point cloud, without writing it.
+  pub fn point_cloud(&mut self, frame: &Frame) -> Result<PointCloud> {
+    self.rectifier.rectify_into(frame, &mut self.rectified)?;
+    let depth = self.matcher.compute(&self.rectified)?.to_depth(&self.rectifier);
+    let mut cloud = PointCloud::from_depth(&depth, &self.rectified)?;
+    if let Some((min, max)) = self.depth_range {
+      cloud.crop_depth(min, max);
+    }
+    Ok(cloud)
+  }
+
+  /// Exports the frames at `indices` of `archive`, e.g. a `RecordingReader`, regardless of the interval. Returns the number of files written.
+  pub fn export_archive(&mut self, archive: &mut dyn FrameArchive, indices: std::ops::Range<usize>) -> Result<usize> {
+    let mut written = 0;
+    for index in indices.start..indices.end.min(archive.frame_count()) {
+      let frame = archive.read_frame(index)?;
+      self.export(&frame)?;
+      written += 1;
+    }
+    Ok(written)
+  }
+
+  fn export(&mut self, frame: &Frame) -> Result<()> {
+    if !self.created {
+      fs::create_dir_all(&self.dir).map_err(|err| Error::new(format!("Failed to create {}: {}", self.dir.display(), err)))?;
+      self.created = true;
+    }
+    let cloud = self.point_cloud(frame)?;
+    cloud.save(self.dir.join(format!("{:08}.{}", frame.sequence, self.format.extension())), self.format)
+  }
+}
+
+impl FrameSink for PointCloudExporter {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    if frame.sequence % self.interval != 0 { return Ok(()); }
+    self.export(frame)
+  }
+
+  fn finish(&mut self) -> Result<()> { Ok(()) }
+}
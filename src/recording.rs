@@ -0,0 +1,714 @@
+// recording.rs - tinyrigel
+//
+// Native recording format for raw stereo sessions. A Recorder (a FrameSink, so it can be attached to a Rigel) writes frames as they arrive; a RecordingReader opens the result for random access, and ReplaySource plays it back.
+//
+// Frame records are self-delimiting and checksummed, so the seek index at the end is only an accelerator: if it never gets written, `recover` rebuilds it from the records.
+//
+// File layout (all integers little-endian):
+//
+//   File header
+//     magic          8 bytes   "TRGLREC\0"
+//     version        u16       3 (version 2 files, without the codec field, are still read)
+//     reserved       u16       0
+//     header_len     u32       length of the header body that follows
+//     header body:
+//       model        str       device name, e.g. "Leap Motion Controller (Rigel)"
+//       serial       opt<str>
+//       width        u32       per-eye width
+//       height       u32
+//       fps          u32
+//       start_time   u64       wall-clock start of the recording, nanoseconds since the Unix epoch
+//       calibration  opt<calib>
+//       codec        u8        0 raw, 1 lossless
+//
+//   Frame records, one per frame
+//     tag            4 bytes   "FRME"
+//     payload_len    u32       length of the payload, everything after the crc
+//     crc            u32       CRC-32 (IEEE) of the payload
+//     sequence       u64
+//     timestamp      u64       device capture timestamp, nanoseconds
+//     host_time      u64       host receive time, nanoseconds since start_time
+//     width          u32
+//     height         u32
+//     pixels         payload_len - 32 bytes: side-by-side 8-bit grayscale, or with the lossless codec an encoded frame (see codec.rs)
+//
+//   Seek index, written when the recording is finished (or by `recover`)
+//     tag            4 bytes   "INDX"
+//     count          u64
+//     entries        count x (offset u64, sequence u64, timestamp u64), offset of each frame record from the start of the file
+//
+//   Footer
+//     index_offset   u64       offset of the seek index
+//     magic          8 bytes   "TRGLEND\0"
+//
+// Strings are a u16 byte length followed by UTF-8. An opt<T> is a u8 presence flag (0 or 1) followed by T if present. A calib is the left then right camera (width u32, height u32, fx, fy, cx, cy f64, distortion model u8 [0 none, 1 Brown-Conrady, 2 equidistant], coefficient count u8, coefficients f64), then the rotation (9 f64, row-major) and translation (3 f64, meters).
+
+use std::{fs::{File, OpenOptions}, io::{self, BufReader, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use crate::*;
+
+const FILE_MAGIC: &[u8; 8] = b"TRGLREC\0";
+const FOOTER_MAGIC: &[u8; 8] = b"TRGLEND\0";
+const FRAME_TAG: &[u8; 4] = b"FRME";
+const INDEX_TAG: &[u8; 4] = b"INDX";
+const FORMAT_VERSION: u16 = 3;
+const OLDEST_FORMAT_VERSION: u16 = 2;
+const FRAME_PREFIX_LEN: usize = 12;
+const FRAME_FIELDS_LEN: u32 = 32;
+const FOOTER_LEN: u64 = 16;
+
+/// Describes a recorded session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingHeader {
+  pub model: String,
+  pub serial: Option<String>,
+  pub mode: CaptureMode,
+  pub start_time: SystemTime,
+  pub calibration: Option<StereoCalibration>,
+  pub codec: Codec
+}
+
+impl RecordingHeader {
+  /// A header for a raw recording starting now, without calibration.
+  pub fn new(info: &DeviceInfo, mode: CaptureMode) -> Self {
+    Self { model: info.name.clone(), serial: info.serial.clone(), mode, start_time: SystemTime::now(), calibration: None, codec: Codec::Raw }
+  }
+
+  /// A header for a raw recording of `rigel` starting now, carrying the calibration the Rigel found for itself, if any.
+  pub fn for_rigel<Cb>(rigel: &Rigel<Cb>) -> Self
+  where Cb: Fn(&Frame) + Send + 'static
+  {
+    Self { calibration: rigel.calibration().cloned(), ..Self::new(&rigel.info(), rigel.mode()) }
+  }
+}
+
+/// Seek index entry for one recorded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+  /// Byte offset of the frame record in the file.
+  pub offset: u64,
+  pub sequence: u64,
+  pub timestamp: Duration
+}
+
+fn io_error(context: &str, err: io::Error) -> Error {
+  Error::new(format!("{}: {}", context, err))
+}
+
+fn duration_to_nanos(duration: Duration) -> u64 { duration.as_nanos() as u64 }
+
+// Encoding
+// ---
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+  fn u8(&mut self, v: u8) { self.0.push(v); }
+  fn u16(&mut self, v: u16) { self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn u32(&mut self, v: u32) { self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn u64(&mut self, v: u64) { self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn f64(&mut self, v: f64) { self.0.extend_from_slice(&v.to_le_bytes()); }
+
+  fn str(&mut self, s: &str) {
+    let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+    self.u16(bytes.len() as u16);
+    self.0.extend_from_slice(bytes);
+  }
+
+  fn intrinsics(&mut self, camera: &CameraIntrinsics) {
+    self.u32(camera.width);
+    self.u32(camera.height);
+    for v in [camera.fx, camera.fy, camera.cx, camera.cy].iter() { self.f64(*v); }
+    let (model, coefficients): (u8, &[f64]) = match &camera.distortion {
+      Distortion::None => (0, &[]),
+      Distortion::BrownConrady(k) => (1, k),
+      Distortion::Equidistant(k) => (2, k)
+    };
+    self.u8(model);
+    self.u8(coefficients.len() as u8);
+    for v in coefficients { self.f64(*v); }
+  }
+
+  fn calibration(&mut self, calibration: &StereoCalibration) {
+    self.intrinsics(&calibration.left);
+    self.intrinsics(&calibration.right);
+    for row in calibration.rotation.iter() {
+      for v in row { self.f64(*v); }
+    }
+    for v in calibration.translation.iter() { self.f64(*v); }
+  }
+
+  fn header(&mut self, header: &RecordingHeader) {
+    self.str(&header.model);
+    match &header.serial {
+      Some(serial) => { self.u8(1); self.str(serial); }
+      None => self.u8(0)
+    }
+    self.u32(header.mode.width);
+    self.u32(header.mode.height);
+    self.u32(header.mode.fps);
+    self.u64(duration_to_nanos(header.start_time.duration_since(UNIX_EPOCH).unwrap_or_default()));
+    match &header.calibration {
+      Some(calibration) => { self.u8(1); self.calibration(calibration); }
+      None => self.u8(0)
+    }
+    self.u8(match header.codec { Codec::Raw => 0, Codec::Lossless => 1 });
+  }
+}
+
+// Decoding
+// ---
+
+struct Decoder<'a> {
+  bytes: &'a [u8],
+  pos: usize
+}
+
+impl<'a> Decoder<'a> {
+  fn new(bytes: &'a [u8]) -> Self { Self { bytes, pos: 0 } }
+
+  fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+    if self.pos + len > self.bytes.len() {
+      return Err(Error::new("Recording header is truncated.".to_string()));
+    }
+    let slice = &self.bytes[self.pos..self.pos + len];
+    self.pos += len;
+    Ok(slice)
+  }
+
+  fn u8(&mut self) -> Result<u8> { Ok(self.take(1)?[0]) }
+  fn u16(&mut self) -> Result<u16> { let b = self.take(2)?; Ok(u16::from_le_bytes([b[0], b[1]])) }
+  fn u32(&mut self) -> Result<u32> { let mut b = [0u8; 4]; b.copy_from_slice(self.take(4)?); Ok(u32::from_le_bytes(b)) }
+  fn u64(&mut self) -> Result<u64> { let mut b = [0u8; 8]; b.copy_from_slice(self.take(8)?); Ok(u64::from_le_bytes(b)) }
+  fn f64(&mut self) -> Result<f64> { Ok(f64::from_bits(self.u64()?)) }
+
+  fn str(&mut self) -> Result<String> {
+    let len = self.u16()? as usize;
+    String::from_utf8(self.take(len)?.to_vec()).map_err(|_| Error::new("Recording header contains invalid UTF-8.".to_string()))
+  }
+
+  fn intrinsics(&mut self) -> Result<CameraIntrinsics> {
+    let (width, height) = (self.u32()?, self.u32()?);
+    let (fx, fy, cx, cy) = (self.f64()?, self.f64()?, self.f64()?, self.f64()?);
+    let model = self.u8()?;
+    let count = self.u8()? as usize;
+    let mut coefficients = Vec::with_capacity(count);
+    for _ in 0..count { coefficients.push(self.f64()?); }
+    let distortion = match (model, count) {
+      (0, 0) => Distortion::None,
+      (1, 5) => Distortion::BrownConrady([coefficients[0], coefficients[1], coefficients[2], coefficients[3], coefficients[4]]),
+      (2, 4) => Distortion::Equidistant([coefficients[0], coefficients[1], coefficients[2], coefficients[3]]),
+      _ => return Err(Error::new(format!("Unknown distortion model {} with {} coefficients.", model, count)))
+    };
+    Ok(CameraIntrinsics { width, height, fx, fy, cx, cy, distortion })
+  }
+
+  fn calibration(&mut self) -> Result<StereoCalibration> {
+    let left = self.intrinsics()?;
+    let right = self.intrinsics()?;
+    let mut rotation = [[0.0; 3]; 3];
+    for row in rotation.iter_mut() {
+      for v in row.iter_mut() { *v = self.f64()?; }
+    }
+    let mut translation = [0.0; 3];
+    for v in translation.iter_mut() { *v = self.f64()?; }
+    // The serial isn't stored with the calibration; `header` fills it in from the recording's.
+    Ok(StereoCalibration { left, right, rotation, translation, serial: None })
+  }
+
+  fn header(&mut self, version: u16) -> Result<RecordingHeader> {
+    let model = self.str()?;
+    let serial = if self.u8()? == 1 { Some(self.str()?) } else { None };
+    let mode = CaptureMode { width: self.u32()?, height: self.u32()?, fps: self.u32()? };
+    let start_time = UNIX_EPOCH + Duration::from_nanos(self.u64()?);
+    let calibration = if self.u8()? == 1 { Some(StereoCalibration { serial: serial.clone(), ..self.calibration()? }) } else { None };
+    let codec = match if version >= 3 { self.u8()? } else { 0 } {
+      0 => Codec::Raw,
+      1 => Codec::Lossless,
+      codec => return Err(Error::new(format!("Unknown recording codec {}.", codec)))
+    };
+    Ok(RecordingHeader { model, serial, mode, start_time, calibration, codec })
+  }
+}
+
+// Recorder
+// ---
+
+/// Writes a recording frame by frame. Attach it to a Rigel with `Rigel::attach_sink`, or call `write_frame` directly.
+///
+/// Every frame is handed to the OS as soon as it's written, so a crash of the recording process loses nothing but the seek index, which `recover` can rebuild. Use `set_sync_interval` to also survive power loss.
+pub struct Recorder {
+  path: PathBuf,
+  header: RecordingHeader,
+  segments: Vec<PathBuf>,
+  file: Option<File>,
+  offset: u64,
+  index: Vec<IndexEntry>,
+  started: Instant,
+  // Time from `started` to the start of the current segment.
+  segment_start: Duration,
+  max_segment_size: Option<u64>,
+  max_segment_duration: Option<Duration>,
+  sync_interval: Option<u32>,
+  unsynced: u32,
+  // Present for `Codec::Lossless` recordings.
+  encoder: Option<FrameEncoder>
+}
+
+impl Recorder {
+  pub fn create<P: AsRef<Path>>(path: P, header: &RecordingHeader) -> Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    let mut recorder = Self {
+      path: path.clone(),
+      header: header.clone(),
+      segments: Vec::new(),
+      file: None,
+      offset: 0,
+      index: Vec::new(),
+      started: Instant::now(),
+      segment_start: Duration::from_secs(0),
+      max_segment_size: None,
+      max_segment_duration: None,
+      sync_interval: None,
+      unsynced: 0,
+      encoder: match header.codec { Codec::Raw => None, Codec::Lossless => Some(FrameEncoder::new()?) }
+    };
+    recorder.start_segment(path)?;
+    Ok(recorder)
+  }
+
+  /// Path of the segment currently being written.
+  pub fn path(&self) -> &Path { self.segments.last().unwrap_or(&self.path) }
+
+  /// Every file written so far, in order. The first is the path passed to `create`; later segments insert `_001`, `_002`, ... before the extension.
+  pub fn segments(&self) -> &[PathBuf] { &self.segments }
+
+  /// Frames written to the current segment.
+  pub fn frame_count(&self) -> usize { self.index.len() }
+
+  /// Starts a new file once the current one would grow beyond `max_size` bytes or has been recording for `max_duration`. Each segment is a complete recording with its own header and index; a segment always holds at least one frame.
+  pub fn set_rotation(&mut self, max_size: Option<u64>, max_duration: Option<Duration>) {
+    self.max_segment_size = max_size;
+    self.max_segment_duration = max_duration;
+  }
+
+  /// Forces written frames to disk (fsync) every `frames` frames. Off by default, in which case frames reach the OS immediately but the disk only when it decides to flush.
+  pub fn set_sync_interval(&mut self, frames: Option<u32>) {
+    self.sync_interval = frames.map(|frames| frames.max(1));
+  }
+
+  fn start_segment(&mut self, path: PathBuf) -> Result<()> {
+    let mut file = File::create(&path).map_err(|err| io_error(&format!("Failed to create {}", path.display()), err))?;
+
+    // Each segment's start time is when that segment began, so host times stay relative to it.
+    self.segment_start = self.started.elapsed();
+    let mut header = self.header.clone();
+    header.start_time += self.segment_start;
+    let mut body = Encoder(Vec::new());
+    body.header(&header);
+    let mut preamble = Encoder(Vec::new());
+    preamble.0.extend_from_slice(FILE_MAGIC);
+    preamble.u16(FORMAT_VERSION);
+    preamble.u16(0);
+    preamble.u32(body.0.len() as u32);
+    preamble.0.extend_from_slice(&body.0);
+    file.write_all(&preamble.0).map_err(|err| io_error("Failed to write recording header", err))?;
+
+    // Each segment has to be readable on its own, so it starts with a keyframe.
+    if let Some(encoder) = &mut self.encoder { encoder.reset(); }
+    self.segments.push(path);
+    self.file = Some(file);
+    self.offset = preamble.0.len() as u64;
+    self.index.clear();
+    Ok(())
+  }
+
+  fn should_rotate(&self, record_len: u64) -> bool {
+    if self.index.is_empty() { return false; }
+    let too_big = self.max_segment_size.map(|max| self.offset + record_len + trailer_len(self.index.len() + 1) > max).unwrap_or(false);
+    let too_long = self.max_segment_duration.map(|max| self.started.elapsed() - self.segment_start >= max).unwrap_or(false);
+    too_big || too_long
+  }
+
+  fn next_segment_path(&self) -> PathBuf {
+    let stem = self.path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let name = match self.path.extension() {
+      Some(ext) => format!("{}_{:03}.{}", stem, self.segments.len(), ext.to_string_lossy()),
+      None => format!("{}_{:03}", stem, self.segments.len())
+    };
+    self.path.with_file_name(name)
+  }
+
+  fn frame_payload(&mut self, frame: &Frame) -> Result<Encoder> {
+    let mut payload = Encoder(Vec::with_capacity(FRAME_FIELDS_LEN as usize + frame.data.len()));
+    payload.u64(frame.sequence);
+    payload.u64(duration_to_nanos(frame.timestamp));
+    payload.u64(duration_to_nanos(self.started.elapsed().saturating_sub(self.segment_start)));
+    payload.u32(frame.width);
+    payload.u32(frame.height);
+    match &mut self.encoder {
+      Some(encoder) => payload.0.extend_from_slice(&encoder.encode(&frame.data, frame.stride())?),
+      None => payload.0.extend_from_slice(&frame.data)
+    }
+    Ok(payload)
+  }
+
+  fn finish_segment(&mut self) -> Result<()> {
+    let trailer = trailer(&self.index, self.offset);
+    let mut file = self.file.take().ok_or_else(|| Error::new("The recording has already been finished.".to_string()))?;
+    file.write_all(&trailer).map_err(|err| io_error("Failed to write recording index", err))?;
+    file.sync_all().map_err(|err| io_error("Failed to sync recording", err))?;
+    Ok(())
+  }
+}
+
+fn trailer_len(frame_count: usize) -> u64 { 12 + frame_count as u64 * 24 + FOOTER_LEN }
+
+// The seek index and footer, for a file whose frame records end at `index_offset`.
+fn trailer(index: &[IndexEntry], index_offset: u64) -> Vec<u8> {
+  let mut trailer = Encoder(Vec::with_capacity(trailer_len(index.len()) as usize));
+  trailer.0.extend_from_slice(INDEX_TAG);
+  trailer.u64(index.len() as u64);
+  for entry in index {
+    trailer.u64(entry.offset);
+    trailer.u64(entry.sequence);
+    trailer.u64(duration_to_nanos(entry.timestamp));
+  }
+  trailer.u64(index_offset);
+  trailer.0.extend_from_slice(FOOTER_MAGIC);
+  trailer.0
+}
+
+impl FrameSink for Recorder {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    if self.file.is_none() {
+      return Err(Error::new("The recording has already been finished.".to_string()));
+    }
+    let mut payload = self.frame_payload(frame)?;
+    let mut record_len = (FRAME_PREFIX_LEN + payload.0.len()) as u64;
+    if self.should_rotate(record_len) {
+      self.finish_segment()?;
+      let path = self.next_segment_path();
+      self.start_segment(path)?;
+      // The frame may have been encoded against the previous segment's last frame.
+      if self.encoder.is_some() {
+        payload = self.frame_payload(frame)?;
+        record_len = (FRAME_PREFIX_LEN + payload.0.len()) as u64;
+      }
+    }
+
+    let mut record = Encoder(Vec::with_capacity(record_len as usize));
+    record.0.extend_from_slice(FRAME_TAG);
+    record.u32(payload.0.len() as u32);
+    record.u32(crc32fast::hash(&payload.0));
+    record.0.extend_from_slice(&payload.0);
+
+    // One write per record, straight to the file, so a crash can only ever cut the last record short.
+    let file = self.file.as_mut().ok_or_else(|| Error::new("The recording has already been finished.".to_string()))?;
+    file.write_all(&record.0).map_err(|err| io_error("Failed to write frame", err))?;
+    self.index.push(IndexEntry { offset: self.offset, sequence: frame.sequence, timestamp: frame.timestamp });
+    self.offset += record_len;
+
+    if let Some(interval) = self.sync_interval {
+      self.unsynced += 1;
+      if self.unsynced >= interval {
+        file.sync_data().map_err(|err| io_error("Failed to sync recording", err))?;
+        self.unsynced = 0;
+      }
+    }
+    Ok(())
+  }
+
+  fn finish(&mut self) -> Result<()> { self.finish_segment() }
+}
+
+impl Drop for Recorder {
+  fn drop(&mut self) {
+    if self.file.is_some() {
+      let _ = self.finish_segment();
+    }
+  }
+}
+
+// Reader
+// ---
+
+/// Frames found by `recover`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryReport {
+  /// Complete, intact frames now in the index.
+  pub frames: usize,
+  /// Damaged records between intact frames, plus in lossless recordings the delta frames that followed one up to the next keyframe. They stay in the file but are left out of the index.
+  pub skipped_records: usize,
+  /// Bytes cut from the end of the file: a partially written record, or anything else after the last intact frame.
+  pub discarded_bytes: u64
+}
+
+/// Repairs a recording that was never finished, e.g. because the recording process crashed or the machine lost power. Scans the frame records, skipping any that are damaged, truncates whatever follows the last complete frame with a valid CRC and writes a fresh seek index, after which the file opens normally with `RecordingReader::open`. A recording that's already intact is left untouched.
+pub fn recover<P: AsRef<Path>>(path: P) -> Result<RecoveryReport> {
+  let path = path.as_ref();
+  if let Ok(reader) = RecordingReader::open(path) {
+    return Ok(RecoveryReport { frames: reader.index.len(), skipped_records: 0, discarded_bytes: 0 });
+  }
+
+  let mut file = OpenOptions::new().read(true).write(true).open(path)
+    .map_err(|err| io_error(&format!("Failed to open {}", path.display()), err))?;
+  let file_len = file.metadata().map_err(|err| io_error("Failed to stat recording", err))?.len();
+  let (_, index, end, skipped_records) = {
+    let mut reader = BufReader::new(&mut file);
+    scan(&mut reader, file_len)?
+  };
+
+  file.set_len(end).map_err(|err| io_error("Failed to truncate recording", err))?;
+  file.seek(SeekFrom::Start(end)).map_err(|err| io_error("Failed to seek recording", err))?;
+  file.write_all(&trailer(&index, end)).and_then(|_| file.sync_all()).map_err(|err| io_error("Failed to write recording index", err))?;
+
+  Ok(RecoveryReport { frames: index.len(), skipped_records, discarded_bytes: file_len - end })
+}
+
+// Reads the header, then walks the frame records from the start. A damaged record is skipped by searching on for the next record that checks out, so one flipped bit costs one frame; the search only comes up empty at the end of the file, where a record was cut short or something that isn't a frame (such as the seek index of a finished file) follows. Returns the header, the index of every good frame, the offset just past the last one and the number of damaged records skipped.
+fn scan<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<(RecordingHeader, Vec<IndexEntry>, u64, usize)> {
+  let header = read_header(reader, file_len)?;
+  let mut offset = reader.stream_position().map_err(|err| io_error("Failed to read recording", err))?;
+  let (mut index, mut end, mut skipped) = (Vec::new(), offset, 0);
+  // In a lossless recording, the delta frames after a skipped record have lost the frame they decode from.
+  let mut broken = false;
+  loop {
+    if let Some((entry, len, keyframe)) = record_at(reader, offset, file_len)? {
+      broken &= header.codec == Codec::Lossless && !keyframe;
+      if broken { skipped += 1; } else { index.push(entry); }
+      offset += len;
+      end = offset;
+      continue;
+    }
+    match next_record(reader, offset + 1, file_len)? {
+      Some(next) => {
+        skipped += 1;
+        broken = true;
+        offset = next;
+      }
+      None => break
+    }
+  }
+  Ok((header, index, end, skipped))
+}
+
+// Checks for an intact frame record at `offset`, returning its index entry, its length and whether its pixels are a lossless keyframe.
+fn record_at<R: Read + Seek>(reader: &mut R, offset: u64, file_len: u64) -> Result<Option<(IndexEntry, u64, bool)>> {
+  if offset + FRAME_PREFIX_LEN as u64 > file_len { return Ok(None); }
+  reader.seek(SeekFrom::Start(offset)).map_err(|err| io_error("Failed to read recording", err))?;
+  let mut prefix = [0u8; FRAME_PREFIX_LEN];
+  reader.read_exact(&mut prefix).map_err(|err| io_error("Failed to read recording", err))?;
+  let (payload_len, crc) = record_prefix(&prefix);
+  let len = FRAME_PREFIX_LEN as u64 + payload_len as u64;
+  if &prefix[..4] != FRAME_TAG || payload_len < FRAME_FIELDS_LEN || offset + len > file_len { return Ok(None); }
+  let mut payload = vec![0u8; payload_len as usize];
+  reader.read_exact(&mut payload).map_err(|err| io_error("Failed to read recording", err))?;
+  if crc32fast::hash(&payload) != crc { return Ok(None); }
+
+  let mut fields = Decoder::new(&payload);
+  let entry = IndexEntry { offset, sequence: fields.u64()?, timestamp: Duration::from_nanos(fields.u64()?) };
+  Ok(Some((entry, len, codec::is_keyframe(&payload[FRAME_FIELDS_LEN as usize..]))))
+}
+
+// The offset of the first intact frame record at or after `from`.
+fn next_record<R: Read + Seek>(reader: &mut R, from: u64, file_len: u64) -> Result<Option<u64>> {
+  let mut chunk = vec![0u8; 64 * 1024];
+  let mut start = from;
+  while start + FRAME_PREFIX_LEN as u64 <= file_len {
+    let len = chunk.len().min((file_len - start) as usize);
+    reader.seek(SeekFrom::Start(start)).and_then(|_| reader.read_exact(&mut chunk[..len])).map_err(|err| io_error("Failed to read recording", err))?;
+    let candidates: Vec<usize> = chunk[..len].windows(FRAME_TAG.len()).enumerate().filter(|(_, window)| window == FRAME_TAG).map(|(at, _)| at).collect();
+    for at in candidates {
+      if record_at(reader, start + at as u64, file_len)?.is_some() { return Ok(Some(start + at as u64)); }
+    }
+    // Chunks overlap by the tag length less one, so a tag split between two of them is still found.
+    start += (len - (FRAME_TAG.len() - 1)) as u64;
+  }
+  Ok(None)
+}
+
+// Payload length and CRC from a frame record's prefix.
+fn record_prefix(prefix: &[u8; FRAME_PREFIX_LEN]) -> (u32, u32) {
+  (u32::from_le_bytes([prefix[4], prefix[5], prefix[6], prefix[7]]), u32::from_le_bytes([prefix[8], prefix[9], prefix[10], prefix[11]]))
+}
+
+/// Opens a finished recording for random access. Also a FrameArchive, so `ReplaySource` can play it back.
+pub struct RecordingReader {
+  path: PathBuf,
+  reader: BufReader<File>,
+  file_len: u64,
+  header: RecordingHeader,
+  index: Vec<IndexEntry>,
+  // The last frame decoded from a lossless recording, so sequential reads only decode one frame each.
+  decoded: Option<(usize, Vec<u8>)>
+}
+
+impl RecordingReader {
+  /// Opens a finished recording. Fails if the seek index is missing; see `recover`.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    let file = File::open(&path).map_err(|err| io_error(&format!("Failed to open {}", path.display()), err))?;
+    let file_len = file.metadata().map_err(|err| io_error("Failed to stat recording", err))?.len();
+    let mut reader = BufReader::new(file);
+
+    let header = read_header(&mut reader, file_len)?;
+
+    if file_len < FOOTER_LEN {
+      return Err(Error::new(format!("{} is too short to be a recording.", path.display())));
+    }
+    let mut footer = [0u8; FOOTER_LEN as usize];
+    reader.seek(SeekFrom::Start(file_len - FOOTER_LEN)).and_then(|_| reader.read_exact(&mut footer))
+      .map_err(|err| io_error("Failed to read recording footer", err))?;
+    if &footer[8..] != FOOTER_MAGIC {
+      return Err(Error::new(format!("{} has no seek index; the recording was not finished. Use recover() to repair it.", path.display())));
+    }
+    let mut index_offset = [0u8; 8];
+    index_offset.copy_from_slice(&footer[..8]);
+    let index = read_index(&mut reader, u64::from_le_bytes(index_offset), file_len)?;
+
+    Ok(Self { path, reader, file_len, header, index, decoded: None })
+  }
+
+  pub fn path(&self) -> &Path { &self.path }
+
+  pub fn header(&self) -> &RecordingHeader { &self.header }
+
+  pub fn index(&self) -> &[IndexEntry] { &self.index }
+
+  /// Host receive time of frame `index`, relative to the recording's start time.
+  pub fn host_time(&mut self, index: usize) -> Result<Duration> {
+    Ok(self.read_record(index)?.1)
+  }
+
+  fn read_record(&mut self, index: usize) -> Result<(Frame, Duration)> {
+    let (mut frame, host_time) = self.read_stored(index)?;
+    if self.header.codec == Codec::Lossless {
+      let stride = frame.stride();
+      frame.data = self.decode_pixels(index, std::mem::take(&mut frame.data), stride)?;
+    }
+    Ok((frame, host_time))
+  }
+
+  // Decodes frame `index` from its stored pixels. Delta frames decode from the cached previous frame when possible, otherwise from the nearest keyframe before them.
+  fn decode_pixels(&mut self, index: usize, encoded: Vec<u8>, stride: usize) -> Result<Vec<u8>> {
+    let mut pending = vec![encoded];
+    let mut previous = None;
+    let mut first = index;
+    while !codec::is_keyframe(&pending[pending.len() - 1]) {
+      match self.decoded.take() {
+        Some((cached, data)) if cached + 1 == first => { previous = Some(data); break; }
+        other => self.decoded = other
+      }
+      if first == 0 {
+        return Err(Error::new("The recording doesn't start with a keyframe.".to_string()));
+      }
+      first -= 1;
+      pending.push(self.read_stored(first)?.0.data);
+    }
+
+    let mut data = previous;
+    for encoded in pending.iter().rev() {
+      data = Some(codec::decode(encoded, stride, data.as_deref())?);
+    }
+    let data = data.unwrap_or_default();
+    self.decoded = Some((index, data.clone()));
+    Ok(data)
+  }
+
+  // Reads and checks frame record `index`, leaving its pixels as stored.
+  fn read_stored(&mut self, index: usize) -> Result<(Frame, Duration)> {
+    let entry = *self.index.get(index).ok_or_else(|| Error::new(format!("Frame {} is out of range.", index)))?;
+    self.reader.seek(SeekFrom::Start(entry.offset)).map_err(|err| io_error("Failed to seek to frame", err))?;
+
+    let mut prefix = [0u8; FRAME_PREFIX_LEN];
+    self.reader.read_exact(&mut prefix).map_err(|err| io_error("Failed to read frame record", err))?;
+    let (payload_len, crc) = record_prefix(&prefix);
+    if &prefix[..4] != FRAME_TAG || payload_len < FRAME_FIELDS_LEN || entry.offset.saturating_add(FRAME_PREFIX_LEN as u64 + payload_len as u64) > self.file_len {
+      return Err(Error::new(format!("Frame record {} is corrupt.", index)));
+    }
+    let mut payload = vec![0u8; payload_len as usize];
+    self.reader.read_exact(&mut payload).map_err(|err| io_error("Failed to read frame record", err))?;
+    if crc32fast::hash(&payload) != crc {
+      return Err(Error::new(format!("Frame record {} failed its CRC check.", index)));
+    }
+
+    let mut fields = Decoder::new(&payload[..FRAME_FIELDS_LEN as usize]);
+    let sequence = fields.u64()?;
+    let timestamp = Duration::from_nanos(fields.u64()?);
+    let host_time = Duration::from_nanos(fields.u64()?);
+    let (width, height) = (fields.u32()?, fields.u32()?);
+    payload.drain(..FRAME_FIELDS_LEN as usize);
+    Ok((Frame::new(width, height, payload, sequence, timestamp), host_time))
+  }
+}
+
+fn read_header<R: Read>(reader: &mut R, file_len: u64) -> Result<RecordingHeader> {
+  let mut preamble = [0u8; 16];
+  reader.read_exact(&mut preamble).map_err(|err| io_error("Failed to read recording header", err))?;
+  if &preamble[..8] != FILE_MAGIC {
+    return Err(Error::new("Not a tinyrigel recording.".to_string()));
+  }
+  let version = u16::from_le_bytes([preamble[8], preamble[9]]);
+  if !(OLDEST_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+    return Err(Error::new(format!("Unsupported recording version {}.", version)));
+  }
+  let header_len = u32::from_le_bytes([preamble[12], preamble[13], preamble[14], preamble[15]]) as u64;
+  // As with the index, a length the file can't hold is corrupt and mustn't become a huge allocation.
+  if header_len > file_len.saturating_sub(16) {
+    return Err(Error::new("Recording header is corrupt.".to_string()));
+  }
+  let mut body = vec![0u8; header_len as usize];
+  reader.read_exact(&mut body).map_err(|err| io_error("Failed to read recording header", err))?;
+  Decoder::new(&body).header(version)
+}
+
+fn read_index<R: Read + Seek>(reader: &mut R, index_offset: u64, file_len: u64) -> Result<Vec<IndexEntry>> {
+  if index_offset > file_len.saturating_sub(12) {
+    return Err(Error::new("Recording index is corrupt.".to_string()));
+  }
+  reader.seek(SeekFrom::Start(index_offset)).map_err(|err| io_error("Failed to seek to recording index", err))?;
+  let mut prefix = [0u8; 12];
+  reader.read_exact(&mut prefix).map_err(|err| io_error("Failed to read recording index", err))?;
+  if &prefix[..4] != INDEX_TAG {
+    return Err(Error::new("Recording index is corrupt.".to_string()));
+  }
+  let mut count = [0u8; 8];
+  count.copy_from_slice(&prefix[4..]);
+  let count = u64::from_le_bytes(count);
+  // A count the rest of the file can't hold is corrupt; checking it first keeps it from becoming a huge allocation.
+  let size = count.checked_mul(24).filter(|&size| size <= file_len - index_offset - 12)
+    .ok_or_else(|| Error::new("Recording index is corrupt.".to_string()))?;
+  let count = count as usize;
+
+  let mut entries = vec![0u8; size as usize];
+  reader.read_exact(&mut entries).map_err(|err| io_error("Failed to read recording index", err))?;
+  let mut decoder = Decoder::new(&entries);
+  let mut index = Vec::with_capacity(count);
+  for _ in 0..count {
+    index.push(IndexEntry { offset: decoder.u64()?, sequence: decoder.u64()?, timestamp: Duration::from_nanos(decoder.u64()?) });
+  }
+  Ok(index)
+}
+
+impl FrameArchive for RecordingReader {
+  fn info(&self) -> DeviceInfo {
+    DeviceInfo { name: self.header.model.clone(), path: self.path.to_string_lossy().to_string(), serial: self.header.serial.clone() }
+  }
+
+  fn mode(&self) -> CaptureMode { self.header.mode }
+
+  fn frame_count(&self) -> usize { self.index.len() }
+
+  fn timestamp(&self, index: usize) -> Result<Duration> {
+    self.index.get(index).map(|entry| entry.timestamp).ok_or_else(|| Error::new(format!("Frame {} is out of range.", index)))
+  }
+
+  fn sequence(&self, index: usize) -> Result<u64> {
+    self.index.get(index).map(|entry| entry.sequence).ok_or_else(|| Error::new(format!("Frame {} is out of range.", index)))
+  }
+
+  fn read_frame(&mut self, index: usize) -> Result<Frame> {
+    Ok(self.read_record(index)?.0)
+  }
+}
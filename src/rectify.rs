@@ -0,0 +1,137 @@
+// rectify.rs - tinyrigel
+//
+// Stereo rectification: rotates both eyes onto a common image plane so that epipolar lines become image rows, and removes lens distortion on the way. The rotations follow Bouguet's method (as OpenCV's stereoRectify does), splitting the rotation between the eyes evenly to keep the reprojection distortion small. Rectified frames are the input for disparity and depth.
+
+use crate::*;
+
+/// Produces row-aligned left/right images from a stereo calibration, and the matrices to triangulate them.
+///
+/// The rectified eyes share one focal length and principal point (zero disparity at infinity), so a point seen at `(x, y)` in the left image appears at `(x - d, y)` in the right image, with `d` the disparity.
+pub struct StereoRectifier {
+  left: RemapTable,
+  right: RemapTable,
+  rotations: [Mat3; 2],
+  projections: [[[f64; 4]; 3]; 2],
+  q: [[f64; 4]; 4],
+  cameras: [CameraIntrinsics; 2],
+  calibration: StereoCalibration,
+  threads: usize
+}
+
+impl StereoRectifier {
+  pub fn new(calibration: &StereoCalibration) -> Self { Self::with_focal_scale(calibration, 1.0) }
+
+  /// Scales the rectified focal length, which otherwise is the smaller of the two eyes' vertical focal lengths. Values below 1 keep more of a wide-angle field of view.
+  pub fn with_focal_scale(calibration: &StereoCalibration, scale: f64) -> Self {
+    let (r1, r2) = rectifying_rotations(&calibration.rotation, calibration.translation);
+    let t = mat_vec(&r2, calibration.translation);
+    let horizontal = t[0].abs() >= t[1].abs();
+    let focal = if horizontal { calibration.left.fy.min(calibration.right.fy) } else { calibration.left.fx.min(calibration.right.fx) } * scale;
+
+    // Center the rectified images on where the original image centers land, averaged over both eyes.
+    let mut center = [0.0; 2];
+    for (camera, rotation) in [(&calibration.left, &r1), (&calibration.right, &r2)].iter() {
+      let (x, y) = camera.unproject((camera.width as f64 - 1.0) / 2.0, (camera.height as f64 - 1.0) / 2.0);
+      let ray = mat_vec(rotation, [x, y, 1.0]);
+      center[0] += ((camera.width as f64 - 1.0) / 2.0 - focal * ray[0] / ray[2]) / 2.0;
+      center[1] += ((camera.height as f64 - 1.0) / 2.0 - focal * ray[1] / ray[2]) / 2.0;
+    }
+    let [cx, cy] = center;
+
+    let rectified = |camera: &CameraIntrinsics| CameraIntrinsics { fx: focal, fy: focal, cx, cy, distortion: Distortion::None, ..camera.clone() };
+    let cameras = [rectified(&calibration.left), rectified(&calibration.right)];
+    let left_projection = [[focal, 0.0, cx, 0.0], [0.0, focal, cy, 0.0], [0.0, 0.0, 1.0, 0.0]];
+    let mut right_projection = left_projection;
+    if horizontal { right_projection[0][3] = focal * t[0]; } else { right_projection[1][3] = focal * t[1]; }
+    // With zero disparity at infinity, W = -d / T and Z = f / W, so Z = f |T| / d for a right eye to the right of the left.
+    let baseline = if horizontal { t[0] } else { t[1] };
+    let q = [
+      [1.0, 0.0, 0.0, -cx],
+      [0.0, 1.0, 0.0, -cy],
+      [0.0, 0.0, 0.0, focal],
+      [0.0, 0.0, -1.0 / baseline, 0.0]
+    ];
+
+    let table = |input: &CameraIntrinsics, output: &CameraIntrinsics, rotation: &Mat3| {
+      // Output pixels are rays in the rectified frame; rotate them back into the original camera and project with its lens model.
+      let unrotate = transpose(rotation);
+      RemapTable::build(input.width, input.height, input.width as usize * 2, move |u, v| {
+        let ray = mat_vec(&unrotate, [(u - output.cx) / output.fx, (v - output.cy) / output.fy, 1.0]);
+        if ray[2] <= 0.0 { return None; }
+        Some(input.project(ray[0] / ray[2], ray[1] / ray[2]))
+      })
+    };
+    Self {
+      left: table(&calibration.left, &cameras[0], &r1),
+      right: table(&calibration.right, &cameras[1], &r2),
+      rotations: [r1, r2],
+      projections: [left_projection, right_projection],
+      q,
+      cameras,
+      calibration: calibration.clone(),
+      threads: 1
+    }
+  }
+
+  /// The rotation taking points from an original camera frame into its rectified frame (R1 / R2 in OpenCV terms).
+  pub fn rotation(&self, eye: Eye) -> [[f64; 3]; 3] { self.rotations[eye_index(eye)] }
+
+  /// The 3x4 projection matrix of a rectified eye (P1 / P2), in the rectified left camera frame. The right eye's carries the baseline as `P[0][3] = f * Tx`, or `P[1][3] = f * Ty` for a vertically rectified rig.
+  pub fn projection(&self, eye: Eye) -> [[f64; 4]; 3] { self.projections[eye_index(eye)] }
+
+  /// The 4x4 disparity-to-depth matrix Q: `Q * [x, y, d, 1]` is the homogeneous 3D point, in the rectified left camera frame, seen at left pixel `(x, y)` with disparity `d`.
+  pub fn q(&self) -> [[f64; 4]; 4] { self.q }
+
+  /// Intrinsics of a rectified eye, with no distortion.
+  pub fn rectified_camera(&self, eye: Eye) -> &CameraIntrinsics { &self.cameras[eye_index(eye)] }
+
+  /// Splits each frame across `threads` threads. Defaults to 1.
+  pub fn set_threads(&mut self, threads: usize) { self.threads = threads.max(1); }
+
+  /// Rectifies both eyes of `frame` into a new frame with the same sequence number and timestamp. Errors if the frame is incomplete or doesn't match the calibration's dimensions.
+  pub fn rectify(&self, frame: &Frame) -> Result<Frame> {
+    let mut out = Frame::new(frame.width, frame.height, Vec::new(), frame.sequence, frame.timestamp);
+    self.rectify_into(frame, &mut out)?;
+    Ok(out)
+  }
+
+  /// Like `rectify`, but reuses the buffer of `out`, avoiding an allocation per frame.
+  pub fn rectify_into(&self, frame: &Frame, out: &mut Frame) -> Result<()> {
+    remap_frame(&self.left, &self.right, frame, out, self.threads)
+  }
+
+  /// Maps a pixel of an original (distorted) eye image to its position in the rectified image.
+  pub fn rectify_point(&self, eye: Eye, u: f64, v: f64) -> (f64, f64) {
+    let (x, y) = self.calibration.camera(eye).unproject(u, v);
+    let ray = mat_vec(&self.rotations[eye_index(eye)], [x, y, 1.0]);
+    let camera = &self.cameras[eye_index(eye)];
+    (camera.fx * ray[0] / ray[2] + camera.cx, camera.fy * ray[1] / ray[2] + camera.cy)
+  }
+
+  /// The 3D point, in meters in the rectified left camera frame, seen at rectified left pixel `(x, y)` with disparity `d`. `None` for disparities that don't put the point in front of the cameras.
+  pub fn reproject(&self, x: f64, y: f64, disparity: f64) -> Option<[f64; 3]> {
+    let q = &self.q;
+    let input = [x, y, disparity, 1.0];
+    let row = |r: usize| (0..4).map(|c| q[r][c] * input[c]).sum::<f64>();
+    let w = row(3);
+    if w <= 0.0 { return None; }
+    Some([row(0) / w, row(1) / w, row(2) / w])
+  }
+}
+
+fn eye_index(eye: Eye) -> usize {
+  match eye { Eye::Left => 0, Eye::Right => 1 }
+}
+
+// Bouguet's rectifying rotations for x_right = R x_left + T: each eye turns by half of R, then both turn together so the baseline lies along the nearer of the x and y axes.
+fn rectifying_rotations(rotation: &Mat3, translation: Vec3) -> (Mat3, Mat3) {
+  let half = rodrigues(scale(rotation_vector(rotation), -0.5));
+  let t = mat_vec(&half, translation);
+  let axis = if t[0].abs() >= t[1].abs() { 0 } else { 1 };
+  let mut target = [0.0; 3];
+  target[axis] = if t[axis] > 0.0 { 1.0 } else { -1.0 };
+  let w = cross(t, target);
+  let w_norm = norm(w);
+  let align = if w_norm > 0.0 { rodrigues(scale(w, (t[axis].abs() / norm(t)).acos() / w_norm)) } else { IDENTITY };
+  (mat_mul(&align, &transpose(&half)), mat_mul(&align, &half))
+}
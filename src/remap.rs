@@ -0,0 +1,105 @@
+// remap.rs - tinyrigel
+//
+// Precomputed per-pixel remapping of eye images with fixed-point bilinear sampling, shared by undistortion and rectification. Building a table does all the floating point work once; applying it is four loads and a handful of integer multiplies per output pixel.
+
+use std::thread;
+
+use crate::*;
+
+// Bits of subpixel precision in the sampling weights.
+const FRACTION_BITS: u32 = 8;
+const ONE: u32 = 1 << FRACTION_BITS;
+// Marks output pixels whose source lies outside the eye image. They come out black.
+const OUTSIDE: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+struct Sample {
+  // Index of the top-left source pixel, relative to the start of the eye in a side-by-side row layout.
+  offset: u32,
+  fx: u16,
+  fy: u16
+}
+
+/// Where each output pixel of one eye is sampled from in the input eye.
+pub(crate) struct RemapTable {
+  width: u32,
+  height: u32,
+  samples: Vec<Sample>
+}
+
+impl RemapTable {
+  /// Builds a table for a `width` x `height` eye in frames with `stride` bytes per row. `source` maps output pixel coordinates to input pixel coordinates, or `None` where there is nothing to sample.
+  pub(crate) fn build<F: Fn(f64, f64) -> Option<(f64, f64)>>(width: u32, height: u32, stride: usize, source: F) -> Self {
+    let mut samples = Vec::with_capacity((width * height) as usize);
+    for v in 0..height {
+      for u in 0..width {
+        let sample = source(u as f64, v as f64).and_then(|(x, y)| {
+          // Samples need a 2x2 neighbourhood; the last row and column are reached with a full weight on them.
+          let (max_x, max_y) = (width as f64 - 1.0, height as f64 - 1.0);
+          if !(x >= 0.0 && y >= 0.0 && x <= max_x && y <= max_y) || width < 2 || height < 2 { return None; }
+          let (x0, y0) = ((x.floor() as u32).min(width - 2), (y.floor() as u32).min(height - 2));
+          let fx = ((x - x0 as f64) * ONE as f64).round() as u16;
+          let fy = ((y - y0 as f64) * ONE as f64).round() as u16;
+          Some(Sample { offset: (y0 as usize * stride + x0 as usize) as u32, fx, fy })
+        });
+        samples.push(sample.unwrap_or(Sample { offset: OUTSIDE, fx: 0, fy: 0 }));
+      }
+    }
+    Self { width, height, samples }
+  }
+
+  /// Remaps rows `first_row..first_row + out_rows.len() / stride` of the eye starting at `src[0]` into the same eye of `out_rows`, which holds whole side-by-side rows.
+  fn apply_rows(&self, src: &[u8], stride: usize, eye_offset: usize, first_row: usize, out_rows: &mut [u8]) {
+    let width = self.width as usize;
+    for (row, out) in out_rows.chunks_exact_mut(stride).enumerate() {
+      let samples = &self.samples[(first_row + row) * width..(first_row + row + 1) * width];
+      for (px, sample) in out[eye_offset..eye_offset + width].iter_mut().zip(samples) {
+        *px = if sample.offset == OUTSIDE { 0 } else {
+          let at = sample.offset as usize;
+          let (fx, fy) = (sample.fx as u32, sample.fy as u32);
+          let top = src[at] as u32 * (ONE - fx) + src[at + 1] as u32 * fx;
+          let bottom = src[at + stride] as u32 * (ONE - fx) + src[at + stride + 1] as u32 * fx;
+          ((top * (ONE - fy) + bottom * fy + (1 << (2 * FRACTION_BITS - 1))) >> (2 * FRACTION_BITS)) as u8
+        };
+      }
+    }
+  }
+}
+
+/// Remaps both eyes of `frame` into `out`, splitting the rows across `threads` threads.
+pub(crate) fn remap_frame(left: &RemapTable, right: &RemapTable, frame: &Frame, out: &mut Frame, threads: usize) -> Result<()> {
+  if frame.width != left.width || frame.height != left.height {
+    return Err(Error::new(format!(
+      "Frame is {}x{} per eye, but the remap tables are for {}x{}.", frame.width, frame.height, left.width, left.height
+    )));
+  }
+  if !frame.is_complete() {
+    return Err(Error::new(format!("Frame {} is incomplete ({} of {} bytes).", frame.sequence, frame.data.len(), frame.expected_len())));
+  }
+  out.width = frame.width;
+  out.height = frame.height;
+  out.sequence = frame.sequence;
+  out.timestamp = frame.timestamp;
+  out.data.resize(frame.expected_len(), 0);
+
+  let stride = frame.stride();
+  let (height, width) = (frame.height as usize, frame.width as usize);
+  if height == 0 || width == 0 { return Ok(()); }
+  let remap = |first_row: usize, rows: &mut [u8]| {
+    left.apply_rows(&frame.data, stride, 0, first_row, rows);
+    right.apply_rows(&frame.data[width..], stride, width, first_row, rows);
+  };
+  let threads = threads.clamp(1, height);
+  if threads == 1 {
+    remap(0, &mut out.data);
+    return Ok(());
+  }
+  let band_rows = height.div_ceil(threads);
+  thread::scope(|scope| {
+    for (band, rows) in out.data.chunks_mut(band_rows * stride).enumerate() {
+      let remap = &remap;
+      scope.spawn(move || remap(band * band_rows, rows));
+    }
+  });
+  Ok(())
+}
@@ -0,0 +1,269 @@
+// replay.rs - tinyrigel
+//
+// ReplaySource plays back previously captured frames as if they were coming from a live Rigel, so processing code can run on recorded sessions through exactly the same Rigel callback / next_frame path as live capture.
+
+use std::{fs, path::{Path, PathBuf}, thread, time::{Duration, Instant}};
+
+use crate::*;
+
+/// Random-access storage of captured frames that a ReplaySource can play back.
+pub trait FrameArchive: Send {
+  /// Describes the device the frames were captured from.
+  fn info(&self) -> DeviceInfo;
+
+  /// The capture mode the frames were recorded in.
+  fn mode(&self) -> CaptureMode;
+
+  fn frame_count(&self) -> usize;
+
+  /// Capture timestamp of frame `index`, without decoding its pixels.
+  fn timestamp(&self, index: usize) -> Result<Duration>;
+
+  /// Sequence number of frame `index`, without decoding its pixels.
+  fn sequence(&self, index: usize) -> Result<u64>;
+
+  fn read_frame(&mut self, index: usize) -> Result<Frame>;
+}
+
+/// How fast a ReplaySource delivers frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+  /// Reproduce the original inter-frame timing.
+  Realtime,
+  /// Original timing scaled by this factor; 2.0 plays twice as fast.
+  Multiplier(f64),
+  /// No pacing; every frame is delivered as soon as it's requested.
+  AsFastAsPossible
+}
+
+/// A directory of side-by-side 8-bit grayscale PNG frames, played back in file name order at a fixed frame rate.
+pub struct PngSequence {
+  dir: PathBuf,
+  files: Vec<PathBuf>,
+  mode: CaptureMode
+}
+
+impl PngSequence {
+  /// Opens `dir`, assuming the frames were captured at 90 fps.
+  pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+    Self::with_fps(dir, CaptureMode::RIGEL_DEFAULT.fps)
+  }
+
+  pub fn with_fps<P: AsRef<Path>>(dir: P, fps: u32) -> Result<Self> {
+    let dir = dir.as_ref().to_path_buf();
+    let entries = fs::read_dir(&dir).map_err(|err| Error::new(format!("Failed to read {}: {}", dir.display(), err)))?;
+    let mut files: Vec<PathBuf> = entries
+      .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|path| path.extension().map(|ext| ext.eq_ignore_ascii_case("png")).unwrap_or(false))
+      .collect();
+    files.sort();
+    if files.is_empty() {
+      return Err(Error::new(format!("No PNG frames found in {}.", dir.display())));
+    }
+
+    let (width, height) = image::image_dimensions(&files[0])
+      .map_err(|err| Error::new(format!("Failed to read {}: {}", files[0].display(), err)))?;
+    let mode = CaptureMode { width: width / 2, height, fps: fps.max(1) };
+    Ok(Self { dir, files, mode })
+  }
+}
+
+impl FrameArchive for PngSequence {
+  fn info(&self) -> DeviceInfo {
+    DeviceInfo { name: "PNG sequence".to_string(), path: self.dir.to_string_lossy().to_string(), serial: None }
+  }
+
+  fn mode(&self) -> CaptureMode { self.mode }
+
+  fn frame_count(&self) -> usize { self.files.len() }
+
+  fn timestamp(&self, index: usize) -> Result<Duration> {
+    Ok(Duration::from_secs(1) * index as u32 / self.mode.fps)
+  }
+
+  fn sequence(&self, index: usize) -> Result<u64> { Ok(index as u64) }
+
+  fn read_frame(&mut self, index: usize) -> Result<Frame> {
+    let path = self.files.get(index).ok_or_else(|| Error::new(format!("Frame {} is out of range.", index)))?;
+    let img = image::open(path).map_err(|err| Error::new(format!("Failed to decode {}: {}", path.display(), err)))?.to_luma8();
+    if img.width() != self.mode.width * 2 || img.height() != self.mode.height {
+      return Err(Error::new(format!(
+        "{} is {}x{}, but the sequence is {}x{}.",
+        path.display(), img.width(), img.height(), self.mode.width * 2, self.mode.height
+      )));
+    }
+    Frame::from_side_by_side(img, index as u64, self.timestamp(index)?)
+  }
+}
+
+pub struct ReplaySource {
+  archive: Box<dyn FrameArchive>,
+  speed: ReplaySpeed,
+  looping: bool,
+  streaming: bool,
+  position: usize,
+  // Added to sequence numbers and timestamps once playback has looped.
+  loop_offset: (u64, Duration),
+  // Wall-clock time and archive timestamp that pacing is measured from. Reset on start and seek.
+  anchor: Option<(Instant, Duration)>
+}
+
+impl ReplaySource {
+  /// Opens a directory of PNG frames or a recording file.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref();
+    if path.is_dir() {
+      return Ok(Self::from_archive(Box::new(PngSequence::open(path)?)));
+    }
+    if path.is_file() {
+      return Ok(Self::from_archive(Box::new(RecordingReader::open(path)?)));
+    }
+    Err(Error::new(format!("{} is not a recognized replay source.", path.display())))
+  }
+
+  pub fn from_archive(archive: Box<dyn FrameArchive>) -> Self {
+    Self { archive, speed: ReplaySpeed::Realtime, looping: false, streaming: false, position: 0, loop_offset: (0, Duration::from_secs(0)), anchor: None }
+  }
+
+  pub fn set_speed(&mut self, speed: ReplaySpeed) {
+    self.speed = speed;
+    self.anchor = None;
+  }
+
+  /// When looping, playback wraps to the first frame instead of ending. Sequence numbers and timestamps keep increasing across loops.
+  pub fn set_looping(&mut self, looping: bool) { self.looping = looping; }
+
+  pub fn frame_count(&self) -> usize { self.archive.frame_count() }
+
+  /// Index of the next frame `next_frame` will deliver.
+  pub fn position(&self) -> usize { self.position }
+
+  /// Total duration of the recording, from the first to the last frame.
+  pub fn duration(&self) -> Result<Duration> {
+    let count = self.archive.frame_count();
+    if count == 0 { return Ok(Duration::from_secs(0)); }
+    Ok(self.archive.timestamp(count - 1)?.saturating_sub(self.archive.timestamp(0)?))
+  }
+
+  pub fn seek(&mut self, index: usize) -> Result<()> {
+    if index >= self.archive.frame_count() {
+      return Err(Error::new(format!("Cannot seek to frame {}; the recording has {} frames.", index, self.archive.frame_count())));
+    }
+    self.position = index;
+    self.anchor = None;
+    Ok(())
+  }
+
+  /// Seeks to the first frame at or after `offset` from the start of the recording.
+  pub fn seek_to_time(&mut self, offset: Duration) -> Result<()> {
+    let count = self.archive.frame_count();
+    let target = self.archive.timestamp(0)? + offset;
+    let (mut lo, mut hi) = (0, count);
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      if self.archive.timestamp(mid)? < target { lo = mid + 1; } else { hi = mid; }
+    }
+    self.seek(lo.min(count.saturating_sub(1)))
+  }
+
+  // Waits until the frame with archive timestamp `timestamp` is due. Returns a timeout error if it isn't due within `timeout`.
+  fn pace(&mut self, timestamp: Duration, timeout: Duration) -> Result<()> {
+    let factor = match self.speed {
+      ReplaySpeed::AsFastAsPossible => return Ok(()),
+      ReplaySpeed::Realtime => 1.0,
+      ReplaySpeed::Multiplier(factor) if factor > 0.0 => factor,
+      ReplaySpeed::Multiplier(_) => return Ok(())
+    };
+    let (anchor_wall, anchor_ts) = *self.anchor.get_or_insert((Instant::now(), timestamp));
+    let due = anchor_wall + timestamp.saturating_sub(anchor_ts).div_f64(factor);
+    let now = Instant::now();
+    if due > now {
+      if due - now > timeout {
+        thread::sleep(timeout);
+        return Err(Error::timeout("Timed out waiting for the next replayed frame.".to_string()));
+      }
+      thread::sleep(due - now);
+    }
+    Ok(())
+  }
+}
+
+impl DeviceSource for ReplaySource {
+  fn info(&self) -> DeviceInfo { self.archive.info() }
+
+  fn modes(&self) -> Result<Vec<CaptureMode>> { Ok(vec![self.archive.mode()]) }
+
+  fn mode(&self) -> CaptureMode { self.archive.mode() }
+
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> {
+    if mode != self.archive.mode() {
+      return Err(Error::new("A replayed recording can only be played back in the mode it was recorded in.".to_string()));
+    }
+    Ok(mode)
+  }
+
+  fn start(&mut self) -> Result<()> {
+    self.streaming = true;
+    self.anchor = None;
+    Ok(())
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    self.streaming = false;
+    Ok(())
+  }
+
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+    if !self.streaming {
+      return Err(Error::new("ReplaySource is not streaming; call start() first.".to_string()));
+    }
+    let count = self.archive.frame_count();
+    if self.position >= count {
+      if !self.looping || count == 0 {
+        return Err(Error::end_of_stream("The replayed recording has ended.".to_string()));
+      }
+      // Offset each loop by the recording's length plus one frame interval, so sequence numbers and time keep moving forward.
+      let interval = Duration::from_secs(1) / self.archive.mode().fps.max(1);
+      // Sequence numbers that went backwards (the device was reopened, or its counter wrapped) leave only the frame count to go by.
+      let sequence_span = self.archive.sequence(count - 1)?.checked_sub(self.archive.sequence(0)?).map_or(count as u64, |span| span + 1);
+      let time_span = self.archive.timestamp(count - 1)?.saturating_sub(self.archive.timestamp(0)?) + interval;
+      self.loop_offset = (self.loop_offset.0 + sequence_span, self.loop_offset.1 + time_span);
+      self.position = 0;
+      self.anchor = None;
+    }
+
+    let timestamp = self.archive.timestamp(self.position)?;
+    self.pace(timestamp, timeout)?;
+
+    let mut frame = self.archive.read_frame(self.position)?;
+    frame.sequence += self.loop_offset.0;
+    frame.timestamp += self.loop_offset.1;
+    self.position += 1;
+    Ok(frame)
+  }
+}
+
+/// A Backend over a fixed list of recordings, so replayed sessions can be opened like any other device.
+pub struct ReplayBackend {
+  paths: Vec<PathBuf>
+}
+
+impl ReplayBackend {
+  pub fn new(paths: Vec<PathBuf>) -> Self { Self { paths } }
+}
+
+impl Backend for ReplayBackend {
+  fn name(&self) -> &str { "replay" }
+
+  fn enumerate(&self) -> Result<Vec<DeviceInfo>> {
+    let mut devices = Vec::new();
+    for path in &self.paths {
+      devices.push(ReplaySource::open(path)?.info());
+    }
+    Ok(devices)
+  }
+
+  fn open(&self, device: &DeviceInfo) -> Result<Box<dyn DeviceSource>> {
+    Ok(Box::new(ReplaySource::open(&device.path)?))
+  }
+}
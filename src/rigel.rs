@@ -1,32 +1,232 @@
 // rigel.rs - tinyrigel
 
+use std::{path::Path, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread::{self, JoinHandle}, time::Duration};
+
 use crate::*;
 
-pub struct Rigel<Cb>
-where Cb: Fn(&[u8]) -> ()
+// How long the capture thread waits on the source before re-checking whether it should stop.
+const CAPTURE_POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A Rigel (or anything else behind a `DeviceSource`).
+///
+/// Frames are delivered either to a callback, invoked from a capture thread once `open` is called, or pulled with `next_frame` when no callback is set. Without a callback the callback type can't be inferred; annotate the binding as `let rigel: Rigel = ...`.
+pub struct Rigel<Cb = fn(&Frame)>
+where Cb: Fn(&Frame) + Send + 'static
 {
-  callback_fn: Option<Cb>
+  source: Arc<Mutex<Box<dyn DeviceSource>>>,
+  sinks: Arc<Mutex<Vec<Box<dyn FrameSink>>>>,
+  callback_fn: Option<Cb>,
+  streaming: bool,
+  capture_running: Arc<AtomicBool>,
+  capture_thread: Option<JoinHandle<Cb>>,
+  capture_error: Arc<Mutex<Option<Error>>>,
+  calibration: Option<StereoCalibration>
 }
 
+/// Opens the first Rigel found by the platform's default backend.
 pub fn get_rigel<Cb>() -> Result<Rigel<Cb>>
-where Cb: Fn(&[u8]) -> ()
+where Cb: Fn(&Frame) + Send + 'static
 {
-  Err(Error::new("get_rigel not yet implemented.".to_string()))
-  // Ok(Rigel { callback_fn: None })
+  Rigel::from_backend(default_backend()?.as_ref())
 }
 
 impl<Cb> Rigel<Cb>
-where Cb: Fn(&[u8]) -> ()
+where Cb: Fn(&Frame) + Send + 'static
 {
+  /// Opens the first device `backend` enumerates.
+  pub fn from_backend(backend: &dyn Backend) -> Result<Self> {
+    let devices = backend.enumerate()?;
+    let device = devices.first()
+      .ok_or_else(|| Error::new(format!("No devices found by the {} backend. Is your Rigel plugged in?", backend.name())))?;
+    Ok(Self::from_source(backend.open(device)?))
+  }
+
+  /// Wraps an already opened source. If the device reports a serial, its calibration is looked up in `calibration_dir()`.
+  pub fn from_source(source: Box<dyn DeviceSource>) -> Self {
+    // A missing or unreadable directory just leaves the Rigel uncalibrated.
+    let calibration = source.info().serial.and_then(|serial| StereoCalibration::find(calibration_dir()?, &serial).ok()?);
+    Self {
+      source: Arc::new(Mutex::new(source)),
+      sinks: Arc::new(Mutex::new(Vec::new())),
+      callback_fn: None,
+      streaming: false,
+      capture_running: Arc::new(AtomicBool::new(false)),
+      capture_thread: None,
+      capture_error: Arc::new(Mutex::new(None)),
+      calibration
+    }
+  }
+
   pub fn set_callback(&mut self, callback_fn: Cb) {
     self.callback_fn = Some(callback_fn);
   }
 
+  /// Attaches a sink that receives every captured frame, e.g. a `Recorder`. Sinks stay attached across `close` / `open` until `finish_sinks`. The sink is told the current value of every control, and of every later change made through `set_control`.
+  pub fn attach_sink(&mut self, mut sink: Box<dyn FrameSink>) {
+    for info in self.controls().unwrap_or_default() {
+      if let Ok(value) = self.control(info.control) {
+        sink.control_changed(info.control, value);
+      }
+    }
+    self.sinks.lock().unwrap().push(sink);
+  }
+
+  /// Detaches all sinks and finishes them, e.g. writing a recording's index. Returns the first error any sink reported.
+  pub fn finish_sinks(&mut self) -> Result<()> {
+    let sinks: Vec<Box<dyn FrameSink>> = self.sinks.lock().unwrap().drain(..).collect();
+    let mut result = Ok(());
+    for mut sink in sinks {
+      let finished = sink.finish();
+      if result.is_ok() { result = finished; }
+    }
+    result
+  }
+
+  pub fn info(&self) -> DeviceInfo { self.lock_source().info() }
+
+  /// The device's calibration: the one found for its serial when the Rigel was created, or one set since.
+  pub fn calibration(&self) -> Option<&StereoCalibration> { self.calibration.as_ref() }
+
+  pub fn set_calibration(&mut self, calibration: Option<StereoCalibration>) { self.calibration = calibration; }
+
+  /// Looks for the device's calibration in `dir` rather than `calibration_dir()`, as `StereoCalibration::find` does. Returns whether one was found; if not, the current calibration stays.
+  pub fn load_calibration<P: AsRef<Path>>(&mut self, dir: P) -> Result<bool> {
+    let serial = self.info().serial.ok_or_else(|| Error::new("The device doesn't report a serial number to find its calibration by.".to_string()))?;
+    let Some(calibration) = StereoCalibration::find(dir, &serial)? else { return Ok(false); };
+    self.calibration = Some(calibration);
+    Ok(true)
+  }
+
+  pub fn modes(&self) -> Result<Vec<CaptureMode>> { self.lock_source().modes() }
+
+  pub fn mode(&self) -> CaptureMode { self.lock_source().mode() }
+
+  /// Selects a capture mode. Must be called before `open`.
+  pub fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> {
+    if self.streaming {
+      return Err(Error::new("Cannot change the capture mode while the Rigel is open.".to_string()));
+    }
+    self.lock_source().configure(mode)
+  }
+
+  pub fn controls(&self) -> Result<Vec<ControlInfo>> { self.lock_source().controls() }
+
+  pub fn control(&self, control: Control) -> Result<i64> { self.lock_source().control(control) }
+
+  pub fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+    self.lock_source().set_control(control, value)?;
+    // Report the value the device actually applied, which may be clamped or rounded.
+    let applied = self.control(control).unwrap_or(value);
+    for sink in self.sinks.lock().unwrap().iter_mut() {
+      sink.control_changed(control, applied);
+    }
+    Ok(())
+  }
+
+  /// Starts streaming. If a callback is set, it is invoked for every frame on a dedicated capture thread until `close`.
   pub fn open(&mut self) -> Result<()> {
-    Err(Error::new("open() not yet implemented.".to_string()))
+    if self.streaming {
+      return Err(Error::new("The Rigel is already open.".to_string()));
+    }
+    self.lock_source().start()?;
+    self.streaming = true;
+    *self.capture_error.lock().unwrap() = None;
+
+    if let Some(callback_fn) = self.callback_fn.take() {
+      let source = self.source.clone();
+      let sinks = self.sinks.clone();
+      let running = self.capture_running.clone();
+      let capture_error = self.capture_error.clone();
+      running.store(true, Ordering::SeqCst);
+      self.capture_thread = Some(thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+          // Release the source between frames so controls can be changed while streaming.
+          let frame = source.lock().unwrap().next_frame(CAPTURE_POLL_TIMEOUT);
+          match frame {
+            Ok(frame) => {
+              // A failing sink shouldn't stop capture; its error is kept for take_error().
+              if let Err(err) = write_to_sinks(&sinks, &frame) {
+                *capture_error.lock().unwrap() = Some(err);
+              }
+              callback_fn(&frame)
+            }
+            Err(err) if err.kind() == ErrorKind::Timeout => continue,
+            Err(err) => {
+              *capture_error.lock().unwrap() = Some(err);
+              running.store(false, Ordering::SeqCst);
+            }
+          }
+        }
+        // Hand the callback back so the Rigel can be reopened.
+        callback_fn
+      }));
+    }
+
+    Ok(())
   }
 
+  /// Stops streaming and joins the capture thread, if any.
   pub fn close(&mut self) -> Result<()> {
-    Err(Error::new("close() not yet implemented.".to_string()))
+    if !self.streaming {
+      return Err(Error::new("The Rigel is not open.".to_string()));
+    }
+    self.capture_running.store(false, Ordering::SeqCst);
+    if let Some(capture_thread) = self.capture_thread.take() {
+      match capture_thread.join() {
+        Ok(callback_fn) => self.callback_fn = Some(callback_fn),
+        Err(_) => {
+          self.streaming = false;
+          let _ = self.lock_source().stop();
+          return Err(Error::new("The frame callback panicked.".to_string()));
+        }
+      }
+    }
+    self.streaming = false;
+    self.lock_source().stop()
+  }
+
+  pub fn is_open(&self) -> bool { self.streaming }
+
+  /// Pulls the next frame. Only available when no callback is set, since otherwise the capture thread consumes every frame.
+  pub fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+    if !self.streaming {
+      return Err(Error::new("The Rigel is not open; call open() first.".to_string()));
+    }
+    if self.capture_thread.is_some() {
+      return Err(Error::new("Frames are being delivered to the callback; next_frame() is only available without a callback.".to_string()));
+    }
+    let frame = self.lock_source().next_frame(timeout)?;
+    // As with the callback, a failing sink doesn't cost the caller the frame; its error is kept for take_error().
+    if let Err(err) = write_to_sinks(&self.sinks, &frame) {
+      *self.capture_error.lock().unwrap() = Some(err);
+    }
+    Ok(frame)
+  }
+
+  /// Takes the last error from capture: the one that stopped the capture thread, such as a disconnect, or a sink that failed to write.
+  pub fn take_error(&mut self) -> Option<Error> { self.capture_error.lock().unwrap().take() }
+
+  fn lock_source(&self) -> std::sync::MutexGuard<'_, Box<dyn DeviceSource>> {
+    self.source.lock().unwrap()
+  }
+}
+
+impl<Cb> Drop for Rigel<Cb>
+where Cb: Fn(&Frame) + Send + 'static
+{
+  fn drop(&mut self) {
+    if self.streaming {
+      let _ = self.close();
+    }
+    let _ = self.finish_sinks();
+  }
+}
+
+fn write_to_sinks(sinks: &Mutex<Vec<Box<dyn FrameSink>>>, frame: &Frame) -> Result<()> {
+  let mut result = Ok(());
+  for sink in sinks.lock().unwrap().iter_mut() {
+    let written = sink.write_frame(frame);
+    if result.is_ok() { result = written; }
   }
+  result
 }
@@ -0,0 +1,345 @@
+// rosbag.rs - tinyrigel
+//
+// ROS 1 bag (format 2.0) export, written directly with no ROS install needed. Each frame becomes one message per topic, all with the same header stamp:
+//
+//   /rigel/left/image_raw     /rigel/right/image_raw     sensor_msgs/Image (mono8)
+//   /rigel/left/camera_info   /rigel/right/camera_info   sensor_msgs/CameraInfo, only when a calibration is known
+//
+// A bag is a sequence of records, each a header (a u32 length, then "name=value" fields, each with its own u32 length) followed by a u32 data length and the data. Messages are grouped into uncompressed chunks, each followed by index records; the connections and a chunk info per chunk come last, and the bag header record at the start is rewritten on finish to point at them. See http://wiki.ros.org/Bags/Format/2.0.
+
+use std::{collections::BTreeMap, fs::File, io::{self, BufWriter, Seek, SeekFrom, Write}, path::Path, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::*;
+
+const MAGIC: &[u8] = b"#ROSBAG V2.0\n";
+
+const OP_MESSAGE_DATA: u8 = 0x02;
+const OP_BAG_HEADER: u8 = 0x03;
+const OP_INDEX_DATA: u8 = 0x04;
+const OP_CHUNK: u8 = 0x05;
+const OP_CHUNK_INFO: u8 = 0x06;
+const OP_CONNECTION: u8 = 0x07;
+
+// The bag header record is padded to this size so it can be rewritten in place.
+const BAG_HEADER_LEN: usize = 4096;
+// Chunks are closed after the frame that takes their data past this size.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+const IMAGE_TYPE: &str = "sensor_msgs/Image";
+const IMAGE_MD5: &str = "060021388200f6f0f447d0fcd9c64743";
+const CAMERA_INFO_TYPE: &str = "sensor_msgs/CameraInfo";
+const CAMERA_INFO_MD5: &str = "c9a58c1b0b154e0e6da7578cb991d214";
+
+const HEADER_DEFINITION: &str = "MSG: std_msgs/Header\nuint32 seq\ntime stamp\nstring frame_id\n";
+const DEFINITION_SEPARATOR: &str = "================================================================================\n";
+
+fn image_definition() -> String {
+  format!(
+    "std_msgs/Header header\nuint32 height\nuint32 width\nstring encoding\nuint8 is_bigendian\nuint32 step\nuint8[] data\n\n{}{}",
+    DEFINITION_SEPARATOR, HEADER_DEFINITION
+  )
+}
+
+fn camera_info_definition() -> String {
+  format!(
+    "std_msgs/Header header\nuint32 height\nuint32 width\nstring distortion_model\nfloat64[] D\nfloat64[9] K\nfloat64[9] R\nfloat64[12] P\nuint32 binning_x\nuint32 binning_y\nRegionOfInterest roi\n\n{}{}{}{}",
+    DEFINITION_SEPARATOR, HEADER_DEFINITION, DEFINITION_SEPARATOR,
+    "MSG: sensor_msgs/RegionOfInterest\nuint32 x_offset\nuint32 y_offset\nuint32 height\nuint32 width\nbool do_rectify\n"
+  )
+}
+
+// A ROS time: seconds and nanoseconds since the epoch.
+type RosTime = (u32, u32);
+
+// Record header fields.
+struct RecordHeader(Vec<u8>);
+
+impl RecordHeader {
+  fn new(op: u8) -> Self {
+    let mut header = Self(Vec::new());
+    header.field("op", &[op]);
+    header
+  }
+
+  fn field(&mut self, name: &str, value: &[u8]) -> &mut Self {
+    self.0.extend_from_slice(&((name.len() + 1 + value.len()) as u32).to_le_bytes());
+    self.0.extend_from_slice(name.as_bytes());
+    self.0.push(b'=');
+    self.0.extend_from_slice(value);
+    self
+  }
+
+  fn u32(&mut self, name: &str, v: u32) -> &mut Self { self.field(name, &v.to_le_bytes()) }
+  fn u64(&mut self, name: &str, v: u64) -> &mut Self { self.field(name, &v.to_le_bytes()) }
+  fn time(&mut self, name: &str, time: RosTime) -> &mut Self { self.field(name, &ros_time_bytes(time)) }
+
+  fn record(&self, data: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(8 + self.0.len() + data.len());
+    record.extend_from_slice(&(self.0.len() as u32).to_le_bytes());
+    record.extend_from_slice(&self.0);
+    record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    record.extend_from_slice(data);
+    record
+  }
+}
+
+fn ros_time(time: SystemTime) -> RosTime {
+  let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+  (since_epoch.as_secs() as u32, since_epoch.subsec_nanos())
+}
+
+fn ros_time_bytes((sec, nsec): RosTime) -> [u8; 8] {
+  let mut bytes = [0u8; 8];
+  bytes[..4].copy_from_slice(&sec.to_le_bytes());
+  bytes[4..].copy_from_slice(&nsec.to_le_bytes());
+  bytes
+}
+
+// ROS 1 message serialization: little-endian, unaligned, strings and arrays prefixed with a u32 length.
+struct Ros1(Vec<u8>);
+
+impl Ros1 {
+  fn u8(&mut self, v: u8) { self.0.push(v); }
+  fn u32(&mut self, v: u32) { self.0.extend_from_slice(&v.to_le_bytes()); }
+  fn f64s(&mut self, values: &[f64]) { for v in values { self.0.extend_from_slice(&v.to_le_bytes()); } }
+  fn str(&mut self, s: &str) { self.bytes(s.as_bytes()); }
+  fn bytes(&mut self, b: &[u8]) { self.u32(b.len() as u32); self.0.extend_from_slice(b); }
+
+  fn header(&mut self, seq: u32, stamp: RosTime, frame_id: &str) {
+    self.u32(seq);
+    self.0.extend_from_slice(&ros_time_bytes(stamp));
+    self.str(frame_id);
+  }
+}
+
+/// Writes frames to a ROS 1 bag. A FrameSink, so it can be attached to a Rigel; `write_archive` converts a recording.
+pub struct BagWriter<W: Write + Seek + Send> {
+  writer: Option<W>,
+  position: u64,
+  calibration: Option<StereoCalibration>,
+  clock: FrameClock,
+  // Connection records, indexed by connection id.
+  connections: Vec<Vec<u8>>,
+  chunk: Vec<u8>,
+  chunk_times: Option<(RosTime, RosTime)>,
+  // Per connection: (time, offset in the chunk) of each message in the open chunk.
+  chunk_index: BTreeMap<u32, Vec<(RosTime, u32)>>,
+  chunk_infos: Vec<Vec<u8>>,
+  finished: bool
+}
+
+fn io_error(err: io::Error) -> Error { Error::new(format!("Failed to write bag: {}", err)) }
+
+impl BagWriter<BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(path: P, calibration: Option<StereoCalibration>) -> Result<Self> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|err| Error::new(format!("Failed to create {}: {}", path.display(), err)))?;
+    Self::new(BufWriter::new(file), calibration)
+  }
+}
+
+impl<W: Write + Seek + Send> BagWriter<W> {
+  /// Starts a bag on `writer`. The camera_info topics are only present with a calibration.
+  pub fn new(writer: W, calibration: Option<StereoCalibration>) -> Result<Self> {
+    let mut topics = vec![
+      ("/rigel/left/image_raw", IMAGE_TYPE, IMAGE_MD5, image_definition()),
+      ("/rigel/right/image_raw", IMAGE_TYPE, IMAGE_MD5, image_definition())
+    ];
+    if calibration.is_some() {
+      topics.push(("/rigel/left/camera_info", CAMERA_INFO_TYPE, CAMERA_INFO_MD5, camera_info_definition()));
+      topics.push(("/rigel/right/camera_info", CAMERA_INFO_TYPE, CAMERA_INFO_MD5, camera_info_definition()));
+    }
+    let connections = topics.into_iter().enumerate().map(|(id, (topic, msg_type, md5, definition))| {
+      let mut data = RecordHeader(Vec::new());
+      data.field("topic", topic.as_bytes()).field("type", msg_type.as_bytes()).field("md5sum", md5.as_bytes())
+        .field("message_definition", definition.as_bytes()).field("callerid", b"/tinyrigel").field("latching", b"0");
+      RecordHeader::new(OP_CONNECTION).u32("conn", id as u32).field("topic", topic.as_bytes()).record(&data.0)
+    }).collect();
+
+    let mut bag = Self {
+      writer: Some(writer),
+      position: 0,
+      calibration,
+      clock: FrameClock::new(),
+      connections,
+      chunk: Vec::new(),
+      chunk_times: None,
+      chunk_index: BTreeMap::new(),
+      chunk_infos: Vec::new(),
+      finished: false
+    };
+    bag.write(MAGIC)?;
+    // A placeholder, rewritten by finish.
+    let header = bag_header_record(0, 0, 0);
+    bag.write(&header)?;
+    Ok(bag)
+  }
+
+  /// Anchors header stamps: the first frame written is stamped `start`, later frames by their timestamp offset from it. Defaults to the wall-clock time the first frame is written.
+  pub fn set_time_base(&mut self, start: SystemTime) { self.clock.set_start(start); }
+
+  /// Writes every frame of `archive`, e.g. a `RecordingReader`. Returns the number of frames written.
+  pub fn write_archive(&mut self, archive: &mut dyn FrameArchive) -> Result<usize> {
+    for index in 0..archive.frame_count() {
+      self.write_frame(&archive.read_frame(index)?)?;
+    }
+    Ok(archive.frame_count())
+  }
+
+  /// Finishes the bag if that hasn't happened yet and returns the underlying writer.
+  pub fn into_inner(mut self) -> Result<W> {
+    if !self.finished {
+      self.finish()?;
+    }
+    Ok(self.writer.take().unwrap())
+  }
+
+  fn write(&mut self, bytes: &[u8]) -> Result<()> {
+    self.writer.as_mut().unwrap().write_all(bytes).map_err(io_error)?;
+    self.position += bytes.len() as u64;
+    Ok(())
+  }
+
+  fn add_message(&mut self, connection: u32, time: RosTime, data: &[u8]) -> Result<()> {
+    // Each chunk that carries a connection's messages gets its own copy of the connection record, ahead of the first of them, so chunks can be read on their own.
+    if !self.chunk_index.contains_key(&connection) {
+      self.chunk.extend_from_slice(&self.connections[connection as usize]);
+    }
+    self.chunk_index.entry(connection).or_default().push((time, self.chunk.len() as u32));
+    let record = RecordHeader::new(OP_MESSAGE_DATA).u32("conn", connection).time("time", time).record(data);
+    self.chunk.extend_from_slice(&record);
+    self.chunk_times = Some(match self.chunk_times {
+      Some((start, end)) => (start.min(time), end.max(time)),
+      None => (time, time)
+    });
+    Ok(())
+  }
+
+  fn flush_chunk(&mut self) -> Result<()> {
+    let (start, end) = match self.chunk_times.take() {
+      Some(times) => times,
+      None => return Ok(())
+    };
+    let chunk_pos = self.position;
+    let data = std::mem::take(&mut self.chunk);
+    let record = RecordHeader::new(OP_CHUNK).field("compression", b"none").u32("size", data.len() as u32).record(&data);
+    self.write(&record)?;
+
+    let mut counts = Vec::new();
+    for (connection, entries) in std::mem::take(&mut self.chunk_index) {
+      let mut index = Vec::with_capacity(entries.len() * 12);
+      for (time, offset) in &entries {
+        index.extend_from_slice(&ros_time_bytes(*time));
+        index.extend_from_slice(&offset.to_le_bytes());
+      }
+      let record = RecordHeader::new(OP_INDEX_DATA).u32("ver", 1).u32("conn", connection).u32("count", entries.len() as u32).record(&index);
+      self.write(&record)?;
+      counts.extend_from_slice(&connection.to_le_bytes());
+      counts.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    }
+
+    let connection_count = counts.len() as u32 / 8;
+    self.chunk_infos.push(RecordHeader::new(OP_CHUNK_INFO)
+      .u32("ver", 1).u64("chunk_pos", chunk_pos).time("start_time", start).time("end_time", end).u32("count", connection_count)
+      .record(&counts));
+    Ok(())
+  }
+
+  fn image_message(&self, frame: &Frame, eye: Eye, stamp: RosTime) -> Vec<u8> {
+    let layout = match eye { Eye::Left => EyeLayout::Left, Eye::Right => EyeLayout::Right };
+    let mut msg = Ros1(Vec::with_capacity(64 + (frame.width * frame.height) as usize));
+    msg.header(frame.sequence as u32, stamp, frame_id(eye));
+    msg.u32(frame.height);
+    msg.u32(frame.width);
+    msg.str("mono8");
+    msg.u8(0);
+    msg.u32(frame.width);
+    msg.bytes(&frame.layout_pixels(layout));
+    msg.0
+  }
+
+  fn camera_info_message(calibration: &StereoCalibration, eye: Eye, sequence: u32, stamp: RosTime) -> Vec<u8> {
+    let camera = calibration.camera(eye);
+    let (model, coefficients) = camera.ros_distortion();
+    let k: Vec<f64> = camera.camera_matrix().iter().flatten().copied().collect();
+    let mut msg = Ros1(Vec::new());
+    msg.header(sequence, stamp, frame_id(eye));
+    msg.u32(camera.height);
+    msg.u32(camera.width);
+    msg.str(model);
+    msg.u32(coefficients.len() as u32);
+    msg.f64s(&coefficients);
+    msg.f64s(&k);
+    msg.f64s(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    msg.f64s(&calibration.ros_projection(eye));
+    // binning_x, binning_y, then an empty region of interest.
+    for _ in 0..6 { msg.u32(0); }
+    msg.u8(0);
+    msg.0
+  }
+}
+
+fn bag_header_record(index_pos: u64, connection_count: u32, chunk_count: u32) -> Vec<u8> {
+  let mut header = RecordHeader::new(OP_BAG_HEADER);
+  header.u64("index_pos", index_pos).u32("conn_count", connection_count).u32("chunk_count", chunk_count);
+  // Pad the data with spaces so the whole record is exactly BAG_HEADER_LEN bytes.
+  let padding = vec![b' '; BAG_HEADER_LEN - header.0.len() - 8];
+  header.record(&padding)
+}
+
+fn frame_id(eye: Eye) -> &'static str {
+  match eye { Eye::Left => "rigel_left", Eye::Right => "rigel_right" }
+}
+
+impl<W: Write + Seek + Send> FrameSink for BagWriter<W> {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    if self.finished {
+      return Err(Error::new("The bag has already been finished.".to_string()));
+    }
+    let stamp = ros_time(self.clock.stamp(frame.timestamp));
+    for (connection, eye) in [(0, Eye::Left), (1, Eye::Right)].iter().copied() {
+      let message = self.image_message(frame, eye, stamp);
+      self.add_message(connection, stamp, &message)?;
+    }
+    if let Some(calibration) = self.calibration.clone() {
+      for (connection, eye) in [(2, Eye::Left), (3, Eye::Right)].iter().copied() {
+        let message = Self::camera_info_message(&calibration, eye, frame.sequence as u32, stamp);
+        self.add_message(connection, stamp, &message)?;
+      }
+    }
+    // Chunks close between frames, so both eyes of a frame share a chunk.
+    if self.chunk.len() >= CHUNK_SIZE {
+      self.flush_chunk()?;
+    }
+    Ok(())
+  }
+
+  /// Writes the last chunk and the index section, and points the bag header at it.
+  fn finish(&mut self) -> Result<()> {
+    if self.finished {
+      return Ok(());
+    }
+    self.finished = true;
+    self.flush_chunk()?;
+
+    let index_pos = self.position;
+    let connections = self.connections.concat();
+    self.write(&connections)?;
+    let chunk_infos = std::mem::take(&mut self.chunk_infos);
+    self.write(&chunk_infos.concat())?;
+
+    let header = bag_header_record(index_pos, self.connections.len() as u32, chunk_infos.len() as u32);
+    let writer = self.writer.as_mut().unwrap();
+    writer.seek(SeekFrom::Start(MAGIC.len() as u64)).and_then(|_| writer.write_all(&header))
+      .and_then(|_| writer.seek(SeekFrom::End(0))).and_then(|_| writer.flush())
+      .map_err(io_error)
+  }
+}
+
+impl<W: Write + Seek + Send> Drop for BagWriter<W> {
+  fn drop(&mut self) {
+    if !self.finished && self.writer.is_some() {
+      let _ = self.finish();
+    }
+  }
+}
@@ -0,0 +1,38 @@
+// sink.rs - tinyrigel
+
+use std::time::{Duration, SystemTime};
+
+use crate::*;
+
+/// Consumes frames as they're captured, e.g. to record or export them. Sinks attached to a Rigel receive every frame before the frame callback (or before `next_frame` returns) and run on the capture thread, so they should be quick or hand work off to another thread.
+pub trait FrameSink: Send {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()>;
+
+  /// Called with the value of each control when the sink is attached, and again whenever a control changes, so sinks can note the settings frames were captured with.
+  fn control_changed(&mut self, _control: Control, _value: i64) {}
+
+  /// Flushes and finalizes the output. Called once, when the sink is detached from its Rigel.
+  fn finish(&mut self) -> Result<()>;
+}
+
+/// Maps frame timestamps onto wall-clock time, for sinks whose formats stamp messages with absolute times. The first frame is stamped with the start time and later frames by their timestamp offset from it.
+pub(crate) struct FrameClock {
+  start: Option<SystemTime>,
+  first_timestamp: Option<Duration>
+}
+
+impl FrameClock {
+  pub(crate) fn new() -> Self { Self { start: None, first_timestamp: None } }
+
+  /// Defaults to the wall-clock time of the first `stamp` call.
+  pub(crate) fn set_start(&mut self, start: SystemTime) {
+    self.start = Some(start);
+    self.first_timestamp = None;
+  }
+
+  pub(crate) fn stamp(&mut self, timestamp: Duration) -> SystemTime {
+    let start = *self.start.get_or_insert_with(SystemTime::now);
+    let first = *self.first_timestamp.get_or_insert(timestamp);
+    start + timestamp.saturating_sub(first)
+  }
+}
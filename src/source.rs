@@ -0,0 +1,122 @@
+// source.rs - tinyrigel
+//
+// The Backend / DeviceSource pair is the single interface every frame producer sits behind: platform capture (V4L2), synthetic and replayed sources, and anything users plug in themselves, e.g. a network receiver. A Backend finds devices; a DeviceSource is one opened device.
+
+use std::time::Duration;
+
+use crate::*;
+
+/// Identifies a device a Backend can open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+  /// Human-readable device name, e.g. "Leap Motion Controller (Rigel)".
+  pub name: String,
+  /// Backend-specific locator, e.g. "/dev/video2" for V4L2.
+  pub path: String,
+  /// Device serial number, if the backend can determine it.
+  pub serial: Option<String>
+}
+
+/// Per-eye resolution and frame rate of a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureMode {
+  pub width: u32,
+  pub height: u32,
+  pub fps: u32
+}
+
+impl CaptureMode {
+  /// The Rigel's native mode, 384x384 per eye at 90 fps.
+  pub const RIGEL_DEFAULT: CaptureMode = CaptureMode { width: RIGEL_EYE_WIDTH, height: RIGEL_EYE_HEIGHT, fps: 90 };
+}
+
+/// Device controls. The named variants cover what the Rigel exposes; `Other` passes a backend-specific control id through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Control {
+  Exposure,
+  Gain,
+  Gamma,
+  Brightness,
+  Contrast,
+  Other(u32)
+}
+
+/// Describes a control a device supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlInfo {
+  pub control: Control,
+  pub name: String,
+  pub min: i64,
+  pub max: i64,
+  pub step: i64,
+  pub default: i64
+}
+
+/// Enumerates and opens devices of one kind.
+pub trait Backend {
+  /// Short identifier for the backend, e.g. "v4l2" or "mock".
+  fn name(&self) -> &str;
+
+  /// Lists the devices this backend can currently open.
+  fn enumerate(&self) -> Result<Vec<DeviceInfo>>;
+
+  /// Opens a device returned by `enumerate`. The returned source is configured for its default mode but not yet streaming.
+  fn open(&self, device: &DeviceInfo) -> Result<Box<dyn DeviceSource>>;
+}
+
+/// Anything that can produce a stream of Rigel frames: a physical device, a synthetic mock, or a wrapper around another source.
+pub trait DeviceSource: Send {
+  /// Describes the device this source reads from.
+  fn info(&self) -> DeviceInfo;
+
+  /// Lists the capture modes the device supports.
+  fn modes(&self) -> Result<Vec<CaptureMode>>;
+
+  /// The currently configured capture mode.
+  fn mode(&self) -> CaptureMode;
+
+  /// Selects a capture mode. Only valid while not streaming. Returns the mode actually applied, which may differ if the device adjusts the request.
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode>;
+
+  /// Begin streaming. Frames are available from `next_frame` until `stop` is called.
+  fn start(&mut self) -> Result<()>;
+
+  /// Stop streaming and release any streaming resources.
+  fn stop(&mut self) -> Result<()>;
+
+  /// Block until the next frame is available, or fail with `ErrorKind::Timeout` after `timeout`. Fails with `ErrorKind::Disconnected` if the device has gone away.
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame>;
+
+  /// Lists the controls the device supports. Sources without controls return an empty list.
+  fn controls(&self) -> Result<Vec<ControlInfo>> { Ok(Vec::new()) }
+
+  fn control(&self, control: Control) -> Result<i64> {
+    Err(Error::new(format!("Control {:?} is not supported by {}.", control, self.info().name)))
+  }
+
+  fn set_control(&mut self, control: Control, _value: i64) -> Result<()> {
+    Err(Error::new(format!("Control {:?} is not supported by {}.", control, self.info().name)))
+  }
+}
+
+impl<S: DeviceSource + ?Sized> DeviceSource for Box<S> {
+  fn info(&self) -> DeviceInfo { (**self).info() }
+  fn modes(&self) -> Result<Vec<CaptureMode>> { (**self).modes() }
+  fn mode(&self) -> CaptureMode { (**self).mode() }
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> { (**self).configure(mode) }
+  fn start(&mut self) -> Result<()> { (**self).start() }
+  fn stop(&mut self) -> Result<()> { (**self).stop() }
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame> { (**self).next_frame(timeout) }
+  fn controls(&self) -> Result<Vec<ControlInfo>> { (**self).controls() }
+  fn control(&self, control: Control) -> Result<i64> { (**self).control(control) }
+  fn set_control(&mut self, control: Control, value: i64) -> Result<()> { (**self).set_control(control, value) }
+}
+
+/// The capture backend for the current platform.
+pub fn default_backend() -> Result<Box<dyn Backend>> {
+  #[cfg(target_os = "linux")]
+  { Ok(Box::new(V4l2Backend::new())) }
+
+  #[cfg(not(target_os = "linux"))]
+  { Err(Error::new("No capture backend is implemented for this platform yet.".to_string())) }
+}
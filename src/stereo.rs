@@ -0,0 +1,296 @@
+// stereo.rs - tinyrigel
+//
+// Dense disparity from rectified stereo frames, and metric depth from disparity. Matching costs are Hamming distances between 5x5 census transforms, which hold up well against the brightness differences between the Rigel's two IR cameras. The costs are then either summed over a square block (fast) or aggregated semi-globally along eight directions (smoother, fewer holes), followed by a winner-takes-all pick, a uniqueness test, a left-right consistency check and parabolic subpixel refinement.
+
+use crate::*;
+
+// Census windows are 5x5, so costs are at most 24.
+const CENSUS_RADIUS: usize = 2;
+const MAX_CENSUS_COST: u16 = 24;
+
+/// How matching costs are combined before picking a disparity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchingMethod {
+  /// Sums costs over a square block around each pixel.
+  Block,
+  /// Semi-global matching: aggregates costs along eight directions, penalizing disparity changes between neighbours.
+  SemiGlobal
+}
+
+/// Computes disparity maps from rectified frames. The left eye is the reference: a left pixel at column `x` with disparity `d` matches the right pixel at `x - d`.
+pub struct StereoMatcher {
+  method: MatchingMethod,
+  min_disparity: u32,
+  num_disparities: u32,
+  block_size: u32,
+  penalties: (u16, u16),
+  uniqueness_ratio: u32,
+  left_right_tolerance: Option<f32>
+}
+
+impl StereoMatcher {
+  /// Searches disparities `0..num_disparities`. For the Rigel's baseline, hand range (10-60 cm) needs about 64 at full resolution.
+  pub fn new(method: MatchingMethod, num_disparities: u32) -> Self {
+    Self {
+      method,
+      min_disparity: 0,
+      num_disparities: num_disparities.max(1),
+      block_size: 7,
+      penalties: (8, 32),
+      uniqueness_ratio: 10,
+      left_right_tolerance: Some(1.0)
+    }
+  }
+
+  /// Starts the search at `min_disparity` instead of 0, skipping far-away depths. Searches `min_disparity..min_disparity + num_disparities`.
+  pub fn set_min_disparity(&mut self, min_disparity: u32) { self.min_disparity = min_disparity; }
+
+  /// Side of the square block costs are summed over with `MatchingMethod::Block`, rounded up to an odd size. Defaults to 7.
+  pub fn set_block_size(&mut self, block_size: u32) { self.block_size = (block_size.max(1) | 1).min(31); }
+
+  /// Semi-global penalties for a disparity change of one (`p1`) and of more than one (`p2`) between neighbouring pixels, in census cost units (0-24 per pixel). Larger values give smoother maps. Defaults to 8 and 32.
+  pub fn set_penalties(&mut self, p1: u16, p2: u16) {
+    let p2 = p2.clamp(1, 1000);
+    self.penalties = (p1.min(p2), p2);
+  }
+
+  /// How much worse, in percent, the best cost of any clearly different disparity must be for a match to count as unique. 0 disables the test. Defaults to 10.
+  pub fn set_uniqueness_ratio(&mut self, percent: u32) { self.uniqueness_ratio = percent; }
+
+  /// Maximum difference between the left-to-right and right-to-left disparities of a pixel, or `None` to skip the consistency check. Defaults to 1 pixel.
+  pub fn set_left_right_check(&mut self, tolerance: Option<f32>) { self.left_right_tolerance = tolerance; }
+
+  /// Computes the disparity map of a rectified frame (see `StereoRectifier`), in left-eye pixels.
+  pub fn compute(&self, frame: &Frame) -> Result<DisparityMap> {
+    if !frame.is_complete() {
+      return Err(Error::new(format!("Frame {} is incomplete ({} of {} bytes).", frame.sequence, frame.data.len(), frame.expected_len())));
+    }
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let levels = self.num_disparities as usize;
+    let left = census(&frame.data, width, height, frame.stride());
+    let right = census(&frame.data[width.min(frame.data.len())..], width, height, frame.stride());
+
+    // Matching costs, laid out [row][column][disparity]. Matches falling off the right image cost the maximum.
+    let min_disparity = self.min_disparity as usize;
+    let mut costs = vec![MAX_CENSUS_COST; width * height * levels];
+    for y in 0..height {
+      let (left_row, right_row) = (&left[y * width..(y + 1) * width], &right[y * width..(y + 1) * width]);
+      for (x, &left_bits) in left_row.iter().enumerate() {
+        // Level l compares against right column x - min_disparity - l.
+        let valid_levels = (x + 1).saturating_sub(min_disparity).min(levels);
+        if valid_levels == 0 { continue; }
+        let candidates = right_row[x + 1 - min_disparity - valid_levels..x + 1 - min_disparity].iter().rev();
+        let cell = &mut costs[(y * width + x) * levels..(y * width + x) * levels + valid_levels];
+        for (cost, &right_bits) in cell.iter_mut().zip(candidates) {
+          *cost = (left_bits ^ right_bits).count_ones() as u16;
+        }
+      }
+    }
+    let aggregated = match self.method {
+      MatchingMethod::Block => box_filter(&costs, width, height, levels, self.block_size as usize / 2),
+      MatchingMethod::SemiGlobal => semi_global(&costs, width, height, levels, self.penalties)
+    };
+    Ok(self.select(&aggregated, width, height, levels))
+  }
+
+  // Winner-takes-all with the uniqueness, left-right and subpixel steps.
+  fn select(&self, aggregated: &[u16], width: usize, height: usize, levels: usize) -> DisparityMap {
+    let min_disparity = self.min_disparity as usize;
+    let mut data = vec![f32::NAN; width * height];
+    let mut best_levels = vec![usize::MAX; width];
+    let mut right_best = vec![(u16::MAX, usize::MAX); width];
+    for y in 0..height {
+      for slot in right_best.iter_mut() { *slot = (u16::MAX, usize::MAX); }
+      for x in 0..width {
+        best_levels[x] = usize::MAX;
+        // Only disparities that land inside the right image count.
+        let valid_levels = (x + 1).saturating_sub(min_disparity).min(levels);
+        if valid_levels == 0 { continue; }
+        let cell = &aggregated[(y * width + x) * levels..(y * width + x) * levels + valid_levels];
+        let (mut best, mut best_cost) = (0, u16::MAX);
+        for (level, &cost) in cell.iter().enumerate() {
+          if cost < best_cost { best = level; best_cost = cost; }
+          let right = &mut right_best[x - min_disparity - level];
+          if cost < right.0 { *right = (cost, level); }
+        }
+        if self.uniqueness_ratio > 0 {
+          let others = cell[..best.saturating_sub(1)].iter().chain(cell.get(best + 2..).unwrap_or(&[]));
+          let runner_up = others.copied().min().unwrap_or(u16::MAX) as u32;
+          if runner_up * 100 < best_cost as u32 * (100 + self.uniqueness_ratio) { continue; }
+        }
+        best_levels[x] = best;
+        let mut disparity = best as f32;
+        if best > 0 && best + 1 < valid_levels {
+          let (before, after) = (cell[best - 1] as f32, cell[best + 1] as f32);
+          let curvature = before - 2.0 * best_cost as f32 + after;
+          if curvature > 0.0 { disparity += (before - after) / (2.0 * curvature); }
+        }
+        data[y * width + x] = disparity + min_disparity as f32;
+      }
+      if let Some(tolerance) = self.left_right_tolerance {
+        for x in 0..width {
+          if best_levels[x] == usize::MAX { continue; }
+          let xr = x - min_disparity - best_levels[x];
+          if (right_best[xr].1 as f32 - best_levels[x] as f32).abs() > tolerance {
+            data[y * width + x] = f32::NAN;
+          }
+        }
+      }
+    }
+    DisparityMap { width: width as u32, height: height as u32, data }
+  }
+}
+
+// 5x5 census transform of one eye: one bit per neighbour, set where the neighbour is darker than the center. Borders repeat the edge pixels.
+fn census(data: &[u8], width: usize, height: usize, stride: usize) -> Vec<u32> {
+  let mut out = vec![0u32; width * height];
+  let radius = CENSUS_RADIUS;
+  // Edge-padded copy of the eye, so the window never needs clamping.
+  let padded_width = width + 2 * radius;
+  let mut padded = vec![0u8; padded_width * (height + 2 * radius)];
+  for (py, padded_row) in padded.chunks_exact_mut(padded_width).enumerate() {
+    let row = &data[(py.saturating_sub(radius)).min(height.saturating_sub(1)) * stride..][..width];
+    for (px, value) in padded_row.iter_mut().enumerate() {
+      *value = row[px.saturating_sub(radius).min(width.saturating_sub(1))];
+    }
+  }
+  for y in 0..height {
+    for x in 0..width {
+      let center = padded[(y + radius) * padded_width + x + radius];
+      let mut bits = 0u32;
+      for dy in 0..2 * radius + 1 {
+        let window = &padded[(y + dy) * padded_width + x..][..2 * radius + 1];
+        for (dx, &value) in window.iter().enumerate() {
+          if dx == radius && dy == radius { continue; }
+          bits = (bits << 1) | (value < center) as u32;
+        }
+      }
+      out[y * width + x] = bits;
+    }
+  }
+  out
+}
+
+// Sums each disparity slice over a (2 radius + 1)^2 block, clipped at the image edges, with running sums along rows and then columns.
+fn box_filter(costs: &[u16], width: usize, height: usize, levels: usize, radius: usize) -> Vec<u16> {
+  let row_len = width * levels;
+  let mut rows = vec![0u16; costs.len()];
+  let mut running = vec![0u16; levels];
+  for y in 0..height {
+    let (src, dst) = (&costs[y * row_len..(y + 1) * row_len], &mut rows[y * row_len..(y + 1) * row_len]);
+    running.iter_mut().for_each(|sum| *sum = 0);
+    for x in 0..radius.min(width) { add_cells(&mut running, &src[x * levels..(x + 1) * levels]); }
+    for x in 0..width {
+      if x + radius < width { add_cells(&mut running, &src[(x + radius) * levels..(x + radius + 1) * levels]); }
+      if x > radius { sub_cells(&mut running, &src[(x - radius - 1) * levels..(x - radius) * levels]); }
+      dst[x * levels..(x + 1) * levels].copy_from_slice(&running);
+    }
+  }
+  let mut out = vec![0u16; costs.len()];
+  let mut running = vec![0u16; row_len];
+  for y in 0..radius.min(height) { add_cells(&mut running, &rows[y * row_len..(y + 1) * row_len]); }
+  for y in 0..height {
+    if y + radius < height { add_cells(&mut running, &rows[(y + radius) * row_len..(y + radius + 1) * row_len]); }
+    if y > radius { sub_cells(&mut running, &rows[(y - radius - 1) * row_len..(y - radius) * row_len]); }
+    out[y * row_len..(y + 1) * row_len].copy_from_slice(&running);
+  }
+  out
+}
+
+fn add_cells(sum: &mut [u16], cells: &[u16]) { sum.iter_mut().zip(cells).for_each(|(sum, cell)| *sum += cell); }
+
+fn sub_cells(sum: &mut [u16], cells: &[u16]) { sum.iter_mut().zip(cells).for_each(|(sum, cell)| *sum -= cell); }
+
+// Semi-global aggregation along the eight horizontal, vertical and diagonal directions.
+fn semi_global(costs: &[u16], width: usize, height: usize, levels: usize, (p1, p2): (u16, u16)) -> Vec<u16> {
+  let mut sum = vec![0u16; costs.len()];
+  let mut path = vec![0u16; costs.len()];
+  // The previous pixel's path costs, padded with a large value on both ends so every level has two neighbours.
+  let mut previous = vec![u16::MAX / 2; levels + 2];
+  let directions: [(i64, i64); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, 1), (1, -1), (-1, -1)];
+  for (dx, dy) in directions.iter().copied() {
+    // Visit pixels so that the previous pixel along the direction, (x - dx, y - dy), is always done first.
+    for row in 0..height {
+      let y = if dy < 0 { height - 1 - row } else { row };
+      for col in 0..width {
+        let x = if dx < 0 { width - 1 - col } else { col };
+        let at = (y * width + x) * levels;
+        let cost = &costs[at..at + levels];
+        let (px, py) = (x as i64 - dx, y as i64 - dy);
+        if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+          path[at..at + levels].copy_from_slice(cost);
+        } else {
+          let prev_at = (py as usize * width + px as usize) * levels;
+          previous[1..=levels].copy_from_slice(&path[prev_at..prev_at + levels]);
+          let min_prev = *previous[1..=levels].iter().min().unwrap();
+          let jump = min_prev + p2;
+          for ((current, cost), neighbours) in path[at..at + levels].iter_mut().zip(cost).zip(previous.windows(3)) {
+            let best = neighbours[1].min(neighbours[0].min(neighbours[2]) + p1).min(jump);
+            *current = cost + best - min_prev;
+          }
+        }
+        add_cells(&mut sum[at..at + levels], &path[at..at + levels]);
+      }
+    }
+  }
+  sum
+}
+
+/// Disparities of a rectified left eye image, in pixels. Pixels without a reliable match hold NaN.
+#[derive(Debug, Clone)]
+pub struct DisparityMap {
+  pub width: u32,
+  pub height: u32,
+  /// Row-major disparities.
+  pub data: Vec<f32>
+}
+
+impl DisparityMap {
+  /// The disparity at `(x, y)`, or `None` where there was no reliable match.
+  pub fn get(&self, x: u32, y: u32) -> Option<f32> {
+    let d = self.data[(y * self.width + x) as usize];
+    if d.is_nan() { None } else { Some(d) }
+  }
+
+  /// Fraction of pixels with a disparity.
+  pub fn density(&self) -> f32 {
+    if self.data.is_empty() { return 0.0; }
+    self.data.iter().filter(|d| !d.is_nan()).count() as f32 / self.data.len() as f32
+  }
+
+  /// Converts to metric depth using the rectified focal length and baseline: Z = f * B / d. Disparities of zero or less have no depth.
+  pub fn to_depth(&self, rectifier: &StereoRectifier) -> DepthMap {
+    let camera = rectifier.rectified_camera(Eye::Left).clone();
+    // From Q: Z = Q[2][3] / (Q[3][2] d), which holds for a vertically rectified rig as well as a horizontal one.
+    let q = rectifier.q();
+    let focal_baseline = (q[2][3] / q[3][2]) as f32;
+    let data = self.data.iter().map(|&d| if d > 0.0 { focal_baseline / d } else { f32::NAN }).collect();
+    DepthMap { width: self.width, height: self.height, data, camera }
+  }
+}
+
+/// Depth along the optical axis of the rectified left camera, in meters. Pixels without a depth hold NaN.
+#[derive(Debug, Clone)]
+pub struct DepthMap {
+  pub width: u32,
+  pub height: u32,
+  /// Row-major depths.
+  pub data: Vec<f32>,
+  /// The rectified left camera the depths are measured in.
+  pub camera: CameraIntrinsics
+}
+
+impl DepthMap {
+  /// The depth at `(x, y)`, or `None` where there is none.
+  pub fn get(&self, x: u32, y: u32) -> Option<f32> {
+    let z = self.data[(y * self.width + x) as usize];
+    if z.is_nan() { None } else { Some(z) }
+  }
+
+  /// The 3D point seen at `(x, y)`, in meters in the rectified left camera frame.
+  pub fn point(&self, x: u32, y: u32) -> Option<[f32; 3]> {
+    let z = self.get(x, y)?;
+    let camera = &self.camera;
+    Some([((x as f64 - camera.cx) / camera.fx) as f32 * z, ((y as f64 - camera.cy) / camera.fy) as f32 * z, z])
+  }
+}
@@ -0,0 +1,104 @@
+// tests/fixtures.rs
+//
+// Calibrations, renderers and small helpers shared by the tests.
+
+use std::time::Duration;
+
+use crate::*;
+
+// A stereo pair with the right eye at `rotation` (a rotation vector) and `translation` from the left.
+pub(crate) fn stereo_rig(left: CameraIntrinsics, right: CameraIntrinsics, rotation: Vec3, translation: Vec3) -> StereoCalibration {
+  StereoCalibration { left, right, rotation: rodrigues(rotation), translation, serial: None }
+}
+
+// Two copies of `camera` side by side, `baseline` meters apart.
+pub(crate) fn matched_pair(camera: CameraIntrinsics, baseline: f64) -> StereoCalibration {
+  stereo_rig(camera.clone(), camera, [0.0; 3], [-baseline, 0.0, 0.0])
+}
+
+// A 320x240 rig a little out of alignment, with the given lens models.
+pub(crate) fn qvga_rig(left: Distortion, right: Distortion) -> StereoCalibration {
+  stereo_rig(
+    CameraIntrinsics { width: 320, height: 240, fx: 205.0, fy: 204.0, cx: 158.3, cy: 121.7, distortion: left },
+    CameraIntrinsics { width: 320, height: 240, fx: 208.0, fy: 207.5, cx: 162.1, cy: 118.4, distortion: right },
+    [0.004, -0.01, 0.002],
+    [-0.064, 0.0007, 0.001]
+  )
+}
+
+// A random number for the square block of side `block` that (x, y) falls in, the same every time.
+pub(crate) fn block_hash(x: f64, y: f64, block: f64) -> u64 {
+  let (i, j) = ((x / block).floor() as i64 as u64, (y / block).floor() as i64 as u64);
+  let mut hash = i.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ j.wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+  hash ^= hash >> 29;
+  hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+  hash ^= hash >> 32;
+  hash
+}
+
+pub(crate) fn distance(a: Vec3, b: Vec3) -> f64 { norm([a[0] - b[0], a[1] - b[1], a[2] - b[2]]) }
+
+// Little-endian integers in encoded records.
+pub(crate) fn u32_at(b: &[u8], at: usize) -> u32 { let mut v = [0; 4]; v.copy_from_slice(&b[at..at + 4]); u32::from_le_bytes(v) }
+pub(crate) fn u64_at(b: &[u8], at: usize) -> u64 { let mut v = [0; 8]; v.copy_from_slice(&b[at..at + 8]); u64::from_le_bytes(v) }
+
+// A rig that renders a plane (z = 0 in its own frame) into both eyes. Each eye's pixels are 4x4 normalized rays, unprojected once for all renders.
+pub(crate) struct Rig {
+  pub(crate) calibration: StereoCalibration,
+  rays: [Vec<(f64, f64)>; 2]
+}
+
+impl Rig {
+  pub(crate) fn new(calibration: StereoCalibration) -> Self {
+    let rays = |camera: &CameraIntrinsics| -> Vec<(f64, f64)> {
+      let mut rays = Vec::with_capacity((camera.width * camera.height * 16) as usize);
+      for v in 0..camera.height {
+        for u in 0..camera.width {
+          for sub in 0..16 {
+            rays.push(camera.unproject(u as f64 + (sub % 4) as f64 / 4.0 - 0.375, v as f64 + (sub / 4) as f64 / 4.0 - 0.375));
+          }
+        }
+      }
+      rays
+    };
+    let rays = [rays(&calibration.left), rays(&calibration.right)];
+    Self { calibration, rays }
+  }
+
+  // The plane's pose in each eye, from its pose in the left camera frame.
+  pub(crate) fn poses(&self, (rotation, translation): (Mat3, Vec3)) -> [(Mat3, Vec3); 2] {
+    let moved = mat_vec(&self.calibration.rotation, translation);
+    let t = self.calibration.translation;
+    [(rotation, translation), (mat_mul(&self.calibration.rotation, &rotation), [moved[0] + t[0], moved[1] + t[1], moved[2] + t[2]])]
+  }
+
+  // A frame of the plane at `pose`, where `shade` gives its gray at each point. Rays that miss it see gray 100.
+  pub(crate) fn render(&self, shade: &dyn Fn(f64, f64) -> f64, pose: (Mat3, Vec3), sequence: u64) -> Frame {
+    let (width, height) = (self.calibration.left.width, self.calibration.left.height);
+    let mut data = vec![0u8; (width * height * 2) as usize];
+    for (eye, (rotation, translation)) in self.poses(pose).iter().enumerate() {
+      let back = transpose(rotation);
+      let origin = mat_vec(&back, scale(*translation, -1.0));
+      for (pixel, rays) in self.rays[eye].chunks(16).enumerate() {
+        let mut sum = 0.0;
+        for &(x, y) in rays {
+          let ray = mat_vec(&back, [x, y, 1.0]);
+          let s = -origin[2] / ray[2];
+          sum += if s > 0.0 { shade(origin[0] + s * ray[0], origin[1] + s * ray[1]) } else { 100.0 };
+        }
+        let (u, v) = (pixel as u32 % width, pixel as u32 / width);
+        data[(v * width * 2 + eye as u32 * width + u) as usize] = (sum / 16.0).round() as u8;
+      }
+    }
+    Frame::new(width, height, data, sequence, Duration::from_millis(sequence * 11))
+  }
+
+  // Where a board corner lands in an eye.
+  pub(crate) fn project(&self, target: &CalibrationTarget, pose: (Mat3, Vec3), eye: Eye, id: u32) -> (f64, f64) {
+    let (rotation, translation) = self.poses(pose)[eye as usize];
+    let (x, y) = target.corner_position(id).unwrap();
+    let p = mat_vec(&rotation, [x, y, 0.0]);
+    let p = [p[0] + translation[0], p[1] + translation[1], p[2] + translation[2]];
+    self.calibration.camera(eye).project(p[0] / p[2], p[1] / p[2])
+  }
+}
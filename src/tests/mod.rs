@@ -8,3 +8,36 @@ mod tests_macos;
 
 #[cfg(target_os = "linux")]
 mod tests_linux;
+
+#[cfg(target_os = "linux")]
+mod tests_v4l2;
+
+// Calibrations, renderers and helpers shared by the tests below.
+mod fixtures;
+
+// Platform-independent tests.
+mod tests_backend;
+mod tests_frame;
+mod tests_fault;
+mod tests_replay;
+mod tests_recording;
+mod tests_export;
+mod tests_rosbag;
+mod tests_undistort;
+mod tests_rectify;
+mod tests_stereo;
+mod tests_pointcloud;
+mod tests_blobs;
+mod tests_tracker;
+mod tests_apriltag;
+mod tests_calibrate;
+mod tests_calibration;
+mod tests_validation;
+mod tests_features;
+mod tests_enhance;
+
+#[cfg(feature = "mcap")]
+mod tests_mcap;
+
+#[cfg(any(feature = "ndarray", feature = "nalgebra"))]
+mod tests_arrays;
@@ -0,0 +1,135 @@
+// tests/tests_apriltag.rs
+//
+// Renders tags at known poses through a lens model and checks IDs, corners and recovered poses.
+
+use std::time::Duration;
+
+use crate::*;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+fn camera() -> CameraIntrinsics {
+  CameraIntrinsics { width: WIDTH, height: HEIGHT, fx: 210.0, fy: 211.0, cx: 161.5, cy: 118.0, distortion: Distortion::Equidistant([0.02, -0.01, 0.0, 0.0]) }
+}
+
+struct Placement {
+  family: TagFamily,
+  code: u64,
+  rotation: Mat3,
+  translation: Vec3,
+  size: f64
+}
+
+impl Placement {
+  fn new(family: TagFamily, id: u32, rotation: Vec3, translation: Vec3, size: f64) -> Self {
+    Self { code: family.code(id).unwrap(), family, rotation: rodrigues(rotation), translation, size }
+  }
+
+  // Gray level of the tag plane at tag coordinates (x right, y down, meters from the center), or None off the tag and its white margin.
+  fn shade(&self, x: f64, y: f64) -> Option<f64> {
+    let n = self.family.size() as i64 + 2;
+    let cell = self.size / n as f64;
+    let (cx, cy) = (((x + self.size / 2.0) / cell).floor() as i64, ((y + self.size / 2.0) / cell).floor() as i64);
+    if cx < -1 || cy < -1 || cx > n || cy > n { return None; }
+    if cx == -1 || cy == -1 || cx == n || cy == n { return Some(215.0); }
+    if cx == 0 || cy == 0 || cx == n - 1 || cy == n - 1 { return Some(35.0); }
+    let d = self.family.size() as i64;
+    let bit = (self.code >> (d * d - 1 - ((cy - 1) * d + cx - 1))) & 1;
+    Some(if bit == 1 { 215.0 } else { 35.0 })
+  }
+
+  fn corner(&self, camera: &CameraIntrinsics, (x, y): (f64, f64)) -> (f64, f64) {
+    let p = mat_vec(&self.rotation, [x * self.size / 2.0, y * self.size / 2.0, 0.0]);
+    let p = [p[0] + self.translation[0], p[1] + self.translation[1], p[2] + self.translation[2]];
+    camera.project(p[0] / p[2], p[1] / p[2])
+  }
+}
+
+// Renders the left eye with 4x4 supersampling; the right eye is flat gray.
+fn render(camera: &CameraIntrinsics, placements: &[Placement]) -> Frame {
+  let mut data = vec![120u8; (WIDTH * HEIGHT * 2) as usize];
+  for v in 0..HEIGHT {
+    for u in 0..WIDTH {
+      let mut sum = 0.0;
+      for sub in 0..16 {
+        let (du, dv) = ((sub % 4) as f64 / 4.0 - 0.375, (sub / 4) as f64 / 4.0 - 0.375);
+        let (x, y) = camera.unproject(u as f64 + du, v as f64 + dv);
+        let mut shade = 120.0;
+        for placement in placements {
+          // Intersect the ray with the tag plane, in tag coordinates.
+          let back = transpose(&placement.rotation);
+          let (ray, origin) = (mat_vec(&back, [x, y, 1.0]), mat_vec(&back, scale(placement.translation, -1.0)));
+          let s = -origin[2] / ray[2];
+          if s <= 0.0 { continue; }
+          if let Some(value) = placement.shade(origin[0] + s * ray[0], origin[1] + s * ray[1]) { shade = value; }
+        }
+        sum += shade;
+      }
+      data[(v * WIDTH * 2 + u) as usize] = (sum / 16.0).round() as u8;
+    }
+  }
+  Frame::new(WIDTH, HEIGHT, data, 0, Duration::from_secs(0))
+}
+
+fn angle_between(a: &Mat3, b: &Mat3) -> f64 { norm(rotation_vector(&mat_mul(&transpose(a), b))) }
+
+#[test]
+fn detects_tag36h11_with_corners_and_pose() -> Result<()> {
+  let camera = camera();
+  let placement = Placement::new(TagFamily::tag36h11(73)?, 42, [0.35, -0.25, 0.3], [0.03, -0.02, 0.45], 0.12);
+  let frame = render(&camera, &[placement]);
+  let placement = Placement::new(TagFamily::tag36h11(73)?, 42, [0.35, -0.25, 0.3], [0.03, -0.02, 0.45], 0.12);
+
+  let detector = AprilTagDetector::new(TagFamily::tag36h11(73)?);
+  let detections = detector.detect(&frame, Eye::Left)?;
+  assert_eq!(detections.len(), 1, "{:?}", detections);
+  let tag = &detections[0];
+  assert_eq!((tag.family.as_str(), tag.id, tag.hamming), ("tag36h11", 42, 0));
+  for (corner, unit) in tag.corners.iter().zip([(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter()) {
+    let expected = placement.corner(&camera, *unit);
+    assert!((corner.0 - expected.0).hypot(corner.1 - expected.1) < 0.25, "{:?} vs {:?}", corner, expected);
+  }
+
+  let pose = tag.pose(&camera, 0.12).unwrap();
+  assert!(norm([pose.translation[0] - 0.03, pose.translation[1] + 0.02, pose.translation[2] - 0.45]) < 0.003, "{:?}", pose.translation);
+  assert!(angle_between(&pose.rotation, &placement.rotation) < 1f64.to_radians(), "{:?}", pose.rotation);
+  assert!(pose.error < 0.1);
+
+  assert!(detector.detect(&render(&camera, &[]), Eye::Left)?.is_empty());
+  // A family limited to fewer IDs doesn't decode the tag, and IDs that aren't built in are refused outright.
+  assert!(AprilTagDetector::new(TagFamily::tag36h11(40)?).detect(&frame, Eye::Left)?.is_empty());
+  assert_eq!(TagFamily::tag36h11(73)?.len(), 73);
+  assert!(TagFamily::tag36h11(587).is_err());
+  assert!(detector.detect(&Frame::new(WIDTH, HEIGHT, vec![0; 10], 0, Duration::from_secs(0)), Eye::Left).is_err());
+  Ok(())
+}
+
+#[test]
+fn detects_several_families_and_orientations() -> Result<()> {
+  let camera = camera();
+  let family = TagFamily::tag16h5();
+  // A 16h5 tag turned upside down with one data bit flipped, and a 36h11 tag turned a quarter.
+  let mut damaged = Placement::new(TagFamily::tag16h5(), 7, [0.0, 0.1, std::f64::consts::PI + 0.2], [-0.12, 0.0, 0.6], 0.1);
+  damaged.code ^= 1 << 5;
+  let turned = Placement::new(TagFamily::tag36h11(73)?, 3, [-0.2, 0.0, std::f64::consts::FRAC_PI_2], [0.12, 0.02, 0.55], 0.11);
+  let corner = turned.corner(&camera, (-1.0, -1.0));
+  let frame = render(&camera, &[damaged, turned]);
+
+  let mut detector = AprilTagDetector::new(family);
+  detector.add_family(TagFamily::tag36h11(73)?);
+  let mut detections = detector.detect(&frame, Eye::Left)?;
+  detections.sort_by(|a, b| a.family.cmp(&b.family));
+  assert_eq!(detections.iter().map(|tag| (tag.family.as_str(), tag.id, tag.hamming)).collect::<Vec<_>>(), vec![("tag16h5", 7, 1), ("tag36h11", 3, 0)]);
+  // The top-left corner of the upright tag, wherever it lands in the image.
+  assert!((detections[1].corners[0].0 - corner.0).hypot(detections[1].corners[0].1 - corner.1) < 0.3);
+
+  detector.set_max_hamming(0);
+  assert_eq!(detector.detect(&frame, Eye::Left)?.len(), 1);
+
+  assert!(TagFamily::new("tiny", 1, 1, vec![0]).is_err());
+  assert!(TagFamily::new("tag16h5", 4, 5, vec![0x1_0000]).is_err());
+  let custom = TagFamily::new("custom", 4, 5, vec![0x231b])?;
+  assert_eq!((custom.name(), custom.len(), custom.code(0)), ("custom", 1, Some(0x231b)));
+  Ok(())
+}
@@ -0,0 +1,48 @@
+// tests/tests_arrays.rs
+//
+// Checks the ndarray and nalgebra views index the side-by-side buffer correctly.
+
+use std::time::Duration;
+
+use crate::*;
+
+// A 3x2 (per eye) frame: left eye pixels count up from 0, right eye from 100.
+fn small_frame() -> Frame {
+  let data = vec![0, 1, 2, 100, 101, 102, 3, 4, 5, 103, 104, 105];
+  Frame::new(3, 2, data, 0, Duration::from_secs(0))
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn ndarray_eye_and_stereo_views() -> Result<()> {
+  let frame = small_frame();
+  let right = frame.eye_array(Eye::Right).unwrap();
+  assert_eq!(right.shape(), &[2, 3]);
+  assert_eq!(right, ndarray::arr2(&[[100, 101, 102], [103, 104, 105]]));
+  assert_eq!(frame.eye_array(Eye::Left).unwrap().column(2).to_vec(), vec![2, 5]);
+
+  let stereo = frame.stereo_array().unwrap();
+  assert_eq!(stereo.shape(), &[2, 2, 3]);
+  assert_eq!(stereo[[0, 1, 0]], 3);
+  assert_eq!(stereo[[1, 1, 2]], 105);
+  assert_eq!(stereo.index_axis(ndarray::Axis(0), 1), right);
+
+  let mut short = small_frame();
+  short.data.pop();
+  assert!(short.eye_array(Eye::Left).is_none());
+  assert!(short.stereo_array().is_none());
+  Ok(())
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn nalgebra_eye_views() -> Result<()> {
+  let frame = small_frame();
+  let right = frame.eye_matrix(Eye::Right).unwrap();
+  assert_eq!(right.shape(), (2, 3));
+  assert_eq!(right[(1, 0)], 103);
+  assert_eq!(right.row(0).iter().copied().collect::<Vec<_>>(), vec![100, 101, 102]);
+  assert_eq!(frame.eye_matrix(Eye::Left).unwrap().column(1).iter().copied().collect::<Vec<_>>(), vec![1, 4]);
+  assert_eq!(frame.eye_matrix(Eye::Left).unwrap().map(u32::from).sum(), 15);
+  Ok(())
+}
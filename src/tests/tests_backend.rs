@@ -0,0 +1,94 @@
+// tests/tests_backend.rs
+//
+// Drives Rigel through the Backend / DeviceSource interface using the mock backend.
+
+use std::{sync::mpsc, time::Duration};
+
+use crate::*;
+
+fn mock_backend() -> MockBackend {
+  let mut backend = MockBackend::new();
+  backend.set_realtime(false);
+  backend
+}
+
+#[test]
+fn mock_backend_enumerates_and_opens() -> Result<()> {
+  let mut backend = mock_backend();
+  backend.set_device_count(2);
+  let devices = backend.enumerate()?;
+  assert_eq!(devices.len(), 2);
+  assert_eq!(devices[1].serial.as_deref(), Some("MOCK0001"));
+
+  let source = backend.open(&devices[1])?;
+  assert_eq!(source.info(), devices[1]);
+  assert_eq!(source.mode(), CaptureMode::RIGEL_DEFAULT);
+  Ok(())
+}
+
+#[test]
+fn rigel_pulls_frames_from_source() -> Result<()> {
+  let mut rigel: Rigel = Rigel::from_backend(&mock_backend())?;
+  assert!(rigel.next_frame(Duration::from_millis(10)).is_err());
+
+  rigel.open()?;
+  let first = rigel.next_frame(Duration::from_millis(100))?;
+  let second = rigel.next_frame(Duration::from_millis(100))?;
+  assert_eq!((first.sequence, second.sequence), (0, 1));
+  assert!(first.is_complete());
+  rigel.close()?;
+  Ok(())
+}
+
+#[test]
+fn rigel_delivers_frames_to_callback() -> Result<()> {
+  let mut rigel = Rigel::from_backend(&mock_backend())?;
+  let (tx, rx) = mpsc::channel();
+  rigel.set_callback(move |frame: &Frame| { let _ = tx.send(frame.sequence); });
+
+  rigel.open()?;
+  assert!(rigel.next_frame(Duration::from_millis(10)).is_err());
+  let sequences: Vec<u64> = (0..3).map(|_| rx.recv_timeout(Duration::from_secs(1)).unwrap()).collect();
+  rigel.close()?;
+  assert_eq!(sequences, vec![0, 1, 2]);
+
+  // The callback survives a close/open cycle.
+  rigel.open()?;
+  assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+  rigel.close()?;
+  Ok(())
+}
+
+#[test]
+fn rigel_configures_mode_and_controls() -> Result<()> {
+  let mut rigel: Rigel = Rigel::from_backend(&mock_backend())?;
+  let slow_mode = *rigel.modes()?.iter().find(|mode| mode.fps == 30).unwrap();
+  assert_eq!(rigel.configure(slow_mode)?, slow_mode);
+
+  rigel.set_control(Control::Gain, 40)?;
+  assert_eq!(rigel.control(Control::Gain)?, 40);
+  assert!(rigel.set_control(Control::Gain, 1000).is_err());
+  assert!(rigel.control(Control::Gamma).is_err());
+
+  rigel.open()?;
+  assert!(rigel.configure(CaptureMode::RIGEL_DEFAULT).is_err());
+  rigel.close()?;
+  Ok(())
+}
+
+#[test]
+fn rigel_reports_disconnect_from_capture_thread() -> Result<()> {
+  let mut source = MockSource::new();
+  source.set_realtime(false);
+  let mut injector = FaultInjector::new(source);
+  injector.schedule(5, Fault::Disconnect);
+
+  let mut rigel = Rigel::from_source(Box::new(injector));
+  let (tx, rx) = mpsc::channel();
+  rigel.set_callback(move |frame: &Frame| { let _ = tx.send(frame.sequence); });
+  rigel.open()?;
+  while rx.recv_timeout(Duration::from_secs(1)).is_ok() {}
+  assert_eq!(rigel.take_error().map(|e| e.kind()), Some(ErrorKind::Disconnected));
+  let _ = rigel.close();
+  Ok(())
+}
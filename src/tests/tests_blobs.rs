@@ -0,0 +1,96 @@
+// tests/tests_blobs.rs
+//
+// Renders bright markers through a distorted stereo calibration and checks they are found, matched and triangulated.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+fn calibration() -> StereoCalibration {
+  let left = CameraIntrinsics { width: WIDTH, height: HEIGHT, fx: 200.0, fy: 201.0, cx: 161.0, cy: 118.5, distortion: Distortion::Equidistant([0.03, -0.01, 0.0, 0.0]) };
+  let right = CameraIntrinsics { fx: 202.0, fy: 202.0, cx: 158.0, cy: 121.0, distortion: Distortion::BrownConrady([-0.05, 0.01, 0.0, 0.0, 0.0]), ..left.clone() };
+  stereo_rig(left, right, [0.01, -0.02, 0.005], [-0.04, 0.001, 0.0005])
+}
+
+fn project(calibration: &StereoCalibration, eye: Eye, point: [f64; 3]) -> (f64, f64) {
+  let p = match eye {
+    Eye::Left => point,
+    Eye::Right => {
+      let rotated = mat_vec(&calibration.rotation, point);
+      [rotated[0] + calibration.translation[0], rotated[1] + calibration.translation[1], rotated[2] + calibration.translation[2]]
+    }
+  };
+  calibration.camera(eye).project(p[0] / p[2], p[1] / p[2])
+}
+
+// Gaussian spots on a dim background. `extra` adds spots to the left eye only, as (u, v, sigma).
+fn render(spots: &[[(f64, f64); 2]], extra: &[(f64, f64, f64)]) -> Frame {
+  let mut data = vec![20u8; (WIDTH * HEIGHT * 2) as usize];
+  let mut draw = |eye: usize, (cu, cv): (f64, f64), sigma: f64| {
+    for v in 0..HEIGHT as usize {
+      for u in 0..WIDTH as usize {
+        let r2 = (u as f64 - cu).powi(2) + (v as f64 - cv).powi(2);
+        let pixel = &mut data[v * WIDTH as usize * 2 + eye * WIDTH as usize + u];
+        *pixel = (*pixel as f64).max(20.0 + 235.0 * (-r2 / (2.0 * sigma * sigma)).exp()).round() as u8;
+      }
+    }
+  };
+  for spot in spots {
+    draw(0, spot[0], 1.8);
+    draw(1, spot[1], 1.8);
+  }
+  for &(u, v, sigma) in extra { draw(0, (u, v), sigma); }
+  Frame::new(WIDTH, HEIGHT, data, 0, Duration::from_secs(0))
+}
+
+#[test]
+fn finds_subpixel_centroids() -> Result<()> {
+  let frame = render(&[[(40.3, 50.7), (10.0, 10.0)], [(200.5, 180.25), (300.0, 200.0)]], &[(120.0, 30.0, 0.3)]);
+  let mut detector = BlobDetector::new(&calibration());
+  detector.set_threshold(100);
+  let mut blobs = detector.detect_eye(&frame, Eye::Left)?;
+  // The tiny spot is a single pixel, below the default minimum area.
+  assert_eq!(blobs.len(), 2);
+  blobs.sort_by(|a, b| a.x.total_cmp(&b.x));
+  assert!((blobs[0].x - 40.3).abs() < 0.05 && (blobs[0].y - 50.7).abs() < 0.05, "{:?}", blobs[0]);
+  assert!((blobs[1].x - 200.5).abs() < 0.05 && (blobs[1].y - 180.25).abs() < 0.05, "{:?}", blobs[1]);
+  assert!(blobs[0].area > 5 && blobs[0].peak > 240 && blobs[0].intensity > 100.0);
+
+  detector.set_area_range(1, 5000);
+  assert_eq!(detector.detect_eye(&frame, Eye::Left)?.len(), 3);
+  assert!(detector.detect_eye(&Frame::new(WIDTH, HEIGHT, vec![0; 10], 0, Duration::from_secs(0)), Eye::Left).is_err());
+  Ok(())
+}
+
+#[test]
+fn matches_and_triangulates_markers() -> Result<()> {
+  let calibration = calibration();
+  let points = [[0.05, -0.02, 0.5], [-0.1, 0.05, 0.8], [0.0, 0.08, 0.4], [0.12, 0.0, 1.2]];
+  let spots: Vec<[(f64, f64); 2]> = points.iter().map(|&p| [project(&calibration, Eye::Left, p), project(&calibration, Eye::Right, p)]).collect();
+  // A reflection only the left eye sees, on the same row as a marker.
+  let (u, v) = spots[0][0];
+  let frame = render(&spots, &[(u + 60.0, v, 1.8)]);
+
+  let mut detector = BlobDetector::new(&calibration);
+  detector.set_threshold(120);
+  let detection = detector.detect(&frame)?;
+  assert_eq!((detection.left.len(), detection.right.len()), (5, 4));
+  assert_eq!(detection.markers.len(), 4);
+  for point in points.iter() {
+    let marker = detection.markers.iter()
+      .min_by(|a, b| distance(a.position, *point).total_cmp(&distance(b.position, *point)))
+      .unwrap();
+    // Centroid errors of a few hundredths of a pixel grow with depth squared.
+    assert!(distance(marker.position, *point) < 0.002 * point[2] * point[2] / 0.25, "{:?} vs {:?}", marker.position, point);
+    assert!(marker.epipolar_error < 0.2);
+  }
+
+  detector.set_depth_range(0.45, 1.0);
+  assert_eq!(detector.detect(&frame)?.markers.len(), 2);
+  Ok(())
+}
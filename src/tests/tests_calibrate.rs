@@ -0,0 +1,178 @@
+// tests/tests_calibrate.rs
+//
+// Renders checkerboard and ChArUco boards through a known stereo rig, then checks detected corners against their true projections and the calibration solved from them against the rig.
+
+use std::fs;
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn calibration() -> StereoCalibration {
+  qvga_rig(Distortion::Equidistant([0.03, -0.01, 0.004, 0.0]), Distortion::Equidistant([0.025, -0.008, 0.002, 0.0]))
+}
+
+// A printed board on white paper, one square of margin wide.
+struct Board {
+  columns: u32,
+  rows: u32,
+  square: f64,
+  markers: Option<(TagFamily, f64)>
+}
+
+impl Board {
+  fn new(target: &CalibrationTarget) -> Self {
+    match target {
+      CalibrationTarget::Checkerboard { columns, rows, square } => Board { columns: *columns, rows: *rows, square: *square, markers: None },
+      CalibrationTarget::ChArUco { columns, rows, square, marker, family } => Board { columns: *columns, rows: *rows, square: *square, markers: Some((family.clone(), *marker)) }
+    }
+  }
+
+  fn shade(&self, x: f64, y: f64) -> f64 {
+    let (cx, cy) = ((x / self.square).floor() as i64, (y / self.square).floor() as i64);
+    if cx < -1 || cy < -1 || cx > self.columns as i64 || cy > self.rows as i64 { return 100.0; }
+    if cx == -1 || cy == -1 || cx == self.columns as i64 || cy == self.rows as i64 { return 220.0; }
+    if (cx + cy) % 2 == 0 { return 30.0; }
+    let Some((family, marker)) = &self.markers else { return 220.0; };
+    let id = (cy * self.columns as i64 + cx) / 2;
+    let cells = family.size() as i64 + 2;
+    let cell = marker / cells as f64;
+    let margin = (self.square - marker) / 2.0;
+    let (mx, my) = (((x - cx as f64 * self.square - margin) / cell).floor() as i64, ((y - cy as f64 * self.square - margin) / cell).floor() as i64);
+    if mx < 0 || my < 0 || mx >= cells || my >= cells { return 220.0; }
+    if mx == 0 || my == 0 || mx == cells - 1 || my == cells - 1 { return 30.0; }
+    let d = family.size() as i64;
+    let bit = (family.code(id as u32).unwrap() >> (d * d - 1 - ((my - 1) * d + mx - 1))) & 1;
+    if bit == 1 { 220.0 } else { 30.0 }
+  }
+}
+
+// A board centered at `center` in the left camera frame, turned by `rotation`.
+fn pose(target: &CalibrationTarget, rotation: Vec3, center: Vec3) -> (Mat3, Vec3) {
+  let (columns, rows, square) = match target {
+    CalibrationTarget::Checkerboard { columns, rows, square } | CalibrationTarget::ChArUco { columns, rows, square, .. } => (*columns, *rows, *square)
+  };
+  let rotation = rodrigues(rotation);
+  let middle = mat_vec(&rotation, [columns as f64 * square / 2.0, rows as f64 * square / 2.0, 0.0]);
+  (rotation, [center[0] - middle[0], center[1] - middle[1], center[2] - middle[2]])
+}
+
+fn assert_corners(rig: &Rig, target: &CalibrationTarget, pose: (Mat3, Vec3), eye: Eye, corners: &[BoardCorner], tolerance: f64) {
+  for corner in corners {
+    let expected = rig.project(target, pose, eye, corner.id);
+    assert!((corner.x - expected.0).hypot(corner.y - expected.1) < tolerance, "corner {}: ({}, {}) vs {:?}", corner.id, corner.x, corner.y, expected);
+  }
+}
+
+#[test]
+fn detects_checkerboard_and_charuco_corners() -> Result<()> {
+  let rig = Rig::new(calibration());
+  let checkerboard = CalibrationTarget::checkerboard(7, 6, 0.03)?;
+  let board = Board::new(&checkerboard);
+  // Upright, and upside down: corner 0 stays at the board's black top-left square either way.
+  for rotation in [[0.3, -0.2, 0.1], [0.0, 0.3, std::f64::consts::PI - 0.2]].iter() {
+    let pose = pose(&checkerboard, *rotation, [0.02, 0.01, 0.4]);
+    let frame = rig.render(&|x, y| board.shade(x, y), pose, 0);
+    for eye in [Eye::Left, Eye::Right].iter().copied() {
+      let corners = checkerboard.detect(&frame, eye)?;
+      assert_eq!(corners.len(), 30, "{:?}", eye);
+      assert!(corners.iter().enumerate().all(|(i, corner)| corner.id == i as u32));
+      assert_corners(&rig, &checkerboard, pose, eye, &corners, 0.25);
+    }
+  }
+  // Partly out of view, a checkerboard is not found at all.
+  assert!(checkerboard.detect(&rig.render(&|x, y| board.shade(x, y), pose(&checkerboard, [0.0; 3], [0.35, 0.0, 0.4]), 0), Eye::Left)?.is_empty());
+
+  // A ChArUco board hanging off the left edge of the image still yields the corners next to visible tags.
+  let charuco = CalibrationTarget::charuco(6, 5, 0.05, 0.035, TagFamily::tag16h5())?;
+  let pose = pose(&charuco, [0.2, 0.3, -0.1], [-0.4, 0.0, 0.45]);
+  let board = Board::new(&charuco);
+  let frame = rig.render(&|x, y| board.shade(x, y), pose, 0);
+  let corners = charuco.detect(&frame, Eye::Left)?;
+  assert!(corners.len() >= 6 && corners.len() < charuco.corner_count(), "{} corners", corners.len());
+  assert_corners(&rig, &charuco, pose, Eye::Left, &corners, 0.25);
+
+  assert!(CalibrationTarget::checkerboard(6, 6, 0.03).is_err());
+  assert!(CalibrationTarget::charuco(6, 5, 0.04, 0.035, TagFamily::tag16h5()).is_err());
+  assert!(CalibrationTarget::charuco(8, 8, 0.04, 0.028, TagFamily::tag16h5()).is_err());
+  // Boards built without the constructors can be degenerate, but never panic.
+  for (columns, rows, count) in [(0, 5, 0), (1, 5, 0), (2, 2, 1)].iter().copied() {
+    let direct = CalibrationTarget::Checkerboard { columns, rows, square: 0.03 };
+    assert_eq!((direct.corner_count(), direct.corner_position(0).is_some()), (count, count > 0));
+    assert!(direct.detect(&frame, Eye::Left)?.is_empty());
+  }
+  let direct = CalibrationTarget::ChArUco { columns: 1, rows: 1, square: 0.05, marker: 0.035, family: TagFamily::tag16h5() };
+  assert!(direct.corner_count() == 0 && direct.detect(&frame, Eye::Left)?.is_empty());
+  Ok(())
+}
+
+#[test]
+fn calibrates_a_stereo_pair() -> Result<()> {
+  let truth = calibration();
+  let rig = Rig::new(truth.clone());
+  let target = CalibrationTarget::checkerboard(7, 6, 0.035)?;
+  let board = Board::new(&target);
+  let mut calibrator = StereoCalibrator::new(target.clone(), DistortionModel::Equidistant);
+  let views = [
+    ([0.35, 0.0, 0.0], [0.0, 0.0, 0.45]),
+    ([-0.3, 0.1, 0.05], [-0.08, 0.04, 0.42]),
+    ([0.0, 0.4, 0.1], [0.09, -0.04, 0.45]),
+    ([0.0, -0.4, -0.1], [-0.1, -0.05, 0.5]),
+    ([0.25, 0.25, 0.2], [0.1, 0.06, 0.42]),
+    ([-0.25, -0.3, 0.0], [0.0, -0.07, 0.4]),
+    ([0.2, -0.3, -0.15], [0.11, 0.0, 0.48]),
+    ([-0.2, 0.3, 0.3], [-0.1, 0.02, 0.42]),
+    ([0.1, 0.0, 1.0], [0.02, 0.03, 0.42]),
+    ([0.4, 0.1, -0.2], [-0.05, -0.08, 0.44]),
+    // Toward the corners of the images, where distortion is strongest.
+    ([0.2, 0.2, 0.1], [-0.17, -0.12, 0.4]),
+    ([0.2, -0.2, -0.1], [0.2, -0.12, 0.4]),
+    ([-0.2, 0.2, 0.05], [-0.2, 0.12, 0.4]),
+    ([-0.2, -0.2, -0.05], [0.17, 0.12, 0.4]),
+    // Only in the right eye.
+    ([0.0, 0.2, 0.0], [0.26, 0.0, 0.4])
+  ];
+  for (rotation, center) in views.iter() {
+    let counts = calibrator.add_frame(&rig.render(&|x, y| board.shade(x, y), pose(&target, *rotation, *center), 0))?;
+    assert!(counts.contains(&30), "{:?} at {:?}", counts, center);
+  }
+  assert_eq!(calibrator.views().len(), views.len());
+  assert!(calibrator.views()[14][0].is_empty() && !calibrator.views()[14][1].is_empty());
+
+  let result = calibrator.calibrate()?;
+  assert!(result.rms < 0.1 && result.left_rms < 0.1 && result.right_rms < 0.1, "{} {} {}", result.rms, result.left_rms, result.right_rms);
+  assert_eq!(result.view_errors.len(), views.len());
+  assert_eq!(result.view_errors[14][0], None);
+  let solved = &result.calibration;
+  for (camera, expected) in [(&solved.left, &truth.left), (&solved.right, &truth.right)].iter() {
+    assert!((camera.fx / expected.fx - 1.0).abs() < 0.005 && (camera.fy / expected.fy - 1.0).abs() < 0.005, "{:?}", camera);
+    assert!((camera.cx - expected.cx).abs() < 1.0 && (camera.cy - expected.cy).abs() < 1.0, "{:?}", camera);
+    // Out to where the boards reached, the models bend rays alike: a leftover principal point offset only shifts everything, which the poses absorb.
+    let shift = |u: f64, v: f64| {
+      let (x, y) = expected.unproject(u, v);
+      let (pu, pv) = camera.project(x, y);
+      (pu - u, pv - v)
+    };
+    let center = shift(160.0, 120.0);
+    for &(u, v) in [(40.0, 30.0), (280.0, 30.0), (40.0, 210.0), (280.0, 210.0), (100.0, 120.0)].iter() {
+      let (du, dv) = shift(u, v);
+      assert!((du - center.0).hypot(dv - center.1) < 0.3, "({}, {}) moves by ({}, {})", u, v, du - center.0, dv - center.1);
+    }
+  }
+  assert!((solved.baseline() - truth.baseline()).abs() < 0.0005, "{}", solved.baseline());
+  assert!(norm(rotation_vector(&mat_mul(&transpose(&solved.rotation), &truth.rotation))) < 0.1f64.to_radians());
+
+  // The result saves as native JSON that loads back as the same calibration.
+  let path = std::env::temp_dir().join(format!("tinyrigel-calibration-{}.json", std::process::id()));
+  result.save(&path)?;
+  let text = fs::read_to_string(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+  assert!(text.contains("\"rms\""));
+  assert_eq!(&StereoCalibration::from_json(&text)?, solved);
+  assert!(StereoCalibration::from_json("{\"left\": {}}").is_err());
+
+  let mut few = StereoCalibrator::new(target, DistortionModel::Equidistant);
+  few.add_frame(&rig.render(&|x, y| board.shade(x, y), pose(few.target(), [0.3, 0.0, 0.0], [0.0, 0.0, 0.45]), 0))?;
+  assert!(few.calibrate().is_err());
+  Ok(())
+}
@@ -0,0 +1,159 @@
+// tests/tests_calibration.rs
+//
+// Round-trips calibrations through JSON, OpenCV and Kalibr files, reads files as those tools write them, converts lens models, and finds a device's calibration by serial.
+
+use std::{env, fs, path::PathBuf};
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn calibration() -> StereoCalibration {
+  let camera = |cx: f64, distortion: Distortion| CameraIntrinsics { width: 384, height: 384, fx: 150.5, fy: 151.25, cx, cy: 190.0, distortion };
+  let left = camera(191.0, Distortion::Equidistant([0.1, -0.02, 0.003, -0.0004]));
+  let right = camera(193.5, Distortion::BrownConrady([-0.05, 0.01, 0.001, 0.002, 0.0]));
+  StereoCalibration { serial: Some("LP12345".to_string()), ..stereo_rig(left, right, [0.01, -0.02, 0.005], [-0.04, 0.0005, 0.001]) }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+  let dir = env::temp_dir().join(format!("tinyrigel-{}-{}", name, std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+#[test]
+fn round_trips_json_opencv_and_kalibr() -> Result<()> {
+  let calibration = calibration();
+  let dir = temp_dir("calibration-formats");
+  for (format, name) in [(CalibrationFormat::Json, "c.json"), (CalibrationFormat::OpenCv, "c.yaml"), (CalibrationFormat::Kalibr, "camchain.yaml")].iter() {
+    let path = dir.join(name);
+    calibration.save(&path, *format)?;
+    assert_eq!(StereoCalibration::load(&path)?, calibration, "{:?}", format);
+  }
+  let opencv = calibration.to_opencv_yaml();
+  assert!(opencv.starts_with("%YAML:1.0\n---\n") && opencv.contains("camera_matrix_left: !!opencv-matrix") && opencv.contains("distortion_model_left: \"fisheye\""));
+  let kalibr = calibration.to_kalibr_yaml()?;
+  assert!(kalibr.contains("distortion_model: equidistant") && kalibr.contains("distortion_model: radtan") && kalibr.contains("  - [0.0, 0.0, 0.0, 1.0]"));
+
+  let uncalibrated = StereoCalibration { left: CameraIntrinsics { distortion: Distortion::None, ..calibration.left.clone() }, serial: None, ..calibration.clone() };
+  assert_eq!(StereoCalibration::from_opencv_yaml(&uncalibrated.to_opencv_yaml())?, uncalibrated);
+  assert_eq!(StereoCalibration::from_kalibr_yaml(&uncalibrated.to_kalibr_yaml()?)?, uncalibrated);
+
+  fs::write(dir.join("broken.yaml"), "cam0:\n  intrinsics: [1, 2\n").unwrap();
+  assert!(StereoCalibration::load(dir.join("broken.yaml")).is_err());
+  assert!(StereoCalibration::load(dir.join("missing.json")).is_err());
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
+
+#[test]
+fn reads_files_written_by_opencv_and_kalibr() -> Result<()> {
+  // As cv::FileStorage writes the results of OpenCV's stereo_calib sample, with the rational model's unused terms.
+  let opencv = "%YAML:1.0\n---\nimage_width: 640\nimage_height: 480\nM1: !!opencv-matrix\n   rows: 3\n   cols: 3\n   dt: d\n   data: [ 5.2e+02, 0., 3.2e+02, 0., 5.2e+02, 2.4e+02, 0., 0., 1. ]\n\
+    D1: !!opencv-matrix\n   rows: 1\n   cols: 8\n   dt: d\n   data: [ -2.8e-01, 9.1e-02, 1.0e-03, -2.0e-04,\n       0., 0., 0., 0. ]\n\
+    M2: !!opencv-matrix\n   rows: 3\n   cols: 3\n   dt: d\n   data: [ 5.3e+02, 0., 3.1e+02, 0., 5.3e+02, 2.5e+02, 0., 0., 1. ]\n\
+    D2: !!opencv-matrix\n   rows: 1\n   cols: 5\n   dt: d\n   data: [ -2.7e-01, 8.5e-02, 0., 0., -1.0e-02 ]\n\
+    R: !!opencv-matrix\n   rows: 3\n   cols: 3\n   dt: d\n   data: [ 1., 0., 0., 0., 1., 0., 0., 0., 1. ]\n\
+    T: !!opencv-matrix\n   rows: 3\n   cols: 1\n   dt: d\n   data: [ -6.0e-02, 0., 0. ] # meters\n";
+  let calibration = StereoCalibration::from_opencv_yaml(opencv)?;
+  assert_eq!((calibration.left.width, calibration.left.fx, calibration.left.cx, calibration.right.cy), (640, 520.0, 320.0, 250.0));
+  assert_eq!(calibration.left.distortion, Distortion::BrownConrady([-0.28, 0.091, 0.001, -0.0002, 0.0]));
+  assert_eq!(calibration.right.distortion, Distortion::BrownConrady([-0.27, 0.085, 0.0, 0.0, -0.01]));
+  assert_eq!((calibration.translation, calibration.serial), ([-0.06, 0.0, 0.0], None));
+  assert!(StereoCalibration::from_opencv_yaml(&opencv.replace("0., 0., 0., 0. ]", "0., 0.1, 0., 0. ]")).is_err());
+  assert!(StereoCalibration::from_opencv_yaml(&opencv.replace("T: !!", "Q: !!")).is_err());
+
+  // As Kalibr writes a camchain, with the transform's rows at the key's own indentation.
+  let kalibr = "cam0:\n  cam_overlaps: [1]\n  camera_model: pinhole\n  distortion_coeffs: [0.05, -0.01, 0.002, -0.0003]\n  distortion_model: equidistant\n\
+    \x20 intrinsics: [150.1, 150.3, 192.2, 191.7]\n  resolution: [384, 384]\n  rostopic: /rigel/left/image_raw\n\
+    cam1:\n  T_cn_cnm1:\n  - [0.9999, -0.0020, 0.0100, -0.0401]\n  - [0.0021, 0.9999, -0.0050, 0.0002]\n  - [-0.0100, 0.0050, 0.9999, 0.0011]\n  - [0.0, 0.0, 0.0, 1.0]\n\
+    \x20 cam_overlaps: [0]\n  camera_model: pinhole\n  distortion_coeffs: [-0.2, 0.04, 0.0, 0.001]\n  distortion_model: radtan\n\
+    \x20 intrinsics: [151.0, 151.2, 190.0, 193.0]\n  resolution: [384, 384]\n  rostopic: /rigel/right/image_raw\n";
+  let calibration = StereoCalibration::from_kalibr_yaml(kalibr)?;
+  assert_eq!(calibration.left.distortion, Distortion::Equidistant([0.05, -0.01, 0.002, -0.0003]));
+  assert_eq!(calibration.right.distortion, Distortion::BrownConrady([-0.2, 0.04, 0.0, 0.001, 0.0]));
+  assert_eq!((calibration.right.fx, calibration.right.cy, calibration.rotation[1][0], calibration.translation), (151.0, 193.0, 0.0021, [-0.0401, 0.0002, 0.0011]));
+  assert!(StereoCalibration::from_kalibr_yaml(&kalibr.replace("camera_model: pinhole\n  distortion_coeffs: [-0.2", "camera_model: omni\n  distortion_coeffs: [-0.2")).is_err());
+  assert!(StereoCalibration::from_kalibr_yaml(&kalibr.replace("distortion_model: radtan", "distortion_model: fov")).is_err());
+  Ok(())
+}
+
+#[test]
+fn converts_between_lens_models() -> Result<()> {
+  let max_error = |a: &CameraIntrinsics, b: &CameraIntrinsics| {
+    let mut worst: f64 = 0.0;
+    for v in (0..384).step_by(16) {
+      for u in (0..384).step_by(16) {
+        let (x, y) = a.unproject(u as f64, v as f64);
+        let (pu, pv) = b.project(x, y);
+        worst = worst.max((pu - u as f64).hypot(pv - v as f64));
+      }
+    }
+    worst
+  };
+  // A mildly distorted lens can be described either way.
+  let narrow = CameraIntrinsics { width: 384, height: 384, fx: 400.0, fy: 400.0, cx: 192.0, cy: 192.0, distortion: Distortion::BrownConrady([-0.1, 0.02, 0.0, 0.0, 0.0]) };
+  let fisheye = narrow.convert_distortion(DistortionModel::Equidistant)?;
+  assert_eq!(fisheye.distortion.model(), DistortionModel::Equidistant);
+  assert!(max_error(&narrow, &fisheye) < 0.1);
+  assert!(max_error(&fisheye, &fisheye.convert_distortion(DistortionModel::BrownConrady)?) < 0.1);
+  assert_eq!(narrow.convert_distortion(DistortionModel::BrownConrady)?, narrow);
+  assert!(narrow.convert_distortion(DistortionModel::None).is_err());
+
+  // Kalibr's radtan has no k3, so a lens with one is refit without it when written.
+  let mut calibration = calibration();
+  calibration.right = CameraIntrinsics { distortion: Distortion::BrownConrady([-0.1, 0.02, 0.0, 0.0, -0.001]), ..narrow.clone() };
+  let written = StereoCalibration::from_kalibr_yaml(&calibration.to_kalibr_yaml()?)?;
+  assert!(matches!(written.right.distortion, Distortion::BrownConrady([_, _, _, _, k3]) if k3 == 0.0));
+  assert!(max_error(&calibration.right, &written.right) < 0.1);
+
+  // The Rigel's own lenses are far too wide for Brown-Conrady.
+  let wide = CameraIntrinsics { width: 384, height: 384, fx: 120.0, fy: 120.0, cx: 192.0, cy: 192.0, distortion: Distortion::Equidistant([0.0; 4]) };
+  assert!(wide.convert_distortion(DistortionModel::BrownConrady).is_err());
+  calibration.right = CameraIntrinsics { distortion: Distortion::BrownConrady([-0.3, 0.1, 0.0, 0.0, -0.05]), ..wide };
+  assert!(calibration.to_kalibr_yaml().is_err());
+  Ok(())
+}
+
+#[test]
+fn finds_the_calibration_for_a_device_serial() -> Result<()> {
+  let dir = temp_dir("calibration-find");
+  let mut other = calibration();
+  other.serial = Some("MOCK0001".to_string());
+  other.save(dir.join("a.json"), CalibrationFormat::Json)?;
+  // A file named after the serial counts for formats that don't record one.
+  let mut named = calibration();
+  named.serial = None;
+  named.translation = [-0.05, 0.0, 0.0];
+  named.save(dir.join("MOCK0002.yaml"), CalibrationFormat::OpenCv)?;
+  fs::write(dir.join("notes.yaml"), "not: [a calibration").unwrap();
+  fs::write(dir.join("readme.txt"), "ignored").unwrap();
+
+  let found = StereoCalibration::find(&dir, "MOCK0002")?.unwrap();
+  assert_eq!((found.translation, found.serial.as_deref()), ([-0.05, 0.0, 0.0], Some("MOCK0002")));
+  assert_eq!(StereoCalibration::find(&dir, "MOCK0001")?, Some(other.clone()));
+  assert_eq!(StereoCalibration::find(&dir, "MOCK9999")?, None);
+  assert_eq!(StereoCalibration::find(dir.join("missing"), "MOCK0001")?, None);
+  // A file that records the serial wins over one named after it.
+  let mut recorded = other.clone();
+  recorded.serial = Some("MOCK0002".to_string());
+  recorded.save(dir.join("b.json"), CalibrationFormat::Json)?;
+  assert_eq!(StereoCalibration::find(&dir, "MOCK0002")?, Some(recorded.clone()));
+
+  // Creating a Rigel picks up its calibration from the calibration directory.
+  let mut backend = MockBackend::new();
+  backend.set_device_count(3);
+  let devices = backend.enumerate()?;
+  env::set_var("TINYRIGEL_CALIBRATIONS", &dir);
+  assert_eq!(calibration_dir(), Some(dir.clone()));
+  let mut rigel: Rigel = Rigel::from_source(backend.open(&devices[2])?);
+  env::remove_var("TINYRIGEL_CALIBRATIONS");
+  assert_eq!(rigel.calibration(), Some(&recorded));
+  rigel.set_calibration(None);
+  assert!(rigel.load_calibration(&dir)? && rigel.calibration() == Some(&recorded));
+  assert!(!rigel.load_calibration(dir.join("missing"))? && rigel.calibration().is_some());
+  let mut first: Rigel = Rigel::from_source(backend.open(&devices[0])?);
+  assert!(!first.load_calibration(&dir)? && first.calibration().is_none());
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
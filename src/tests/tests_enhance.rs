@@ -0,0 +1,127 @@
+// tests/tests_enhance.rs
+//
+// Applies each contrast enhancement to synthetic dark IR frames and checks the values that come out.
+
+use std::time::Duration;
+
+use crate::*;
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+
+// Both eyes drawn by `pixel(x, y)`, the right eye inverted so that changes to the wrong eye show.
+fn frame(pixel: impl Fn(u32, u32) -> u8) -> Frame {
+  let mut data = Vec::with_capacity((WIDTH * HEIGHT * 2) as usize);
+  for y in 0..HEIGHT {
+    for x in 0..WIDTH { data.push(pixel(x, y)); }
+    for x in 0..WIDTH { data.push(255 - pixel(x, y)); }
+  }
+  Frame::new(WIDTH, HEIGHT, data, 7, Duration::from_millis(77))
+}
+
+fn eye(frame: &Frame, eye: Eye) -> Vec<u8> { frame.image(if eye == Eye::Left { EyeLayout::Left } else { EyeLayout::Right }).into_raw().to_vec() }
+
+// Standard deviation of the pixels in columns `x0..x1`.
+fn spread(pixels: &[u8], x0: usize, x1: usize) -> f64 {
+  let values: Vec<f64> = pixels.chunks_exact(WIDTH as usize).flat_map(|row| row[x0..x1].iter().map(|&v| v as f64)).collect();
+  let mean = values.iter().sum::<f64>() / values.len() as f64;
+  (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+// A dim texture in 4 pixel blocks between `low` and `low + range`.
+fn texture(x: u32, y: u32, low: u8, range: u8) -> u8 {
+  let hash = ((x / 4).wrapping_mul(2_654_435_761) ^ (y / 4).wrapping_mul(2_246_822_519)).wrapping_mul(3_266_489_917) >> 16;
+  low + (hash % (range as u32 + 1)) as u8
+}
+
+#[test]
+fn equalizes_one_eye_in_place() -> Result<()> {
+  // A dark ramp from 10 to 41.
+  let mut enhanced = frame(|x, _| 10 + (x / 4) as u8);
+  let original = enhanced.clone();
+  Enhancement::Equalize.apply_eye(&mut enhanced, Eye::Left)?;
+  let left = eye(&enhanced, Eye::Left);
+  assert_eq!(left[0], 0);
+  assert_eq!(left[WIDTH as usize - 1], 255);
+  // Every value takes the same share of the pixels, so they come out evenly spaced.
+  assert_eq!(left[64], 132);
+  assert!(left[..WIDTH as usize].windows(2).all(|pair| pair[0] <= pair[1]));
+  assert_eq!(eye(&enhanced, Eye::Right), eye(&original, Eye::Right));
+  Ok(())
+}
+
+#[test]
+fn clahe_raises_local_contrast_within_its_clip_limit() -> Result<()> {
+  // A dark half and a bright half, both with faint texture.
+  let original = frame(|x, y| if x < WIDTH / 2 { texture(x, y, 20, 12) } else { texture(x, y, 180, 12) });
+  let before = eye(&original, Eye::Left);
+  let half = WIDTH as usize / 2;
+
+  let mut gains = Vec::new();
+  for &clip_limit in &[1.5, 4.0] {
+    let mut enhanced = original.clone();
+    Enhancement::clahe(4, 3, clip_limit)?.apply(&mut enhanced)?;
+    let after = eye(&enhanced, Eye::Left);
+    let gain = (spread(&after, 0, half - 8) / spread(&before, 0, half - 8), spread(&after, half + 8, WIDTH as usize) / spread(&before, half + 8, WIDTH as usize));
+    gains.push(gain);
+  }
+  assert!(gains[0].0 > 1.3 && gains[0].1 > 1.3, "{:?}", gains);
+  assert!(gains[1].0 > gains[0].0 * 1.5 && gains[1].1 > gains[0].1 * 1.5, "{:?}", gains);
+
+  // With one tile there is nothing to blend, so the mapping keeps the values' order across the whole eye.
+  let mut single = original.clone();
+  Enhancement::clahe(1, 1, 256.0)?.apply(&mut single)?;
+  let mut pairs: Vec<(u8, u8)> = before.iter().copied().zip(eye(&single, Eye::Left)).collect();
+  pairs.sort_unstable();
+  assert!(pairs.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+  assert!(pairs[0].1 < 20 && pairs[pairs.len() - 1].1 == 255, "{:?} to {:?}", pairs[0], pairs[pairs.len() - 1]);
+
+  assert!(Enhancement::clahe(0, 8, 2.0).is_err());
+  assert!(Enhancement::clahe(8, 8, 0.5).is_err());
+  Ok(())
+}
+
+#[test]
+fn applies_gamma_and_lookup_tables() -> Result<()> {
+  let mut enhanced = frame(|x, y| ((x + y * WIDTH) % 256) as u8);
+  Enhancement::gamma(2.0)?.apply_eye(&mut enhanced, Eye::Left)?;
+  let left = eye(&enhanced, Eye::Left);
+  assert_eq!((left[0], left[64], left[255]), (0, 128, 255));
+
+  let mut inverted = [0u8; 256];
+  for (v, out) in inverted.iter_mut().enumerate() { *out = 255 - v as u8; }
+  let mut enhanced = frame(|x, _| x as u8);
+  Enhancement::lut(inverted).apply(&mut enhanced)?;
+  assert_eq!(eye(&enhanced, Eye::Left)[5], 250);
+  assert_eq!(eye(&enhanced, Eye::Right)[5], 5);
+
+  assert!(Enhancement::gamma(0.0).is_err());
+  assert!(Enhancement::Gamma(f32::NAN).apply(&mut enhanced).is_err());
+  Ok(())
+}
+
+#[test]
+fn auto_levels_ignore_led_highlights() -> Result<()> {
+  // Values from 50 to 100, and a saturated highlight over 0.25% of the eye.
+  let original = frame(|x, y| if x < 6 && y < 5 { 255 } else { texture(x, y, 50, 50) });
+  let enhanced = Enhancement::auto_levels(1.0, 99.0)?.enhance(&original)?;
+  assert_eq!((enhanced.sequence, enhanced.timestamp), (original.sequence, original.timestamp));
+  let left = eye(&enhanced, Eye::Left);
+  let (&darkest, &brightest) = (left[WIDTH as usize * 10..].iter().min().unwrap(), left[WIDTH as usize * 10..].iter().max().unwrap());
+  assert!(darkest <= 8 && brightest >= 247, "{} to {}", darkest, brightest);
+  assert_eq!(left[0], 255);
+  // A stretch of about 5: one value step in, five out.
+  let (a, b) = (eye(&original, Eye::Left)[WIDTH as usize * 20 + 40], left[WIDTH as usize * 20 + 40]);
+  assert!(((a as f64 - 50.0) * 5.1 - b as f64).abs() < 12.0, "{} became {}", a, b);
+
+  // A flat eye stays as it is.
+  let mut flat = frame(|_, _| 30);
+  Enhancement::auto_levels(1.0, 99.0)?.apply(&mut flat)?;
+  assert!(eye(&flat, Eye::Left).iter().all(|&v| v == 30));
+
+  assert!(Enhancement::auto_levels(99.0, 1.0).is_err());
+  let mut incomplete = original.clone();
+  incomplete.data.truncate(100);
+  assert!(Enhancement::Equalize.apply(&mut incomplete).is_err());
+  Ok(())
+}
@@ -0,0 +1,187 @@
+// tests/tests_export.rs
+//
+// Exports small synthetic frames and checks the written bytes and files.
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::*;
+
+// A 4x2 (per eye) frame: left eye pixels count up from 0, right eye from 100.
+fn small_frame(sequence: u64) -> Frame {
+  let mut data = Vec::new();
+  for row in 0..2u8 {
+    data.extend((0..4).map(|col| row * 4 + col));
+    data.extend((0..4).map(|col| 100 + row * 4 + col));
+  }
+  Frame::new(4, 2, data, sequence, Duration::from_millis(sequence * 11))
+}
+
+#[test]
+fn y4m_mono_side_by_side() -> Result<()> {
+  let mut y4m = Y4mWriter::new(Vec::new(), EyeLayout::SideBySide, Y4mColorspace::Mono, 90);
+  y4m.write_frame(&small_frame(0))?;
+  y4m.write_frame(&small_frame(1))?;
+  y4m.finish()?;
+
+  let out = y4m.into_inner();
+  let header = b"YUV4MPEG2 W8 H2 F90:1 Ip A1:1 Cmono\n";
+  assert!(out.starts_with(header));
+  let frame_len = b"FRAME\n".len() + 16;
+  assert_eq!(out.len(), header.len() + 2 * frame_len);
+  assert_eq!(&out[header.len()..header.len() + 6], b"FRAME\n");
+  assert_eq!(&out[header.len() + 6..header.len() + frame_len], &small_frame(0).data[..]);
+  Ok(())
+}
+
+#[test]
+fn y4m_single_eye_c420() -> Result<()> {
+  let mut y4m = Y4mWriter::new(Vec::new(), EyeLayout::Right, Y4mColorspace::C420, 30);
+  y4m.write_frame(&small_frame(0))?;
+
+  let out = y4m.into_inner();
+  let header = b"YUV4MPEG2 W4 H2 F30:1 Ip A1:1 C420jpeg\n";
+  assert!(out.starts_with(header));
+  let body = &out[header.len() + 6..];
+  // 4x2 luma, then two 2x1 chroma planes.
+  assert_eq!(&body[..8], &[100, 101, 102, 103, 104, 105, 106, 107]);
+  assert_eq!(&body[8..], &[128, 128, 128, 128]);
+  Ok(())
+}
+
+#[test]
+fn y4m_rawvideo_pads_short_frames_and_rejects_resizes() -> Result<()> {
+  let mut y4m = Y4mWriter::new(Vec::new(), EyeLayout::Left, Y4mColorspace::Mono, 90);
+  y4m.set_headers(false);
+  let mut short = small_frame(0);
+  short.data.truncate(10);
+  y4m.write_frame(&short)?;
+  assert!(y4m.write_frame(&Frame::new(2, 2, vec![0; 8], 1, Duration::from_secs(0))).is_err());
+
+  // Row 0 is whole; row 1 of the left eye starts at byte 8, so only bytes 8 and 9 survived.
+  assert_eq!(y4m.into_inner(), vec![0, 1, 2, 3, 4, 5, 0, 0]);
+  Ok(())
+}
+
+fn export_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("tinyrigel-export-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  dir
+}
+
+#[test]
+fn image_sequence_per_eye_with_json() -> Result<()> {
+  let dir = export_dir("per-eye");
+  let mut exporter = ImageSequenceExporter::new(&dir, ImageFileFormat::Png);
+  exporter.set_workers(2);
+  exporter.control_changed(Control::Exposure, 500);
+  for sequence in 0..3 { exporter.write_frame(&small_frame(sequence))?; }
+  exporter.finish()?;
+
+  let right = image::open(dir.join("right/00000002.png")).unwrap().to_luma8();
+  assert_eq!(right.dimensions(), (4, 2));
+  assert_eq!(right.into_raw(), vec![100, 101, 102, 103, 104, 105, 106, 107]);
+  assert!(dir.join("left/00000000.png").is_file());
+
+  let json = fs::read_to_string(dir.join("00000001.json")).unwrap();
+  assert!(json.contains("\"sequence\": 1,"));
+  assert!(json.contains("\"timestamp_ns\": 11000000,"));
+  assert!(json.contains("\"images\": [\"left/00000001.png\", \"right/00000001.png\"]"));
+  assert!(json.contains("\"controls\": {\"exposure\": 500}"));
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
+
+#[test]
+fn image_sequence_survives_restarted_sequence_numbers() -> Result<()> {
+  let dir = export_dir("restart");
+  let mut exporter = ImageSequenceExporter::new(&dir, ImageFileFormat::Png);
+  exporter.set_layout(SequenceLayout::Combined);
+  // The device was reopened after frame 6, so its sequence numbers start over.
+  for sequence in [5, 6, 0].iter().copied() { exporter.write_frame(&small_frame(sequence))?; }
+  exporter.finish()?;
+
+  for (number, sequence) in [5, 6, 0].iter().enumerate() {
+    assert!(dir.join(format!("{:08}.png", number)).is_file());
+    let json = fs::read_to_string(dir.join(format!("{:08}.json", number))).unwrap();
+    assert!(json.contains(&format!("\"sequence\": {},", sequence)), "{}", json);
+  }
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
+
+#[test]
+fn image_sequence_combined_pgm_from_recording_with_csv() -> Result<()> {
+  // Any FrameArchive will do as the recording; two side-by-side PNG frames keep it small.
+  let src = export_dir("combined-src");
+  fs::create_dir_all(&src).unwrap();
+  for idx in 0..2u8 {
+    image::GrayImage::from_pixel(8, 2, image::Luma([idx * 10])).save(src.join(format!("{}.png", idx))).unwrap();
+  }
+  let mut archive = PngSequence::open(&src)?;
+
+  let dir = export_dir("combined");
+  let mut exporter = ImageSequenceExporter::new(&dir, ImageFileFormat::Pgm);
+  exporter.set_layout(SequenceLayout::Combined);
+  exporter.set_sidecar(Sidecar::Csv);
+  assert_eq!(exporter.export_archive(&mut archive)?, 2);
+  exporter.finish()?;
+
+  let img = image::open(dir.join("00000001.pgm")).unwrap().to_luma8();
+  assert_eq!(img.dimensions(), (8, 2));
+  assert!(img.pixels().all(|px| px.0[0] == 10));
+  let csv = fs::read_to_string(dir.join("frames.csv")).unwrap();
+  let rows: Vec<&str> = csv.lines().collect();
+  assert_eq!(rows.len(), 3);
+  assert!(rows[2].starts_with("1,"));
+  assert!(rows[2].contains(",00000001.pgm,"));
+  fs::remove_dir_all(&dir).unwrap();
+  fs::remove_dir_all(&src).unwrap();
+  Ok(())
+}
+
+#[test]
+fn rigel_reports_controls_to_sinks() -> Result<()> {
+  let dir = export_dir("rigel");
+  let mut backend = MockBackend::new();
+  backend.set_realtime(false);
+  let mut rigel = Rigel::<fn(&Frame)>::from_backend(&backend)?;
+  let mut exporter = ImageSequenceExporter::new(&dir, ImageFileFormat::Png);
+  exporter.set_layout(SequenceLayout::Combined);
+  rigel.attach_sink(Box::new(exporter));
+  rigel.set_control(Control::Gain, 42)?;
+  rigel.open()?;
+  let frame = rigel.next_frame(Duration::from_millis(500))?;
+  rigel.close()?;
+  rigel.finish_sinks()?;
+
+  let json = fs::read_to_string(dir.join("00000000.json")).unwrap();
+  assert!(json.contains(&format!("\"sequence\": {},", frame.sequence)));
+  assert!(json.contains("\"controls\": {\"exposure\": 1000, \"gain\": 42}"));
+  assert!(!json.contains("\"host_time_ns\": null"));
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
+
+// A sink whose disk is always full.
+struct FullSink;
+
+impl FrameSink for FullSink {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> { Err(Error::new(format!("No room for frame {}.", frame.sequence))) }
+  fn finish(&mut self) -> Result<()> { Ok(()) }
+}
+
+#[test]
+fn rigel_keeps_frames_when_a_sink_fails() -> Result<()> {
+  let mut backend = MockBackend::new();
+  backend.set_realtime(false);
+  let mut rigel = Rigel::<fn(&Frame)>::from_backend(&backend)?;
+  rigel.attach_sink(Box::new(FullSink));
+  rigel.open()?;
+  let first = rigel.next_frame(Duration::from_millis(500))?;
+  let second = rigel.next_frame(Duration::from_millis(500))?;
+  rigel.close()?;
+  assert_eq!(second.sequence, first.sequence + 1);
+  assert_eq!(rigel.take_error().map(|err| err.to_string()), Some(format!("No room for frame {}.", second.sequence)));
+  assert!(rigel.take_error().is_none());
+  Ok(())
+}
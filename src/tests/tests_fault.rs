@@ -0,0 +1,130 @@
+// tests/tests_fault.rs
+//
+// Exercises FaultInjector against MockSource, so these run anywhere, without a Rigel attached.
+
+use std::time::{Duration, Instant};
+
+use crate::*;
+
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+fn mock_injector() -> FaultInjector<MockSource> {
+  let mut mock = MockSource::new();
+  mock.set_realtime(false);
+  FaultInjector::new(mock)
+}
+
+#[test]
+fn passes_frames_through_without_faults() -> Result<()> {
+  let mut source = mock_injector();
+  source.start()?;
+  for expected_seq in 0..5 {
+    let frame = source.next_frame(TIMEOUT)?;
+    assert_eq!(frame.sequence, expected_seq);
+    assert!(frame.is_complete());
+  }
+  source.stop()?;
+  assert_eq!(source.stats().frames_delivered, 5);
+  Ok(())
+}
+
+#[test]
+fn scheduled_drop_leaves_sequence_gap() -> Result<()> {
+  let mut source = mock_injector();
+  source.schedule(2, Fault::DropFrame);
+  source.start()?;
+  let seqs: Vec<u64> = (0..4).map(|_| source.next_frame(TIMEOUT).unwrap().sequence).collect();
+  assert_eq!(seqs, vec![0, 1, 3, 4]);
+  assert_eq!(source.stats().frames_dropped, 1);
+  Ok(())
+}
+
+#[test]
+fn truncates_and_corrupts_buffers() -> Result<()> {
+  let mut source = mock_injector();
+  source.schedule(0, Fault::TruncateBuffer(100));
+  source.schedule(1, Fault::CorruptBuffer(1000));
+  source.start()?;
+
+  let truncated = source.next_frame(TIMEOUT)?;
+  assert_eq!(truncated.data.len(), 100);
+  assert!(!truncated.is_complete());
+
+  let mut clean = MockSource::new();
+  clean.set_realtime(false);
+  clean.start()?;
+  clean.next_frame(TIMEOUT)?;
+  let reference = clean.next_frame(TIMEOUT)?;
+  let corrupted = source.next_frame(TIMEOUT)?;
+  assert_eq!(corrupted.data.len(), reference.data.len());
+  assert_ne!(corrupted.data, reference.data);
+  Ok(())
+}
+
+#[test]
+fn stall_times_out_then_recovers() -> Result<()> {
+  let mut source = mock_injector();
+  source.schedule(1, Fault::Stall(Duration::from_millis(150)));
+  source.start()?;
+  source.next_frame(TIMEOUT)?;
+
+  let stalled = source.next_frame(Duration::from_millis(20));
+  assert_eq!(stalled.err().map(|e| e.kind()), Some(ErrorKind::Timeout));
+
+  let resumed = source.next_frame(TIMEOUT)?;
+  assert_eq!(resumed.sequence, 2);
+  Ok(())
+}
+
+#[test]
+fn latency_respects_the_timeout_and_skips_dropped_frames() -> Result<()> {
+  let mut source = mock_injector();
+  source.schedule(0, Fault::Latency(Duration::from_millis(500)));
+  source.schedule(0, Fault::DropFrame);
+  source.schedule(2, Fault::Latency(Duration::from_millis(500)));
+  source.start()?;
+
+  let started = Instant::now();
+  assert_eq!(source.next_frame(TIMEOUT)?.sequence, 1);
+  assert!(started.elapsed() < Duration::from_millis(250));
+
+  let started = Instant::now();
+  let late = source.next_frame(Duration::from_millis(30));
+  assert_eq!(late.err().map(|e| e.kind()), Some(ErrorKind::Timeout));
+  assert!(started.elapsed() < Duration::from_millis(250));
+  assert_eq!(source.next_frame(TIMEOUT)?.sequence, 3);
+  Ok(())
+}
+
+#[test]
+fn disconnect_surfaces_as_disconnected_error() -> Result<()> {
+  let mut source = mock_injector();
+  source.schedule(3, Fault::Disconnect);
+  source.start()?;
+  for _ in 0..3 { source.next_frame(TIMEOUT)?; }
+
+  for _ in 0..2 {
+    assert_eq!(source.next_frame(TIMEOUT).err().map(|e| e.kind()), Some(ErrorKind::Disconnected));
+  }
+  assert_eq!(source.start().err().map(|e| e.kind()), Some(ErrorKind::Disconnected));
+
+  source.reconnect();
+  source.start()?;
+  assert_eq!(source.next_frame(TIMEOUT)?.sequence, 0);
+  Ok(())
+}
+
+#[test]
+fn random_faults_are_reproducible_with_seed() -> Result<()> {
+  let run = |seed: u64| -> Vec<u64> {
+    let mut source = mock_injector();
+    source.set_seed(seed);
+    source.set_drop_probability(0.3);
+    source.start().unwrap();
+    (0..20).map(|_| source.next_frame(TIMEOUT).unwrap().sequence).collect()
+  };
+  let first = run(7);
+  assert_eq!(first, run(7));
+  assert!(first.windows(2).any(|w| w[1] - w[0] > 1), "expected at least one dropped frame");
+  Ok(())
+}
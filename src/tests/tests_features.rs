@@ -0,0 +1,123 @@
+// tests/tests_features.rs
+//
+// Draws textures into both eyes with known disparity and motion, then checks that FAST/ORB features match between the eyes and follow the motion between frames.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+const SIZE: u32 = RIGEL_EYE_WIDTH;
+const FOCAL: f64 = 200.0;
+const BASELINE: f64 = 0.06;
+
+fn calibration() -> StereoCalibration {
+  let camera = CameraIntrinsics { width: SIZE, height: SIZE, fx: FOCAL, fy: FOCAL, cx: 191.5, cy: 191.5, distortion: Distortion::None };
+  matched_pair(camera, BASELINE)
+}
+
+// 8 pixel blocks of random grays between `low` and `high`.
+fn blocks(x: f64, y: f64, low: f64, high: f64) -> f64 {
+  low + (block_hash(x, y, 8.0) % 1000) as f64 / 999.0 * (high - low)
+}
+
+// Each eye's pixel (u, v) shows the texture at the point `place` moves it to, averaged over 2x2 samples.
+fn draw(texture: &dyn Fn(f64, f64) -> f64, place: [&dyn Fn(f64, f64) -> (f64, f64); 2], sequence: u64) -> Frame {
+  let mut data = vec![0u8; (SIZE * SIZE * 2) as usize];
+  for (eye, place) in place.iter().enumerate() {
+    for v in 0..SIZE {
+      for u in 0..SIZE {
+        let mut sum = 0.0;
+        for sub in 0..4 {
+          let (x, y) = place(u as f64 + (sub % 2) as f64 / 2.0 - 0.25, v as f64 + (sub / 2) as f64 / 2.0 - 0.25);
+          sum += texture(x, y);
+        }
+        data[(v * SIZE * 2 + eye as u32 * SIZE + u) as usize] = (sum / 4.0).round() as u8;
+      }
+    }
+  }
+  Frame::new(SIZE, SIZE, data, sequence, Duration::from_millis(sequence * 11))
+}
+
+// The texture is a wall 1 m away, so the right eye sees it shifted by this many pixels.
+const DISPARITY: f64 = FOCAL * BASELINE;
+
+fn texture(x: f64, y: f64) -> f64 { blocks(x, y, 20.0, 140.0) }
+
+#[test]
+fn matches_features_between_the_eyes() -> Result<()> {
+  let frame = draw(&texture, [&|u, v| (u, v), &|u, v| (u + DISPARITY, v)], 0);
+  let mut detector = FeatureDetector::new(&calibration());
+  let features = detector.process(&frame)?;
+  assert!(features.left.keypoints.len() > 200 && features.left.keypoints.len() <= 500, "{}", features.left.keypoints.len());
+  assert_eq!(features.left.keypoints.len(), features.left.descriptors.len());
+  assert!(features.left_temporal.is_empty());
+  assert!(features.stereo.len() > 150, "{} stereo matches", features.stereo.len());
+
+  let mut good = 0;
+  for m in &features.stereo {
+    let (left, right) = (features.left.keypoints[m.left], features.right.keypoints[m.right]);
+    if (left.x - right.x - DISPARITY).abs() < 0.5 && (left.y - right.y).abs() < 0.5 && (m.position[2] - 1.0).abs() < 0.05 { good += 1; }
+    assert!(m.distance <= 50 && m.epipolar_error <= 2.0);
+  }
+  assert!(good * 100 >= features.stereo.len() * 98, "{} of {} stereo matches are right", good, features.stereo.len());
+  Ok(())
+}
+
+#[test]
+fn tracks_features_between_frames() -> Result<()> {
+  // The second frame is turned by 15 degrees about the center and shifted.
+  let (sin, cos) = 15f64.to_radians().sin_cos();
+  let turned = move |u: f64, v: f64| {
+    let (x, y) = (u - 191.5 - 4.0, v - 191.5 + 3.0);
+    (cos * x + sin * y + 191.5, -sin * x + cos * y + 191.5)
+  };
+  let first = draw(&texture, [&|u, v| (u, v), &|u, v| (u + DISPARITY, v)], 0);
+  let second = draw(&texture, [&turned, &|u, v| { let (x, y) = turned(u, v); (x + DISPARITY, y) }], 1);
+
+  let mut detector = FeatureDetector::new(&calibration());
+  detector.set_search_radius(64.0);
+  let before = detector.process(&first)?;
+  let after = detector.process(&second)?;
+  assert_eq!(after.sequence, 1);
+  for (temporal, previous, current) in [(&after.left_temporal, &before.left, &after.left), (&after.right_temporal, &before.right, &after.right)].iter() {
+    assert!(temporal.len() > 100, "{} temporal matches", temporal.len());
+    let mut good = 0;
+    for m in temporal.iter() {
+      let (from, to) = (previous.keypoints[m.previous], current.keypoints[m.current]);
+      // Where the texture point under `from` is seen in the second frame.
+      let (x, y) = (from.x - 191.5, from.y - 191.5);
+      let expected = (cos * x - sin * y + 191.5 + 4.0, sin * x + cos * y + 191.5 - 3.0);
+      if (to.x - expected.0).hypot(to.y - expected.1) < 2.0 { good += 1; }
+    }
+    assert!(good * 100 >= temporal.len() * 95, "{} of {} temporal matches are right", good, temporal.len());
+  }
+
+  detector.reset();
+  assert!(detector.process(&second)?.left_temporal.is_empty());
+  Ok(())
+}
+
+#[test]
+fn finds_features_in_dark_low_contrast_images() -> Result<()> {
+  // Faint texture, and a saturated LED reflection whose edge outshines everything else.
+  let dark = |x: f64, y: f64| if (x - 120.0).hypot(y - 200.0) < 30.0 { 255.0 } else { blocks(x, y, 18.0, 34.0) };
+  let frame = draw(&dark, [&|u, v| (u, v), &|u, v| (u + DISPARITY, v)], 0);
+  let cells = |features: &EyeFeatures| {
+    let mut cells: Vec<(u32, u32)> = features.keypoints.iter().map(|keypoint| (keypoint.x as u32 / 32, keypoint.y as u32 / 32)).collect();
+    cells.sort_unstable();
+    cells.dedup();
+    cells.len()
+  };
+
+  let mut detector = FeatureDetector::new(&calibration());
+  let features = detector.process(&frame)?;
+  assert!(cells(&features.left) > 100, "features in {} cells", cells(&features.left));
+  assert!(features.stereo.len() > 100, "{} stereo matches", features.stereo.len());
+
+  detector.set_thresholds(20, 20);
+  let strict = detector.detect_eye(&frame, Eye::Left)?;
+  assert!(cells(&strict) < 20, "features in {} cells", cells(&strict));
+  Ok(())
+}
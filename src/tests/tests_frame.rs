@@ -0,0 +1,66 @@
+// tests/tests_frame.rs
+//
+// Converts frames to and from `image` buffers.
+
+use std::{borrow::Cow, time::Duration};
+
+use image::{GenericImageView, GrayImage, Luma};
+
+use crate::*;
+
+// A 3x2 (per eye) frame: left eye pixels count up from 0, right eye from 100.
+fn small_frame() -> Frame {
+  let data = vec![0, 1, 2, 100, 101, 102, 3, 4, 5, 103, 104, 105];
+  Frame::new(3, 2, data, 7, Duration::from_millis(77))
+}
+
+#[test]
+fn eye_images_and_views() -> Result<()> {
+  let frame = small_frame();
+  let side_by_side = frame.side_by_side();
+  assert!(matches!(side_by_side.as_raw(), Cow::Borrowed(_)));
+  assert_eq!(side_by_side.dimensions(), (6, 2));
+  assert_eq!(frame.left_image().into_raw().into_owned(), vec![0, 1, 2, 3, 4, 5]);
+  assert_eq!(frame.right_image().get_pixel(2, 1), &Luma([105]));
+
+  let right = frame.eye_view(Eye::Right).unwrap();
+  assert_eq!((right.dimensions(), right.stride()), ((3, 2), 6));
+  assert_eq!(right.row(1), &[103, 104, 105]);
+  assert_eq!(right.get_pixel(0, 1), Luma([103]));
+  assert_eq!(right.to_image().into_raw(), vec![100, 101, 102, 103, 104, 105]);
+  assert_eq!(right.pixels().map(|(_, _, px)| px.0[0]).collect::<Vec<_>>(), vec![100, 101, 102, 103, 104, 105]);
+
+  // Incomplete frames are copied and padded, and have no in-place view.
+  let mut short = small_frame();
+  short.data.truncate(8);
+  assert!(matches!(short.side_by_side().as_raw(), Cow::Owned(_)));
+  assert_eq!(short.right_image().into_raw().into_owned(), vec![100, 101, 102, 0, 0, 0]);
+  assert!(short.eye_view(Eye::Left).is_none());
+  Ok(())
+}
+
+#[test]
+fn zero_height_frames_have_empty_images() -> Result<()> {
+  let frame = Frame::new(3, 0, Vec::new(), 0, Duration::from_secs(0));
+  assert!(frame.is_complete());
+  for layout in [EyeLayout::SideBySide, EyeLayout::Left, EyeLayout::Right].iter().copied() {
+    assert!(frame.image(layout).as_raw().is_empty());
+  }
+  assert_eq!(frame.eye_view(Eye::Right).unwrap().dimensions(), (3, 0));
+  Ok(())
+}
+
+#[test]
+fn frames_from_images() -> Result<()> {
+  let frame = small_frame();
+  let rebuilt = Frame::from_images(&frame.left_image(), &frame.right_image(), 7, frame.timestamp)?;
+  assert_eq!((rebuilt.width, rebuilt.height, rebuilt.sequence), (3, 2, 7));
+  assert_eq!(rebuilt.data, frame.data);
+
+  let joined = Frame::from_side_by_side(GrayImage::from_raw(6, 2, frame.data.clone()).unwrap(), 8, Duration::from_millis(88))?;
+  assert_eq!((joined.width, joined.data), (3, frame.data.clone()));
+
+  assert!(Frame::from_images(&GrayImage::new(3, 2), &GrayImage::new(2, 3), 0, Duration::from_secs(0)).is_err());
+  assert!(Frame::from_side_by_side(GrayImage::new(5, 2), 0, Duration::from_secs(0)).is_err());
+  Ok(())
+}
@@ -0,0 +1,114 @@
+// tests/tests_mcap.rs
+//
+// Writes MCAP to memory and walks the records back with a minimal reader.
+
+use std::{collections::BTreeMap, time::{Duration, UNIX_EPOCH}};
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn u16_at(b: &[u8], at: usize) -> u16 { u16::from_le_bytes([b[at], b[at + 1]]) }
+
+// (opcode, content) of each record in `bytes`.
+fn records(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+  let mut out = Vec::new();
+  let mut at = 0;
+  while at < bytes.len() {
+    let len = u64_at(bytes, at + 1) as usize;
+    out.push((bytes[at], &bytes[at + 9..at + 9 + len]));
+    at += 9 + len;
+  }
+  out
+}
+
+fn test_calibration() -> StereoCalibration {
+  let camera = CameraIntrinsics { width: 4, height: 2, fx: 150.0, fy: 150.0, cx: 2.0, cy: 1.0, distortion: Distortion::Equidistant([0.1, 0.0, 0.0, 0.0]) };
+  matched_pair(camera, 0.04)
+}
+
+fn frames(count: u64) -> Vec<Frame> {
+  // 20 fps, with sequence 3 missing.
+  (0..count).filter(|seq| *seq != 3).map(|seq| {
+    let data = (0..16).map(|px| px as u8 + seq as u8).collect();
+    Frame::new(4, 2, data, seq, Duration::from_millis(seq * 50))
+  }).collect()
+}
+
+#[test]
+fn writes_chunked_indexed_mcap() -> Result<()> {
+  let mut mcap = McapWriter::new(Vec::new(), McapSchema::Ros2, Some(test_calibration()))?;
+  mcap.set_time_base(UNIX_EPOCH + Duration::from_secs(1_000));
+  for frame in frames(30) { mcap.write_frame(&frame)?; }
+  let bytes = mcap.into_inner()?;
+
+  assert_eq!(&bytes[..8], b"\x89MCAP0\r\n");
+  assert_eq!(&bytes[bytes.len() - 8..], b"\x89MCAP0\r\n");
+  let all = records(&bytes[8..bytes.len() - 8]);
+  let (footer_op, footer) = all[all.len() - 1];
+  assert_eq!(footer_op, 0x02);
+
+  // The summary's statistics count every message: 29 frames x 4 image/calibration messages plus one stats message.
+  let summary = records(&bytes[u64_at(footer, 0) as usize..u64_at(footer, 8) as usize]);
+  let statistics = summary.iter().find(|(op, _)| *op == 0x0B).unwrap().1;
+  assert_eq!(u64_at(statistics, 0), 29 * 4 + 1);
+  assert_eq!(u16_at(statistics, 8), 3);
+  assert_eq!(u32_at(statistics, 10), 5);
+  assert_eq!(u64_at(statistics, 26), 1_000_000_000_000);
+  let chunk_indexes: Vec<&[u8]> = summary.iter().filter(|(op, _)| *op == 0x08).map(|(_, content)| *content).collect();
+  assert_eq!(chunk_indexes.len(), 1);
+
+  // Decompress the chunk the index points at and check the messages inside.
+  let chunk_start = u64_at(chunk_indexes[0], 16) as usize;
+  let chunk = records(&bytes[chunk_start..chunk_start + u64_at(chunk_indexes[0], 24) as usize])[0].1;
+  let uncompressed_size = u64_at(chunk, 16) as usize;
+  let compression_len = u32_at(chunk, 28) as usize;
+  assert_eq!(&chunk[32..32 + compression_len], b"zstd");
+  let compressed = &chunk[32 + compression_len + 8..];
+  let messages = zstd::bulk::decompress(compressed, uncompressed_size).unwrap();
+  assert_eq!(crc32fast::hash(&messages), u32_at(chunk, 24));
+
+  let mut per_channel = BTreeMap::new();
+  for (op, content) in records(&messages) {
+    assert_eq!(op, 0x05);
+    per_channel.entry(u16_at(content, 0)).or_insert_with(Vec::new).push(content[22..].to_vec());
+  }
+  assert_eq!(per_channel[&1].len(), 29);
+  assert_eq!(per_channel[&5].len(), 1);
+  assert_eq!(String::from_utf8(per_channel[&5][0].clone()).unwrap(), "{\"frames\":20,\"dropped\":1,\"fps\":19.00}");
+
+  // The right image of the first frame: CDR header, stamp, frame id, dimensions, encoding, then the pixels.
+  let image = &per_channel[&2][0];
+  assert_eq!(&image[..4], &[0, 1, 0, 0]);
+  assert_eq!(u32_at(image, 4), 1_000);
+  assert_eq!(&image[image.len() - 8..], &[4, 5, 6, 7, 12, 13, 14, 15]);
+  assert!(image.windows(6).any(|w| w == b"mono8\0"));
+  Ok(())
+}
+
+#[test]
+fn converts_archive_with_foxglove_schema() -> Result<()> {
+  let mut mcap = McapWriter::new(Vec::new(), McapSchema::Foxglove, None)?;
+  let mut archive = MemoryArchive(frames(5));
+  assert_eq!(mcap.write_archive(&mut archive)?, 4);
+  let bytes = mcap.into_inner()?;
+
+  let all = records(&bytes[8..bytes.len() - 8]);
+  let schemas: Vec<&[u8]> = all.iter().filter(|(op, _)| *op == 0x03).map(|(_, content)| *content).collect();
+  // Image and stats schemas, each once before the data and once in the summary.
+  assert_eq!(schemas.len(), 4);
+  assert!(schemas[0].windows(26).any(|w| w == b"foxglove_msgs/msg/RawImage"));
+  Ok(())
+}
+
+// An in-memory FrameArchive.
+struct MemoryArchive(Vec<Frame>);
+
+impl FrameArchive for MemoryArchive {
+  fn info(&self) -> DeviceInfo { DeviceInfo { name: "memory".to_string(), path: String::new(), serial: None } }
+  fn mode(&self) -> CaptureMode { CaptureMode { width: 4, height: 2, fps: 20 } }
+  fn frame_count(&self) -> usize { self.0.len() }
+  fn timestamp(&self, index: usize) -> Result<Duration> { Ok(self.0[index].timestamp) }
+  fn sequence(&self, index: usize) -> Result<u64> { Ok(self.0[index].sequence) }
+  fn read_frame(&mut self, index: usize) -> Result<Frame> { Ok(self.0[index].clone()) }
+}
@@ -0,0 +1,125 @@
+// tests/tests_pointcloud.rs
+//
+// Builds point clouds from small depth maps and a synthetic stereo frame, and checks the written PLY and PCD files.
+
+use std::{fs, time::Duration};
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn camera(width: u32, height: u32) -> CameraIntrinsics {
+  CameraIntrinsics { width, height, fx: 100.0, fy: 100.0, cx: (width as f64 - 1.0) / 2.0, cy: (height as f64 - 1.0) / 2.0, distortion: Distortion::None }
+}
+
+// A 4x2 depth map with three valid pixels, and a frame whose left eye counts up from 10.
+fn small_cloud() -> Result<PointCloud> {
+  let depth = DepthMap { width: 4, height: 2, data: vec![0.5, f32::NAN, 1.0, 2.0, f32::NAN, f32::NAN, f32::NAN, f32::NAN], camera: camera(4, 2) };
+  let mut data = Vec::new();
+  for row in 0..2u8 {
+    data.extend((0..4).map(|col| 10 + row * 4 + col));
+    data.extend([0; 4].iter());
+  }
+  PointCloud::from_depth(&depth, &Frame::new(4, 2, data, 0, Duration::from_secs(0)))
+}
+
+#[test]
+fn depth_maps_become_cropped_clouds() -> Result<()> {
+  let mut cloud = small_cloud()?;
+  assert_eq!(cloud.points.len(), 3);
+  assert_eq!(cloud.points.iter().map(|p| p.intensity).collect::<Vec<_>>(), vec![10, 12, 13]);
+  // Pixel (0, 0) at 0.5 m: x = (0 - 1.5) / 100 * 0.5.
+  assert!((cloud.points[0].position[0] + 0.0075).abs() < 1e-6);
+  assert_eq!(cloud.points[2].position[2], 2.0);
+
+  cloud.crop_depth(0.6, 1.5);
+  assert_eq!(cloud.points.len(), 1);
+  assert_eq!(cloud.points[0].intensity, 12);
+
+  let depth = DepthMap { width: 2, height: 2, data: vec![1.0; 4], camera: camera(2, 2) };
+  assert!(PointCloud::from_depth(&depth, &Frame::new(4, 2, vec![0; 16], 0, Duration::from_secs(0))).is_err());
+  Ok(())
+}
+
+#[test]
+fn writes_ply_and_pcd() -> Result<()> {
+  let cloud = small_cloud()?;
+
+  let mut ascii = Vec::new();
+  cloud.write(&mut ascii, PointCloudFormat::PlyAscii)?;
+  let text = String::from_utf8(ascii).unwrap();
+  let (header, body) = text.split_at(text.find("end_header\n").unwrap() + "end_header\n".len());
+  assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+  assert!(header.contains("element vertex 3\n"));
+  let rows: Vec<Vec<f32>> = body.lines().map(|line| line.split(' ').map(|v| v.parse().unwrap()).collect()).collect();
+  assert_eq!(rows.len(), 3);
+  assert_eq!(rows[1][2..], [1.0, 12.0]);
+
+  let mut binary = Vec::new();
+  cloud.write(&mut binary, PointCloudFormat::PlyBinary)?;
+  let body = &binary[binary.windows(11).position(|w| w == b"end_header\n").unwrap() + 11..];
+  assert!(binary.starts_with(b"ply\nformat binary_little_endian 1.0\n"));
+  assert_eq!(body.len(), 3 * 13);
+  assert_eq!(f32::from_le_bytes([body[21], body[22], body[23], body[24]]), 1.0);
+  assert_eq!(body[25], 12);
+
+  let mut pcd = Vec::new();
+  cloud.write(&mut pcd, PointCloudFormat::PcdBinary)?;
+  let body = &pcd[pcd.windows(12).position(|w| w == b"DATA binary\n").unwrap() + 12..];
+  assert!(String::from_utf8_lossy(&pcd).contains("WIDTH 3\nHEIGHT 1\n"));
+  assert_eq!(body.len(), 3 * 16);
+  assert_eq!(f32::from_le_bytes([body[44], body[45], body[46], body[47]]), 13.0);
+
+  let mut pcd = Vec::new();
+  cloud.write(&mut pcd, PointCloudFormat::PcdAscii)?;
+  let text = String::from_utf8(pcd).unwrap();
+  assert!(text.contains("POINTS 3\nDATA ascii\n"));
+  assert_eq!(text.lines().last(), Some(format!("{} {} 2 13", cloud.points[2].position[0], cloud.points[2].position[1]).as_str()));
+  Ok(())
+}
+
+// A textured plane at a constant disparity of 8 px, seen by ideal cameras 4 cm apart: 100 * 0.04 / 8 = 0.5 m.
+fn plane_frame(sequence: u64) -> Frame {
+  let (width, height) = (64usize, 32usize);
+  let mut state = 7u32;
+  let texture: Vec<u8> = (0..(width + 8) * height).map(|_| {
+    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+    (state >> 16) as u8
+  }).collect();
+  let mut data = Vec::new();
+  for y in 0..height {
+    let row = &texture[y * (width + 8)..(y + 1) * (width + 8)];
+    data.extend_from_slice(&row[..width]);
+    data.extend_from_slice(&row[8..]);
+  }
+  Frame::new(width as u32, height as u32, data, sequence, Duration::from_millis(sequence * 11))
+}
+
+#[test]
+fn exporter_writes_selected_frames() -> Result<()> {
+  let calibration = matched_pair(camera(64, 32), 0.04);
+  let dir = std::env::temp_dir().join(format!("tinyrigel-pointcloud-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+
+  let mut exporter = PointCloudExporter::new(&dir, PointCloudFormat::PlyAscii, &calibration, StereoMatcher::new(MatchingMethod::Block, 16));
+  exporter.set_depth_range(Some((0.45, 0.55)));
+  let cloud = exporter.point_cloud(&plane_frame(0))?;
+  assert!(cloud.points.len() > 64 * 32 / 2, "only {} points", cloud.points.len());
+  assert!(cloud.points.iter().all(|p| (p.position[2] - 0.5).abs() <= 0.05));
+
+  exporter.set_interval(2);
+  for sequence in 0..4 {
+    exporter.write_frame(&plane_frame(sequence))?;
+  }
+  exporter.finish()?;
+  let mut names: Vec<String> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+  names.sort();
+  assert_eq!(names, vec!["00000000.ply", "00000002.ply"]);
+  let text = fs::read_to_string(dir.join("00000002.ply")).unwrap();
+  assert!(text.contains(&format!("element vertex {}\n", cloud.points.len())));
+
+  exporter.set_depth_range(Some((0.6, 1.0)));
+  assert!(exporter.point_cloud(&plane_frame(0))?.points.is_empty());
+  fs::remove_dir_all(&dir).unwrap();
+  Ok(())
+}
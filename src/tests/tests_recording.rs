@@ -0,0 +1,300 @@
+// tests/tests_recording.rs
+//
+// Records frames from a MockSource to the native format, then reads them back directly and through ReplaySource.
+
+use std::{fs::{self, OpenOptions}, io::{Seek, SeekFrom, Write}, path::PathBuf, time::Duration};
+
+use crate::*;
+
+use super::fixtures::*;
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+fn recording_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!("tinyrigel-recording-{}-{}.trec", name, std::process::id()))
+}
+
+fn mock_frames(count: usize) -> Result<(MockSource, Vec<Frame>)> {
+  let mut source = MockSource::new();
+  source.set_realtime(false);
+  source.start()?;
+  let frames = (0..count).map(|_| source.next_frame(TIMEOUT)).collect::<Result<Vec<_>>>()?;
+  Ok((source, frames))
+}
+
+fn test_calibration() -> StereoCalibration {
+  let camera = |cx: f64| CameraIntrinsics { width: 384, height: 384, fx: 150.0, fy: 151.0, cx, cy: 190.0, distortion: Distortion::Equidistant([0.1, -0.02, 0.003, -0.0004]) };
+  StereoCalibration {
+    right: CameraIntrinsics { distortion: Distortion::BrownConrady([-0.3, 0.1, 0.001, 0.002, -0.01]), ..camera(193.5) },
+    ..matched_pair(camera(191.0), 0.04)
+  }
+}
+
+#[test]
+fn roundtrips_frames_and_header() -> Result<()> {
+  let path = recording_path("roundtrip");
+  let (source, frames) = mock_frames(5)?;
+  let mut header = RecordingHeader::new(&source.info(), source.mode());
+  header.calibration = Some(test_calibration());
+
+  let mut recorder = Recorder::create(&path, &header)?;
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+
+  let mut reader = RecordingReader::open(&path)?;
+  assert_eq!(reader.header().model, header.model);
+  assert_eq!(reader.header().serial, header.serial);
+  assert_eq!(reader.header().mode, source.mode());
+  assert_eq!(reader.header().calibration, Some(StereoCalibration { serial: header.serial.clone(), ..test_calibration() }));
+  assert_eq!(reader.frame_count(), 5);
+  // Random access, out of order.
+  for idx in [4, 0, 2].iter().copied() {
+    let frame = reader.read_frame(idx)?;
+    assert_eq!(frame.sequence, frames[idx].sequence);
+    assert_eq!(frame.timestamp, frames[idx].timestamp);
+    assert_eq!(frame.data, frames[idx].data);
+  }
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+// Writes `frames` and then "crashes": the recorder is leaked, so the index and footer are never written.
+fn crashed_recording(name: &str, frames: &[Frame]) -> Result<PathBuf> {
+  let path = recording_path(name);
+  let mut recorder = Recorder::create(&path, &RecordingHeader::new(&MockSource::new().info(), MockSource::new().mode()))?;
+  for frame in frames { recorder.write_frame(frame)?; }
+  std::mem::forget(recorder);
+  Ok(path)
+}
+
+#[test]
+fn recovers_truncated_recording() -> Result<()> {
+  let (_, frames) = mock_frames(4)?;
+  let path = crashed_recording("truncated", &frames)?;
+  assert!(RecordingReader::open(&path).is_err());
+
+  // Cut the last frame record in half, as a power cut mid-write would.
+  let len = fs::metadata(&path).unwrap().len();
+  OpenOptions::new().write(true).open(&path).unwrap().set_len(len - frames[3].data.len() as u64 / 2).unwrap();
+
+  let report = recover(&path)?;
+  assert_eq!(report.frames, 3);
+  assert!(report.discarded_bytes > 0);
+  let mut reader = RecordingReader::open(&path)?;
+  assert_eq!(reader.frame_count(), 3);
+  assert_eq!(reader.read_frame(2)?.data, frames[2].data);
+
+  // Recovering an intact recording changes nothing.
+  assert_eq!(recover(&path)?, RecoveryReport { frames: 3, skipped_records: 0, discarded_bytes: 0 });
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn recovers_the_frames_after_a_damaged_record() -> Result<()> {
+  let (_, frames) = mock_frames(5)?;
+  let path = crashed_recording("damaged", &frames)?;
+  let record_len = 12 + 32 + frames[0].data.len() as u64;
+  let len = fs::metadata(&path).unwrap().len();
+  let first = len - 5 * record_len;
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  // A flipped bit in frame 1's pixels, and frame 3's length field garbled.
+  file.seek(SeekFrom::Start(first + record_len + 500)).unwrap();
+  file.write_all(&[0x55]).unwrap();
+  file.seek(SeekFrom::Start(first + 3 * record_len + 4)).unwrap();
+  file.write_all(&[0xFF; 4]).unwrap();
+  drop(file);
+
+  let report = recover(&path)?;
+  assert_eq!(report, RecoveryReport { frames: 3, skipped_records: 2, discarded_bytes: 0 });
+  let mut reader = RecordingReader::open(&path)?;
+  let sequences: Vec<u64> = reader.index().iter().map(|entry| entry.sequence).collect();
+  assert_eq!(sequences, vec![frames[0].sequence, frames[2].sequence, frames[4].sequence]);
+  assert_eq!(reader.read_frame(2)?.data, frames[4].data);
+  fs::remove_file(&path).unwrap();
+
+  // In a lossless recording the delta frames after a damaged one can't be decoded either.
+  let mut header = RecordingHeader::new(&MockSource::new().info(), MockSource::new().mode());
+  header.codec = Codec::Lossless;
+  let mut recorder = Recorder::create(&path, &header)?;
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+  let (offset, end) = { let reader = RecordingReader::open(&path)?; (reader.index()[2].offset, fs::metadata(&path).unwrap().len() - 16 - 12 - 5 * 24) };
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.set_len(end).unwrap();
+  file.seek(SeekFrom::Start(offset + 60)).unwrap();
+  file.write_all(&[0x55]).unwrap();
+  drop(file);
+  assert_eq!(recover(&path)?, RecoveryReport { frames: 2, skipped_records: 3, discarded_bytes: 0 });
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn recovers_a_recording_with_a_corrupt_index() -> Result<()> {
+  let (_, frames) = mock_frames(3)?;
+  let path = recording_path("index");
+  let mut recorder = Recorder::create(&path, &RecordingHeader::new(&MockSource::new().info(), MockSource::new().mode()))?;
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+
+  // A frame count far beyond what the file could hold.
+  let len = fs::metadata(&path).unwrap().len();
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.seek(SeekFrom::Start(len - 16 - 3 * 24 - 8)).unwrap();
+  file.write_all(&u64::MAX.to_le_bytes()).unwrap();
+  drop(file);
+  assert!(RecordingReader::open(&path).is_err());
+
+  let report = recover(&path)?;
+  assert_eq!((report.frames, report.skipped_records), (3, 0));
+  assert_eq!(RecordingReader::open(&path)?.read_frame(2)?.data, frames[2].data);
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn rejects_a_header_longer_than_the_file() -> Result<()> {
+  let path = recording_path("header");
+  let mut recorder = Recorder::create(&path, &RecordingHeader::new(&MockSource::new().info(), MockSource::new().mode()))?;
+  recorder.finish()?;
+
+  // A truncated file whose header claims to run on for 4 GiB.
+  let file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.set_len(40).unwrap();
+  (&file).seek(SeekFrom::Start(12)).unwrap();
+  (&file).write_all(&u32::MAX.to_le_bytes()).unwrap();
+  drop(file);
+  assert!(RecordingReader::open(&path).is_err());
+  assert!(recover(&path).is_err());
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn detects_corrupt_frames() -> Result<()> {
+  let (_, frames) = mock_frames(3)?;
+  let path = recording_path("corrupt");
+  let mut recorder = Recorder::create(&path, &RecordingHeader::new(&MockSource::new().info(), MockSource::new().mode()))?;
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+
+  let offset = RecordingReader::open(&path)?.index()[1].offset;
+  let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+  file.seek(SeekFrom::Start(offset + 100)).unwrap();
+  file.write_all(&[0xAA; 8]).unwrap();
+  drop(file);
+
+  let mut reader = RecordingReader::open(&path)?;
+  assert!(reader.read_frame(0).is_ok());
+  assert!(reader.read_frame(1).is_err());
+  assert!(reader.read_frame(2).is_ok());
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn rotates_by_size_and_duration() -> Result<()> {
+  let (source, frames) = mock_frames(5)?;
+  let header = RecordingHeader::new(&source.info(), source.mode());
+
+  // Room for two frames per segment.
+  let path = recording_path("rotate-size");
+  let mut recorder = Recorder::create(&path, &header)?;
+  recorder.set_rotation(Some(frames[0].data.len() as u64 * 5 / 2), None);
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+  let segments = recorder.segments().to_vec();
+  assert_eq!(segments.len(), 3);
+  assert_eq!(segments[0], path);
+  let counts: Vec<usize> = segments.iter().map(|segment| RecordingReader::open(segment).unwrap().frame_count()).collect();
+  assert_eq!(counts, vec![2, 2, 1]);
+  assert_eq!(RecordingReader::open(&segments[2])?.read_frame(0)?.sequence, frames[4].sequence);
+  for segment in &segments { fs::remove_file(segment).unwrap(); }
+
+  // A zero duration rotates before every frame but the first in each segment.
+  let path = recording_path("rotate-duration");
+  let mut recorder = Recorder::create(&path, &header)?;
+  recorder.set_rotation(None, Some(Duration::from_secs(0)));
+  for frame in &frames[..3] { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+  assert_eq!(recorder.segments().len(), 3);
+  for segment in recorder.segments() { fs::remove_file(segment).unwrap(); }
+  Ok(())
+}
+
+#[test]
+fn lossless_codec_roundtrips_and_compresses() -> Result<()> {
+  let (source, mut frames) = mock_frames(45)?;
+  // A frame cut short in transit still has to roundtrip exactly.
+  frames[20].data.truncate(1000);
+  let mut header = RecordingHeader::new(&source.info(), source.mode());
+  header.codec = Codec::Lossless;
+
+  let path = recording_path("lossless");
+  let mut recorder = Recorder::create(&path, &header)?;
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+
+  let raw_size = frames.iter().map(|frame| frame.data.len() as u64).sum::<u64>();
+  assert!(fs::metadata(&path).unwrap().len() < raw_size / 2);
+
+  let mut reader = RecordingReader::open(&path)?;
+  assert_eq!(reader.header().codec, Codec::Lossless);
+  // Delta frames well past a keyframe, out of order, then sequentially.
+  for idx in [40, 3, 20, 21].iter().copied().chain(0..frames.len()) {
+    let frame = reader.read_frame(idx)?;
+    assert_eq!(frame.sequence, frames[idx].sequence);
+    assert!(frame.data == frames[idx].data, "frame {} differs", idx);
+  }
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn lossless_segments_decode_independently() -> Result<()> {
+  let (source, frames) = mock_frames(6)?;
+  let mut header = RecordingHeader::new(&source.info(), source.mode());
+  header.codec = Codec::Lossless;
+
+  let path = recording_path("lossless-rotate");
+  let mut recorder = Recorder::create(&path, &header)?;
+  recorder.set_rotation(None, Some(Duration::from_secs(0)));
+  for frame in &frames { recorder.write_frame(frame)?; }
+  recorder.finish()?;
+  for (segment, frame) in recorder.segments().iter().zip(&frames) {
+    assert_eq!(RecordingReader::open(segment)?.read_frame(0)?.data, frame.data);
+    fs::remove_file(segment).unwrap();
+  }
+  Ok(())
+}
+
+#[test]
+fn records_through_rigel_and_replays() -> Result<()> {
+  let path = recording_path("rigel");
+  let mut backend = MockBackend::new();
+  backend.set_realtime(false);
+  let mut rigel = Rigel::<fn(&Frame)>::from_backend(&backend)?;
+  rigel.set_calibration(Some(test_calibration()));
+  rigel.attach_sink(Box::new(Recorder::create(&path, &RecordingHeader::for_rigel(&rigel))?));
+  rigel.open()?;
+  let captured = (0..3).map(|_| rigel.next_frame(TIMEOUT)).collect::<Result<Vec<_>>>()?;
+  rigel.close()?;
+  rigel.finish_sinks()?;
+
+  let header = RecordingReader::open(&path)?.header().clone();
+  assert_eq!(header.calibration, Some(StereoCalibration { serial: header.serial.clone(), ..test_calibration() }));
+
+  let mut replay = ReplaySource::open(&path)?;
+  replay.set_speed(ReplaySpeed::AsFastAsPossible);
+  assert_eq!(replay.info().name, "Mock Rigel");
+  replay.start()?;
+  for frame in &captured {
+    let replayed = replay.next_frame(TIMEOUT)?;
+    assert_eq!(replayed.sequence, frame.sequence);
+    assert_eq!(replayed.data, frame.data);
+  }
+  assert_eq!(replay.next_frame(TIMEOUT).err().map(|e| e.kind()), Some(ErrorKind::EndOfStream));
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
@@ -0,0 +1,96 @@
+// tests/tests_rectify.rs
+//
+// Rectifies a slightly misaligned synthetic stereo pair and checks rows line up and disparities triangulate.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn calibration() -> StereoCalibration {
+  let left = CameraIntrinsics { width: 64, height: 48, fx: 40.0, fy: 40.5, cx: 32.0, cy: 23.0, distortion: Distortion::Equidistant([0.03, -0.01, 0.0, 0.0]) };
+  let right = CameraIntrinsics { fx: 41.0, fy: 41.0, cx: 31.0, cy: 24.5, distortion: Distortion::BrownConrady([-0.05, 0.01, 0.0, 0.0, 0.0]), ..left.clone() };
+  stereo_rig(left, right, [0.02, -0.03, 0.01], [-0.06, 0.002, 0.001])
+}
+
+// Where a ray from `eye`'s original camera, with direction `ray` in that camera's frame, hits the plane z = 2 m of the left camera frame.
+fn hit_plane(calibration: &StereoCalibration, eye: Eye, ray: Vec3) -> Vec3 {
+  let (origin, direction) = match eye {
+    Eye::Left => ([0.0; 3], ray),
+    Eye::Right => {
+      let back = transpose(&calibration.rotation);
+      (scale(mat_vec(&back, calibration.translation), -1.0), mat_vec(&back, ray))
+    }
+  };
+  let s = (2.0 - origin[2]) / direction[2];
+  [origin[0] + s * direction[0], origin[1] + s * direction[1], 2.0]
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 { [a[0] + b[0], a[1] + b[1], a[2] + b[2]] }
+
+fn pattern(point: Vec3) -> f64 { 128.0 + 60.0 * point[0] - 40.0 * point[1] }
+
+#[test]
+fn rectified_points_share_rows_and_triangulate() -> Result<()> {
+  let calibration = calibration();
+  let rectifier = StereoRectifier::new(&calibration);
+
+  // Both rectified frames are parallel: R2 R R1^T is the identity.
+  let relative = mat_mul(&mat_mul(&rectifier.rotation(Eye::Right), &calibration.rotation), &transpose(&rectifier.rotation(Eye::Left)));
+  for (row, expected) in relative.iter().zip(IDENTITY.iter()) {
+    for (value, expected) in row.iter().zip(expected) { assert!((value - expected).abs() < 1e-9); }
+  }
+  let p2 = rectifier.projection(Eye::Right);
+  assert!((p2[0][3] / p2[0][0] + calibration.baseline()).abs() < 1e-9);
+
+  for point in [[0.1, -0.05, 1.0], [-0.4, 0.3, 2.5], [0.6, 0.2, 0.8]].iter().copied() {
+    let right_point = add(mat_vec(&calibration.rotation, point), calibration.translation);
+    let (ul, vl) = calibration.left.project(point[0] / point[2], point[1] / point[2]);
+    let (ur, vr) = calibration.right.project(right_point[0] / right_point[2], right_point[1] / right_point[2]);
+    let (xl, yl) = rectifier.rectify_point(Eye::Left, ul, vl);
+    let (xr, yr) = rectifier.rectify_point(Eye::Right, ur, vr);
+    assert!((yl - yr).abs() < 1e-6, "rows {} and {} differ", yl, yr);
+
+    let triangulated = rectifier.reproject(xl, yl, xl - xr).unwrap();
+    let expected = mat_vec(&rectifier.rotation(Eye::Left), point);
+    for axis in 0..3 { assert!((triangulated[axis] - expected[axis]).abs() < 1e-6, "{:?} vs {:?}", triangulated, expected); }
+  }
+  assert!(rectifier.reproject(10.0, 10.0, -1.0).is_none());
+  Ok(())
+}
+
+#[test]
+fn rectifies_rendered_frames() -> Result<()> {
+  let calibration = calibration();
+  // Render a textured plane 2 m in front of the left camera through each eye's lens.
+  let mut data = Vec::new();
+  for v in 0..48 {
+    for eye in [Eye::Left, Eye::Right].iter().copied() {
+      for u in 0..64 {
+        let (x, y) = calibration.camera(eye).unproject(u as f64, v as f64);
+        data.push(pattern(hit_plane(&calibration, eye, [x, y, 1.0])).round().clamp(0.0, 255.0) as u8);
+      }
+    }
+  }
+  let frame = Frame::new(64, 48, data, 9, Duration::from_millis(100));
+
+  let mut rectifier = StereoRectifier::new(&calibration);
+  rectifier.set_threads(2);
+  let out = rectifier.rectify(&frame)?;
+  assert_eq!((out.sequence, out.timestamp), (9, Duration::from_millis(100)));
+  for eye in [Eye::Left, Eye::Right].iter().copied() {
+    let camera = rectifier.rectified_camera(eye);
+    let unrotate = transpose(&rectifier.rotation(eye));
+    let view = out.eye_view(eye).unwrap();
+    for v in 10..38 {
+      for u in 14..50 {
+        let ray = mat_vec(&unrotate, [(u as f64 - camera.cx) / camera.fx, (v as f64 - camera.cy) / camera.fy, 1.0]);
+        let expected = pattern(hit_plane(&calibration, eye, ray));
+        let actual = view.row(v)[u as usize] as f64;
+        assert!((actual - expected).abs() <= 2.0, "{:?} ({}, {}): {} vs {}", eye, u, v, actual, expected);
+      }
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,115 @@
+// tests/tests_replay.rs
+//
+// Plays back a small generated PNG sequence through ReplaySource and Rigel.
+
+use std::{fs, path::PathBuf, time::{Duration, Instant}};
+
+use crate::*;
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// Writes `count` tiny 8x4 (4x4 per eye) frames, each filled with its own index.
+fn png_sequence_dir(name: &str, count: u8) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("tinyrigel-replay-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  for idx in 0..count {
+    let img = image::GrayImage::from_pixel(8, 4, image::Luma([idx]));
+    img.save(dir.join(format!("frame_{:04}.png", idx))).unwrap();
+  }
+  dir
+}
+
+fn fast_replay(name: &str, count: u8) -> Result<ReplaySource> {
+  let mut replay = ReplaySource::open(png_sequence_dir(name, count))?;
+  replay.set_speed(ReplaySpeed::AsFastAsPossible);
+  Ok(replay)
+}
+
+#[test]
+fn replays_png_sequence_in_order() -> Result<()> {
+  let mut replay = fast_replay("order", 3)?;
+  assert_eq!(replay.mode(), CaptureMode { width: 4, height: 4, fps: 90 });
+  replay.start()?;
+  for idx in 0..3u8 {
+    let frame = replay.next_frame(TIMEOUT)?;
+    assert_eq!(frame.sequence, idx as u64);
+    assert!(frame.is_complete());
+    assert!(frame.data.iter().all(|&px| px == idx));
+  }
+  assert_eq!(replay.next_frame(TIMEOUT).err().map(|e| e.kind()), Some(ErrorKind::EndOfStream));
+  Ok(())
+}
+
+#[test]
+fn loops_with_increasing_sequence_and_time() -> Result<()> {
+  let mut replay = fast_replay("loop", 2)?;
+  replay.set_looping(true);
+  replay.start()?;
+  let frames: Vec<Frame> = (0..5).map(|_| replay.next_frame(TIMEOUT).unwrap()).collect();
+  let sequences: Vec<u64> = frames.iter().map(|f| f.sequence).collect();
+  assert_eq!(sequences, vec![0, 1, 2, 3, 4]);
+  assert!(frames.windows(2).all(|w| w[1].timestamp > w[0].timestamp));
+  assert_eq!(frames[2].data[0], 0);
+  Ok(())
+}
+
+#[test]
+fn loops_a_recording_whose_sequence_restarted() -> Result<()> {
+  // The device was reopened mid-session, so the counter starts over at 0.
+  let path = std::env::temp_dir().join(format!("tinyrigel-replay-restart-{}.trec", std::process::id()));
+  let mut recorder = Recorder::create(&path, &RecordingHeader::new(&MockSource::new().info(), CaptureMode { width: 4, height: 4, fps: 90 }))?;
+  for (idx, &sequence) in [40u64, 41, 0].iter().enumerate() {
+    recorder.write_frame(&Frame::new(4, 4, vec![idx as u8; 32], sequence, Duration::from_millis(idx as u64 * 11)))?;
+  }
+  recorder.finish()?;
+
+  let mut replay = ReplaySource::open(&path)?;
+  replay.set_speed(ReplaySpeed::AsFastAsPossible);
+  replay.set_looping(true);
+  replay.start()?;
+  let sequences: Vec<u64> = (0..4).map(|_| replay.next_frame(TIMEOUT).unwrap().sequence).collect();
+  assert_eq!(sequences, vec![40, 41, 0, 43]);
+  fs::remove_file(&path).unwrap();
+  Ok(())
+}
+
+#[test]
+fn seeks_by_index_and_time() -> Result<()> {
+  let mut replay = fast_replay("seek", 10)?;
+  replay.start()?;
+  replay.seek(7)?;
+  assert_eq!(replay.next_frame(TIMEOUT)?.sequence, 7);
+
+  // Frame 4 is at 4/90 s = 44.4 ms.
+  replay.seek_to_time(Duration::from_millis(40))?;
+  assert_eq!(replay.position(), 4);
+  assert!(replay.seek(10).is_err());
+  Ok(())
+}
+
+#[test]
+fn paces_frames_by_speed_multiplier() -> Result<()> {
+  let mut replay = ReplaySource::open(png_sequence_dir("pace", 10))?;
+  replay.set_speed(ReplaySpeed::Multiplier(2.0));
+  replay.start()?;
+  let started = Instant::now();
+  for _ in 0..10 { replay.next_frame(TIMEOUT)?; }
+  // Nine intervals of 1/90 s at double speed is 50 ms.
+  let elapsed = started.elapsed();
+  assert!(elapsed >= Duration::from_millis(45), "replay ran too fast: {:?}", elapsed);
+  assert!(elapsed < Duration::from_millis(400), "replay ran too slow: {:?}", elapsed);
+  Ok(())
+}
+
+#[test]
+fn rigel_pulls_from_replay_backend() -> Result<()> {
+  let dir = png_sequence_dir("backend", 2);
+  let mut rigel: Rigel = Rigel::from_backend(&ReplayBackend::new(vec![dir]))?;
+  rigel.open()?;
+  assert_eq!(rigel.next_frame(TIMEOUT)?.sequence, 0);
+  assert_eq!(rigel.next_frame(TIMEOUT)?.sequence, 1);
+  assert_eq!(rigel.next_frame(TIMEOUT).err().map(|e| e.kind()), Some(ErrorKind::EndOfStream));
+  rigel.close()?;
+  Ok(())
+}
@@ -0,0 +1,148 @@
+// tests/tests_rosbag.rs
+//
+// Writes bags to memory and walks the records back with a minimal reader.
+
+use std::{collections::BTreeMap, io::Cursor, time::{Duration, UNIX_EPOCH}};
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn f64_at(b: &[u8], at: usize) -> f64 { f64::from_bits(u64_at(b, at)) }
+
+struct Record<'a> {
+  fields: BTreeMap<String, &'a [u8]>,
+  data: &'a [u8],
+  len: usize
+}
+
+impl Record<'_> {
+  fn op(&self) -> u8 { self.fields["op"][0] }
+  fn u32(&self, name: &str) -> u32 { u32_at(self.fields[name], 0) }
+}
+
+fn record(bytes: &[u8]) -> Record<'_> {
+  let header_len = u32_at(bytes, 0) as usize;
+  let mut fields = BTreeMap::new();
+  let mut at = 4;
+  while at < 4 + header_len {
+    let len = u32_at(bytes, at) as usize;
+    let field = &bytes[at + 4..at + 4 + len];
+    let eq = field.iter().position(|b| *b == b'=').unwrap();
+    fields.insert(String::from_utf8(field[..eq].to_vec()).unwrap(), &field[eq + 1..]);
+    at += 4 + len;
+  }
+  let data_len = u32_at(bytes, at) as usize;
+  Record { fields, data: &bytes[at + 4..at + 4 + data_len], len: at + 4 + data_len }
+}
+
+fn records(mut bytes: &[u8]) -> Vec<Record<'_>> {
+  let mut out = Vec::new();
+  while !bytes.is_empty() {
+    let record = record(bytes);
+    bytes = &bytes[record.len..];
+    out.push(record);
+  }
+  out
+}
+
+fn test_calibration() -> StereoCalibration {
+  let camera = CameraIntrinsics { width: 4, height: 2, fx: 150.0, fy: 150.0, cx: 2.0, cy: 1.0, distortion: Distortion::BrownConrady([0.1, -0.01, 0.0, 0.0, 0.0]) };
+  matched_pair(camera, 0.04)
+}
+
+#[test]
+fn writes_indexed_bag_with_camera_info() -> Result<()> {
+  let mut bag = BagWriter::new(Cursor::new(Vec::new()), Some(test_calibration()))?;
+  bag.set_time_base(UNIX_EPOCH + Duration::from_secs(1_000));
+  for seq in 5..8u64 {
+    let data = (0..16).map(|px| px as u8 + seq as u8).collect();
+    bag.write_frame(&Frame::new(4, 2, data, seq, Duration::from_millis(seq * 11)))?;
+  }
+  let bytes = bag.into_inner()?.into_inner();
+
+  assert_eq!(&bytes[..13], b"#ROSBAG V2.0\n");
+  let header = record(&bytes[13..]);
+  assert_eq!(header.op(), 0x03);
+  assert_eq!(header.len, 4096);
+  assert_eq!(header.u32("conn_count"), 4);
+  assert_eq!(header.u32("chunk_count"), 1);
+
+  // The index section: every connection, then the chunk info.
+  let index = records(&bytes[u64_at(header.fields["index_pos"], 0) as usize..]);
+  assert_eq!(index.len(), 5);
+  let topics: Vec<&[u8]> = index[..4].iter().map(|conn| conn.fields["topic"]).collect();
+  assert_eq!(topics, [&b"/rigel/left/image_raw"[..], b"/rigel/right/image_raw", b"/rigel/left/camera_info", b"/rigel/right/camera_info"]);
+  assert!(String::from_utf8_lossy(index[2].data).contains("md5sum=c9a58c1b0b154e0e6da7578cb991d214"));
+  let chunk_info = &index[4];
+  assert_eq!(chunk_info.op(), 0x06);
+  assert_eq!(chunk_info.u32("count"), 4);
+  assert_eq!(u32_at(chunk_info.fields["start_time"], 0), 1_000);
+  assert_eq!(u32_at(chunk_info.fields["end_time"], 4), 22_000_000);
+
+  // The chunk, then one index record per connection.
+  let chunk_pos = u64_at(chunk_info.fields["chunk_pos"], 0) as usize;
+  let chunk = record(&bytes[chunk_pos..]);
+  assert_eq!(chunk.fields["compression"], b"none");
+  let indexes = records(&bytes[chunk_pos + chunk.len..u64_at(header.fields["index_pos"], 0) as usize]);
+  assert_eq!(indexes.len(), 4);
+  let right_index = &indexes[1];
+  assert_eq!((right_index.u32("conn"), right_index.u32("count")), (1, 3));
+
+  // The last right image, found through the index: seq, stamp, frame id, then the image fields and pixels.
+  let offset = u32_at(right_index.data, 2 * 12 + 8) as usize;
+  let message = record(&chunk.data[offset..]);
+  assert_eq!((message.op(), message.u32("conn")), (0x02, 1));
+  let image = message.data;
+  assert_eq!(u32_at(image, 0), 7);
+  assert_eq!((u32_at(image, 4), u32_at(image, 8)), (1_000, 22_000_000));
+  assert_eq!(&image[16..27], b"rigel_right");
+  assert_eq!((u32_at(image, 27), u32_at(image, 31)), (2, 4));
+  assert_eq!(&image[35..44], b"\x05\0\0\0mono8");
+  assert_eq!((image[44], u32_at(image, 45)), (0, 4));
+  assert_eq!(u32_at(image, 49), 8);
+  assert_eq!(&image[53..], &[11, 12, 13, 14, 19, 20, 21, 22]);
+
+  // The right camera info carries the baseline in P.
+  let info_index = &indexes[3];
+  let info = record(&chunk.data[u32_at(info_index.data, 8) as usize..]).data;
+  assert_eq!(&info[39..48], b"plumb_bob");
+  assert_eq!(u32_at(info, 48), 5);
+  let p = 52 + 5 * 8 + 9 * 8 + 9 * 8;
+  assert_eq!(f64_at(info, p), 150.0);
+  assert!((f64_at(info, p + 3 * 8) + 6.0).abs() < 1e-9);
+  assert_eq!(info.len(), p + 12 * 8 + 6 * 4 + 1);
+  Ok(())
+}
+
+#[test]
+fn splits_full_size_frames_across_chunks() -> Result<()> {
+  let mut bag = BagWriter::new(Cursor::new(Vec::new()), None)?;
+  let frames = 12u64;
+  for seq in 0..frames {
+    let data = vec![seq as u8; (RIGEL_EYE_WIDTH * 2 * RIGEL_EYE_HEIGHT) as usize];
+    bag.write_frame(&Frame::new(RIGEL_EYE_WIDTH, RIGEL_EYE_HEIGHT, data, seq, Duration::from_millis(seq * 11)))?;
+  }
+  let bytes = bag.into_inner()?.into_inner();
+
+  let header = record(&bytes[13..]);
+  assert_eq!(header.u32("conn_count"), 2);
+  let chunk_count = header.u32("chunk_count") as usize;
+  assert!(chunk_count > 1);
+  let index = records(&bytes[u64_at(header.fields["index_pos"], 0) as usize..]);
+  assert_eq!(index.len(), 2 + chunk_count);
+
+  // Each chunk info's per-connection counts add up to every frame, and each chunk carries the records of the connections it uses.
+  let mut left_messages = 0;
+  for chunk_info in &index[2..] {
+    let chunk = record(&bytes[u64_at(chunk_info.fields["chunk_pos"], 0) as usize..]);
+    let inner = records(chunk.data);
+    assert_eq!(inner.iter().filter(|record| record.op() == 0x07).count(), 2);
+    assert_eq!(inner[0].op(), 0x07);
+    assert_eq!((u32_at(chunk_info.data, 0), u32_at(chunk_info.data, 8)), (0, 1));
+    left_messages += u32_at(chunk_info.data, 4);
+    assert_eq!(inner.iter().filter(|message| message.op() == 0x02).count() as u32, u32_at(chunk_info.data, 4) + u32_at(chunk_info.data, 12));
+  }
+  assert_eq!(left_messages as u64, frames);
+  Ok(())
+}
@@ -0,0 +1,130 @@
+// tests/tests_stereo.rs
+//
+// Matches synthetic rectified pairs with known disparities.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+
+// A deterministic random texture, blurred a little so subpixel shifts interpolate smoothly.
+fn texture(width: usize, height: usize, seed: u32) -> Vec<f32> {
+  let mut state = seed;
+  let noise: Vec<f32> = (0..width * height).map(|_| {
+    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+    ((state >> 16) & 0xff) as f32
+  }).collect();
+  let mut out = noise.clone();
+  for y in 0..height {
+    for x in 1..width - 1 {
+      out[y * width + x] = (noise[y * width + x - 1] + 2.0 * noise[y * width + x] + noise[y * width + x + 1]) / 4.0;
+    }
+  }
+  out
+}
+
+fn sample(texture: &[f32], width: usize, y: usize, x: f32) -> u8 {
+  let x0 = (x.floor().max(0.0) as usize).min(width - 2);
+  let t = (x - x0 as f32).clamp(0.0, 1.0);
+  (texture[y * width + x0] * (1.0 - t) + texture[y * width + x0 + 1] * t).round() as u8
+}
+
+fn in_square(x: f32, y: usize) -> bool { (40.0..70.0).contains(&x) && (20..44).contains(&y) }
+
+// A background plane and a nearer textured square, and the true disparity at each left pixel. A left pixel x with disparity d shows up at x - d in the right eye.
+fn scene(background: f32, square: f32) -> (Frame, Vec<f32>) {
+  let (width, height) = (WIDTH as usize, HEIGHT as usize);
+  let (background_texture, square_texture) = (texture(width + 64, height, 1), texture(width + 64, height, 2));
+  let mut data = Vec::new();
+  let mut truth = Vec::new();
+  for y in 0..height {
+    for x in 0..width {
+      let on_square = in_square(x as f32, y);
+      truth.push(if on_square { square } else { background });
+      data.push(sample(if on_square { &square_texture } else { &background_texture }, width + 64, y, x as f32));
+    }
+    for x in 0..width {
+      // The square is nearer, so it hides the background wherever both land.
+      let x = x as f32;
+      data.push(if in_square(x + square, y) { sample(&square_texture, width + 64, y, x + square) } else { sample(&background_texture, width + 64, y, x + background) });
+    }
+  }
+  (Frame::new(WIDTH, HEIGHT, data, 0, Duration::from_secs(0)), truth)
+}
+
+// Fraction of the interior pixels away from the square's edges whose disparity is within `tolerance` of the truth.
+fn accuracy(map: &DisparityMap, truth: &[f32], tolerance: f32) -> f32 {
+  let mut good = 0;
+  let mut total = 0;
+  for y in 6..HEIGHT as usize - 6 {
+    for x in 30..WIDTH as usize - 6 {
+      if (36..74).contains(&x) && ((16..20).contains(&y) || (44..48).contains(&y)) { continue; }
+      if (36..44).contains(&x) || (66..74).contains(&x) { continue; }
+      total += 1;
+      if let Some(d) = map.get(x as u32, y as u32) {
+        if (d - truth[y * WIDTH as usize + x]).abs() <= tolerance { good += 1; }
+      }
+    }
+  }
+  good as f32 / total as f32
+}
+
+#[test]
+fn block_and_semi_global_matching_recover_disparities() -> Result<()> {
+  let (frame, truth) = scene(8.0, 20.0);
+  for method in [MatchingMethod::Block, MatchingMethod::SemiGlobal].iter().copied() {
+    let matcher = StereoMatcher::new(method, 32);
+    let map = matcher.compute(&frame)?;
+    assert_eq!((map.width, map.height), (WIDTH, HEIGHT));
+    let score = accuracy(&map, &truth, 0.5);
+    assert!(score > 0.9, "{:?}: only {:.2} of pixels matched", method, score);
+    // The left-most columns have no match in the right image.
+    assert!(map.get(3, 32).is_none());
+  }
+  Ok(())
+}
+
+#[test]
+fn subpixel_disparity_and_search_range() -> Result<()> {
+  let (frame, truth) = scene(12.5, 12.5);
+  let mut matcher = StereoMatcher::new(MatchingMethod::SemiGlobal, 16);
+  matcher.set_min_disparity(6);
+  let map = matcher.compute(&frame)?;
+  let valid: Vec<f32> = map.data.iter().copied().filter(|d| !d.is_nan()).collect();
+  let mean = valid.iter().sum::<f32>() / valid.len() as f32;
+  assert!((mean - 12.5).abs() < 0.2, "mean disparity {}", mean);
+  assert!(accuracy(&map, &truth, 0.5) > 0.8);
+  assert!(map.density() > 0.6);
+  Ok(())
+}
+
+#[test]
+fn disparity_converts_to_depth() -> Result<()> {
+  let camera = CameraIntrinsics { width: WIDTH, height: HEIGHT, fx: 100.0, fy: 100.0, cx: 47.5, cy: 31.5, distortion: Distortion::None };
+  let calibration = matched_pair(camera, 0.04);
+  let rectifier = StereoRectifier::new(&calibration);
+
+  let mut data = vec![f32::NAN; (WIDTH * HEIGHT) as usize];
+  data[(10 * WIDTH + 20) as usize] = 16.0;
+  data[(10 * WIDTH + 21) as usize] = 0.0;
+  let depth = DisparityMap { width: WIDTH, height: HEIGHT, data }.to_depth(&rectifier);
+  // Z = f B / d = 100 * 0.04 / 16.
+  assert!((depth.get(20, 10).unwrap() - 0.25).abs() < 1e-6);
+  assert!(depth.get(21, 10).is_none() && depth.get(22, 10).is_none());
+  let point = depth.point(20, 10).unwrap();
+  let cx = rectifier.rectified_camera(Eye::Left).cx;
+  assert!((point[0] as f64 - (20.0 - cx) / 100.0 * 0.25).abs() < 1e-6);
+
+  // A rig with one eye above the other keeps its baseline in P2's second row.
+  let vertical = StereoRectifier::new(&StereoCalibration { translation: [0.0, -0.04, 0.0], ..calibration });
+  let mut data = vec![f32::NAN; (WIDTH * HEIGHT) as usize];
+  data[(10 * WIDTH + 20) as usize] = 16.0;
+  assert!((DisparityMap { width: WIDTH, height: HEIGHT, data }.to_depth(&vertical).get(20, 10).unwrap() - 0.25).abs() < 1e-6);
+
+  assert!(StereoMatcher::new(MatchingMethod::Block, 16).compute(&Frame::new(4, 4, vec![0; 10], 0, Duration::from_secs(0))).is_err());
+  Ok(())
+}
@@ -0,0 +1,88 @@
+// tests/tests_tracker.rs
+//
+// Tracks synthetic moving targets through noise, dropped frames, disappearances and ambiguous assignments.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+const FRAME: Duration = Duration::from_nanos(11_111_111);
+
+// Uniform noise of +-1 mm from a fixed seed.
+struct Noise(u32);
+
+impl Noise {
+  fn next(&mut self) -> f64 {
+    self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+    ((self.0 >> 8) & 0xffff) as f64 / 65535.0 * 0.002 - 0.001
+  }
+
+  fn add(&mut self, point: [f64; 3]) -> [f64; 3] { [point[0] + self.next(), point[1] + self.next(), point[2] + self.next()] }
+}
+
+#[test]
+fn keeps_ids_through_drops_and_handles_birth_and_death() -> Result<()> {
+  for association in [Association::Hungarian, Association::NearestNeighbour].iter().copied() {
+    let mut tracker = Tracker::new(association);
+    let mut noise = Noise(3);
+    // Two markers passing each other 3 cm apart, at 0.3 m/s.
+    let a = |t: f64| [-0.15 + 0.3 * t, 0.0, 0.5];
+    let b = |t: f64| [0.15 - 0.3 * t, 0.03, 0.5];
+    let mut ids = None;
+    for frame in 0..90u32 {
+      // Every fifth frame is dropped.
+      if frame % 5 == 4 { continue; }
+      let timestamp = FRAME * frame;
+      let t = timestamp.as_secs_f64();
+      let tracks = tracker.update(&[noise.add(b(t)), noise.add(a(t))], timestamp);
+      if frame < 2 {
+        assert!(tracks.is_empty());
+        continue;
+      }
+      assert_eq!(tracks.len(), 2);
+      let id_of = |target: [f64; 3]| tracks.iter().min_by(|x, y| distance(x.position(), target).total_cmp(&distance(y.position(), target))).unwrap().id;
+      let current = (id_of(a(t)), id_of(b(t)));
+      assert_ne!(current.0, current.1);
+      assert_eq!(*ids.get_or_insert(current), current, "{:?}: IDs changed at frame {}", association, frame);
+    }
+
+    let t = (FRAME * 88).as_secs_f64();
+    let track = tracker.tracks().iter().find(|track| track.id == ids.unwrap().0).unwrap();
+    assert!(distance(track.position(), a(t)) < 0.002);
+    assert!(distance(track.velocity(), [0.3, 0.0, 0.0]) < 0.05, "{:?}", track.velocity());
+    assert!(track.position_uncertainty().iter().all(|&sigma| sigma < 0.002));
+
+    // B leaves and C appears. B, last seen at frame 88, coasts for 100 ms; C needs three detections.
+    let c = [0.0, -0.1, 0.7];
+    for frame in 90..110u32 {
+      let timestamp = FRAME * frame;
+      let t = timestamp.as_secs_f64();
+      let tracks = tracker.update(&[noise.add(a(t)), noise.add(c)], timestamp);
+      let ids_now: Vec<u64> = tracks.iter().map(|track| track.id).collect();
+      assert!(ids_now.contains(&ids.unwrap().0));
+      assert_eq!(ids_now.contains(&ids.unwrap().1), frame < 98, "{:?} at frame {}", ids_now, frame);
+      assert_eq!(ids_now.iter().any(|&id| id > 2), frame >= 92);
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn hungarian_assignment_beats_greedy() -> Result<()> {
+  let mut results = Vec::new();
+  for association in [Association::Hungarian, Association::NearestNeighbour].iter().copied() {
+    let mut tracker = Tracker::new(association);
+    tracker.set_gate(1.0);
+    tracker.set_min_hits(1);
+    tracker.set_max_coast(Duration::from_secs(5));
+    assert_eq!(tracker.update(&[[0.0; 3], [1.0, 0.0, 0.0]], Duration::from_secs(1)).len(), 2);
+    // Greedily, the track at 1.0 takes the detection at 0.9 and the one at 0.0 gets nothing.
+    let tracks = tracker.update(&[[0.9, 0.0, 0.0], [1.8, 0.0, 0.0]], Duration::from_secs(2));
+    results.push(tracks.iter().map(|track| (track.id, (track.position()[0] * 10.0).round() / 10.0, track.missed)).collect::<Vec<_>>());
+  }
+  assert_eq!(results[0], vec![(1, 0.9, 0), (2, 1.8, 0)]);
+  assert_eq!(results[1], vec![(1, 0.0, 1), (2, 0.9, 0), (3, 1.8, 0)]);
+  Ok(())
+}
@@ -0,0 +1,106 @@
+// tests/tests_undistort.rs
+//
+// Checks the distortion models and undistorts synthetic frames rendered through them.
+
+use std::time::Duration;
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn camera(distortion: Distortion) -> CameraIntrinsics {
+  CameraIntrinsics { width: 64, height: 48, fx: 40.0, fy: 40.0, cx: 31.5, cy: 23.5, distortion }
+}
+
+fn stereo(distortion: Distortion) -> StereoCalibration {
+  let right = CameraIntrinsics { cx: 30.0, ..camera(distortion.clone()) };
+  StereoCalibration { right, ..matched_pair(camera(distortion), 0.04) }
+}
+
+// A smooth pattern over normalized image coordinates.
+fn pattern(x: f64, y: f64) -> f64 { 128.0 + 100.0 * x - 60.0 * y }
+
+// Renders the pattern as each camera of `calibration` would see it, distortion included.
+fn render(calibration: &StereoCalibration) -> Frame {
+  let (width, height) = (calibration.left.width, calibration.left.height);
+  let mut data = Vec::new();
+  for v in 0..height {
+    for eye in [Eye::Left, Eye::Right].iter() {
+      let camera = calibration.camera(*eye);
+      for u in 0..width {
+        let (x, y) = camera.unproject(u as f64, v as f64);
+        data.push(pattern(x, y).round().clamp(0.0, 255.0) as u8);
+      }
+    }
+  }
+  Frame::new(width, height, data, 3, Duration::from_millis(33))
+}
+
+#[test]
+fn distortion_models_round_trip() -> Result<()> {
+  let models = [
+    Distortion::BrownConrady([-0.28, 0.07, 0.001, -0.0005, -0.008]),
+    Distortion::Equidistant([-0.02, 0.004, -0.003, 0.0005])
+  ];
+  for distortion in models.iter() {
+    let camera = camera(distortion.clone());
+    for (x, y) in [(0.0, 0.0), (0.3, -0.2), (-0.6, 0.5), (0.9, 0.7)].iter().copied() {
+      let (u, v) = camera.project(x, y);
+      let (ux, uy) = camera.unproject(u, v);
+      assert!((ux - x).abs() < 1e-6 && (uy - y).abs() < 1e-6, "{:?}: ({}, {}) came back as ({}, {})", distortion, x, y, ux, uy);
+    }
+  }
+  // The equidistant model maps the angle from the axis, so a point at 45 degrees lands at pi/4 with no coefficients.
+  let (xd, _) = camera(Distortion::Equidistant([0.0; 4])).distort(1.0, 0.0);
+  assert!((xd - std::f64::consts::FRAC_PI_4).abs() < 1e-12);
+  Ok(())
+}
+
+#[test]
+fn undistorts_rendered_frames() -> Result<()> {
+  for distortion in [Distortion::BrownConrady([-0.2, 0.03, 0.0, 0.0, 0.0]), Distortion::Equidistant([0.05, -0.01, 0.0, 0.0])].iter() {
+    let calibration = stereo(distortion.clone());
+    let frame = render(&calibration);
+    let undistorter = Undistorter::new(&calibration);
+    let out = undistorter.undistort(&frame)?;
+    assert_eq!((out.width, out.height, out.sequence, out.timestamp), (64, 48, 3, Duration::from_millis(33)));
+
+    // Away from the borders, the output is the pattern sampled on the ideal pinhole grid.
+    for eye in [Eye::Left, Eye::Right].iter() {
+      let camera = undistorter.output_camera(*eye);
+      let view = out.eye_view(*eye).unwrap();
+      for v in 8..40 {
+        for u in 12..52 {
+          let expected = pattern((u as f64 - camera.cx) / camera.fx, (v as f64 - camera.cy) / camera.fy);
+          let actual = view.row(v)[u as usize] as f64;
+          assert!((actual - expected).abs() <= 2.0, "{:?} {:?} ({}, {}): {} vs {}", distortion, eye, u, v, actual, expected);
+        }
+      }
+    }
+
+    let mut threaded = Undistorter::with_focal_scale(&calibration, 1.0);
+    threaded.set_threads(3);
+    let mut reused = Frame::new(0, 0, Vec::new(), 0, Duration::from_secs(0));
+    threaded.undistort_into(&frame, &mut reused)?;
+    assert_eq!(reused.data, out.data);
+  }
+  Ok(())
+}
+
+#[test]
+fn identity_calibration_is_a_copy() -> Result<()> {
+  let calibration = stereo(Distortion::None);
+  let frame = render(&calibration);
+  assert_eq!(Undistorter::new(&calibration).undistort(&frame)?.data, frame.data);
+
+  // Zooming out leaves a black border where nothing was captured.
+  let wide = Undistorter::with_focal_scale(&calibration, 0.5).undistort(&frame)?;
+  assert_eq!(wide.data[0], 0);
+  assert_ne!(wide.data[24 * 128 + 32], 0);
+
+  let mut short = frame.clone();
+  short.data.pop();
+  assert!(Undistorter::new(&calibration).undistort(&short).is_err());
+  assert!(Undistorter::new(&calibration).undistort(&Frame::new(32, 48, vec![0; 32 * 2 * 48], 0, Duration::from_secs(0))).is_err());
+  Ok(())
+}
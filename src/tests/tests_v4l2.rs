@@ -0,0 +1,268 @@
+// tests/tests_v4l2.rs
+//
+// Drives the Linux backend's V4l2Source against a scripted fake V4l2Device, so negotiation, error mapping and teardown ordering are checked on any Linux machine, Rigel or not.
+
+use std::{collections::VecDeque, io, sync::{Arc, Mutex}, time::Duration};
+
+use crate::*;
+
+const FRAME_LEN: usize = 384 * 2 * 384;
+
+struct ScriptedDevice {
+  log: Arc<Mutex<Vec<String>>>,
+  formats: Vec<FourCc>,
+  modes: Vec<CaptureMode>,
+  // Format the "driver" applies instead of the requested one, if set.
+  forced_format: Option<PixFormat>,
+  dqbuf_script: VecDeque<io::Result<DequeuedBuffer>>,
+  streamon_error: Option<i32>,
+  // Buffer whose VIDIOC_QUERYBUF fails, if set.
+  querybuf_error: Option<u32>,
+  buffers: Vec<Vec<u8>>
+}
+
+impl ScriptedDevice {
+  fn new(log: Arc<Mutex<Vec<String>>>) -> Self {
+    Self {
+      log,
+      formats: vec![YUYV],
+      modes: vec![CaptureMode::RIGEL_DEFAULT, CaptureMode { fps: 60, ..CaptureMode::RIGEL_DEFAULT }],
+      forced_format: None,
+      dqbuf_script: VecDeque::new(),
+      streamon_error: None,
+      querybuf_error: None,
+      buffers: Vec::new()
+    }
+  }
+
+  fn record(&self, call: String) { self.log.lock().unwrap().push(call); }
+}
+
+impl V4l2Device for ScriptedDevice {
+  fn querycap(&self) -> io::Result<Caps> {
+    Ok(Caps { card: "Leap Motion Controller (Rigel)".to_string(), video_capture: true, streaming: true })
+  }
+
+  fn enum_fmt(&self) -> io::Result<Vec<FourCc>> { Ok(self.formats.clone()) }
+
+  fn enum_framesizes(&self, _fourcc: FourCc) -> io::Result<Vec<(u32, u32)>> {
+    let mut sizes: Vec<(u32, u32)> = self.modes.iter().map(|mode| (mode.width, mode.height)).collect();
+    sizes.dedup();
+    Ok(sizes)
+  }
+
+  fn enum_frameintervals(&self, _fourcc: FourCc, width: u32, height: u32) -> io::Result<Vec<Interval>> {
+    Ok(self.modes.iter()
+      .filter(|mode| mode.width == width && mode.height == height)
+      .map(|mode| Interval { numerator: 1, denominator: mode.fps })
+      .collect())
+  }
+
+  fn s_fmt(&mut self, format: PixFormat) -> io::Result<PixFormat> {
+    self.record(format!("s_fmt {}x{}", format.width, format.height));
+    Ok(self.forced_format.unwrap_or(format))
+  }
+
+  fn s_parm(&mut self, interval: Interval) -> io::Result<Interval> {
+    self.record(format!("s_parm {}/{}", interval.numerator, interval.denominator));
+    Ok(interval)
+  }
+
+  // Like the real device: buffers must be freed before they are requested again.
+  fn reqbufs(&mut self, count: u32) -> io::Result<u32> {
+    if count == 0 {
+      self.buffers.clear();
+    } else if !self.buffers.is_empty() {
+      return Err(io::Error::new(io::ErrorKind::Other, "buffers are already allocated"));
+    }
+    self.record(format!("reqbufs {}", count));
+    self.buffers = map_buffers(self, count)?;
+    Ok(count)
+  }
+
+  fn qbuf(&mut self, index: u32) -> io::Result<()> {
+    self.record(format!("qbuf {}", index));
+    Ok(())
+  }
+
+  fn dqbuf(&mut self, _timeout: Duration) -> io::Result<DequeuedBuffer> {
+    self.record("dqbuf".to_string());
+    self.dqbuf_script.pop_front().unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::TimedOut, "VIDIOC_DQBUF")))
+  }
+
+  fn buffer(&self, index: u32) -> &[u8] { &self.buffers[index as usize] }
+
+  fn streamon(&mut self) -> io::Result<()> {
+    self.record("streamon".to_string());
+    match self.streamon_error {
+      Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+      None => Ok(())
+    }
+  }
+
+  fn streamoff(&mut self) -> io::Result<()> {
+    self.record("streamoff".to_string());
+    Ok(())
+  }
+
+  fn query_controls(&self) -> io::Result<Vec<ControlDesc>> { Ok(Vec::new()) }
+
+  fn g_ctrl(&self, _id: u32) -> io::Result<i64> { Err(io::Error::from_raw_os_error(22)) }
+
+  fn s_ctrl(&mut self, _id: u32, _value: i64) -> io::Result<()> { Err(io::Error::from_raw_os_error(19)) }
+}
+
+impl BufferMapper for ScriptedDevice {
+  type Mapping = Vec<u8>;
+
+  fn map_buffer(&mut self, index: u32) -> io::Result<Vec<u8>> {
+    if self.querybuf_error == Some(index) { return Err(io::Error::from_raw_os_error(12)); }
+    Ok(vec![index as u8; FRAME_LEN])
+  }
+
+  fn unmap_buffer(&mut self, mapping: Vec<u8>) { self.record(format!("munmap {}", mapping[0])); }
+
+  fn free_buffers(&mut self) { self.record("reqbufs 0".to_string()); }
+}
+
+fn info() -> DeviceInfo {
+  DeviceInfo { name: "Leap Motion Controller (Rigel)".to_string(), path: "/dev/fake0".to_string(), serial: None }
+}
+
+fn dequeued(index: u32, sequence: u32) -> io::Result<DequeuedBuffer> {
+  Ok(DequeuedBuffer { index, bytesused: FRAME_LEN as u32, sequence, timestamp: Duration::from_millis(sequence as u64 * 11) })
+}
+
+fn take_log(log: &Arc<Mutex<Vec<String>>>) -> Vec<String> { log.lock().unwrap().drain(..).collect() }
+
+#[test]
+fn matches_rigel_by_name_and_caps() {
+  let caps = Caps { card: String::new(), video_capture: true, streaming: true };
+  assert!(is_rigel("Leap Motion Controller (Rigel)", &caps));
+  assert!(!is_rigel("Integrated Webcam", &caps));
+  assert!(!is_rigel("Leap Motion Controller (Rigel)", &Caps { streaming: false, ..caps }));
+}
+
+#[test]
+fn rejects_device_without_yuyv() {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log);
+  device.formats = vec![*b"MJPG"];
+  assert!(V4l2Source::from_device(info(), Box::new(device)).is_err());
+}
+
+#[test]
+fn opens_in_rigel_default_mode() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let source = V4l2Source::from_device(info(), Box::new(ScriptedDevice::new(log.clone())))?;
+  assert_eq!(source.mode(), CaptureMode::RIGEL_DEFAULT);
+  assert_eq!(take_log(&log), vec!["s_fmt 384x384", "s_parm 1/90"]);
+  Ok(())
+}
+
+#[test]
+fn falls_back_to_closest_supported_mode() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log);
+  device.modes = vec![
+    CaptureMode { fps: 60, ..CaptureMode::RIGEL_DEFAULT },
+    CaptureMode { fps: 30, ..CaptureMode::RIGEL_DEFAULT }
+  ];
+  let mut source = V4l2Source::from_device(info(), Box::new(device))?;
+  assert_eq!(source.mode().fps, 60);
+
+  let applied = source.configure(CaptureMode { fps: 45, ..CaptureMode::RIGEL_DEFAULT })?;
+  assert_eq!(applied.fps, 30);
+  let applied = source.configure(CaptureMode { width: 640, height: 240, fps: 30 })?;
+  assert_eq!(applied, CaptureMode { fps: 30, ..CaptureMode::RIGEL_DEFAULT });
+  Ok(())
+}
+
+#[test]
+fn rejects_format_changed_by_driver() {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log);
+  device.forced_format = Some(PixFormat { fourcc: YUYV, width: 320, height: 240 });
+  assert!(V4l2Source::from_device(info(), Box::new(device)).is_err());
+}
+
+#[test]
+fn streams_and_tears_down_in_order() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log.clone());
+  device.dqbuf_script.push_back(dequeued(2, 7));
+  let mut source = V4l2Source::from_device(info(), Box::new(device))?;
+  take_log(&log);
+
+  source.start()?;
+  let frame = source.next_frame(Duration::from_millis(100))?;
+  assert_eq!(frame.sequence, 7);
+  assert_eq!(frame.data[0], 2);
+  assert!(frame.is_complete());
+  source.stop()?;
+
+  assert_eq!(take_log(&log), vec![
+    "reqbufs 4", "qbuf 0", "qbuf 1", "qbuf 2", "qbuf 3", "streamon",
+    "dqbuf", "qbuf 2",
+    "streamoff", "reqbufs 0"
+  ]);
+  Ok(())
+}
+
+#[test]
+fn maps_dqbuf_errors() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log);
+  device.dqbuf_script.push_back(Err(io::Error::new(io::ErrorKind::TimedOut, "VIDIOC_DQBUF")));
+  device.dqbuf_script.push_back(Err(io::Error::from_raw_os_error(19)));
+  let mut source = V4l2Source::from_device(info(), Box::new(device))?;
+  source.start()?;
+
+  assert_eq!(source.next_frame(Duration::from_millis(10)).err().map(|e| e.kind()), Some(ErrorKind::Timeout));
+  assert_eq!(source.next_frame(Duration::from_millis(10)).err().map(|e| e.kind()), Some(ErrorKind::Disconnected));
+  assert_eq!(source.set_control(Control::Gain, 1).err().map(|e| e.kind()), Some(ErrorKind::Disconnected));
+  assert_eq!(source.control(Control::Gain).err().map(|e| e.kind()), Some(ErrorKind::Other));
+  Ok(())
+}
+
+#[test]
+fn failed_streamon_releases_buffers() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log.clone());
+  device.streamon_error = Some(16);
+  let mut source = V4l2Source::from_device(info(), Box::new(device))?;
+  take_log(&log);
+
+  assert!(source.start().is_err());
+  assert_eq!(take_log(&log).split_off(5), vec!["streamon", "streamoff", "reqbufs 0"]);
+  assert!(source.next_frame(Duration::from_millis(10)).is_err());
+  Ok(())
+}
+
+#[test]
+fn failed_querybuf_releases_earlier_buffers() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut device = ScriptedDevice::new(log.clone());
+  device.querybuf_error = Some(2);
+  let mut source = V4l2Source::from_device(info(), Box::new(device))?;
+  take_log(&log);
+
+  assert!(source.start().is_err());
+  // Buffers 0 and 1 were mapped before buffer 2 failed.
+  assert_eq!(take_log(&log), vec!["reqbufs 4", "munmap 0", "munmap 1", "reqbufs 0"]);
+  // Nothing is left allocated, so the next start requests buffers afresh instead of being refused.
+  assert!(source.start().is_err());
+  assert_eq!(take_log(&log), vec!["reqbufs 4", "munmap 0", "munmap 1", "reqbufs 0"]);
+  Ok(())
+}
+
+#[test]
+fn dropping_while_streaming_tears_down() -> Result<()> {
+  let log = Arc::new(Mutex::new(Vec::new()));
+  let mut source = V4l2Source::from_device(info(), Box::new(ScriptedDevice::new(log.clone())))?;
+  source.start()?;
+  take_log(&log);
+  drop(source);
+  assert_eq!(take_log(&log), vec!["streamoff", "reqbufs 0"]);
+  Ok(())
+}
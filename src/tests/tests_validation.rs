@@ -0,0 +1,129 @@
+// tests/tests_validation.rs
+//
+// Renders a textured wall and a checkerboard through a known stereo rig, then validates the rig's calibration and a few that have drifted from it.
+
+use std::{fs, time::Duration};
+
+use crate::*;
+
+use super::fixtures::*;
+
+fn calibration() -> StereoCalibration {
+  qvga_rig(Distortion::BrownConrady([-0.05, 0.01, 0.0005, -0.0003, 0.0]), Distortion::BrownConrady([-0.04, 0.008, -0.0002, 0.0004, 0.0]))
+}
+
+// A wall of 3 cm blocks of random grays.
+fn wall(x: f64, y: f64) -> f64 {
+  20.0 + (block_hash(x, y, 0.03) % 216) as f64
+}
+
+// A 7x6 checkerboard of 3 cm squares with a one square white margin, on a gray background.
+fn checkerboard(x: f64, y: f64) -> f64 {
+  let (cx, cy) = ((x / 0.03).floor() as i64, (y / 0.03).floor() as i64);
+  if !(-1..=7).contains(&cx) || !(-1..=6).contains(&cy) { return 100.0; }
+  if cx == -1 || cy == -1 || cx == 7 || cy == 6 || (cx + cy) % 2 != 0 { 220.0 } else { 30.0 }
+}
+
+// The wall a meter away, turned a little, shifted with `step` so each frame shows new features.
+fn wall_pose(step: u64) -> (Mat3, Vec3) {
+  (rodrigues([0.15, -0.2, 0.05]), [-0.8 + step as f64 * 0.007, -0.6, 1.0])
+}
+
+fn board_pose(step: u64) -> (Mat3, Vec3) {
+  (rodrigues([0.2, -0.25, 0.1 + step as f64 * 0.05]), [-0.1 + step as f64 * 0.005, -0.08, 0.45])
+}
+
+// The calibration with the right eye turned about its x axis by `radians`, which moves its rows.
+fn turned(radians: f64) -> StereoCalibration {
+  let mut calibration = calibration();
+  calibration.rotation = mat_mul(&rodrigues([radians, 0.0, 0.0]), &calibration.rotation);
+  calibration
+}
+
+#[test]
+fn passes_the_true_calibration_and_fails_a_turned_eye() -> Result<()> {
+  let rig = Rig::new(calibration());
+  let frames: Vec<Frame> = (0..3).map(|step| rig.render(&wall, wall_pose(step), step)).collect();
+
+  let mut validator = CalibrationValidator::new(&calibration());
+  validator.set_window(3, 50);
+  assert_eq!(validator.report().status, ValidationStatus::Pending);
+  for frame in &frames { validator.process(frame)?; }
+  let report = validator.report();
+  assert_eq!(report.status, ValidationStatus::Pass, "{:?}", report);
+  assert!(report.matches >= 30 && report.samples >= 90, "{:?}", report);
+  assert!(report.epipolar_error < 0.2 && report.mean_offset.abs() < 0.1, "{:?}", report);
+  assert_eq!(report.reprojection_error, None);
+
+  // Half a degree moves the right eye's rows by about two pixels.
+  let mut validator = CalibrationValidator::new(&turned(0.009));
+  validator.set_window(3, 50);
+  for frame in &frames { validator.process(frame)?; }
+  let report = validator.report();
+  assert_eq!(report.status, ValidationStatus::Fail, "{:?}", report);
+  assert!(report.mean_offset > 1.5 && report.mean_offset < 2.5, "{:?}", report);
+
+  // The window forgets frames that fell out of it.
+  validator.set_window(1, 10);
+  assert!(validator.report().samples < report.samples);
+  validator.reset();
+  assert_eq!(validator.report().samples, 0);
+  Ok(())
+}
+
+#[test]
+fn reprojects_board_corners_to_catch_a_changed_baseline() -> Result<()> {
+  let target = CalibrationTarget::checkerboard(7, 6, 0.03)?;
+  let rig = Rig::new(calibration());
+  let frames: Vec<Frame> = (0..3).map(|step| rig.render(&checkerboard, board_pose(step), step)).collect();
+
+  let mut validator = CalibrationValidator::new(&calibration());
+  validator.set_target(Some(target.clone()));
+  validator.set_window(3, 50);
+  for frame in &frames { validator.process(frame)?; }
+  let report = validator.report();
+  assert_eq!(report.status, ValidationStatus::Pass, "{:?}", report);
+  assert_eq!(report.samples, 3 * 30);
+  assert!(report.epipolar_error < 0.2, "{:?}", report);
+  assert!(report.reprojection_error.unwrap() < 0.3, "{:?}", report);
+
+  // A longer baseline leaves the rows where they were, but not the corners' disparity.
+  let mut longer = calibration();
+  longer.translation = scale(longer.translation, 1.1);
+  let mut validator = CalibrationValidator::new(&longer);
+  validator.set_target(Some(target));
+  validator.set_window(3, 50);
+  for frame in &frames { validator.process(frame)?; }
+  let report = validator.report();
+  assert_eq!(report.status, ValidationStatus::Fail, "{:?}", report);
+  assert!(report.epipolar_error < 0.2, "{:?}", report);
+  assert!(report.reprojection_error.unwrap() > 2.0, "{:?}", report);
+  Ok(())
+}
+
+#[test]
+fn validates_a_rigel_stream() -> Result<()> {
+  let dir = std::env::temp_dir().join(format!("tinyrigel-validation-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  let rig = Rig::new(calibration());
+  for step in 0..3 {
+    let frame = rig.render(&wall, wall_pose(step), step);
+    frame.side_by_side().save(dir.join(format!("frame_{:04}.png", step))).unwrap();
+  }
+  let mut replay = ReplaySource::open(&dir)?;
+  replay.set_speed(ReplaySpeed::AsFastAsPossible);
+  let mut rigel: Rigel = Rigel::from_source(Box::new(replay));
+  assert!(CalibrationValidator::for_rigel(&rigel).is_err());
+
+  rigel.set_calibration(Some(turned(-0.009)));
+  let mut validator = CalibrationValidator::for_rigel(&rigel)?;
+  validator.set_window(3, 50);
+  rigel.open()?;
+  let report = validator.run(&mut rigel, 3, Duration::from_millis(500))?;
+  rigel.close()?;
+  fs::remove_dir_all(&dir).unwrap();
+  assert_eq!(report.status, ValidationStatus::Fail, "{:?}", report);
+  assert!(report.mean_offset < -1.5, "{:?}", report);
+  Ok(())
+}
@@ -0,0 +1,280 @@
+// tracker.rs - tinyrigel
+//
+// Multi-target tracking: associates per-frame detections (triangulated markers, or 2D blob centroids with z = 0) with existing tracks, smooths each track with a constant-velocity Kalman filter, and hands out IDs that stay with a target for as long as it is tracked. Time steps come from frame timestamps, so dropped frames only mean a longer prediction.
+
+use std::time::Duration;
+
+use crate::*;
+
+/// How detections are assigned to tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Association {
+  /// Globally optimal assignment minimizing the total distance (Hungarian method).
+  Hungarian,
+  /// Greedy: the closest track/detection pairs are assigned first. Cheaper, and fine while targets stay well apart.
+  NearestNeighbour
+}
+
+/// A tracked target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+  /// Unique for the lifetime of the tracker, never reused.
+  pub id: u64,
+  /// Detections assigned so far.
+  pub hits: u32,
+  /// Consecutive updates without a detection.
+  pub missed: u32,
+  /// Timestamp of the last detection.
+  pub last_seen: Duration,
+  confirmed: bool,
+  // Per axis: position and velocity, and their covariance [p p, p v, v v].
+  state: [[f64; 2]; 3],
+  covariance: [[f64; 3]; 3]
+}
+
+impl Track {
+  /// Filtered position, in the units of the detections.
+  pub fn position(&self) -> [f64; 3] { [self.state[0][0], self.state[1][0], self.state[2][0]] }
+
+  /// Estimated velocity, in detection units per second.
+  pub fn velocity(&self) -> [f64; 3] { [self.state[0][1], self.state[1][1], self.state[2][1]] }
+
+  /// Standard deviation of the position estimate per axis.
+  pub fn position_uncertainty(&self) -> [f64; 3] { [self.covariance[0][0].sqrt(), self.covariance[1][0].sqrt(), self.covariance[2][0].sqrt()] }
+
+  pub fn velocity_uncertainty(&self) -> [f64; 3] { [self.covariance[0][2].sqrt(), self.covariance[1][2].sqrt(), self.covariance[2][2].sqrt()] }
+
+  /// Whether the track has been detected often enough to be reported, see `Tracker::set_min_hits`.
+  pub fn is_confirmed(&self) -> bool { self.confirmed }
+
+  fn predict(&mut self, dt: f64, acceleration_noise: f64) {
+    // White acceleration noise with spectral density q: Q = q [dt^3/3, dt^2/2; dt^2/2, dt].
+    let q = acceleration_noise * acceleration_noise;
+    for (state, p) in self.state.iter_mut().zip(self.covariance.iter_mut()) {
+      state[0] += state[1] * dt;
+      let [pp, pv, vv] = *p;
+      *p = [
+        pp + 2.0 * dt * pv + dt * dt * vv + q * dt * dt * dt / 3.0,
+        pv + dt * vv + q * dt * dt / 2.0,
+        vv + q * dt
+      ];
+    }
+  }
+
+  fn correct(&mut self, measurement: [f64; 3], variance: f64) {
+    for ((state, p), z) in self.state.iter_mut().zip(self.covariance.iter_mut()).zip(measurement.iter()) {
+      let [pp, pv, vv] = *p;
+      let s = pp + variance;
+      let (k0, k1) = (pp / s, pv / s);
+      let innovation = z - state[0];
+      state[0] += k0 * innovation;
+      state[1] += k1 * innovation;
+      *p = [(1.0 - k0) * pp, (1.0 - k0) * pv, vv - k1 * pv];
+    }
+  }
+}
+
+/// Associates detections across frames and keeps a filtered track per target.
+///
+/// The defaults suit markers in meters: 5 cm gate, 2 mm measurement noise. For 2D blobs in pixels, scale the gate and noise to match.
+pub struct Tracker {
+  tracks: Vec<Track>,
+  association: Association,
+  gate: f64,
+  measurement_noise: f64,
+  acceleration_noise: f64,
+  initial_velocity: f64,
+  min_hits: u32,
+  max_coast: Duration,
+  next_id: u64,
+  last_update: Option<Duration>
+}
+
+impl Tracker {
+  pub fn new(association: Association) -> Self {
+    Self {
+      tracks: Vec::new(),
+      association,
+      gate: 0.05,
+      measurement_noise: 0.002,
+      acceleration_noise: 5.0,
+      initial_velocity: 1.0,
+      min_hits: 3,
+      max_coast: Duration::from_millis(100),
+      next_id: 1,
+      last_update: None
+    }
+  }
+
+  /// The largest distance between a track's predicted position and a detection for them to be associated. Defaults to 0.05.
+  pub fn set_gate(&mut self, distance: f64) { self.gate = distance; }
+
+  /// Standard deviations of the detection positions, of the targets' random acceleration (units/s² per √s) and of a new track's unknown velocity (units/s). Default to 0.002, 5 and 1.
+  pub fn set_noise(&mut self, measurement: f64, acceleration: f64, initial_velocity: f64) {
+    self.measurement_noise = measurement;
+    self.acceleration_noise = acceleration;
+    self.initial_velocity = initial_velocity;
+  }
+
+  /// How many detections a new track needs before it is confirmed and reported. Defaults to 3; unconfirmed tracks are dropped on their first miss.
+  pub fn set_min_hits(&mut self, hits: u32) { self.min_hits = hits.max(1); }
+
+  /// How long a confirmed track survives without detections, coasting on its velocity. Defaults to 100 ms.
+  pub fn set_max_coast(&mut self, duration: Duration) { self.max_coast = duration; }
+
+  /// All live tracks, confirmed or not.
+  pub fn tracks(&self) -> &[Track] { &self.tracks }
+
+  /// Advances all tracks to `timestamp` (a frame timestamp), assigns `detections` to them and returns the confirmed tracks. Detections left over start new tracks. A timestamp earlier than the previous one is treated as no time passing.
+  pub fn update(&mut self, detections: &[[f64; 3]], timestamp: Duration) -> Vec<Track> {
+    let dt = self.last_update.map_or(0.0, |last| timestamp.saturating_sub(last).as_secs_f64());
+    self.last_update = Some(self.last_update.map_or(timestamp, |last| last.max(timestamp)));
+    for track in &mut self.tracks {
+      track.predict(dt, self.acceleration_noise);
+    }
+
+    let mut costs = vec![vec![0.0; detections.len()]; self.tracks.len()];
+    for (track, row) in self.tracks.iter().zip(costs.iter_mut()) {
+      let predicted = track.position();
+      for (detection, cost) in detections.iter().zip(row.iter_mut()) {
+        *cost = norm([detection[0] - predicted[0], detection[1] - predicted[1], detection[2] - predicted[2]]);
+      }
+    }
+    let assignment = match self.association {
+      Association::Hungarian => hungarian(&costs, self.gate),
+      Association::NearestNeighbour => nearest_neighbour(&costs, self.gate)
+    };
+
+    let variance = self.measurement_noise * self.measurement_noise;
+    let mut used = vec![false; detections.len()];
+    for (track, detection) in self.tracks.iter_mut().zip(assignment) {
+      match detection {
+        Some(index) => {
+          used[index] = true;
+          track.correct(detections[index], variance);
+          track.hits += 1;
+          track.missed = 0;
+          track.last_seen = timestamp;
+          if track.hits >= self.min_hits { track.confirmed = true; }
+        }
+        None => track.missed += 1
+      }
+    }
+    let max_coast = self.max_coast;
+    self.tracks.retain(|track| track.missed == 0 || (track.confirmed && timestamp.saturating_sub(track.last_seen) <= max_coast));
+
+    for (detection, _) in detections.iter().zip(used).filter(|(_, used)| !used) {
+      let velocity_variance = self.initial_velocity * self.initial_velocity;
+      self.tracks.push(Track {
+        id: self.next_id,
+        hits: 1,
+        missed: 0,
+        last_seen: timestamp,
+        confirmed: self.min_hits <= 1,
+        state: [[detection[0], 0.0], [detection[1], 0.0], [detection[2], 0.0]],
+        covariance: [[variance, 0.0, velocity_variance]; 3]
+      });
+      self.next_id += 1;
+    }
+    self.tracks.iter().filter(|track| track.confirmed).cloned().collect()
+  }
+
+  /// Tracks triangulated markers, using the frame's timestamp.
+  pub fn update_markers(&mut self, detection: &BlobDetection, frame: &Frame) -> Vec<Track> {
+    let positions: Vec<[f64; 3]> = detection.markers.iter().map(|marker| marker.position).collect();
+    self.update(&positions, frame.timestamp)
+  }
+
+  /// Tracks 2D blob centroids of one eye, in pixels, with z = 0.
+  pub fn update_blobs(&mut self, blobs: &[Blob], timestamp: Duration) -> Vec<Track> {
+    let positions: Vec<[f64; 3]> = blobs.iter().map(|blob| [blob.x, blob.y, 0.0]).collect();
+    self.update(&positions, timestamp)
+  }
+}
+
+// Minimum-cost assignment of rows to columns (Kuhn-Munkres with potentials, O(n^2 m)), dropping pairs costlier than `gate`.
+fn hungarian(costs: &[Vec<f64>], gate: f64) -> Vec<Option<usize>> {
+  let rows = costs.len();
+  let columns = costs.first().map_or(0, |row| row.len());
+  if rows == 0 || columns == 0 { return vec![None; rows]; }
+  if rows > columns {
+    // The method needs at least as many columns as rows, so solve the transpose.
+    let transposed: Vec<Vec<f64>> = (0..columns).map(|c| (0..rows).map(|r| costs[r][c]).collect()).collect();
+    let mut result = vec![None; rows];
+    for (column, row) in hungarian(&transposed, gate).into_iter().enumerate() {
+      if let Some(row) = row { result[row] = Some(column); }
+    }
+    return result;
+  }
+
+  // Pairs beyond the gate cost more than any set of gated pairs, so they are only used when unavoidable, and dropped below.
+  let penalty = gate * (rows as f64 + 1.0) + 1.0;
+  let cost = |r: usize, c: usize| if costs[r][c] <= gate { costs[r][c] } else { penalty };
+  // 1-based, with row/column 0 as the sentinel.
+  let mut u = vec![0.0; rows + 1];
+  let mut v = vec![0.0; columns + 1];
+  let mut matched = vec![0usize; columns + 1];
+  let mut way = vec![0usize; columns + 1];
+  for row in 1..=rows {
+    matched[0] = row;
+    let mut column = 0;
+    let mut min = vec![f64::INFINITY; columns + 1];
+    let mut visited = vec![false; columns + 1];
+    loop {
+      visited[column] = true;
+      let current = matched[column];
+      let mut delta = f64::INFINITY;
+      let mut next = 0;
+      for c in 1..=columns {
+        if visited[c] { continue; }
+        let reduced = cost(current - 1, c - 1) - u[current] - v[c];
+        if reduced < min[c] {
+          min[c] = reduced;
+          way[c] = column;
+        }
+        if min[c] < delta {
+          delta = min[c];
+          next = c;
+        }
+      }
+      for c in 0..=columns {
+        if visited[c] {
+          u[matched[c]] += delta;
+          v[c] -= delta;
+        } else {
+          min[c] -= delta;
+        }
+      }
+      column = next;
+      if matched[column] == 0 { break; }
+    }
+    while column != 0 {
+      let previous = way[column];
+      matched[column] = matched[previous];
+      column = previous;
+    }
+  }
+
+  let mut result = vec![None; rows];
+  for c in 1..=columns {
+    if matched[c] != 0 && costs[matched[c] - 1][c - 1] <= gate { result[matched[c] - 1] = Some(c - 1); }
+  }
+  result
+}
+
+fn nearest_neighbour(costs: &[Vec<f64>], gate: f64) -> Vec<Option<usize>> {
+  let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+  for (r, row) in costs.iter().enumerate() {
+    pairs.extend(row.iter().enumerate().filter(|(_, &cost)| cost <= gate).map(|(c, &cost)| (cost, r, c)));
+  }
+  pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+  let mut result = vec![None; costs.len()];
+  let mut taken = vec![false; costs.first().map_or(0, |row| row.len())];
+  for (_, r, c) in pairs {
+    if result[r].is_none() && !taken[c] {
+      result[r] = Some(c);
+      taken[c] = true;
+    }
+  }
+  result
+}
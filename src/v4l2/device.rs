@@ -0,0 +1,120 @@
+// v4l2/device.rs - tinyrigel
+//
+// The subset of V4L2 ioctls the Linux backend needs, behind a trait. The real implementation (sys.rs) issues the ioctls against a device node; tests substitute a scripted fake, so format negotiation, mode fallback, error mapping and teardown ordering can be checked without a camera attached.
+
+use std::{io, time::Duration};
+
+pub(crate) type FourCc = [u8; 4];
+
+pub(crate) const YUYV: FourCc = *b"YUYV";
+
+/// VIDIOC_QUERYCAP, reduced to what device matching needs.
+#[derive(Debug, Clone)]
+pub(crate) struct Caps {
+  pub card: String,
+  pub video_capture: bool,
+  pub streaming: bool
+}
+
+/// A pixel format and frame size, as passed to and returned from VIDIOC_S_FMT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PixFormat {
+  pub fourcc: FourCc,
+  pub width: u32,
+  pub height: u32
+}
+
+/// A frame interval as a fraction of a second, e.g. 1/90.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Interval {
+  pub numerator: u32,
+  pub denominator: u32
+}
+
+/// The parts of a `struct v4l2_buffer` returned by VIDIOC_DQBUF that we use.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DequeuedBuffer {
+  pub index: u32,
+  pub bytesused: u32,
+  pub sequence: u32,
+  pub timestamp: Duration
+}
+
+/// VIDIOC_QUERYCTRL, reduced to integer controls.
+#[derive(Debug, Clone)]
+pub(crate) struct ControlDesc {
+  pub id: u32,
+  pub name: String,
+  pub minimum: i64,
+  pub maximum: i64,
+  pub step: i64,
+  pub default: i64
+}
+
+/// One opened V4L2 device node. Each method corresponds to a single ioctl (or, for `reqbufs`, REQBUFS plus the QUERYBUF/mmap of every buffer it allocates).
+pub(crate) trait V4l2Device: Send {
+  fn querycap(&self) -> io::Result<Caps>;
+
+  fn enum_fmt(&self) -> io::Result<Vec<FourCc>>;
+
+  /// Discrete frame sizes for `fourcc`, as (width, height).
+  fn enum_framesizes(&self, fourcc: FourCc) -> io::Result<Vec<(u32, u32)>>;
+
+  fn enum_frameintervals(&self, fourcc: FourCc, width: u32, height: u32) -> io::Result<Vec<Interval>>;
+
+  /// Requests a format; returns the format the driver actually applied.
+  fn s_fmt(&mut self, format: PixFormat) -> io::Result<PixFormat>;
+
+  /// Requests a frame interval; returns the interval the driver actually applied.
+  fn s_parm(&mut self, interval: Interval) -> io::Result<Interval>;
+
+  /// Allocates and maps `count` buffers, returning how many the driver granted. A count of zero unmaps and frees all buffers.
+  fn reqbufs(&mut self, count: u32) -> io::Result<u32>;
+
+  fn qbuf(&mut self, index: u32) -> io::Result<()>;
+
+  /// Waits up to `timeout` for a filled buffer. Fails with `io::ErrorKind::TimedOut` if none arrives.
+  fn dqbuf(&mut self, timeout: Duration) -> io::Result<DequeuedBuffer>;
+
+  /// The mapped memory of buffer `index`.
+  fn buffer(&self, index: u32) -> &[u8];
+
+  fn streamon(&mut self) -> io::Result<()>;
+
+  fn streamoff(&mut self) -> io::Result<()>;
+
+  fn query_controls(&self) -> io::Result<Vec<ControlDesc>>;
+
+  fn g_ctrl(&self, id: u32) -> io::Result<i64>;
+
+  fn s_ctrl(&mut self, id: u32, value: i64) -> io::Result<()>;
+}
+
+/// The per-buffer steps of `reqbufs` after REQBUFS itself, so a failure partway through can be unwound the same way for the real device and for tests.
+pub(crate) trait BufferMapper {
+  type Mapping;
+
+  /// VIDIOC_QUERYBUF and mmap of buffer `index`.
+  fn map_buffer(&mut self, index: u32) -> io::Result<Self::Mapping>;
+
+  fn unmap_buffer(&mut self, mapping: Self::Mapping);
+
+  /// VIDIOC_REQBUFS with a count of zero, freeing the kernel's buffers.
+  fn free_buffers(&mut self);
+}
+
+/// Maps buffers 0 to `count - 1`. If one fails, those already mapped are unmapped and the kernel's buffers freed before returning the error, so a later `reqbufs` starts from scratch.
+pub(crate) fn map_buffers<D: BufferMapper>(device: &mut D, count: u32) -> io::Result<Vec<D::Mapping>> {
+  let mut mappings = Vec::with_capacity(count as usize);
+  for index in 0..count {
+    match device.map_buffer(index) {
+      Ok(mapping) => mappings.push(mapping),
+      Err(err) => {
+        for mapping in mappings { device.unmap_buffer(mapping); }
+        device.free_buffers();
+        return Err(err);
+      }
+    }
+  }
+  Ok(mappings)
+}
@@ -0,0 +1,269 @@
+// v4l2/mod.rs - tinyrigel
+//
+// Linux backend. The Rigel enumerates as a regular UVC camera and is driven through V4L2. The device streams "YUYV" at 384x384, which is really 768x384 bytes of 8-bit grayscale with the two eyes side-by-side, so buffers are passed through as-is.
+//
+// All device access goes through the V4l2Device trait (device.rs), implemented against real device nodes in sys.rs, so the logic here can be unit tested with a scripted fake.
+
+use std::{io, time::Duration};
+
+use crate::*;
+
+mod device;
+pub(crate) use device::*;
+
+mod sys;
+
+const STREAM_BUFFER_COUNT: u32 = 4;
+
+// Standard V4L2 control ids the Rigel exposes through UVC.
+const V4L2_CID_BRIGHTNESS: u32 = 0x0098_0900;
+const V4L2_CID_CONTRAST: u32 = 0x0098_0901;
+const V4L2_CID_GAMMA: u32 = 0x0098_0910;
+const V4L2_CID_GAIN: u32 = 0x0098_0913;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a_0902;
+
+// errno values that mean the device is gone (unplugged, or the USB link dropped).
+const ENXIO: i32 = 6;
+const ENODEV: i32 = 19;
+const ESHUTDOWN: i32 = 108;
+
+fn control_id(control: Control) -> u32 {
+  match control {
+    Control::Brightness => V4L2_CID_BRIGHTNESS,
+    Control::Contrast => V4L2_CID_CONTRAST,
+    Control::Gamma => V4L2_CID_GAMMA,
+    Control::Gain => V4L2_CID_GAIN,
+    Control::Exposure => V4L2_CID_EXPOSURE_ABSOLUTE,
+    Control::Other(id) => id
+  }
+}
+
+fn control_from_id(id: u32) -> Control {
+  match id {
+    V4L2_CID_BRIGHTNESS => Control::Brightness,
+    V4L2_CID_CONTRAST => Control::Contrast,
+    V4L2_CID_GAMMA => Control::Gamma,
+    V4L2_CID_GAIN => Control::Gain,
+    V4L2_CID_EXPOSURE_ABSOLUTE => Control::Exposure,
+    other => Control::Other(other)
+  }
+}
+
+/// Maps an ioctl failure to a tinyrigel Error: unplug errnos become `ErrorKind::Disconnected`, timeouts and EAGAIN become `ErrorKind::Timeout`.
+pub(crate) fn map_io_error(context: &str, err: io::Error) -> Error {
+  match (err.raw_os_error(), err.kind()) {
+    (Some(ENXIO), _) | (Some(ENODEV), _) | (Some(ESHUTDOWN), _) => Error::disconnected(format!("{}: device disconnected ({}).", context, err)),
+    (_, io::ErrorKind::TimedOut) | (_, io::ErrorKind::WouldBlock) => Error::timeout(format!("{}: {}", context, err)),
+    _ => Error::new(format!("{}: {}", context, err))
+  }
+}
+
+/// Whether a device node looks like a Rigel that can stream.
+pub(crate) fn is_rigel(name: &str, caps: &Caps) -> bool {
+  name.contains("Leap Motion") && name.contains("Rigel") && caps.video_capture && caps.streaming
+}
+
+// Picks the supported mode closest to `requested`: an exact match if there is one, otherwise the fastest mode at the requested size that doesn't exceed the requested rate (or the slowest one above it), otherwise the same search at the Rigel's native size, otherwise whatever the device offers first.
+pub(crate) fn choose_mode(supported: &[CaptureMode], requested: CaptureMode) -> Option<CaptureMode> {
+  if supported.contains(&requested) {
+    return Some(requested);
+  }
+  let same_size: Vec<&CaptureMode> = supported.iter()
+    .filter(|mode| mode.width == requested.width && mode.height == requested.height)
+    .collect();
+  let at_or_below = same_size.iter().filter(|mode| mode.fps <= requested.fps).max_by_key(|mode| mode.fps);
+  let above = same_size.iter().filter(|mode| mode.fps > requested.fps).min_by_key(|mode| mode.fps);
+  if let Some(mode) = at_or_below.or(above) {
+    return Some(**mode);
+  }
+  let rigel_size = CaptureMode { fps: requested.fps, ..CaptureMode::RIGEL_DEFAULT };
+  if requested != rigel_size && supported.iter().any(|mode| mode.width == rigel_size.width && mode.height == rigel_size.height) {
+    return choose_mode(supported, rigel_size);
+  }
+  supported.first().copied()
+}
+
+/// Enumerates Rigels attached through V4L2.
+pub struct V4l2Backend;
+
+impl V4l2Backend {
+  pub fn new() -> Self { Self }
+}
+
+impl Default for V4l2Backend {
+  fn default() -> Self { Self::new() }
+}
+
+impl Backend for V4l2Backend {
+  fn name(&self) -> &str { "v4l2" }
+
+  fn enumerate(&self) -> Result<Vec<DeviceInfo>> {
+    let mut devices = Vec::new();
+    for node in sys::enum_nodes() {
+      // Nodes we can't open or query (busy, permissions, metadata-only nodes) are simply not candidates.
+      let device = match sys::open(&node.path) { Ok(device) => device, Err(_) => continue };
+      let caps = match device.querycap() { Ok(caps) => caps, Err(_) => continue };
+      let name = node.name.unwrap_or_else(|| caps.card.clone());
+      if !is_rigel(&name, &caps) { continue; }
+      devices.push(DeviceInfo { name, path: node.path, serial: sys::read_serial(node.index) });
+    }
+    Ok(devices)
+  }
+
+  fn open(&self, device: &DeviceInfo) -> Result<Box<dyn DeviceSource>> {
+    let v4l2_device = sys::open(&device.path).map_err(|err| map_io_error(&format!("Failed to open {}", device.path), err))?;
+    Ok(Box::new(V4l2Source::from_device(device.clone(), v4l2_device)?))
+  }
+}
+
+/// A Rigel opened through V4L2.
+pub struct V4l2Source {
+  info: DeviceInfo,
+  device: Box<dyn V4l2Device>,
+  mode: CaptureMode,
+  buffer_count: u32
+}
+
+impl V4l2Source {
+  /// Checks the device can stream YUYV and applies the Rigel's default mode (or the closest one it supports).
+  pub(crate) fn from_device(info: DeviceInfo, device: Box<dyn V4l2Device>) -> Result<Self> {
+    let caps = device.querycap().map_err(|err| map_io_error("VIDIOC_QUERYCAP failed", err))?;
+    if !caps.video_capture || !caps.streaming {
+      return Err(Error::new(format!("{} does not support streaming video capture.", info.path)));
+    }
+    let formats = device.enum_fmt().map_err(|err| map_io_error("VIDIOC_ENUM_FMT failed", err))?;
+    if !formats.contains(&YUYV) {
+      return Err(Error::new(format!("{} does not offer the YUYV format the Rigel streams in.", info.path)));
+    }
+
+    let mut source = Self { info, device, mode: CaptureMode::RIGEL_DEFAULT, buffer_count: 0 };
+    source.configure(CaptureMode::RIGEL_DEFAULT)?;
+    Ok(source)
+  }
+
+  fn is_streaming(&self) -> bool { self.buffer_count > 0 }
+
+  // Frees whatever start() managed to set up. STREAMOFF must come before the buffers are released, and is attempted even if the device is already gone.
+  fn teardown(&mut self) -> Result<()> {
+    let streamoff = self.device.streamoff();
+    let release = self.device.reqbufs(0);
+    self.buffer_count = 0;
+    streamoff.map_err(|err| map_io_error("VIDIOC_STREAMOFF failed", err))?;
+    release.map_err(|err| map_io_error("Failed to release buffers", err))?;
+    Ok(())
+  }
+}
+
+impl DeviceSource for V4l2Source {
+  fn info(&self) -> DeviceInfo { self.info.clone() }
+
+  fn modes(&self) -> Result<Vec<CaptureMode>> {
+    // YUYV packs two grayscale pixels per "pixel", one per eye, so the reported size is the per-eye size.
+    let sizes = self.device.enum_framesizes(YUYV).map_err(|err| map_io_error("VIDIOC_ENUM_FRAMESIZES failed", err))?;
+    let mut modes = Vec::new();
+    for (width, height) in sizes {
+      let intervals = self.device.enum_frameintervals(YUYV, width, height)
+        .map_err(|err| map_io_error("VIDIOC_ENUM_FRAMEINTERVALS failed", err))?;
+      for interval in intervals {
+        if interval.numerator != 0 && interval.denominator % interval.numerator == 0 {
+          modes.push(CaptureMode { width, height, fps: interval.denominator / interval.numerator });
+        }
+      }
+    }
+    Ok(modes)
+  }
+
+  fn mode(&self) -> CaptureMode { self.mode }
+
+  fn configure(&mut self, mode: CaptureMode) -> Result<CaptureMode> {
+    if self.is_streaming() {
+      return Err(Error::new("Cannot configure a V4L2 device while it is streaming.".to_string()));
+    }
+    let chosen = choose_mode(&self.modes()?, mode)
+      .ok_or_else(|| Error::new(format!("{} reports no YUYV capture modes.", self.info.path)))?;
+
+    let requested = PixFormat { fourcc: YUYV, width: chosen.width, height: chosen.height };
+    let applied = self.device.s_fmt(requested).map_err(|err| map_io_error("VIDIOC_S_FMT failed", err))?;
+    if applied != requested {
+      return Err(Error::new(format!(
+        "The driver changed the requested format {}x{} {} to {}x{} {}.",
+        requested.width, requested.height, String::from_utf8_lossy(&requested.fourcc),
+        applied.width, applied.height, String::from_utf8_lossy(&applied.fourcc)
+      )));
+    }
+
+    let interval = self.device.s_parm(Interval { numerator: 1, denominator: chosen.fps })
+      .map_err(|err| map_io_error("VIDIOC_S_PARM failed", err))?;
+    let fps = interval.denominator.checked_div(interval.numerator).unwrap_or(chosen.fps);
+
+    self.mode = CaptureMode { width: applied.width, height: applied.height, fps };
+    Ok(self.mode)
+  }
+
+  fn start(&mut self) -> Result<()> {
+    if self.is_streaming() { return Ok(()); }
+
+    let count = self.device.reqbufs(STREAM_BUFFER_COUNT).map_err(|err| map_io_error("VIDIOC_REQBUFS failed", err))?;
+    if count == 0 {
+      return Err(Error::new("The driver granted no capture buffers.".to_string()));
+    }
+    self.buffer_count = count;
+
+    let queued: Result<()> = (0..count).try_for_each(|index| {
+      self.device.qbuf(index).map_err(|err| map_io_error("VIDIOC_QBUF failed", err))
+    });
+    let started = queued.and_then(|_| self.device.streamon().map_err(|err| map_io_error("VIDIOC_STREAMON failed", err)));
+    if let Err(err) = started {
+      let _ = self.teardown();
+      return Err(err);
+    }
+    Ok(())
+  }
+
+  fn stop(&mut self) -> Result<()> {
+    if !self.is_streaming() { return Ok(()); }
+    self.teardown()
+  }
+
+  fn next_frame(&mut self, timeout: Duration) -> Result<Frame> {
+    if !self.is_streaming() {
+      return Err(Error::new("The V4L2 device is not streaming; call start() first.".to_string()));
+    }
+
+    let buf = self.device.dqbuf(timeout).map_err(|err| map_io_error("VIDIOC_DQBUF failed", err))?;
+    let mapped = self.device.buffer(buf.index);
+    let data = mapped[..(buf.bytesused as usize).min(mapped.len())].to_vec();
+    // Hand the buffer straight back to the driver; the frame owns a copy.
+    self.device.qbuf(buf.index).map_err(|err| map_io_error("VIDIOC_QBUF failed", err))?;
+
+    Ok(Frame::new(self.mode.width, self.mode.height, data, buf.sequence as u64, buf.timestamp))
+  }
+
+  fn controls(&self) -> Result<Vec<ControlInfo>> {
+    let descriptions = self.device.query_controls().map_err(|err| map_io_error("VIDIOC_QUERYCTRL failed", err))?;
+    Ok(descriptions.into_iter().map(|desc| ControlInfo {
+      control: control_from_id(desc.id),
+      name: desc.name,
+      min: desc.minimum,
+      max: desc.maximum,
+      step: desc.step,
+      default: desc.default
+    }).collect())
+  }
+
+  fn control(&self, control: Control) -> Result<i64> {
+    self.device.g_ctrl(control_id(control)).map_err(|err| map_io_error(&format!("Failed to read {:?}", control), err))
+  }
+
+  fn set_control(&mut self, control: Control, value: i64) -> Result<()> {
+    self.device.s_ctrl(control_id(control), value).map_err(|err| map_io_error(&format!("Failed to set {:?}", control), err))
+  }
+}
+
+impl Drop for V4l2Source {
+  fn drop(&mut self) {
+    if self.is_streaming() {
+      let _ = self.teardown();
+    }
+  }
+}
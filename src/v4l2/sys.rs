@@ -0,0 +1,247 @@
+// v4l2/sys.rs - tinyrigel
+//
+// The real V4l2Device: device queries go through the v4l crate, and the streaming ioctls (REQBUFS, QBUF, DQBUF, STREAMON/OFF) are issued directly so each maps to exactly one trait call. This is the only part of the Linux backend that needs a device node to run.
+
+use std::{fs, io, mem, ptr, slice, time::Duration};
+
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use v4l::{prelude::*, FourCC};
+use v4l::v4l2::{self as v4l2_api, vidioc};
+use v4l::v4l_sys::{v4l2_buffer, v4l2_control, v4l2_requestbuffers};
+use v4l::video::Capture;
+
+use super::device::*;
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+
+/// A /dev/videoN entry.
+pub(crate) struct Node {
+  pub index: usize,
+  pub path: String,
+  pub name: Option<String>
+}
+
+pub(crate) fn enum_nodes() -> Vec<Node> {
+  v4l::context::enum_devices().into_iter().map(|node| Node {
+    index: node.index(),
+    path: node.path().to_string_lossy().to_string(),
+    name: node.name()
+  }).collect()
+}
+
+// UVC devices expose their USB serial number in sysfs, on the USB device that owns the video interface.
+pub(crate) fn read_serial(index: usize) -> Option<String> {
+  let serial = fs::read_to_string(format!("/sys/class/video4linux/video{}/device/../serial", index)).ok()?;
+  let serial = serial.trim();
+  if serial.is_empty() { None } else { Some(serial.to_string()) }
+}
+
+pub(crate) fn open(path: &str) -> io::Result<Box<dyn V4l2Device>> {
+  Ok(Box::new(SysDevice { device: Device::with_path(path)?, buffers: Vec::new() }))
+}
+
+struct Mapping {
+  ptr: *mut u8,
+  len: usize
+}
+
+struct SysDevice {
+  device: Device,
+  buffers: Vec<Mapping>
+}
+
+// The mappings are owned exclusively by this device and only touched through &self / &mut self.
+unsafe impl Send for SysDevice {}
+
+impl SysDevice {
+  fn fd(&self) -> i32 { self.device.handle().fd() }
+
+  fn buffer_desc(index: u32) -> v4l2_buffer {
+    v4l2_buffer {
+      index,
+      type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+      memory: V4L2_MEMORY_MMAP,
+      ..unsafe { mem::zeroed() }
+    }
+  }
+
+  fn unmap_all(&mut self) -> io::Result<()> {
+    for mapping in self.buffers.drain(..) {
+      unsafe { munmap(mapping.ptr as *mut _, mapping.len) }.map_err(nix_to_io)?;
+    }
+    Ok(())
+  }
+}
+
+impl BufferMapper for SysDevice {
+  type Mapping = Mapping;
+
+  fn map_buffer(&mut self, index: u32) -> io::Result<Mapping> {
+    let mut buf = Self::buffer_desc(index);
+    unsafe {
+      v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_QUERYBUF, &mut buf as *mut _ as *mut std::os::raw::c_void)?;
+      let ptr = mmap(
+        ptr::null_mut(),
+        buf.length as usize,
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        MapFlags::MAP_SHARED,
+        self.fd(),
+        buf.m.offset as nix::libc::off_t
+      ).map_err(nix_to_io)?;
+      Ok(Mapping { ptr: ptr as *mut u8, len: buf.length as usize })
+    }
+  }
+
+  fn unmap_buffer(&mut self, mapping: Mapping) {
+    let _ = unsafe { munmap(mapping.ptr as *mut _, mapping.len) };
+  }
+
+  fn free_buffers(&mut self) {
+    let mut req = v4l2_requestbuffers {
+      count: 0,
+      type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+      memory: V4L2_MEMORY_MMAP,
+      ..unsafe { mem::zeroed() }
+    };
+    let _ = unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_REQBUFS, &mut req as *mut _ as *mut std::os::raw::c_void) };
+  }
+}
+
+fn nix_to_io(err: nix::Error) -> io::Error {
+  match err.as_errno() {
+    Some(errno) => io::Error::from_raw_os_error(errno as i32),
+    None => io::Error::new(io::ErrorKind::Other, err.to_string())
+  }
+}
+
+impl V4l2Device for SysDevice {
+  fn querycap(&self) -> io::Result<Caps> {
+    let caps = self.device.query_caps()?;
+    Ok(Caps {
+      card: caps.card.clone(),
+      video_capture: caps.capabilities.contains(v4l::capability::Flags::VIDEO_CAPTURE),
+      streaming: caps.capabilities.contains(v4l::capability::Flags::STREAMING)
+    })
+  }
+
+  fn enum_fmt(&self) -> io::Result<Vec<FourCc>> {
+    Ok(self.device.enum_formats()?.into_iter().map(|desc| desc.fourcc.repr).collect())
+  }
+
+  fn enum_framesizes(&self, fourcc: FourCc) -> io::Result<Vec<(u32, u32)>> {
+    let mut sizes = Vec::new();
+    for framesize in self.device.enum_framesizes(FourCC::new(&fourcc))? {
+      for discrete in framesize.size.to_discrete() {
+        sizes.push((discrete.width, discrete.height));
+      }
+    }
+    Ok(sizes)
+  }
+
+  fn enum_frameintervals(&self, fourcc: FourCc, width: u32, height: u32) -> io::Result<Vec<Interval>> {
+    let mut intervals = Vec::new();
+    for frameinterval in self.device.enum_frameintervals(FourCC::new(&fourcc), width, height)? {
+      if let v4l::frameinterval::FrameIntervalEnum::Discrete(frac) = frameinterval.interval {
+        intervals.push(Interval { numerator: frac.numerator, denominator: frac.denominator });
+      }
+    }
+    Ok(intervals)
+  }
+
+  fn s_fmt(&mut self, format: PixFormat) -> io::Result<PixFormat> {
+    let applied = self.device.set_format(&v4l::Format::new(format.width, format.height, FourCC::new(&format.fourcc)))?;
+    Ok(PixFormat { fourcc: applied.fourcc.repr, width: applied.width, height: applied.height })
+  }
+
+  fn s_parm(&mut self, interval: Interval) -> io::Result<Interval> {
+    let params = v4l::video::capture::Parameters::new(v4l::Fraction::new(interval.numerator, interval.denominator));
+    let applied = self.device.set_params(&params)?;
+    Ok(Interval { numerator: applied.interval.numerator, denominator: applied.interval.denominator })
+  }
+
+  fn reqbufs(&mut self, count: u32) -> io::Result<u32> {
+    if count == 0 {
+      self.unmap_all()?;
+    } else if !self.buffers.is_empty() {
+      return Err(io::Error::new(io::ErrorKind::Other, "buffers are already allocated"));
+    }
+
+    let mut req = v4l2_requestbuffers {
+      count,
+      type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+      memory: V4L2_MEMORY_MMAP,
+      ..unsafe { mem::zeroed() }
+    };
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_REQBUFS, &mut req as *mut _ as *mut std::os::raw::c_void)?; }
+
+    self.buffers = map_buffers(self, req.count)?;
+    Ok(req.count)
+  }
+
+  fn qbuf(&mut self, index: u32) -> io::Result<()> {
+    let mut buf = Self::buffer_desc(index);
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_QBUF, &mut buf as *mut _ as *mut std::os::raw::c_void) }
+  }
+
+  fn dqbuf(&mut self, timeout: Duration) -> io::Result<DequeuedBuffer> {
+    let mut fds = [PollFd::new(self.fd(), PollFlags::POLLIN)];
+    if poll(&mut fds, timeout.as_millis() as i32).map_err(nix_to_io)? == 0 {
+      return Err(io::Error::new(io::ErrorKind::TimedOut, "VIDIOC_DQBUF"));
+    }
+
+    let mut buf = Self::buffer_desc(0);
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_DQBUF, &mut buf as *mut _ as *mut std::os::raw::c_void)?; }
+    Ok(DequeuedBuffer {
+      index: buf.index,
+      bytesused: buf.bytesused,
+      sequence: buf.sequence,
+      timestamp: Duration::from_secs(buf.timestamp.tv_sec as u64) + Duration::from_micros(buf.timestamp.tv_usec as u64)
+    })
+  }
+
+  fn buffer(&self, index: u32) -> &[u8] {
+    let mapping = &self.buffers[index as usize];
+    unsafe { slice::from_raw_parts(mapping.ptr, mapping.len) }
+  }
+
+  fn streamon(&mut self) -> io::Result<()> {
+    let mut typ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_STREAMON, &mut typ as *mut _ as *mut std::os::raw::c_void) }
+  }
+
+  fn streamoff(&mut self) -> io::Result<()> {
+    let mut typ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_STREAMOFF, &mut typ as *mut _ as *mut std::os::raw::c_void) }
+  }
+
+  fn query_controls(&self) -> io::Result<Vec<ControlDesc>> {
+    Ok(self.device.query_controls()?.into_iter().map(|desc| ControlDesc {
+      id: desc.id,
+      name: desc.name,
+      minimum: desc.minimum as i64,
+      maximum: desc.maximum as i64,
+      step: desc.step as i64,
+      default: desc.default as i64
+    }).collect())
+  }
+
+  fn g_ctrl(&self, id: u32) -> io::Result<i64> {
+    let mut ctrl = v4l2_control { id, value: 0 };
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_G_CTRL, &mut ctrl as *mut _ as *mut std::os::raw::c_void)?; }
+    Ok(ctrl.value as i64)
+  }
+
+  fn s_ctrl(&mut self, id: u32, value: i64) -> io::Result<()> {
+    let mut ctrl = v4l2_control { id, value: value as i32 };
+    unsafe { v4l2_api::ioctl(self.fd(), vidioc::VIDIOC_S_CTRL, &mut ctrl as *mut _ as *mut std::os::raw::c_void) }
+  }
+}
+
+impl Drop for SysDevice {
+  fn drop(&mut self) {
+    // Normally V4l2Source has already released the buffers; this only matters if it was dropped mid-stream.
+    let _ = self.unmap_all();
+  }
+}
@@ -0,0 +1,292 @@
+// validation.rs - tinyrigel
+//
+// Checks a calibration against live frames, e.g. after a unit's housing was knocked. A point seen by both eyes lands on the same row of the rectified images when the calibration is right, so the vertical offset between matched points, the epipolar error, shows how far it has drifted. Matches come from corner features found in each eye on its own, so the matching doesn't pull them onto the same row, or from a calibration board, whose pose seen by the left eye also predicts where the right eye sees its corners. Errors are pooled over a window of recent frames.
+
+use std::{collections::VecDeque, time::Duration};
+
+use image::GenericImageView;
+
+use crate::*;
+
+// Features kept per eye, strongest first.
+const MAX_FEATURES: usize = 300;
+// Radius of the patches compared between the eyes.
+const PATCH_RADIUS: i64 = 4;
+// The lowest normalized cross-correlation two patches may have and still match.
+const MIN_CORRELATION: f64 = 0.8;
+// Fewer board corners than this in the left eye and there is no pose to reproject.
+const MIN_POSE_CORNERS: usize = 6;
+
+/// How a `CalibrationValidator`'s recent errors compare to its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationStatus {
+  /// Too few matches so far to judge.
+  Pending,
+  Pass,
+  Fail
+}
+
+/// A `CalibrationValidator`'s verdict on its recent frames. Epipolar errors are in rectified pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+  pub status: ValidationStatus,
+  /// Matches in the latest frame.
+  pub matches: usize,
+  /// Matches over the window.
+  pub samples: usize,
+  /// Median absolute epipolar error, which is held against the threshold since it shrugs off the odd mismatch.
+  pub epipolar_error: f64,
+  pub epipolar_rms: f64,
+  /// Mean signed epipolar error, right row minus left row. An offset this close to the median error means one eye has turned, rather than noise.
+  pub mean_offset: f64,
+  /// Median distance, in right eye pixels, between the board corners the right eye sees and where the board's pose in the left eye puts them. Only with a target; unlike the epipolar error it also catches a changed baseline. Held against the threshold too.
+  pub reprojection_error: Option<f64>
+}
+
+/// Measures how well a calibration still fits a stereo stream.
+pub struct CalibrationValidator {
+  calibration: StereoCalibration,
+  rectifier: StereoRectifier,
+  target: Option<CalibrationTarget>,
+  threshold: f64,
+  window: usize,
+  min_samples: usize,
+  // Per frame: signed epipolar errors, and reprojection errors.
+  history: VecDeque<(Vec<f64>, Vec<f64>)>
+}
+
+impl CalibrationValidator {
+  pub fn new(calibration: &StereoCalibration) -> Self {
+    Self {
+      calibration: calibration.clone(),
+      rectifier: StereoRectifier::new(calibration),
+      target: None,
+      threshold: 0.5,
+      window: 30,
+      min_samples: 30,
+      history: VecDeque::new()
+    }
+  }
+
+  /// A validator for the calibration a `Rigel` found for itself. Errors if it has none.
+  pub fn for_rigel<Cb>(rigel: &Rigel<Cb>) -> Result<Self>
+  where Cb: Fn(&Frame) + Send + 'static
+  {
+    let calibration = rigel.calibration().ok_or_else(|| Error::new("The Rigel has no calibration to validate.".to_string()))?;
+    Ok(Self::new(calibration))
+  }
+
+  /// Matches the corners of a calibration board instead of natural features, which is more precise and adds the reprojection error. `None` goes back to features.
+  pub fn set_target(&mut self, target: Option<CalibrationTarget>) {
+    self.target = target;
+    self.reset();
+  }
+
+  /// The largest median error, in pixels, that passes. Defaults to 0.5.
+  pub fn set_threshold(&mut self, pixels: f64) { self.threshold = pixels; }
+
+  /// How many recent frames the report pools, and how many matches it needs before it passes or fails. Default to 30 and 30.
+  pub fn set_window(&mut self, frames: usize, min_samples: usize) {
+    self.window = frames.max(1);
+    self.min_samples = min_samples.max(1);
+    while self.history.len() > self.window { self.history.pop_front(); }
+  }
+
+  /// Forgets the frames seen so far.
+  pub fn reset(&mut self) { self.history.clear(); }
+
+  /// Matches the eyes of `frame` and returns the report over the window, now including it. Errors if the frame is incomplete.
+  pub fn process(&mut self, frame: &Frame) -> Result<ValidationReport> {
+    let incomplete = || Error::new(format!("Frame {} is incomplete.", frame.sequence));
+    let left = frame.eye_view(Eye::Left).ok_or_else(incomplete)?;
+    let right = frame.eye_view(Eye::Right).ok_or_else(incomplete)?;
+    let errors = match &self.target {
+      Some(target) => self.board_errors(target, &left, &right),
+      None => (self.feature_errors(&left, &right), Vec::new())
+    };
+    self.history.push_back(errors);
+    while self.history.len() > self.window { self.history.pop_front(); }
+    Ok(self.report())
+  }
+
+  /// Pulls `frames` frames from an open `Rigel` without a frame callback and returns the report after the last.
+  pub fn run<Cb>(&mut self, rigel: &mut Rigel<Cb>, frames: usize, timeout: Duration) -> Result<ValidationReport>
+  where Cb: Fn(&Frame) + Send + 'static
+  {
+    let mut report = self.report();
+    for _ in 0..frames { report = self.process(&rigel.next_frame(timeout)?)?; }
+    Ok(report)
+  }
+
+  /// The report over the frames processed so far.
+  pub fn report(&self) -> ValidationReport {
+    let epipolar: Vec<f64> = self.history.iter().flat_map(|(errors, _)| errors.iter().copied()).collect();
+    let reprojection: Vec<f64> = self.history.iter().flat_map(|(_, errors)| errors.iter().copied()).collect();
+    let samples = epipolar.len();
+    let mean = |values: &[f64]| if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 };
+    let absolute: Vec<f64> = epipolar.iter().map(|e| e.abs()).collect();
+    let squares: Vec<f64> = epipolar.iter().map(|e| e * e).collect();
+    let epipolar_error = median(&absolute);
+    let reprojection_error = if self.target.is_some() && !reprojection.is_empty() { Some(median(&reprojection)) } else { None };
+    let status = if samples < self.min_samples {
+      ValidationStatus::Pending
+    } else if epipolar_error <= self.threshold && reprojection_error.iter().all(|&error| error <= self.threshold) {
+      ValidationStatus::Pass
+    } else {
+      ValidationStatus::Fail
+    };
+    ValidationReport {
+      status,
+      matches: self.history.back().map_or(0, |(errors, _)| errors.len()),
+      samples,
+      epipolar_error,
+      epipolar_rms: mean(&squares).sqrt(),
+      mean_offset: mean(&epipolar),
+      reprojection_error
+    }
+  }
+
+  // Signed epipolar errors of the corner features that are each other's best match between the eyes, among those near the same rectified row.
+  fn feature_errors(&self, left: &EyeView, right: &EyeView) -> Vec<f64> {
+    let (left_features, right_features) = (corner_features(left), corner_features(right));
+    let rectified = |eye: Eye, points: &[(f64, f64)]| -> Vec<(f64, f64)> { points.iter().map(|&(x, y)| self.rectifier.rectify_point(eye, x, y)).collect() };
+    let (left_rectified, right_rectified) = (rectified(Eye::Left, &left_features), rectified(Eye::Right, &right_features));
+    // Wide enough to still find the matches of a calibration well off the threshold.
+    let band = (self.threshold * 4.0).max(8.0);
+    let max_disparity = left.width() as f64 / 2.0;
+
+    let mut scores = vec![vec![f64::NEG_INFINITY; right_features.len()]; left_features.len()];
+    for (i, &(xl, yl)) in left_rectified.iter().enumerate() {
+      for (j, &(xr, yr)) in right_rectified.iter().enumerate() {
+        let disparity = xl - xr;
+        if (yl - yr).abs() > band || disparity < -1.0 || disparity > max_disparity { continue; }
+        scores[i][j] = correlation(left, left_features[i], right, right_features[j]);
+      }
+    }
+    let best = |values: &mut dyn Iterator<Item = f64>| values.enumerate().fold((0, f64::NEG_INFINITY), |best, (index, score)| if score > best.1 { (index, score) } else { best });
+    let mut errors = Vec::new();
+    for (i, row) in scores.iter().enumerate() {
+      let (j, score) = best(&mut row.iter().copied());
+      if score < MIN_CORRELATION { continue; }
+      if best(&mut scores.iter().map(|row| row[j])).0 != i { continue; }
+      errors.push(right_rectified[j].1 - left_rectified[i].1);
+    }
+    errors
+  }
+
+  // Signed epipolar errors of the board corners both eyes found, and the reprojection errors of the right eye's corners from the board's pose in the left eye.
+  fn board_errors(&self, target: &CalibrationTarget, left: &EyeView, right: &EyeView) -> (Vec<f64>, Vec<f64>) {
+    let (left_corners, right_corners) = (target.detect_view(left), target.detect_view(right));
+    let mut epipolar = Vec::new();
+    for corner in &left_corners {
+      let Some(other) = right_corners.iter().find(|other| other.id == corner.id) else { continue; };
+      let (_, yl) = self.rectifier.rectify_point(Eye::Left, corner.x, corner.y);
+      let (_, yr) = self.rectifier.rectify_point(Eye::Right, other.x, other.y);
+      epipolar.push(yr - yl);
+    }
+    if left_corners.len() < MIN_POSE_CORNERS || right_corners.is_empty() { return (epipolar, Vec::new()); }
+    let Some((rotation, translation)) = board_pose(target, &self.calibration.left, &left_corners) else { return (epipolar, Vec::new()); };
+
+    let mut reprojection = Vec::with_capacity(right_corners.len());
+    for corner in &right_corners {
+      let (x, y) = target.corner_position(corner.id).unwrap();
+      let point = mat_vec(&rotation, [x, y, 0.0]);
+      let point = mat_vec(&self.calibration.rotation, [point[0] + translation[0], point[1] + translation[1], point[2] + translation[2]]);
+      let point = [point[0] + self.calibration.translation[0], point[1] + self.calibration.translation[1], point[2] + self.calibration.translation[2]];
+      if point[2] <= 0.0 { continue; }
+      let (u, v) = self.calibration.right.project(point[0] / point[2], point[1] / point[2]);
+      reprojection.push((u - corner.x).hypot(v - corner.y));
+    }
+    (epipolar, reprojection)
+  }
+}
+
+fn median(values: &[f64]) -> f64 {
+  if values.is_empty() { return 0.0; }
+  let mut sorted = values.to_vec();
+  sorted.sort_by(|a, b| a.total_cmp(b));
+  let middle = sorted.len() / 2;
+  if sorted.len() % 2 == 1 { sorted[middle] } else { (sorted[middle - 1] + sorted[middle]) / 2.0 }
+}
+
+// The board's pose in the camera that saw `corners`.
+fn board_pose(target: &CalibrationTarget, camera: &CameraIntrinsics, corners: &[BoardCorner]) -> Option<(Mat3, Vec3)> {
+  let board: Vec<(f64, f64)> = corners.iter().map(|corner| target.corner_position(corner.id).unwrap()).collect();
+  let observed: Vec<(f64, f64)> = corners.iter().map(|corner| camera.unproject(corner.x, corner.y)).collect();
+  plane_pose(&board, &observed)
+}
+
+// Shi-Tomasi corners: the smaller eigenvalue of the gradient structure tensor over 5x5 windows, kept where it's the largest within 3 pixels and at least 5% of the strongest, then refined to subpixel.
+fn corner_features(view: &EyeView) -> Vec<(f64, f64)> {
+  let (width, height) = (view.width() as usize, view.height() as usize);
+  let margin = (PATCH_RADIUS + 4) as usize;
+  if width < 2 * margin + 1 || height < 2 * margin + 1 { return Vec::new(); }
+  let mut tensor = vec![[0f32; 3]; width * height];
+  for y in 1..height - 1 {
+    let (above, row, below) = (view.row(y as u32 - 1), view.row(y as u32), view.row(y as u32 + 1));
+    for x in 1..width - 1 {
+      let gx = row[x + 1] as f32 - row[x - 1] as f32;
+      let gy = below[x] as f32 - above[x] as f32;
+      tensor[y * width + x] = [gx * gx, gx * gy, gy * gy];
+    }
+  }
+  // 5x5 box sums, across then down.
+  let mut across = vec![[0f32; 3]; width * height];
+  for y in 0..height {
+    for x in 2..width - 2 {
+      let mut sum = [0f32; 3];
+      for value in &tensor[y * width + x - 2..=y * width + x + 2] { for k in 0..3 { sum[k] += value[k]; } }
+      across[y * width + x] = sum;
+    }
+  }
+  let mut score = vec![0f32; width * height];
+  for y in 2..height - 2 {
+    for x in 2..width - 2 {
+      let mut sum = [0f32; 3];
+      for dy in 0..5 { for k in 0..3 { sum[k] += across[(y + dy - 2) * width + x][k]; } }
+      let [a, b, c] = sum;
+      score[y * width + x] = (a + c) / 2.0 - (((a - c) / 2.0).powi(2) + b * b).sqrt();
+    }
+  }
+
+  let strongest = score.iter().cloned().fold(0.0, f32::max);
+  // Flat images have no corners worth the name, only noise.
+  if strongest < 1e4 { return Vec::new(); }
+  let mut candidates = Vec::new();
+  for y in margin..height - margin {
+    for x in margin..width - margin {
+      let s = score[y * width + x];
+      if s < strongest * 0.05 { continue; }
+      let is_peak = (y - 3..=y + 3).all(|ny| (x - 3..=x + 3).all(|nx| {
+        let other = score[ny * width + nx];
+        other < s || (other == s && (ny, nx) >= (y, x))
+      }));
+      if is_peak { candidates.push((s, x, y)); }
+    }
+  }
+  candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+  candidates.into_iter().take(MAX_FEATURES).filter_map(|(_, x, y)| refine_corner(view, (x as f64, y as f64), 3)).collect()
+}
+
+// Normalized cross-correlation of the patches around two points, -1 to 1.
+fn correlation(a: &EyeView, pa: (f64, f64), b: &EyeView, pb: (f64, f64)) -> f64 {
+  let (ax, ay, bx, by) = (pa.0.round() as i64, pa.1.round() as i64, pb.0.round() as i64, pb.1.round() as i64);
+  let inside = |view: &EyeView, x: i64, y: i64| x >= PATCH_RADIUS && y >= PATCH_RADIUS && x + PATCH_RADIUS < view.width() as i64 && y + PATCH_RADIUS < view.height() as i64;
+  if !inside(a, ax, ay) || !inside(b, bx, by) { return f64::NEG_INFINITY; }
+  let n = ((2 * PATCH_RADIUS + 1) * (2 * PATCH_RADIUS + 1)) as f64;
+  let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+  for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+    let (row_a, row_b) = (a.row((ay + dy) as u32), b.row((by + dy) as u32));
+    for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+      let (va, vb) = (row_a[(ax + dx) as usize] as f64, row_b[(bx + dx) as usize] as f64);
+      sa += va;
+      sb += vb;
+      saa += va * va;
+      sbb += vb * vb;
+      sab += va * vb;
+    }
+  }
+  let (var_a, var_b) = (saa - sa * sa / n, sbb - sb * sb / n);
+  if var_a <= 1e-9 || var_b <= 1e-9 { return f64::NEG_INFINITY; }
+  (sab - sa * sb / n) / (var_a * var_b).sqrt()
+}
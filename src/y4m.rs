@@ -0,0 +1,114 @@
+// y4m.rs - tinyrigel
+//
+// Streams frames as YUV4MPEG2 (or bare rawvideo) for ffmpeg and other video tools, either to a file or a pipe:
+//
+//   cargo run --example pipe_y4m | ffmpeg -i - out.mkv
+
+use std::{fs::File, io::{self, BufWriter, Stdout, Write}, path::Path};
+
+use crate::*;
+
+/// Pixel format of the exported video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Y4mColorspace {
+  /// 8-bit grayscale ("Cmono"), the frame pixels as-is.
+  Mono,
+  /// 4:2:0 YUV ("C420jpeg") with neutral chroma, for tools that don't accept grayscale input.
+  C420
+}
+
+/// Writes frames as a YUV4MPEG2 stream. A FrameSink, so it can be attached to a Rigel.
+///
+/// The stream header is written with the first frame, whose dimensions every later frame must match. Incomplete frames are padded with black.
+pub struct Y4mWriter<W: Write + Send> {
+  writer: W,
+  layout: EyeLayout,
+  colorspace: Y4mColorspace,
+  fps: u32,
+  headers: bool,
+  // Exported image dimensions, once the stream header has been written.
+  dimensions: Option<(u32, u32)>,
+  chroma: Vec<u8>
+}
+
+impl<W: Write + Send> Y4mWriter<W> {
+  pub fn new(writer: W, layout: EyeLayout, colorspace: Y4mColorspace, fps: u32) -> Self {
+    Self { writer, layout, colorspace, fps: fps.max(1), headers: true, dimensions: None, chroma: Vec::new() }
+  }
+
+  /// Writes bare planes without the YUV4MPEG2 stream and frame headers, for `ffmpeg -f rawvideo -pix_fmt gray` (or `yuv420p`). Must be set before the first frame.
+  pub fn set_headers(&mut self, headers: bool) { self.headers = headers; }
+
+  pub fn into_inner(self) -> W { self.writer }
+
+  fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+    self.writer.write_all(bytes).map_err(write_error)
+  }
+}
+
+fn write_error(err: io::Error) -> Error {
+  match err.kind() {
+    io::ErrorKind::BrokenPipe => Error::disconnected("The Y4M output pipe was closed.".to_string()),
+    _ => Error::new(format!("Failed to write Y4M stream: {}", err))
+  }
+}
+
+impl Y4mWriter<BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(path: P, layout: EyeLayout, colorspace: Y4mColorspace, fps: u32) -> Result<Self> {
+    let path = path.as_ref();
+    let file = File::create(path).map_err(|err| Error::new(format!("Failed to create {}: {}", path.display(), err)))?;
+    Ok(Self::new(BufWriter::new(file), layout, colorspace, fps))
+  }
+}
+
+impl Y4mWriter<BufWriter<Stdout>> {
+  /// Writes to standard output, for piping into another process. Nothing else should print to stdout while it's in use.
+  pub fn stdout(layout: EyeLayout, colorspace: Y4mColorspace, fps: u32) -> Self {
+    // Stdout is line buffered, which would split binary frames at every newline byte.
+    Self::new(BufWriter::new(io::stdout()), layout, colorspace, fps)
+  }
+}
+
+impl<W: Write + Send> FrameSink for Y4mWriter<W> {
+  fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    let (width, height) = self.layout.dimensions(frame.width, frame.height);
+    match self.dimensions {
+      None => {
+        if self.headers {
+          let colorspace = match self.colorspace { Y4mColorspace::Mono => "mono", Y4mColorspace::C420 => "420jpeg" };
+          let header = format!("YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C{}\n", width, height, self.fps, colorspace);
+          self.write_all(header.as_bytes())?;
+        }
+        if self.colorspace == Y4mColorspace::C420 {
+          let chroma_len = (width as usize).div_ceil(2) * (height as usize).div_ceil(2);
+          self.chroma = vec![128u8; chroma_len * 2];
+        }
+        self.dimensions = Some((width, height));
+      }
+      Some(dimensions) if dimensions != (width, height) => {
+        return Err(Error::new(format!(
+          "Frame is {}x{}, but the Y4M stream is {}x{}.", width, height, dimensions.0, dimensions.1
+        )));
+      }
+      Some(_) => ()
+    }
+
+    if self.headers {
+      self.write_all(b"FRAME\n")?;
+    }
+    let pixels = frame.layout_pixels(self.layout);
+    self.write_all(&pixels)?;
+    if self.colorspace == Y4mColorspace::C420 {
+      let chroma = std::mem::take(&mut self.chroma);
+      let written = self.write_all(&chroma);
+      self.chroma = chroma;
+      written?;
+    }
+    // Hand each frame on whole, so a reader at the other end of a pipe isn't kept waiting on a buffer.
+    self.writer.flush().map_err(write_error)
+  }
+
+  fn finish(&mut self) -> Result<()> {
+    self.writer.flush().map_err(|err| Error::new(format!("Failed to flush Y4M stream: {}", err)))
+  }
+}
@@ -0,0 +1,315 @@
+// yaml.rs - tinyrigel
+//
+// A small reader for the YAML that calibration tools write, OpenCV's FileStorage and Kalibr's camchain files: block mappings and sequences by indentation, flow collections (which may run over several lines), plain and quoted scalars, tags (skipped) and comments. Anchors, block scalars and multiple documents are not supported. Values come back as `Json`, which has the same shape.
+
+use crate::*;
+
+pub(crate) fn parse_yaml(text: &str) -> Result<Json> {
+  let mut lines: Vec<Line> = Vec::new();
+  // Flow collections still open at the end of the previous line, which continues on this one.
+  let mut open = 0;
+  for (index, raw) in text.lines().enumerate() {
+    let content = strip_comment(raw).trim_end();
+    let body = content.trim_start();
+    if open > 0 {
+      let last = lines.last_mut().unwrap();
+      last.text.push(' ');
+      last.text.push_str(body);
+      open += nesting(body);
+      continue;
+    }
+    // Directives (OpenCV writes "%YAML:1.0") and document markers.
+    if body.is_empty() || body.starts_with('%') || body.starts_with("---") { continue; }
+    if body == "..." { break; }
+    open = nesting(body);
+    lines.push(Line { number: index + 1, indent: content.len() - body.len(), text: body.to_string() });
+  }
+  if open > 0 { return Err(Error::new("Invalid YAML: unterminated flow collection.".to_string())); }
+  if lines.is_empty() { return Ok(Json::Null); }
+  let mut reader = Reader { lines, position: 0 };
+  let value = reader.block(reader.lines[0].indent)?;
+  if reader.position < reader.lines.len() { return Err(reader.error("unexpected indentation")); }
+  Ok(value)
+}
+
+struct Line {
+  number: usize,
+  indent: usize,
+  text: String
+}
+
+// Drops a comment: a '#' outside quotes that starts the line or follows whitespace.
+fn strip_comment(line: &str) -> &str {
+  let mut quote = None;
+  let mut previous = ' ';
+  for (index, c) in line.char_indices() {
+    match quote {
+      Some(q) if c == q => quote = None,
+      Some(_) => {}
+      None if c == '"' || c == '\'' => quote = Some(c),
+      None if c == '#' && previous.is_whitespace() => return &line[..index],
+      None => {}
+    }
+    previous = c;
+  }
+  line
+}
+
+// How many more flow collections `text` opens than it closes, outside quotes.
+fn nesting(text: &str) -> i32 {
+  let mut quote = None;
+  let mut depth = 0;
+  for c in text.chars() {
+    match quote {
+      Some(q) if c == q => quote = None,
+      Some(_) => {}
+      None => match c {
+        '"' | '\'' => quote = Some(c),
+        '[' | '{' => depth += 1,
+        ']' | '}' => depth -= 1,
+        _ => {}
+      }
+    }
+  }
+  depth
+}
+
+fn is_item(text: &str) -> bool { text == "-" || text.starts_with("- ") }
+
+// Splits "key: value" at the first colon outside quotes and flow collections that ends the line or is followed by a space.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+  if text.starts_with('[') || text.starts_with('{') { return None; }
+  let bytes = text.as_bytes();
+  let mut quote = None;
+  for (index, &byte) in bytes.iter().enumerate() {
+    match quote {
+      Some(q) if byte == q => quote = None,
+      Some(_) => {}
+      None if byte == b'"' || byte == b'\'' => quote = Some(byte),
+      None if byte == b':' && matches!(bytes.get(index + 1), None | Some(b' ')) => return Some((text[..index].trim(), text[index + 1..].trim())),
+      None => {}
+    }
+  }
+  None
+}
+
+// Drops a leading tag such as OpenCV's "!!opencv-matrix".
+fn strip_tag(value: &str) -> &str {
+  if !value.starts_with('!') { return value; }
+  value.split_once(' ').map_or("", |(_, rest)| rest.trim_start())
+}
+
+struct Reader {
+  lines: Vec<Line>,
+  position: usize
+}
+
+impl Reader {
+  fn error(&self, message: &str) -> Error {
+    let line = self.lines.get(self.position).or_else(|| self.lines.last()).map_or(0, |line| line.number);
+    Error::new(format!("Invalid YAML at line {}: {}.", line, message))
+  }
+
+  // The block starting at the current line, whose entries are indented by `indent`.
+  fn block(&mut self, indent: usize) -> Result<Json> {
+    if is_item(&self.lines[self.position].text) { self.sequence(indent) } else { self.mapping(indent) }
+  }
+
+  // The value of a key or item with nothing after it: the block indented below it, a sequence at the key's own indentation (which YAML allows for mapping values), or null.
+  fn nested(&mut self, indent: usize, key: bool) -> Result<Json> {
+    match self.lines.get(self.position) {
+      Some(line) if line.indent > indent => {
+        let indent = line.indent;
+        self.block(indent)
+      }
+      Some(line) if key && line.indent == indent && is_item(&line.text) => self.sequence(indent),
+      _ => Ok(Json::Null)
+    }
+  }
+
+  fn sequence(&mut self, indent: usize) -> Result<Json> {
+    let mut items = Vec::new();
+    while let Some(line) = self.lines.get(self.position) {
+      if line.indent != indent || !is_item(&line.text) { break; }
+      let (number, text) = (line.number, line.text.clone());
+      let rest = text[1..].trim_start().to_string();
+      if rest.is_empty() {
+        self.position += 1;
+        items.push(self.nested(indent, false)?);
+      } else if split_key(&rest).is_some() {
+        // "- key: value" starts a mapping whose keys line up with `key`.
+        let inner = indent + text.len() - rest.len();
+        self.lines[self.position] = Line { number, indent: inner, text: rest };
+        items.push(self.mapping(inner)?);
+      } else {
+        items.push(self.scalar_or_flow(&rest)?);
+        self.position += 1;
+      }
+    }
+    Ok(Json::Array(items))
+  }
+
+  fn mapping(&mut self, indent: usize) -> Result<Json> {
+    let mut entries = Vec::new();
+    while let Some(line) = self.lines.get(self.position) {
+      if line.indent < indent { break; }
+      if line.indent > indent || is_item(&line.text) { return Err(self.error("unexpected indentation")); }
+      let text = line.text.clone();
+      let (key, value) = split_key(&text).ok_or_else(|| self.error("expected \"key: value\""))?;
+      let key = match self.scalar_or_flow(key)? {
+        Json::String(key) => key,
+        Json::Number(_) | Json::Bool(_) => key.to_string(),
+        _ => return Err(self.error("unsupported key"))
+      };
+      let value = strip_tag(value).to_string();
+      let value = if value.is_empty() {
+        self.position += 1;
+        self.nested(indent, true)?
+      } else {
+        let value = self.scalar_or_flow(&value)?;
+        self.position += 1;
+        value
+      };
+      entries.push((key, value));
+    }
+    Ok(Json::Object(entries))
+  }
+
+  fn scalar_or_flow(&self, text: &str) -> Result<Json> {
+    let mut flow = Flow { bytes: text.as_bytes(), position: 0, depth: 0 };
+    let value = flow.value().map_err(|message| self.error(message))?;
+    flow.whitespace();
+    if flow.position < flow.bytes.len() { return Err(self.error("trailing characters")); }
+    Ok(value)
+  }
+}
+
+// A value on one (joined) line: a flow sequence or mapping, or a scalar.
+struct Flow<'a> {
+  bytes: &'a [u8],
+  position: usize,
+  depth: usize
+}
+
+impl Flow<'_> {
+  fn whitespace(&mut self) {
+    while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() { self.position += 1; }
+  }
+
+  fn peek(&mut self) -> Option<u8> {
+    self.whitespace();
+    self.bytes.get(self.position).copied()
+  }
+
+  fn value(&mut self) -> std::result::Result<Json, &'static str> {
+    match self.peek() {
+      Some(b'[') => {
+        self.position += 1;
+        self.depth += 1;
+        let mut items = Vec::new();
+        loop {
+          if self.peek() == Some(b']') { break; }
+          items.push(self.value()?);
+          match self.peek() {
+            Some(b',') => self.position += 1,
+            Some(b']') => break,
+            _ => return Err("expected ',' or ']'")
+          }
+        }
+        self.position += 1;
+        self.depth -= 1;
+        Ok(Json::Array(items))
+      }
+      Some(b'{') => {
+        self.position += 1;
+        self.depth += 1;
+        let mut entries = Vec::new();
+        loop {
+          if self.peek() == Some(b'}') { break; }
+          let key = match self.value()? { Json::String(key) => key, _ => return Err("expected a key") };
+          if self.peek() != Some(b':') { return Err("expected ':'"); }
+          self.position += 1;
+          entries.push((key, self.value()?));
+          match self.peek() {
+            Some(b',') => self.position += 1,
+            Some(b'}') => break,
+            _ => return Err("expected ',' or '}'")
+          }
+        }
+        self.position += 1;
+        self.depth -= 1;
+        Ok(Json::Object(entries))
+      }
+      Some(b'"') => self.double_quoted(),
+      Some(b'\'') => {
+        self.position += 1;
+        let mut text = Vec::new();
+        loop {
+          match self.bytes.get(self.position) {
+            None => return Err("unterminated string"),
+            // A doubled quote stands for one.
+            Some(b'\'') if self.bytes.get(self.position + 1) == Some(&b'\'') => { text.push(b'\''); self.position += 2; }
+            Some(b'\'') => { self.position += 1; break; }
+            Some(&byte) => { text.push(byte); self.position += 1; }
+          }
+        }
+        String::from_utf8(text).map(Json::String).map_err(|_| "invalid UTF-8")
+      }
+      _ => {
+        let start = self.position;
+        // Inside flow collections, plain scalars end at the punctuation; outside, they run to the end of the line (a key's scalar ends at its colon, which split_key already cut off).
+        while let Some(&byte) = self.bytes.get(self.position) {
+          if self.depth > 0 && matches!(byte, b',' | b']' | b'}' | b':') { break; }
+          self.position += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).map_err(|_| "invalid UTF-8")?;
+        Ok(plain(text.trim()))
+      }
+    }
+  }
+
+  fn double_quoted(&mut self) -> std::result::Result<Json, &'static str> {
+    self.position += 1;
+    let mut text = Vec::new();
+    loop {
+      let Some(&byte) = self.bytes.get(self.position) else { return Err("unterminated string"); };
+      self.position += 1;
+      match byte {
+        b'"' => break,
+        b'\\' => {
+          let Some(&escape) = self.bytes.get(self.position) else { return Err("unterminated string"); };
+          self.position += 1;
+          text.push(match escape {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'0' => 0,
+            b'"' | b'\\' | b'/' => escape,
+            _ => return Err("unsupported escape")
+          });
+        }
+        _ => text.push(byte)
+      }
+    }
+    String::from_utf8(text).map(Json::String).map_err(|_| "invalid UTF-8")
+  }
+}
+
+// A plain scalar: null, a boolean, a number, or else a string.
+fn plain(text: &str) -> Json {
+  match text {
+    "" | "~" | "null" | "Null" | "NULL" => return Json::Null,
+    "true" | "True" | "TRUE" => return Json::Bool(true),
+    "false" | "False" | "FALSE" => return Json::Bool(false),
+    ".inf" | "+.inf" | ".Inf" | "+.Inf" => return Json::Number(f64::INFINITY),
+    "-.inf" | "-.Inf" => return Json::Number(f64::NEG_INFINITY),
+    ".nan" | ".NaN" => return Json::Number(f64::NAN),
+    _ => {}
+  }
+  // Rust also reads "inf" and "nan", which YAML keeps as strings.
+  let numeric = text.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'+' | b'-' | b'.' | b'e' | b'E')) && text.bytes().any(|byte| byte.is_ascii_digit());
+  match text.parse() {
+    Ok(number) if numeric => Json::Number(number),
+    _ => Json::String(text.to_string())
+  }
+}